@@ -0,0 +1,54 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use crypto::base58check::{self, Base58CheckString};
+
+/// A P2PKH address payload and an extended key serialization, with their checksums.
+const LENGTHS: [usize; 2] = [25, 82];
+
+fn payload(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 131 + 7) as u8).collect()
+}
+
+fn bench_encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode");
+
+    for len in LENGTHS.iter() {
+        let data = payload(*len);
+
+        group.bench_with_input(BenchmarkId::new("constant_time", len), &data, |b, data| {
+            b.iter(|| base58check::encode(black_box(data)))
+        });
+
+        let mut string = String::new();
+        group.bench_with_input(BenchmarkId::new("vartime_into", len), &data, |b, data| {
+            b.iter(|| {
+                string.clear();
+                base58check::encode_vartime_into(black_box(data), &mut string);
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_base58check(c: &mut Criterion) {
+    let mut group = c.benchmark_group("base58check");
+
+    for len in LENGTHS.iter() {
+        let data = payload(*len - 4);
+
+        group.bench_with_input(BenchmarkId::new("from_bytes", len), &data, |b, data| {
+            b.iter(|| Base58CheckString::from_bytes(black_box(data)))
+        });
+
+        group.bench_with_input(
+            BenchmarkId::new("from_public_bytes", len),
+            &data,
+            |b, data| b.iter(|| Base58CheckString::from_public_bytes(black_box(data))),
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_encode, bench_base58check);
+criterion_main!(benches);
@@ -0,0 +1,83 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use crypto::address::{self, ScriptType};
+use crypto::base58check::Base58CheckString;
+use crypto::bip32::{Chain, ChildNumber, DerivationCache, ExtendedPublicKey};
+use std::convert::TryFrom;
+
+const XPUB: &str = "xpub6FFQ9VG4C9qhWBgoa6nURfEkYAbkE6pyScvERKKniwfxGqFabPGUo7uaiHfBb2vpKqdiFkKW1Wab9T2EJahdWXmHXXLV6F53xtaae4uaqR1";
+
+fn xpub() -> ExtendedPublicKey {
+    ExtendedPublicKey::try_from(&Base58CheckString::try_from(XPUB.to_string()).unwrap()).unwrap()
+}
+
+fn derive(c: &mut Criterion) {
+    let xpub = xpub();
+    let mut group = c.benchmark_group("derive 100 receive addresses");
+    group.bench_function("derive_public_path", |b| {
+        b.iter(|| {
+            (0..100)
+                .map(|i| {
+                    xpub.derive_public_path(&[ChildNumber::Normal(0), ChildNumber::Normal(i)])
+                        .unwrap()
+                })
+                .collect::<Vec<_>>()
+        })
+    });
+    group.bench_function("DerivationCache::derive", |b| {
+        b.iter(|| {
+            let mut cache = DerivationCache::new(xpub.clone());
+            (0..100)
+                .map(|i| {
+                    cache
+                        .derive(&[ChildNumber::Normal(0), ChildNumber::Normal(i)])
+                        .unwrap()
+                })
+                .collect::<Vec<_>>()
+        })
+    });
+    group.bench_function("derive_public", |b| {
+        let chain = xpub.derive_public(ChildNumber::Normal(0)).unwrap();
+        b.iter(|| {
+            (0..100)
+                .map(|i| chain.derive_public(ChildNumber::Normal(i)).unwrap())
+                .collect::<Vec<_>>()
+        })
+    });
+    group.bench_function("derive_range", |b| {
+        b.iter(|| {
+            xpub.derive_public(ChildNumber::Normal(0))
+                .unwrap()
+                .derive_range(0..100)
+                .unwrap()
+        })
+    });
+    group.finish();
+}
+
+fn derive_addresses(c: &mut Criterion) {
+    let xpub = xpub();
+    let mut group = c.benchmark_group("derive 10000 P2WPKH addresses");
+    group.sample_size(10);
+    group.bench_function("serial", |b| {
+        b.iter(|| {
+            let chain = xpub.derive_public(Chain::Receive.child_number()).unwrap();
+            (0..10_000)
+                .map(|i| {
+                    let xpub = chain.derive_public(ChildNumber::Normal(i)).unwrap();
+                    ScriptType::P2wpkh
+                        .address(&xpub.public_key, xpub.network)
+                        .unwrap()
+                })
+                .collect::<Vec<_>>()
+        })
+    });
+    group.bench_function("derive_addresses", |b| {
+        b.iter(|| {
+            address::derive_addresses(&xpub, Chain::Receive, 0..10_000, ScriptType::P2wpkh).unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, derive, derive_addresses);
+criterion_main!(benches);
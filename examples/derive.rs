@@ -1,4 +1,5 @@
-use crypto::{address, base58check::Base58CheckString, bip32::ExtendedPublicKey};
+use crypto::address::{self, ScriptType};
+use crypto::{base58check::Base58CheckString, bip32::Chain, bip32::ExtendedPublicKey};
 use std::convert::TryFrom;
 
 fn main() {
@@ -8,11 +9,11 @@ fn main() {
     let master_xpub = Base58CheckString::try_from(master_xpub).unwrap();
     let master_xpub = ExtendedPublicKey::try_from(&master_xpub).unwrap();
 
-    let xpub = master_xpub.derive_public(0.into()).unwrap();
+    let addresses =
+        address::derive_addresses(&master_xpub, Chain::Receive, 0..20, ScriptType::P2shP2wpkh)
+            .unwrap();
 
-    for i in 0..20 {
-        let xpub = xpub.derive_public(i.into()).unwrap();
-        let address = address::p2sh_p2wpkh(&xpub.public_key);
+    for (i, address) in addresses.iter().enumerate() {
         println!("{}\t{}", i, address);
     }
 }
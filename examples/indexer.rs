@@ -1,6 +1,6 @@
 use crypto::rpc;
 
-const GENESIS: &'static str = "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
+const GENESIS: &str = "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
 
 fn main() {
     let client = rpc::BlockingClient::new(
@@ -12,7 +12,7 @@ fn main() {
     );
 
     let mut block = client
-        .get_block(String::from(GENESIS))
+        .get_block(GENESIS.parse().expect("valid block hash"))
         .expect("genesis block not found on-chain");
 
     loop {
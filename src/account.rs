@@ -0,0 +1,482 @@
+//! Single-key wallet accounts following BIP44, BIP49, BIP84 and BIP86, which derive their keys
+//! at `m/purpose'/coin_type'/account'` and their addresses at `.../chain/index`. Account keys
+//! are exported with the SLIP-132 version bytes of their purpose (`ypub`, `zpub`...).
+
+use crate::address::{self, ScriptType};
+use crate::base58check::{self, Base58CheckString};
+use crate::bip32::{
+    self, Chain, ChildNumber, DerivationPath, ExtendedPrivateKey, ExtendedPublicKey,
+};
+use crate::network::Network;
+use crate::psbt;
+use crate::script::Script;
+use std::convert::TryFrom;
+use std::ops::Range;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Purpose {
+    /// P2PKH addresses.
+    Bip44,
+    /// P2SH-wrapped P2WPKH addresses.
+    Bip49,
+    /// P2WPKH addresses.
+    Bip84,
+    /// P2TR addresses with no script tree.
+    Bip86,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    Address(address::Error),
+    Base58Check(base58check::Error),
+    Bip32(bip32::Error),
+    InvalidPrefix(Vec<u8>),
+    /// The key isn't a master key, or the account key isn't a hardened key at depth 3.
+    InvalidDepth(u8),
+    NotHardened(ChildNumber),
+    /// The account key's origin isn't the account's path.
+    InvalidOrigin(DerivationPath),
+}
+
+impl From<address::Error> for Error {
+    fn from(err: address::Error) -> Error {
+        Error::Address(err)
+    }
+}
+
+impl From<base58check::Error> for Error {
+    fn from(err: base58check::Error) -> Error {
+        Error::Base58Check(err)
+    }
+}
+
+impl From<bip32::Error> for Error {
+    fn from(err: bip32::Error) -> Error {
+        Error::Bip32(err)
+    }
+}
+
+const PURPOSES: [Purpose; 4] = [
+    Purpose::Bip44,
+    Purpose::Bip49,
+    Purpose::Bip84,
+    Purpose::Bip86,
+];
+
+impl Purpose {
+    pub fn number(self) -> u32 {
+        match self {
+            Purpose::Bip44 => 44,
+            Purpose::Bip49 => 49,
+            Purpose::Bip84 => 84,
+            Purpose::Bip86 => 86,
+        }
+    }
+
+    pub fn script_type(self) -> ScriptType {
+        match self {
+            Purpose::Bip44 => ScriptType::P2pkh,
+            Purpose::Bip49 => ScriptType::P2shP2wpkh,
+            Purpose::Bip84 => ScriptType::P2wpkh,
+            Purpose::Bip86 => ScriptType::P2tr,
+        }
+    }
+
+    /// Returns the SLIP-132 version bytes of this purpose's extended public keys. SLIP-132
+    /// assigns none to BIP86, or to most purposes of other coins, whose keys keep their
+    /// network's BIP32 versions and so decode as BIP44 accounts.
+    pub fn xpub_version(self, network: Network) -> [u8; 4] {
+        match (self, network) {
+            (Purpose::Bip49, Network::Mainnet) => [0x04, 0x9d, 0x7c, 0xb2],
+            (Purpose::Bip49, Network::Testnet) => [0x04, 0x4a, 0x52, 0x62],
+            (Purpose::Bip49, Network::Litecoin) => [0x01, 0xb2, 0x6e, 0xf6],
+            (Purpose::Bip84, Network::Mainnet) => [0x04, 0xb2, 0x47, 0x46],
+            (Purpose::Bip84, Network::Testnet) => [0x04, 0x5f, 0x1c, 0xf6],
+            _ => network.xpub_version(),
+        }
+    }
+
+    /// Returns the path of account `index` for `coin_type`.
+    pub fn account_path(self, coin_type: u32, index: u32) -> DerivationPath {
+        DerivationPath::from(vec![
+            ChildNumber::Hardened(self.number()),
+            ChildNumber::Hardened(coin_type),
+            ChildNumber::Hardened(index),
+        ])
+    }
+}
+
+/// The SLIP-44 coin type of Ether, whose BIP44 accounts are at `m/44'/60'/account'`.
+pub const ETHEREUM_COIN_TYPE: u32 = 60;
+
+/// A watch-only account, which hands out the addresses of its purpose's script type.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Account {
+    purpose: Purpose,
+    coin_type: u32,
+    index: u32,
+    xpub: ExtendedPublicKey,
+    receive: ExtendedPublicKey,
+    change: ExtendedPublicKey,
+}
+
+impl Account {
+    /// Derives account `index` of `purpose` and `coin_type` from `master`, whose addresses are
+    /// on the master key's network. Most coins use their network's coin type,
+    /// `Network::coin_type`.
+    pub fn from_master(
+        master: &ExtendedPrivateKey,
+        purpose: Purpose,
+        coin_type: u32,
+        index: u32,
+    ) -> Result<Account, Error> {
+        if master.depth != 0 {
+            return Err(Error::InvalidDepth(master.depth));
+        }
+
+        let path = purpose.account_path(coin_type, index);
+        let xprv = master.derive_private_path(path.as_ref())?;
+        Account::from_xpub(ExtendedPublicKey::from(&xprv), purpose, coin_type)
+    }
+
+    /// Creates the account of `purpose` and `coin_type` whose key is `xpub`.
+    pub fn from_xpub(
+        xpub: ExtendedPublicKey,
+        purpose: Purpose,
+        coin_type: u32,
+    ) -> Result<Account, Error> {
+        let script_type = purpose.script_type();
+        if !script_type.is_supported(xpub.network) {
+            return Err(address::Error::UnsupportedScriptType(script_type, xpub.network).into());
+        }
+
+        if xpub.depth != 3 {
+            return Err(Error::InvalidDepth(xpub.depth));
+        }
+
+        let index = match xpub.child_number {
+            ChildNumber::Hardened(index) => index,
+            child_number => return Err(Error::NotHardened(child_number)),
+        };
+
+        if let Some((_, path)) = &xpub.origin {
+            if *path != purpose.account_path(coin_type, index) {
+                return Err(Error::InvalidOrigin(path.clone()));
+            }
+        }
+
+        Ok(Account {
+            purpose,
+            coin_type,
+            index,
+            receive: xpub.derive_public(Chain::Receive.child_number())?,
+            change: xpub.derive_public(Chain::Change.child_number())?,
+            xpub,
+        })
+    }
+
+    pub fn purpose(&self) -> Purpose {
+        self.purpose
+    }
+
+    pub fn coin_type(&self) -> u32 {
+        self.coin_type
+    }
+
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn network(&self) -> Network {
+        self.xpub.network
+    }
+
+    pub fn xpub(&self) -> &ExtendedPublicKey {
+        &self.xpub
+    }
+
+    /// Returns the account's path from the master key.
+    pub fn path(&self) -> DerivationPath {
+        self.purpose.account_path(self.coin_type(), self.index)
+    }
+
+    /// Returns the address at `index` of `chain`.
+    pub fn address(&self, chain: Chain, index: u32) -> Result<String, Error> {
+        let xpub = self.derive(chain, index)?;
+        Ok(self
+            .purpose
+            .script_type()
+            .address(&xpub.public_key, self.network())?)
+    }
+
+    /// Returns the output script of the address at `index` of `chain`.
+    pub fn script_pubkey(&self, chain: Chain, index: u32) -> Result<Script, Error> {
+        let xpub = self.derive(chain, index)?;
+        Ok(self.purpose.script_type().script_pubkey(&xpub.public_key))
+    }
+
+    /// Adds the key derivation identifying a PSBT output as paying to the address at `index` of
+    /// `chain`. The account key is taken to be its own master key if its origin is unknown.
+    ///
+    /// Taproot outputs are left alone, since their derivations belong in BIP371 fields.
+    pub fn update_psbt_output(
+        &self,
+        output: &mut psbt::Output,
+        chain: Chain,
+        index: u32,
+    ) -> Result<(), Error> {
+        if self.purpose.script_type() == ScriptType::P2tr {
+            return Ok(());
+        }
+
+        let (fingerprint, path) = self
+            .xpub
+            .origin
+            .clone()
+            .unwrap_or_else(|| (self.xpub.fingerprint(), DerivationPath::default()));
+        let path = path.extend(&[chain.child_number(), ChildNumber::Normal(index)]);
+
+        output
+            .bip32_derivation
+            .insert(self.derive(chain, index)?.public_key, (fingerprint, path));
+        Ok(())
+    }
+
+    fn chain(&self, chain: Chain) -> &ExtendedPublicKey {
+        match chain {
+            Chain::Receive => &self.receive,
+            Chain::Change => &self.change,
+        }
+    }
+
+    fn derive(&self, chain: Chain, index: u32) -> Result<ExtendedPublicKey, Error> {
+        Ok(self
+            .chain(chain)
+            .derive_public(ChildNumber::Normal(index))?)
+    }
+
+    /// Returns the addresses at indices `range` of `chain`, derived in parallel.
+    pub fn addresses(&self, chain: Chain, range: Range<u32>) -> Result<Vec<String>, Error> {
+        Ok(address::derive_chain_addresses(
+            self.chain(chain),
+            range,
+            self.purpose.script_type(),
+        )?)
+    }
+}
+
+impl From<&Account> for Base58CheckString {
+    /// Encodes the account key with its purpose's SLIP-132 version bytes.
+    fn from(account: &Account) -> Base58CheckString {
+        let mut data = account.xpub.serialize();
+        data[..4].copy_from_slice(&account.purpose.xpub_version(account.network()));
+        Base58CheckString::from_public_bytes(data)
+    }
+}
+
+impl TryFrom<&Base58CheckString> for Account {
+    type Error = Error;
+
+    /// Decodes an account key with SLIP-132 version bytes, which determine its purpose and
+    /// network, and so its coin type. Plain `xpub`s and `tpub`s are taken to be BIP44 accounts.
+    fn try_from(v: &Base58CheckString) -> Result<Self, Self::Error> {
+        let mut data = v.into_bytes()?;
+        if data.len() != 78 {
+            return Err(bip32::Error::InvalidLength(data.len()).into());
+        }
+
+        let (purpose, network) = PURPOSES
+            .iter()
+            .flat_map(|purpose| Network::ALL.iter().map(move |network| (*purpose, *network)))
+            .find(|(purpose, network)| data[..4] == purpose.xpub_version(*network))
+            .ok_or_else(|| Error::InvalidPrefix(data[..4].to_vec()))?;
+
+        data[..4].copy_from_slice(&network.xpub_version());
+        let xpub = ExtendedPublicKey::deserialize(&data)?;
+        Account::from_xpub(xpub, purpose, network.coin_type())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::bip39;
+
+    fn root() -> ExtendedPrivateKey {
+        bip39::test_root(Network::Mainnet)
+    }
+
+    #[test]
+    fn test_accounts() {
+        let tests = [
+            (
+                Purpose::Bip44,
+                "xpub6BosfCnifzxcFwrSzQiqu2DBVTshkCXacvNsWGYJVVhhawA7d4R5WSWGFNbi8Aw6ZRc1brxMyWMzG3DSSSSoekkudhUd9yLb6qx39T9nMdj",
+                ["1LqBGSKuX5yYUonjxT5qGfpUsXKYYWeabA", "1Ak8PffB2meyfYnbXZR9EGfLfFZVpzJvQP", "1J3J6EvPrv8q6AC3VCjWV45Uf3nssNMRtH"],
+            ),
+            (
+                Purpose::Bip49,
+                "ypub6Ww3ibxVfGzLrAH1PNcjyAWenMTbbAosGNB6VvmSEgytSER9azLDWCxoJwW7Ke7icmizBMXrzBx9979FfaHxHcrArf3zbeJJJUZPf663zsP",
+                ["37VucYSaXLCAsxYyAPfbSi9eh4iEcbShgf", "3LtMnn87fqUeHBUG414p9CWwnoV6E2pNKS", "34K56kSjgUCUSD8GTtuF7c9Zzwokbs6uZ7"],
+            ),
+            (
+                Purpose::Bip84,
+                "zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs",
+                ["bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu", "bc1qnjg0jd8228aq7egyzacy8cys3knf9xvrerkf9g", "bc1q8c6fshw2dlwun7ekn9qwf37cu2rn755upcp6el"],
+            ),
+            (
+                Purpose::Bip86,
+                "xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ",
+                ["bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr", "bc1p4qhjn9zdvkux4e44uhx8tc55attvtyu358kutcqkudyccelu0was9fqzwh", "bc1p3qkhfews2uk44qtvauqyr2ttdsw7svhkl9nkm9s9c3x4ax5h60wqwruhk7"],
+            ),
+        ];
+
+        for (purpose, xpub, [receive_0, receive_1, change_0]) in tests.iter() {
+            let account = Account::from_master(&root(), *purpose, 0, 0).unwrap();
+            assert_eq!(account.path(), purpose.account_path(0, 0));
+            assert_eq!(
+                account.xpub().origin,
+                Some((root().fingerprint(), account.path()))
+            );
+            assert_eq!(Base58CheckString::from(&account).as_str(), *xpub);
+
+            assert_eq!(account.address(Chain::Receive, 0).unwrap(), *receive_0);
+            assert_eq!(account.address(Chain::Receive, 1).unwrap(), *receive_1);
+            assert_eq!(account.address(Chain::Change, 0).unwrap(), *change_0);
+            assert_eq!(
+                account.addresses(Chain::Receive, 0..2),
+                Ok(vec![receive_0.to_string(), receive_1.to_string()])
+            );
+        }
+    }
+
+    #[test]
+    fn test_slip132_roundtrip() {
+        for purpose in PURPOSES.iter() {
+            for network in [Network::Mainnet, Network::Testnet].iter() {
+                let mut root = root();
+                root.network = *network;
+                let account =
+                    Account::from_master(&root, *purpose, network.coin_type(), 7).unwrap();
+
+                let encoded = Base58CheckString::from(&account);
+                let decoded = Account::try_from(&encoded).unwrap();
+                assert_eq!(decoded.xpub().origin, None);
+                assert_eq!(decoded.index(), 7);
+                assert_eq!(decoded.coin_type(), network.coin_type());
+                assert_eq!(decoded.network(), *network);
+                assert_eq!(Base58CheckString::from(&decoded), encoded);
+
+                // BIP86 keys can't be told apart from BIP44 ones.
+                if *purpose != Purpose::Bip86 {
+                    assert_eq!(decoded.purpose(), *purpose);
+                    assert_eq!(
+                        decoded.address(Chain::Change, 3),
+                        account.address(Chain::Change, 3)
+                    );
+                }
+            }
+        }
+
+        let mut root = root();
+        root.network = Network::Testnet;
+        let account = Account::from_master(&root, Purpose::Bip84, 1, 0).unwrap();
+        assert!(Base58CheckString::from(&account)
+            .as_str()
+            .starts_with("vpub"));
+        assert!(account
+            .address(Chain::Receive, 0)
+            .unwrap()
+            .starts_with("tb1q"));
+    }
+
+    #[test]
+    fn test_altcoin_accounts() {
+        let mut root = root();
+        root.network = Network::Litecoin;
+        let account = Account::from_master(&root, Purpose::Bip44, 2, 0).unwrap();
+        assert_eq!(account.path(), Purpose::Bip44.account_path(2, 0));
+        assert_eq!(
+            Base58CheckString::from(&account).as_str(),
+            "Ltub2YDQmP391UYeDYvLye9P1SuNJFkcRGN7SYHM8JMxaDnegcPTXHJ2BnYmvHnFnGPGKu2WMuCga6iZV3SDxDMGrRyMcrYEfSPhrpS1EPkC43E"
+        );
+        assert_eq!(
+            account.address(Chain::Receive, 0).unwrap(),
+            "LUWPbpM43E2p7ZSh8cyTBEkvpHmr3cB8Ez"
+        );
+
+        let account = Account::from_master(&root, Purpose::Bip49, 2, 0).unwrap();
+        let encoded = Base58CheckString::from(&account);
+        assert!(encoded.as_str().starts_with("Mtub"));
+        let decoded = Account::try_from(&encoded).unwrap();
+        assert_eq!(decoded.purpose(), Purpose::Bip49);
+        assert_eq!(
+            decoded.address(Chain::Receive, 0).unwrap(),
+            "M7wtsL7wSHDBJVMWWhtQfTMSYYkyooAAXM"
+        );
+
+        root.network = Network::Dogecoin;
+        assert_eq!(
+            Account::from_master(&root, Purpose::Bip84, 3, 0),
+            Err(Error::Address(address::Error::UnsupportedScriptType(
+                ScriptType::P2wpkh,
+                Network::Dogecoin
+            )))
+        );
+
+        // Ether has no network of its own, so its coin type has to be given.
+        root.network = Network::Mainnet;
+        let account = Account::from_master(&root, Purpose::Bip44, ETHEREUM_COIN_TYPE, 0).unwrap();
+        assert_eq!(account.coin_type(), ETHEREUM_COIN_TYPE);
+        assert_eq!(account.path().to_string(), "m/44'/60'/0'");
+        assert_eq!(
+            address::derive_ethereum_addresses(account.xpub(), Chain::Receive, 0..1),
+            Ok(vec![
+                "0x9858EfFD232B4033E47d90003D41EC34EcaEda94".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_invalid_accounts() {
+        let root = root();
+        let account = root.derive_private(ChildNumber::Hardened(84)).unwrap();
+        assert_eq!(
+            Account::from_master(&account, Purpose::Bip84, 0, 0),
+            Err(Error::InvalidDepth(1))
+        );
+        assert_eq!(
+            Account::from_xpub(ExtendedPublicKey::from(&root), Purpose::Bip84, 0),
+            Err(Error::InvalidDepth(0))
+        );
+
+        let path = [
+            ChildNumber::Hardened(84),
+            ChildNumber::Hardened(0),
+            ChildNumber::Normal(0),
+        ];
+        let xpub = ExtendedPublicKey::from(&root.derive_private_path(&path).unwrap());
+        assert_eq!(
+            Account::from_xpub(xpub, Purpose::Bip84, 0),
+            Err(Error::NotHardened(ChildNumber::Normal(0)))
+        );
+
+        // An account key whose origin is known has to be at the account's path.
+        let account = Account::from_master(&root, Purpose::Bip84, 0, 0).unwrap();
+        assert_eq!(
+            Account::from_xpub(account.xpub().clone(), Purpose::Bip84, 1),
+            Err(Error::InvalidOrigin(account.path()))
+        );
+        assert_eq!(
+            Account::from_xpub(account.xpub().clone(), Purpose::Bip49, 0),
+            Err(Error::InvalidOrigin(account.path()))
+        );
+
+        let xprv = Base58CheckString::from(&root);
+        assert!(matches!(
+            Account::try_from(&xprv),
+            Err(Error::InvalidPrefix(_))
+        ));
+    }
+}
@@ -1,16 +1,75 @@
-use crate::base58check::Base58CheckString;
+use crate::base58check::{self, Base58CheckString};
+use crate::bech32;
+use crate::bip32::{self, Chain, ExtendedPublicKey};
+use crate::cashaddr::{self, AddressType};
+use crate::context;
 use crate::hash;
+use crate::network::Network;
+use crate::script::interpreter;
+use crate::script::{opcodes, Builder, Script};
 
-use secp256k1::PublicKey;
+use rayon::prelude::*;
+use secp256k1::{schnorrsig, PublicKey};
+use std::convert::TryFrom;
+use std::ops::Range;
 
 pub const PREFIX_P2PKH: &[u8] = &[0x00];
 pub const PREFIX_P2SH: &[u8] = &[0x05];
+pub const BECH32_HRP: &str = "bc";
+
+/// The number of consecutive addresses each parallel task of `derive_addresses` derives with
+/// `ExtendedPublicKey::derive_range`.
+const DERIVE_CHUNK: u32 = 256;
+
+/// Error represents all of the possible errors that can arise while parsing an address.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    Base58Check(base58check::Error),
+    Bech32(bech32::Error),
+    Bip32(bip32::Error),
+    CashAddr(cashaddr::Error),
+    Hex(hex::FromHexError),
+    InvalidLength(usize),
+    InvalidPrefix(Vec<u8>),
+    InvalidChecksum,
+    UnsupportedScriptType(ScriptType, Network),
+}
+
+impl From<base58check::Error> for Error {
+    fn from(err: base58check::Error) -> Error {
+        Error::Base58Check(err)
+    }
+}
+
+impl From<bech32::Error> for Error {
+    fn from(err: bech32::Error) -> Error {
+        Error::Bech32(err)
+    }
+}
+
+impl From<bip32::Error> for Error {
+    fn from(err: bip32::Error) -> Error {
+        Error::Bip32(err)
+    }
+}
+
+impl From<cashaddr::Error> for Error {
+    fn from(err: cashaddr::Error) -> Error {
+        Error::CashAddr(err)
+    }
+}
+
+impl From<hex::FromHexError> for Error {
+    fn from(err: hex::FromHexError) -> Error {
+        Error::Hex(err)
+    }
+}
 
 pub fn p2pkh(pubkey: &PublicKey) -> Base58CheckString {
     let pubkey_hash = hash::hash160(&pubkey.serialize());
 
-    Base58CheckString::from_bytes(
-        &PREFIX_P2PKH
+    Base58CheckString::from_public_bytes(
+        PREFIX_P2PKH
             .iter()
             .chain(pubkey_hash.iter())
             .copied()
@@ -27,5 +86,706 @@ pub fn p2sh_p2wpkh(pubkey: &PublicKey) -> Base58CheckString {
     let mut script_hash = Vec::from(PREFIX_P2SH);
     script_hash.extend(&hash::hash160(&script_sig));
 
-    Base58CheckString::from_bytes(&script_hash)
+    Base58CheckString::from_public_bytes(&script_hash)
+}
+
+pub fn p2wpkh(pubkey: &PublicKey) -> String {
+    bech32::encode_segwit_address(BECH32_HRP, 0, &hash::hash160(&pubkey.serialize()))
+}
+
+/// Returns the address of a taproot output spendable only with `internal_key`, tweaked as BIP86
+/// recommends for outputs without a script tree.
+pub fn p2tr(internal_key: &schnorrsig::PublicKey) -> String {
+    let output_key = taproot_output_key(internal_key);
+    bech32::encode_segwit_address(BECH32_HRP, 1, &output_key.serialize())
+}
+
+fn taproot_output_key(internal_key: &schnorrsig::PublicKey) -> schnorrsig::PublicKey {
+    let secp = context::secp256k1();
+    let tweak = interpreter::taptweak_hash(&internal_key.serialize(), None);
+
+    let mut output_key = *internal_key;
+    output_key
+        .tweak_add_assign(secp, &tweak)
+        .expect("tweak is a hash, so is a valid scalar with overwhelming probability");
+    output_key
+}
+
+/// Returns the address of a P2SH output committing to `redeem_script`.
+pub fn p2sh(redeem_script: &Script) -> Base58CheckString {
+    let mut script_hash = Vec::from(PREFIX_P2SH);
+    script_hash.extend(&hash::hash160(redeem_script.as_bytes()));
+
+    Base58CheckString::from_public_bytes(&script_hash)
+}
+
+/// Returns the address of a P2WSH output committing to `witness_script`.
+pub fn p2wsh(witness_script: &Script) -> String {
+    bech32::encode_segwit_address(BECH32_HRP, 0, &hash::sha256(witness_script.as_bytes()))
+}
+
+/// Returns the address of a P2SH output wrapping a P2WSH output committing to `witness_script`.
+pub fn p2sh_p2wsh(witness_script: &Script) -> Base58CheckString {
+    p2sh(&witness_script.to_p2wsh())
+}
+
+/// Returns the address paying to `script_pubkey`, if it has a standard address form.
+pub fn from_script_pubkey(script_pubkey: &Script) -> Option<String> {
+    from_script_pubkey_on(script_pubkey, Network::Mainnet)
+}
+
+/// Returns the address paying to `script_pubkey` on `network`, if it has a standard address
+/// form there. Networks with CashAddr prefixes use CashAddr rather than base58 addresses, and
+/// only networks with segwit have addresses for witness programs.
+pub fn from_script_pubkey_on(script_pubkey: &Script, network: Network) -> Option<String> {
+    let bytes = script_pubkey.as_bytes();
+
+    if let Some(prefix) = network.cashaddr_prefix() {
+        if script_pubkey.is_p2pkh() {
+            return Some(cashaddr::encode(prefix, AddressType::P2pkh, &bytes[3..23]));
+        }
+        if script_pubkey.is_p2sh() {
+            return Some(cashaddr::encode(prefix, AddressType::P2sh, &bytes[2..22]));
+        }
+    }
+
+    if script_pubkey.is_p2pkh() {
+        let mut data = vec![network.p2pkh_prefix()];
+        data.extend(&bytes[3..23]);
+        return Some(
+            Base58CheckString::from_public_bytes(data)
+                .as_str()
+                .to_string(),
+        );
+    }
+
+    if script_pubkey.is_p2sh() {
+        let mut data = vec![network.p2sh_prefix()];
+        data.extend(&bytes[2..22]);
+        return Some(
+            Base58CheckString::from_public_bytes(data)
+                .as_str()
+                .to_string(),
+        );
+    }
+
+    let hrp = network.bech32_hrp()?;
+    script_pubkey
+        .witness_program()
+        .filter(|(version, program)| *version != 0 || program.len() == 20 || program.len() == 32)
+        .map(|(version, program)| bech32::encode_segwit_address(hrp, version, program))
+}
+
+/// Returns the Ethereum address of `pubkey`, which is the last 20 bytes of the Keccak-256 hash of
+/// its uncompressed encoding, with an EIP-55 checksum.
+pub fn ethereum(pubkey: &PublicKey) -> String {
+    let hash = hash::keccak256(&pubkey.serialize_uncompressed()[1..]);
+    eip55(&copy_from_slice!([0u8; 20], &hash[12..]))
+}
+
+/// Encodes an Ethereum address as hex with an EIP-55 checksum, which uppercases each letter whose
+/// nibble in the Keccak-256 hash of the lowercase hex is 8 or more.
+pub fn eip55(address: &[u8; 20]) -> String {
+    let lowercase = hex::encode(address);
+    let hash = hash::keccak256(lowercase.as_bytes());
+
+    let checksummed = lowercase.chars().enumerate().map(|(i, c)| {
+        let nibble = (hash[i / 2] >> (4 - 4 * (i % 2))) & 0xf;
+        if nibble >= 8 {
+            c.to_ascii_uppercase()
+        } else {
+            c
+        }
+    });
+    "0x".chars().chain(checksummed).collect()
+}
+
+/// Parses an Ethereum address, checking its EIP-55 checksum unless it's entirely lowercase or
+/// uppercase, which predates EIP-55.
+pub fn parse_ethereum(address: &str) -> Result<[u8; 20], Error> {
+    let hex = match address.strip_prefix("0x") {
+        Some(hex) => hex,
+        None => return Err(Error::InvalidPrefix(address.bytes().take(2).collect())),
+    };
+
+    let data = hex::decode(hex)?;
+    if data.len() != 20 {
+        return Err(Error::InvalidLength(data.len()));
+    }
+    let data = copy_from_slice!([0u8; 20], &data);
+
+    let mixed_case =
+        hex.chars().any(|c| c.is_ascii_lowercase()) && hex.chars().any(|c| c.is_ascii_uppercase());
+    if mixed_case && eip55(&data) != address {
+        return Err(Error::InvalidChecksum);
+    }
+
+    Ok(data)
+}
+
+/// Parses a mainnet address into the output script it pays to.
+pub fn to_script_pubkey(address: &str) -> Result<Script, Error> {
+    to_script_pubkey_on(address, Network::Mainnet)
+}
+
+/// Parses an address on `network` into the output script it pays to. On networks with CashAddr
+/// prefixes, CashAddr addresses are accepted as well as legacy base58 ones.
+pub fn to_script_pubkey_on(address: &str, network: Network) -> Result<Script, Error> {
+    let lowercase = address.to_lowercase();
+
+    if let Some(prefix) = network.cashaddr_prefix() {
+        if lowercase.contains(':') || lowercase.starts_with('q') || lowercase.starts_with('p') {
+            let (address_type, hash) = cashaddr::decode(prefix, address)?;
+            return Ok(match address_type {
+                AddressType::P2pkh => p2pkh_script(&hash),
+                AddressType::P2sh => p2sh_script(&hash),
+            });
+        }
+    }
+
+    if let Some(hrp) = network.bech32_hrp() {
+        if lowercase.starts_with(hrp) && lowercase[hrp.len()..].starts_with('1') {
+            let (version, program) = bech32::decode_segwit_address(hrp, address)?;
+
+            return Ok(Builder::new()
+                .push_int(version as i64)
+                .push_slice(&program)
+                .into_script());
+        }
+    }
+
+    let data = Base58CheckString::try_from(address.to_string())?.into_bytes()?;
+    if data.len() != 21 {
+        return Err(Error::InvalidLength(data.len()));
+    }
+
+    let (prefix, hash) = data.split_at(1);
+    if prefix[0] == network.p2pkh_prefix() {
+        Ok(p2pkh_script(hash))
+    } else if prefix[0] == network.p2sh_prefix() {
+        Ok(p2sh_script(hash))
+    } else {
+        Err(Error::InvalidPrefix(prefix.to_vec()))
+    }
+}
+
+fn p2pkh_script(pubkey_hash: &[u8]) -> Script {
+    Builder::new()
+        .push_opcode(opcodes::OP_DUP)
+        .push_opcode(opcodes::OP_HASH160)
+        .push_slice(pubkey_hash)
+        .push_opcode(opcodes::OP_EQUALVERIFY)
+        .push_opcode(opcodes::OP_CHECKSIG)
+        .into_script()
+}
+
+fn p2sh_script(script_hash: &[u8]) -> Script {
+    Builder::new()
+        .push_opcode(opcodes::OP_HASH160)
+        .push_slice(script_hash)
+        .push_opcode(opcodes::OP_EQUAL)
+        .into_script()
+}
+
+/// The single-key output types an account's addresses can have.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ScriptType {
+    P2pkh,
+    P2shP2wpkh,
+    P2wpkh,
+    /// A taproot output with no script tree, as BIP86 specifies.
+    P2tr,
+}
+
+impl ScriptType {
+    /// Returns the output script of this type paying to `pubkey`.
+    pub fn script_pubkey(self, pubkey: &PublicKey) -> Script {
+        let pubkey_hash = hash::hash160(&pubkey.serialize());
+        let p2wpkh = || {
+            Builder::new()
+                .push_opcode(opcodes::OP_0)
+                .push_slice(&pubkey_hash)
+                .into_script()
+        };
+
+        match self {
+            ScriptType::P2pkh => p2pkh_script(&pubkey_hash),
+            ScriptType::P2shP2wpkh => p2wpkh().to_p2sh(),
+            ScriptType::P2wpkh => p2wpkh(),
+            ScriptType::P2tr => {
+                let internal_key = schnorrsig::PublicKey::from_slice(&pubkey.serialize()[1..])
+                    .expect("x coordinate of a valid point");
+                Builder::new()
+                    .push_opcode(opcodes::OP_1)
+                    .push_slice(&taproot_output_key(&internal_key).serialize())
+                    .into_script()
+            }
+        }
+    }
+
+    /// Returns whether `network` has addresses of this type. Only networks with segwit have
+    /// the segwit types, including P2SH-wrapped P2WPKH, which is spendable by anyone without
+    /// segwit.
+    pub fn is_supported(self, network: Network) -> bool {
+        self == ScriptType::P2pkh || network.bech32_hrp().is_some()
+    }
+
+    /// Returns the address on `network` of this type paying to `pubkey`.
+    pub fn address(self, pubkey: &PublicKey, network: Network) -> Result<String, Error> {
+        if !self.is_supported(network) {
+            return Err(Error::UnsupportedScriptType(self, network));
+        }
+        Ok(from_script_pubkey_on(&self.script_pubkey(pubkey), network)
+            .expect("single-key outputs have addresses"))
+    }
+}
+
+/// Derives the addresses of type `script_type` at indices `range` of `account`'s `chain`, on
+/// the account's network, spreading the work across all cores. The addresses are returned in
+/// index order.
+pub fn derive_addresses(
+    account: &ExtendedPublicKey,
+    chain: Chain,
+    range: Range<u32>,
+    script_type: ScriptType,
+) -> Result<Vec<String>, Error> {
+    let chain = account.derive_public(chain.child_number())?;
+    derive_chain_addresses(&chain, range, script_type)
+}
+
+/// Derives the addresses at indices `range` of an already derived `chain` key, as
+/// `derive_addresses` does.
+pub(crate) fn derive_chain_addresses(
+    chain: &ExtendedPublicKey,
+    range: Range<u32>,
+    script_type: ScriptType,
+) -> Result<Vec<String>, Error> {
+    if !script_type.is_supported(chain.network) {
+        return Err(Error::UnsupportedScriptType(script_type, chain.network));
+    }
+
+    derive_with(chain, range, |pubkey| {
+        script_type
+            .address(pubkey, chain.network)
+            .expect("script type is supported")
+    })
+}
+
+/// Derives the Ethereum addresses at indices `range` of `account`'s `chain`, as
+/// `derive_addresses` does. Ethereum's BIP44 accounts use coin type
+/// `account::ETHEREUM_COIN_TYPE`, and wallets normally only use the receive chain.
+pub fn derive_ethereum_addresses(
+    account: &ExtendedPublicKey,
+    chain: Chain,
+    range: Range<u32>,
+) -> Result<Vec<String>, Error> {
+    let chain = account.derive_public(chain.child_number())?;
+    derive_with(&chain, range, ethereum)
+}
+
+fn derive_with<F>(
+    chain: &ExtendedPublicKey,
+    range: Range<u32>,
+    address: F,
+) -> Result<Vec<String>, Error>
+where
+    F: Fn(&PublicKey) -> String + Sync,
+{
+    if range.end > 1 << 31 {
+        return Err(bip32::Error::ImpossibleDerivation.into());
+    }
+
+    let chunks = range
+        .clone()
+        .step_by(DERIVE_CHUNK as usize)
+        .map(|start| start..range.end.min(start + DERIVE_CHUNK))
+        .collect::<Vec<_>>();
+
+    let addresses = chunks
+        .into_par_iter()
+        .map(|chunk| {
+            Ok(chain
+                .derive_range(chunk)?
+                .iter()
+                .map(|xpub| address(&xpub.public_key))
+                .collect::<Vec<_>>())
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    Ok(addresses.concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::{Purpose, ETHEREUM_COIN_TYPE};
+    use crate::bip32::ChildNumber;
+    use crate::bip39;
+    use serde_json::Value;
+
+    #[test]
+    fn test_bitcoin_core_valid_mainnet_addresses() {
+        let tests: Vec<Value> =
+            serde_json::from_str(include_str!("../testdata/key_io_valid.json")).unwrap();
+
+        for test in tests {
+            if test[2]["chain"] != "main" || test[2]["isPrivkey"] == true {
+                continue;
+            }
+
+            let address = test[0].as_str().unwrap();
+            let script_pubkey = Script::from(hex::decode(test[1].as_str().unwrap()).unwrap());
+
+            assert_eq!(to_script_pubkey(address), Ok(script_pubkey.clone()));
+            assert_eq!(from_script_pubkey(&script_pubkey).as_deref(), Some(address));
+        }
+    }
+
+    #[test]
+    fn test_bitcoin_core_valid_testnet_addresses() {
+        let tests: Vec<Value> =
+            serde_json::from_str(include_str!("../testdata/key_io_valid.json")).unwrap();
+
+        for test in tests {
+            // Signet shares testnet's address prefixes.
+            let chain = &test[2]["chain"];
+            if (chain != "testnet4" && chain != "signet") || test[2]["isPrivkey"] == true {
+                continue;
+            }
+
+            let address = test[0].as_str().unwrap();
+            let script_pubkey = Script::from(hex::decode(test[1].as_str().unwrap()).unwrap());
+
+            assert_eq!(
+                to_script_pubkey_on(address, Network::Testnet),
+                Ok(script_pubkey.clone())
+            );
+            assert_eq!(
+                from_script_pubkey_on(&script_pubkey, Network::Testnet).as_deref(),
+                Some(address)
+            );
+        }
+    }
+
+    #[test]
+    fn test_bitcoin_core_invalid_addresses() {
+        let tests: Vec<Value> =
+            serde_json::from_str(include_str!("../testdata/key_io_invalid.json")).unwrap();
+
+        for test in tests {
+            let address = test[0].as_str().unwrap();
+            assert!(to_script_pubkey(address).is_err(), "{}", address);
+        }
+    }
+
+    #[test]
+    fn test_script_hash_addresses() {
+        let witness_script = Builder::new()
+            .push_int(1)
+            .push_slice(&[0x02; 33])
+            .push_int(1)
+            .push_opcode(opcodes::OP_CHECKMULTISIG)
+            .into_script();
+
+        assert_eq!(
+            to_script_pubkey(p2sh(&witness_script).as_str()),
+            Ok(witness_script.to_p2sh())
+        );
+        assert_eq!(
+            to_script_pubkey(&p2wsh(&witness_script)),
+            Ok(witness_script.to_p2wsh())
+        );
+        assert_eq!(
+            to_script_pubkey(p2sh_p2wsh(&witness_script).as_str()),
+            Ok(witness_script.to_p2wsh().to_p2sh())
+        );
+    }
+
+    #[test]
+    fn test_p2tr_bip341_key_path_addresses() {
+        let tests: Value =
+            serde_json::from_str(include_str!("../testdata/bip341_wallet_vectors.json")).unwrap();
+
+        for test in tests["scriptPubKey"].as_array().unwrap() {
+            if !test["given"]["scriptTree"].is_null() {
+                continue;
+            }
+
+            let internal_key = hex::decode(test["given"]["internalPubkey"].as_str().unwrap());
+            let internal_key = schnorrsig::PublicKey::from_slice(&internal_key.unwrap()).unwrap();
+
+            assert_eq!(p2tr(&internal_key), test["expected"]["bip350Address"]);
+        }
+    }
+
+    #[test]
+    fn test_derive_addresses() {
+        // The first receive address of the BIP44, BIP49, BIP84 and BIP86 accounts.
+        let root = bip39::test_root(Network::Mainnet);
+
+        let tests = [
+            (44, ScriptType::P2pkh, "1LqBGSKuX5yYUonjxT5qGfpUsXKYYWeabA"),
+            (
+                49,
+                ScriptType::P2shP2wpkh,
+                "37VucYSaXLCAsxYyAPfbSi9eh4iEcbShgf",
+            ),
+            (
+                84,
+                ScriptType::P2wpkh,
+                "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu",
+            ),
+            (
+                86,
+                ScriptType::P2tr,
+                "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr",
+            ),
+        ];
+
+        for (purpose, script_type, address) in tests.iter() {
+            let path = [
+                ChildNumber::Hardened(*purpose),
+                ChildNumber::Hardened(0),
+                ChildNumber::Hardened(0),
+            ];
+            let account = ExtendedPublicKey::from(&root.derive_private_path(&path).unwrap());
+
+            let addresses =
+                derive_addresses(&account, Chain::Receive, 0..50, *script_type).unwrap();
+            assert_eq!(addresses[0], *address);
+
+            let expected = (0..50)
+                .map(|i| {
+                    let xpub = account
+                        .derive_public_path(&[ChildNumber::Normal(0), ChildNumber::Normal(i)])
+                        .unwrap();
+                    script_type
+                        .address(&xpub.public_key, Network::Mainnet)
+                        .unwrap()
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(addresses, expected);
+        }
+
+        // Testnet accounts derive testnet addresses, across several chunks.
+        let testnet_root = bip39::test_root(Network::Testnet);
+        let path = [
+            ChildNumber::Hardened(84),
+            ChildNumber::Hardened(1),
+            ChildNumber::Hardened(0),
+        ];
+        let account = ExtendedPublicKey::from(&testnet_root.derive_private_path(&path).unwrap());
+        let addresses = derive_addresses(
+            &account,
+            Chain::Receive,
+            0..DERIVE_CHUNK * 2 + 1,
+            ScriptType::P2wpkh,
+        )
+        .unwrap();
+        assert_eq!(addresses.len(), DERIVE_CHUNK as usize * 2 + 1);
+        assert_eq!(addresses[0], "tb1q6rz28mcfaxtmd6v789l9rrlrusdprr9pqcpvkl");
+        let last = account
+            .derive_public_path(&[
+                ChildNumber::Normal(0),
+                ChildNumber::Normal(DERIVE_CHUNK * 2),
+            ])
+            .unwrap();
+        assert_eq!(
+            addresses[DERIVE_CHUNK as usize * 2],
+            ScriptType::P2wpkh
+                .address(&last.public_key, Network::Testnet)
+                .unwrap()
+        );
+
+        let account = ExtendedPublicKey::from(&root);
+        assert_eq!(
+            derive_addresses(&account, Chain::Change, 5..5, ScriptType::P2wpkh),
+            Ok(vec![])
+        );
+        assert_eq!(
+            derive_addresses(
+                &account,
+                Chain::Change,
+                0..(1 << 31) + 1,
+                ScriptType::P2wpkh
+            ),
+            Err(Error::Bip32(bip32::Error::ImpossibleDerivation))
+        );
+    }
+
+    #[test]
+    fn test_altcoin_addresses() {
+        let mut root = bip39::test_root(Network::Mainnet);
+
+        let tests = [
+            (
+                Network::Litecoin,
+                44,
+                ScriptType::P2pkh,
+                "LUWPbpM43E2p7ZSh8cyTBEkvpHmr3cB8Ez",
+            ),
+            (
+                Network::Litecoin,
+                49,
+                ScriptType::P2shP2wpkh,
+                "M7wtsL7wSHDBJVMWWhtQfTMSYYkyooAAXM",
+            ),
+            (
+                Network::Litecoin,
+                84,
+                ScriptType::P2wpkh,
+                "ltc1qjmxnz78nmc8nq77wuxh25n2es7rzm5c2rkk4wh",
+            ),
+            (
+                Network::Dogecoin,
+                44,
+                ScriptType::P2pkh,
+                "DBus3bamQjgJULBJtYXpEzDWQRwF5iwxgC",
+            ),
+            (
+                Network::BitcoinCash,
+                44,
+                ScriptType::P2pkh,
+                "bitcoincash:qqyx49mu0kkn9ftfj6hje6g2wfer34yfnq5tahq3q6",
+            ),
+        ];
+
+        for (network, purpose, script_type, address) in tests.iter() {
+            root.network = *network;
+            let path = [
+                ChildNumber::Hardened(*purpose),
+                ChildNumber::Hardened(network.coin_type()),
+                ChildNumber::Hardened(0),
+            ];
+            let account = ExtendedPublicKey::from(&root.derive_private_path(&path).unwrap());
+
+            let addresses = derive_addresses(&account, Chain::Receive, 0..1, *script_type).unwrap();
+            assert_eq!(addresses, vec![address.to_string()]);
+
+            let script_pubkey = to_script_pubkey_on(address, *network).unwrap();
+            assert_eq!(
+                from_script_pubkey_on(&script_pubkey, *network).as_deref(),
+                Some(*address)
+            );
+        }
+
+        // Bitcoin Cash also accepts legacy addresses, and CashAddr ones without their prefix.
+        let script_pubkey = to_script_pubkey_on(
+            "bitcoincash:qqyx49mu0kkn9ftfj6hje6g2wfer34yfnq5tahq3q6",
+            Network::BitcoinCash,
+        );
+        assert_eq!(
+            to_script_pubkey_on("1mW6fDEMjKrDHvLvoEsaeLxSCzZBf3Bfg", Network::BitcoinCash),
+            script_pubkey
+        );
+        assert_eq!(
+            to_script_pubkey_on(
+                "qqyx49mu0kkn9ftfj6hje6g2wfer34yfnq5tahq3q6",
+                Network::BitcoinCash
+            ),
+            script_pubkey
+        );
+        assert_eq!(
+            to_script_pubkey_on(
+                "bitcoincash:qqyx49mu0kkn9ftfj6hje6g2wfer34yfnq5tahq3q6",
+                Network::Mainnet
+            ),
+            Err(Error::Base58Check(base58check::Error::InvalidCharacter {
+                character: ':',
+                position: 11
+            }))
+        );
+
+        let account = ExtendedPublicKey::from(&root);
+        for network in [Network::Dogecoin, Network::BitcoinCash].iter() {
+            let mut account = account.clone();
+            account.network = *network;
+            assert_eq!(
+                derive_addresses(&account, Chain::Receive, 0..1, ScriptType::P2wpkh),
+                Err(Error::UnsupportedScriptType(ScriptType::P2wpkh, *network))
+            );
+        }
+        assert_eq!(
+            ScriptType::P2tr.address(&account.public_key, Network::Dogecoin),
+            Err(Error::UnsupportedScriptType(
+                ScriptType::P2tr,
+                Network::Dogecoin
+            ))
+        );
+    }
+
+    #[test]
+    fn test_ethereum_addresses() {
+        // The private key 1, whose public key is the generator.
+        let secp = context::secp256k1();
+        let mut private_key = [0u8; 32];
+        private_key[31] = 1;
+        let private_key = secp256k1::SecretKey::from_slice(&private_key).unwrap();
+        assert_eq!(
+            ethereum(&PublicKey::from_secret_key(secp, &private_key)),
+            "0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf"
+        );
+
+        // From EIP-55.
+        let checksummed = [
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+            "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+            "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+            "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+        ];
+        for address in checksummed.iter() {
+            assert_eq!(eip55(&parse_ethereum(address).unwrap()), *address);
+        }
+
+        let unchecksummed = [
+            "0x52908400098527886E0F7030069857D2E4169EE7",
+            "0x8617E340B3D01FA5F11F306F4090FD50E238070D",
+            "0xde709f2102306220921060314715629080e2fb77",
+            "0x27b1fdb04752bbc536007a920d24acb045561c26",
+        ];
+        for address in unchecksummed.iter() {
+            let data = parse_ethereum(address).unwrap();
+            assert_eq!(hex::encode(data), address[2..].to_lowercase());
+        }
+    }
+
+    #[test]
+    fn test_invalid_ethereum_addresses() {
+        assert_eq!(
+            parse_ethereum("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD"),
+            Err(Error::InvalidChecksum)
+        );
+        assert_eq!(
+            parse_ethereum("5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"),
+            Err(Error::InvalidPrefix(b"5a".to_vec()))
+        );
+        assert_eq!(
+            parse_ethereum("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeA"),
+            Err(Error::InvalidLength(19))
+        );
+        assert_eq!(
+            parse_ethereum("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeg"),
+            Err(Error::Hex(hex::FromHexError::InvalidHexCharacter {
+                c: 'g',
+                index: 39
+            }))
+        );
+    }
+
+    #[test]
+    fn test_derive_ethereum_addresses() {
+        // The first receive addresses of the Ethereum account.
+        let root = bip39::test_root(Network::Mainnet);
+
+        let path = Purpose::Bip44.account_path(ETHEREUM_COIN_TYPE, 0);
+        let account = ExtendedPublicKey::from(&root.derive_private_path(path.as_ref()).unwrap());
+
+        assert_eq!(
+            derive_ethereum_addresses(&account, Chain::Receive, 0..2),
+            Ok(vec![
+                "0x9858EfFD232B4033E47d90003D41EC34EcaEda94".to_string(),
+                "0x6Fac4D18c912343BF86fa7049364Dd4E424Ab9C0".to_string(),
+            ])
+        );
+    }
 }
@@ -1,36 +1,37 @@
 use crate::hash;
+use sha2::{Digest, Sha256};
 use std::convert::{TryFrom, TryInto};
 use std::fmt;
+use std::str::FromStr;
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
 
-/// Base58 index to Base58 character conversion table.
-///
-/// Given a byte in the range `0..58`, this table enables constant-time conversion to a Base58
-/// character.
-const ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+/// Base58 index to Base58 character conversion table, used only when encoding public data.
+const ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
 
-/// Base58 character to Base58 index conversion table.
-///
-/// This enables constant-time conversion of a `u8` containing a character from the Base58 alphabet
-/// to the corresponding index within the alphabet. An index of `0xff` indicates that the value is
-/// not a valid Base58 character.
-const INDEXES: &[u8] = &[
-    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
-    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
-    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
-    0xff, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
-    0xff, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f, 0x10, 0xff, 0x11, 0x12, 0x13, 0x14, 0x15, 0xff,
-    0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f, 0x20, 0xff, 0xff, 0xff, 0xff, 0xff,
-    0xff, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x28, 0x29, 0x2a, 0x2b, 0xff, 0x2c, 0x2d, 0x2e,
-    0x2f, 0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0xff, 0xff, 0xff, 0xff, 0xff,
-    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
-    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
-    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
-    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
-    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
-    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
-    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
-    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
-];
+/// 58^10, the largest power of 58 below 2^64, which the fast encoder uses as the radix of its
+/// limbs.
+const LIMB_RADIX: u128 = 430_804_206_899_405_824;
+
+/// The number of Base58 digits in a limb.
+const LIMB_DIGITS: usize = 10;
+
+/// Scratch buffers of up to this many elements are kept on the stack.
+const STACK_SCRATCH: usize = 128;
+
+#[cfg(test)]
+thread_local! {
+    /// The number of steps the constant-time conversions have taken on this thread, which tests
+    /// check depends only on the length of the input.
+    static STEPS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// Counts one step of a constant-time loop in tests, and does nothing otherwise.
+#[inline(always)]
+fn step() {
+    #[cfg(test)]
+    STEPS.with(|steps| steps.set(steps.get() + 1));
+}
 
 /// Error represents all of the possible errors that can arise during Base58Check decoding.
 #[derive(Debug, PartialEq)]
@@ -44,26 +45,65 @@ pub enum Error {
 #[derive(Debug, PartialEq, Eq)]
 pub struct Base58CheckString(String);
 
+/// A Base58Check payload split into its version prefix and the data it versions, such as an
+/// address's hash or an extended key's serialization.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VersionedPayload {
+    pub version: Vec<u8>,
+    pub payload: Zeroizing<Vec<u8>>,
+}
+
 impl Base58CheckString {
     /// Encodes a byte slice into a Base58CheckString.
+    ///
+    /// The running time depends only on the length of the slice, so secret payloads such as
+    /// private keys can be encoded.
     pub fn from_bytes<T: AsRef<[u8]>>(v: T) -> Base58CheckString {
         let v = v.as_ref();
-        let checksum = &hash::double_sha256(v)[..4];
-        let (head, tail) = v.split_at(v.iter().position(|c| *c != 0).unwrap_or(0));
-        let tail = to_base58(&[tail, checksum]);
 
-        let string = head
-            .iter()
-            .chain(tail.iter())
-            .map(|index| ALPHABET[*index as usize] as char)
-            .collect();
+        let mut data = Zeroizing::new(Vec::with_capacity(v.len() + 4));
+        data.extend_from_slice(v);
+        data.extend_from_slice(&hash::double_sha256(v)[..4]);
 
+        Base58CheckString(encode(&data))
+    }
+
+    /// Encodes a byte slice which isn't secret, such as an address, into a Base58CheckString.
+    ///
+    /// This is several times faster than `from_bytes`, but its running time depends on the data.
+    pub fn from_public_bytes<T: AsRef<[u8]>>(v: T) -> Base58CheckString {
+        let mut string = String::new();
+        encode_check_vartime_into(v.as_ref(), &mut string);
         Base58CheckString(string)
     }
 
-    /// Decodes a Base58Check-encoded string.
-    pub fn into_bytes(&self) -> Vec<u8> {
-        try_decode(&self.0).unwrap()
+    /// Encodes a version prefix followed by its payload.
+    pub fn from_versioned(v: &VersionedPayload) -> Base58CheckString {
+        let mut data = Zeroizing::new(Vec::with_capacity(v.version.len() + v.payload.len()));
+        data.extend_from_slice(&v.version);
+        data.extend_from_slice(&v.payload);
+
+        Base58CheckString::from_bytes(&data[..])
+    }
+
+    /// Decodes a Base58Check-encoded string. The payload is wiped when dropped, as it may be
+    /// a private key.
+    pub fn into_bytes(&self) -> Result<Zeroizing<Vec<u8>>, Error> {
+        try_decode(&self.0)
+    }
+
+    /// Decodes a Base58Check-encoded string, splitting off a version prefix of `version_len`
+    /// bytes.
+    pub fn into_versioned(&self, version_len: usize) -> Result<VersionedPayload, Error> {
+        let data = self.into_bytes()?;
+        if data.len() < version_len {
+            return Err(Error::InvalidLength(data.len()));
+        }
+
+        Ok(VersionedPayload {
+            version: data[..version_len].to_vec(),
+            payload: Zeroizing::new(data[version_len..].to_vec()),
+        })
     }
 
     /// Extracts a string slice containing the entire Base58CheckString.
@@ -81,109 +121,391 @@ impl TryFrom<String> for Base58CheckString {
     }
 }
 
+impl FromStr for Base58CheckString {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Base58CheckString::try_from(s.to_string())
+    }
+}
+
+impl AsRef<str> for Base58CheckString {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
 impl fmt::Display for Base58CheckString {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.as_str())
     }
 }
 
-/// Attempt to decode an allegedly Base58Check-encoded string.
-fn try_decode(v: &str) -> Result<Vec<u8>, Error> {
-    let indexes = v
-        .bytes()
-        .enumerate()
-        .map(|(position, character)| match INDEXES[character as usize] {
-            0xff => Err(Error::InvalidCharacter {
+/// Encodes `data` as plain Base58, without a checksum.
+///
+/// Like Base58Check, the running time depends only on the length of `data`.
+pub fn encode(data: &[u8]) -> String {
+    let zeros = leading_zeros(data);
+    let digits = Zeroizing::new(to_base58(data));
+    let skip = leading_zeros(&digits);
+
+    let mut string = String::with_capacity(zeros + digits.len() - skip);
+    string.extend((0..zeros).map(|_| to_char(0)));
+    string.extend(digits[skip..].iter().map(|digit| to_char(*digit)));
+    string
+}
+
+/// Appends the plain Base58 encoding of `data` to `out`, which can be reused across calls to
+/// avoid allocating.
+///
+/// `data` is converted to limbs of ten Base58 digits, eight bytes at a time, so this is much
+/// faster than `encode`, but its running time depends on `data`, which mustn't be secret.
+pub fn encode_vartime_into(data: &[u8], out: &mut String) {
+    let zeros = data.iter().take_while(|byte| **byte == 0).count();
+    let data = &data[zeros..];
+
+    // log 256 ÷ log 58^10 ≈ 14 ÷ 100
+    with_scratch(data.len() * 14 / 100 + 1, |limbs: &mut [u64]| {
+        let mut len = 0;
+
+        let head = data.len() % 8;
+        if head > 0 {
+            let word = data[..head]
+                .iter()
+                .fold(0, |word, byte| word << 8 | *byte as u128);
+            len = mul_add_limbs(limbs, len, word, 8 * head as u32);
+        }
+        for chunk in data[head..].chunks_exact(8) {
+            let word = u64::from_be_bytes(copy_from_slice!([0u8; 8], chunk)) as u128;
+            len = mul_add_limbs(limbs, len, word, 64);
+        }
+
+        out.reserve(zeros + len * LIMB_DIGITS);
+        out.extend((0..zeros).map(|_| ALPHABET[0] as char));
+
+        for (i, limb) in limbs[..len].iter().rev().enumerate() {
+            let mut limb = *limb;
+            let mut digits = [0u8; LIMB_DIGITS];
+            for digit in digits.iter_mut().rev() {
+                *digit = ALPHABET[(limb % 58) as usize];
+                limb /= 58;
+            }
+
+            // Only the most significant limb has leading zeros to drop.
+            let skip = if i == 0 {
+                digits.iter().take_while(|c| **c == ALPHABET[0]).count()
+            } else {
+                0
+            };
+            out.extend(digits[skip..].iter().map(|c| *c as char));
+        }
+    });
+}
+
+/// Appends the Base58Check encoding of `data` to `out`, in time depending on `data`.
+pub fn encode_check_vartime_into(data: &[u8], out: &mut String) {
+    let checksum = Sha256::digest(&Sha256::digest(data));
+
+    with_scratch(data.len() + 4, |buf: &mut [u8]| {
+        buf[..data.len()].copy_from_slice(data);
+        buf[data.len()..].copy_from_slice(&checksum[..4]);
+        encode_vartime_into(buf, out);
+    });
+}
+
+/// Multiplies the `len` little-endian limbs in `limbs` by 2^`bits` and adds `word`, returning the
+/// new number of limbs.
+fn mul_add_limbs(limbs: &mut [u64], mut len: usize, word: u128, bits: u32) -> usize {
+    let mut carry = word;
+
+    for limb in limbs[..len].iter_mut() {
+        carry += (*limb as u128) << bits;
+        *limb = (carry % LIMB_RADIX) as u64;
+        carry /= LIMB_RADIX;
+    }
+
+    while carry > 0 {
+        limbs[len] = (carry % LIMB_RADIX) as u64;
+        carry /= LIMB_RADIX;
+        len += 1;
+    }
+
+    len
+}
+
+/// Calls `f` with a zeroed buffer of `len` elements, which is on the stack unless it's large.
+fn with_scratch<T, F>(len: usize, f: F)
+where
+    T: Copy + Default,
+    F: FnOnce(&mut [T]),
+{
+    if len <= STACK_SCRATCH {
+        f(&mut [T::default(); STACK_SCRATCH][..len]);
+    } else {
+        f(&mut vec![T::default(); len]);
+    }
+}
+
+/// Decodes a plain Base58 string, without a checksum, in time depending only on its length.
+pub fn decode(v: &str) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let mut digits = Zeroizing::new(Vec::with_capacity(v.len()));
+    let mut invalid = None;
+
+    for (position, character) in v.bytes().enumerate() {
+        let (digit, valid) = from_char(character);
+        if valid == 0 && invalid.is_none() {
+            invalid = Some(Error::InvalidCharacter {
                 character: character as char,
                 position,
-            }),
-            idx => Ok(idx as u8),
-        })
-        .collect::<Result<Vec<u8>, Error>>()?;
+            });
+        }
+        digits.push(digit);
+    }
+
+    if let Some(err) = invalid {
+        return Err(err);
+    }
 
-    let (head, tail) = indexes.split_at(indexes.iter().position(|c| *c != 0).unwrap_or(0));
-    let tail = to_base256(&[tail]);
+    let zeros = leading_zeros(&digits);
+    let value = Zeroizing::new(to_base256(&digits));
+    let skip = leading_zeros(&value);
+
+    let mut data = Zeroizing::new(Vec::with_capacity(zeros + value.len() - skip));
+    data.resize(zeros, 0);
+    data.extend_from_slice(&value[skip..]);
+    Ok(data)
+}
 
-    let mut data = head
-        .iter()
-        .copied()
-        .chain(tail.iter().copied())
-        .collect::<Vec<u8>>();
+/// Attempt to decode an allegedly Base58Check-encoded string, in time depending only on its
+/// length.
+fn try_decode(v: &str) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let mut data = decode(v)?;
 
     if data.len() < 4 {
         return Err(Error::InvalidLength(data.len()));
     }
 
-    let expected_checksum = &data.drain(data.len() - 4..).collect::<Vec<u8>>()[..];
-    let actual_checksum = &hash::double_sha256(&data)[..4];
+    let payload_len = data.len() - 4;
+    let expected_checksum: [u8; 4] = data[payload_len..].try_into().unwrap();
+    let actual_checksum: [u8; 4] = hash::double_sha256(&data[..payload_len])[..4]
+        .try_into()
+        .unwrap();
 
-    if expected_checksum == actual_checksum {
+    if bool::from(expected_checksum.ct_eq(&actual_checksum)) {
+        // Truncating leaves the checksum in the buffer's capacity, which is wiped with it.
+        data.truncate(payload_len);
         Ok(data)
     } else {
         Err(Error::InvalidChecksum {
-            expected: expected_checksum.try_into().unwrap(),
-            actual: actual_checksum.try_into().unwrap(),
+            expected: expected_checksum,
+            actual: actual_checksum,
         })
     }
 }
 
-/// Convert a series of base-256 integer slices into a base-58 integer vector.
-fn to_base58(parts: &[&[u8]]) -> Vec<u8> {
-    let combined_len: usize = parts.iter().map(|p| p.len()).sum();
+/// Converts a base-256 integer into a base-58 integer with as many digits as any integer of the
+/// same length could need, most significant first.
+///
+/// Every digit is updated for every input byte, rather than stopping once the carry runs out, so
+/// that the running time depends only on the length of the input.
+fn to_base58(data: &[u8]) -> Vec<u8> {
     // log 256 ÷ log 58 ≈ 138 ÷ 100
-    let mut buf = vec![0; combined_len * 138 / 100 + 1];
-    let mut end = 0;
-
-    for part in parts.iter() {
-        for word in part.iter() {
-            let mut carry = *word as u32;
-            let mut cursor = 0;
-
-            while carry != 0 || cursor < end {
-                carry += 256 * buf[cursor] as u32;
-                buf[cursor] = (carry % 58) as u8;
-                carry /= 58;
-                cursor += 1;
-            }
-
-            end = cursor;
+    let mut digits = vec![0u8; data.len() * 138 / 100 + 1];
+
+    for byte in data {
+        let mut carry = *byte as u32;
+        for digit in digits.iter_mut() {
+            step();
+            carry += 256 * *digit as u32;
+            let quotient = div58(carry);
+            *digit = (carry - 58 * quotient) as u8;
+            carry = quotient;
         }
     }
 
-    buf.truncate(end);
-    buf.iter().rev().copied().collect()
+    digits.reverse();
+    digits
 }
 
-/// Convert a series of base-58 integer slices into a base-256 integer vector.
-fn to_base256(parts: &[&[u8]]) -> Vec<u8> {
-    let combined_len: usize = parts.iter().map(|p| p.len()).sum();
+/// Converts a base-58 integer into a base-256 integer with as many bytes as any integer of the
+/// same length could need, most significant first, in time depending only on the length of the
+/// input.
+fn to_base256(digits: &[u8]) -> Vec<u8> {
     // log 58 ÷ log 256 ≈ 733 ÷ 1000
-    let mut buf = vec![0u8; combined_len * 733 / 1000 + 1];
-    let mut end = 0;
-
-    for part in parts.iter() {
-        for word in part.iter() {
-            let mut carry = *word as u32;
-            let mut cursor = 0;
-
-            while carry != 0 || cursor < end {
-                carry += 58 * buf[cursor] as u32;
-                buf[cursor] = (carry % 256) as u8;
-                carry /= 256;
-                cursor += 1;
-            }
-
-            end = cursor;
+    let mut data = vec![0u8; digits.len() * 733 / 1000 + 1];
+
+    for digit in digits {
+        let mut carry = *digit as u32;
+        for byte in data.iter_mut() {
+            step();
+            carry += 58 * *byte as u32;
+            *byte = carry as u8;
+            carry >>= 8;
         }
     }
 
-    buf.truncate(end);
-    buf.iter().rev().copied().collect()
+    data.reverse();
+    data
+}
+
+/// Divides by 58 with a multiplication, since division instructions can take a variable amount
+/// of time. Exact for all `x` below 2^20, which the carries in `to_base58` stay well under.
+fn div58(x: u32) -> u32 {
+    // ⌈2^32 ÷ 58⌉
+    ((x as u64 * 74_051_161) >> 32) as u32
+}
+
+/// Counts the zeros at the start of `v`, looking at every element.
+fn leading_zeros(v: &[u8]) -> usize {
+    let mut count = 0;
+    let mut in_prefix = 1;
+
+    for byte in v {
+        step();
+        in_prefix &= (*byte as usize).wrapping_sub(1) >> (usize::BITS - 1);
+        count += in_prefix;
+    }
+
+    count
+}
+
+/// Returns 1 if `a >= b`, and 0 otherwise.
+fn ge(a: u8, b: u8) -> u8 {
+    (((a as u16).wrapping_sub(b as u16) >> 15) ^ 1) as u8
+}
+
+/// Converts a digit in the range `0..58` to its Base58 character.
+///
+/// The alphabet is `1-9`, `A-Z` without `I` and `O`, and `a-z` without `l`, so the character is
+/// the digit offset by the size of each gap before it. This avoids indexing a table by a secret
+/// digit.
+fn to_char(digit: u8) -> char {
+    (digit
+        + b'1'
+        + 7 * ge(digit, 9)
+        + ge(digit, 17)
+        + ge(digit, 22)
+        + 6 * ge(digit, 33)
+        + ge(digit, 44)) as char
+}
+
+/// Converts a Base58 character to its digit, returning the digit and 1 if the character is
+/// valid, or an unspecified digit and 0 otherwise.
+fn from_char(character: u8) -> (u8, u8) {
+    let ranges = [
+        (b'1', b'9'),
+        (b'A', b'H'),
+        (b'J', b'N'),
+        (b'P', b'Z'),
+        (b'a', b'k'),
+        (b'm', b'z'),
+    ];
+
+    let mut digit = 0;
+    let mut valid = 0;
+    let mut offset = 0;
+
+    for (first, last) in ranges.iter() {
+        step();
+        let in_range = ge(character, *first) & ge(*last, character);
+        let mask = 0u8.wrapping_sub(in_range);
+        digit |= mask & character.wrapping_sub(*first).wrapping_add(offset);
+        valid |= in_range;
+        offset += last - first + 1;
+    }
+
+    (digit, valid)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::Value;
+    use std::time::{Duration, Instant};
+
+    /// Returns the number of steps `f` takes in the constant-time conversions.
+    fn count_steps<F: FnOnce()>(f: F) -> usize {
+        STEPS.with(|steps| steps.set(0));
+        f();
+        STEPS.with(|steps| steps.get())
+    }
+
+    /// Asserts that `f` takes the same, nonzero number of steps for every input.
+    fn assert_constant_steps<T, F: FnMut(&T)>(inputs: &[T], mut f: F) {
+        let counts = inputs
+            .iter()
+            .map(|input| count_steps(|| f(input)))
+            .collect::<Vec<_>>();
+
+        assert!(counts[0] > 0);
+        assert!(
+            counts.iter().all(|count| *count == counts[0]),
+            "step counts vary: {:?}",
+            counts
+        );
+    }
+
+    /// Returns the fastest of many runs of `f` over each input. Runs are interleaved across the
+    /// inputs so that background load affects them all alike, and the minimum is much less
+    /// noisy than the mean.
+    fn fastest_runs<T, F: FnMut(&T)>(inputs: &[T], f: &mut F) -> Vec<Duration> {
+        let mut timings = vec![Duration::from_secs(u64::MAX); inputs.len()];
+
+        for _ in 0..200 {
+            for (input, timing) in inputs.iter().zip(timings.iter_mut()) {
+                let start = Instant::now();
+                f(input);
+                *timing = (*timing).min(start.elapsed());
+            }
+        }
+
+        timings
+    }
+
+    /// 78-byte payloads, as long as an extended key, from zero through small values with many
+    /// leading zero bytes to the largest.
+    fn secret_payloads() -> Vec<Vec<u8>> {
+        let pattern = |i: usize| (i * 131 + 7) as u8;
+        let with_zeros = |zeros: usize| {
+            (0..78)
+                .map(|i| if i < zeros { 0 } else { pattern(i) | 1 })
+                .collect::<Vec<_>>()
+        };
+
+        let mut one = vec![0; 78];
+        one[77] = 1;
+        let mut top_bit = vec![0; 78];
+        top_bit[0] = 0x80;
+
+        vec![
+            vec![0; 78],
+            one,
+            with_zeros(40),
+            with_zeros(10),
+            with_zeros(1),
+            with_zeros(0),
+            top_bit,
+            vec![0xff; 78],
+        ]
+    }
+
+    /// 111-character strings, as long as an encoded extended key, with every number of leading
+    /// `1`s from none to all and decoded values of every magnitude.
+    fn secret_strings() -> Vec<String> {
+        let pattern = (0..111)
+            .map(|i| ALPHABET[(i * 37 + 11) % 58] as char)
+            .collect::<String>();
+
+        vec![
+            "1".repeat(111),
+            format!("{}2", "1".repeat(110)),
+            format!("{}{}", "1".repeat(60), &pattern[60..]),
+            format!("1{}", &pattern[1..]),
+            format!("2{}", "1".repeat(110)),
+            pattern,
+            "z".repeat(111),
+        ]
+    }
 
     #[test]
     fn test_from_bytes() {
@@ -202,11 +524,11 @@ mod tests {
     fn test_into_bytes() {
         assert_eq!(
             Base58CheckString("4h3c6RH52R".to_string()).into_bytes(),
-            b"abc"
+            Ok(Zeroizing::new(b"abc".to_vec()))
         );
         assert_eq!(
             Base58CheckString("16sBRWytR3DeJdK".to_string()).into_bytes(),
-            b"\0hello\0"
+            Ok(Zeroizing::new(b"\0hello\0".to_vec()))
         );
     }
 
@@ -238,4 +560,197 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn test_alphabet() {
+        for (digit, character) in ALPHABET.iter().enumerate() {
+            assert_eq!(to_char(digit as u8), *character as char);
+        }
+
+        for character in 0..=255u8 {
+            match ALPHABET.iter().position(|c| *c == character) {
+                Some(digit) => assert_eq!(from_char(character), (digit as u8, 1)),
+                None => assert_eq!(from_char(character).1, 0),
+            }
+        }
+    }
+
+    #[test]
+    fn test_div58() {
+        for x in 0..1 << 20 {
+            assert_eq!(div58(x), x / 58);
+        }
+    }
+
+    #[test]
+    fn test_leading_zeros() {
+        for payload in &[&[][..], &[0; 3], &[0, 0, 1, 0], &[1, 0]] {
+            let encoded = Base58CheckString::from_bytes(payload);
+            let zeros = payload.iter().take_while(|byte| **byte == 0).count();
+
+            assert_eq!(
+                encoded.as_str().bytes().take_while(|c| *c == b'1').count(),
+                zeros
+            );
+            assert_eq!(encoded.into_bytes(), Ok(Zeroizing::new(payload.to_vec())));
+        }
+    }
+
+    #[test]
+    fn test_constant_time_encode() {
+        let payloads = secret_payloads();
+
+        assert_constant_steps(&payloads, |payload| {
+            encode(payload);
+        });
+        assert_constant_steps(&payloads, |payload| {
+            Base58CheckString::from_bytes(payload);
+        });
+    }
+
+    #[test]
+    fn test_constant_time_decode() {
+        let strings = secret_strings();
+
+        assert_constant_steps(&strings, |string| {
+            decode(string).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_constant_time_checksum() {
+        // A valid checksum, and one wrong in its first or last byte, are checked alike.
+        let payload = &secret_payloads()[3];
+        let encoded = [None, Some(0), Some(3)]
+            .iter()
+            .map(|corrupted| {
+                let mut data = payload.clone();
+                data.extend_from_slice(&hash::double_sha256(payload)[..4]);
+                if let Some(index) = corrupted {
+                    data[78 + index] ^= 1;
+                }
+
+                encode(&data)
+            })
+            .collect::<Vec<_>>();
+
+        assert_constant_steps(&encoded, |string| {
+            let _ = try_decode(string);
+        });
+        assert!(try_decode(&encoded[0]).is_ok());
+        assert!(matches!(
+            try_decode(&encoded[1]),
+            Err(Error::InvalidChecksum { .. })
+        ));
+    }
+
+    /// Compares the running times of encoding and decoding the secret inputs, which is too
+    /// noisy to run by default: `cargo test -- --ignored` on a quiet machine.
+    #[test]
+    #[ignore]
+    fn test_constant_time_wall_clock() {
+        let assert_similar = |timings: Vec<Duration>| {
+            let fastest = timings.iter().min().unwrap();
+            let slowest = timings.iter().max().unwrap();
+            assert!(
+                slowest.as_nanos() * 10 < fastest.as_nanos() * 13,
+                "timings vary too much: {:?}",
+                timings
+            );
+        };
+
+        assert_similar(fastest_runs(&secret_payloads(), &mut |payload| {
+            Base58CheckString::from_bytes(payload);
+        }));
+        assert_similar(fastest_runs(&secret_strings(), &mut |string| {
+            decode(string).unwrap();
+        }));
+    }
+
+    #[test]
+    fn test_bitcoin_core_base58() {
+        let tests: Vec<Value> =
+            serde_json::from_str(include_str!("../testdata/base58_encode_decode.json")).unwrap();
+
+        for test in tests {
+            let data = hex::decode(test[0].as_str().unwrap()).unwrap();
+            let string = test[1].as_str().unwrap();
+
+            assert_eq!(encode(&data), string);
+            assert_eq!(decode(string), Ok(Zeroizing::new(data)));
+        }
+
+        assert_eq!(
+            decode("3mJr0"),
+            Err(Error::InvalidCharacter {
+                character: '0',
+                position: 4
+            })
+        );
+    }
+
+    #[test]
+    fn test_versioned_payload() {
+        let xprv: Base58CheckString = "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi".parse().unwrap();
+        let versioned = xprv.into_versioned(4).unwrap();
+        assert_eq!(versioned.version, [0x04, 0x88, 0xad, 0xe4]);
+        assert_eq!(versioned.payload.len(), 74);
+        assert_eq!(Base58CheckString::from_versioned(&versioned), xprv);
+
+        let address: Base58CheckString = "15CRxFdyRpGZLW9w8HnHvVduizdL5jKNbs".parse().unwrap();
+        let versioned = address.into_versioned(1).unwrap();
+        assert_eq!(versioned.version, [0x00]);
+        assert_eq!(versioned.payload.len(), 20);
+        assert_eq!(Base58CheckString::from_versioned(&versioned), address);
+        assert_eq!(address.into_versioned(22), Err(Error::InvalidLength(21)));
+    }
+
+    #[test]
+    fn test_from_str() {
+        let string = "16sBRWytR3DeJdK";
+        let encoded = Base58CheckString::from_str(string).unwrap();
+        assert_eq!(encoded.as_ref(), string);
+        assert_eq!(encoded.to_string(), string);
+
+        assert!(Base58CheckString::from_str("16sBRWytR3DeJdL").is_err());
+    }
+
+    #[test]
+    fn test_encode_vartime() {
+        let tests: Vec<Value> =
+            serde_json::from_str(include_str!("../testdata/base58_encode_decode.json")).unwrap();
+
+        let mut string = String::new();
+        for test in tests {
+            let data = hex::decode(test[0].as_str().unwrap()).unwrap();
+
+            string.clear();
+            encode_vartime_into(&data, &mut string);
+            assert_eq!(string, test[1].as_str().unwrap());
+        }
+
+        // Every length and leading zero count, including data needing heap scratch space.
+        for len in 0..300 {
+            for zeros in [0, 1, 9].iter().filter(|zeros| **zeros <= len) {
+                let mut data = (0..len).map(|i| (i * 131 + 7) as u8).collect::<Vec<_>>();
+                data[..*zeros].iter_mut().for_each(|byte| *byte = 0);
+
+                string.clear();
+                encode_vartime_into(&data, &mut string);
+                assert_eq!(string, encode(&data));
+                assert_eq!(
+                    Base58CheckString::from_public_bytes(&data),
+                    Base58CheckString::from_bytes(&data)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_vartime_appends() {
+        let mut string = "prefix:".to_string();
+        encode_vartime_into(b"abc", &mut string);
+        encode_check_vartime_into(b"abc", &mut string);
+        assert_eq!(string, "prefix:ZiCa4h3c6RH52R");
+    }
 }
@@ -0,0 +1,269 @@
+//! Bech32 and Bech32m encoding (BIP173, BIP350) and segwit address helpers.
+
+use std::fmt;
+
+pub(crate) const CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const GENERATOR: [u32; 5] = [
+    0x3b6a_57b2,
+    0x2650_8e6d,
+    0x1ea1_19fa,
+    0x3d42_33dd,
+    0x2a14_62b3,
+];
+const MAX_LENGTH: usize = 90;
+
+/// The checksum constant distinguishing the two encodings.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Variant {
+    Bech32,
+    Bech32m,
+}
+
+/// Error represents all of the possible errors that can arise during Bech32 decoding.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    MixedCase,
+    MissingSeparator,
+    InvalidLength(usize),
+    InvalidHrp,
+    InvalidCharacter { character: char, position: usize },
+    InvalidChecksum,
+    InvalidPadding,
+    InvalidWitnessVersion(u8),
+    InvalidProgramLength(usize),
+    InvalidVariant(Variant),
+    HrpMismatch(String),
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Variant::Bech32 => write!(f, "bech32"),
+            Variant::Bech32m => write!(f, "bech32m"),
+        }
+    }
+}
+
+impl Variant {
+    fn constant(self) -> u32 {
+        match self {
+            Variant::Bech32 => 1,
+            Variant::Bech32m => 0x2bc8_30a3,
+        }
+    }
+}
+
+fn polymod(values: impl IntoIterator<Item = u8>) -> u32 {
+    let mut chk = 1u32;
+    for value in values {
+        let top = chk >> 25;
+        chk = (chk & 0x01ff_ffff) << 5 ^ value as u32;
+        for (i, g) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= g;
+            }
+        }
+    }
+    chk
+}
+
+fn hrp_expand(hrp: &str) -> impl Iterator<Item = u8> + '_ {
+    hrp.bytes()
+        .map(|b| b >> 5)
+        .chain(std::iter::once(0))
+        .chain(hrp.bytes().map(|b| b & 0x1f))
+}
+
+/// Encodes 5-bit `data` with the human-readable part `hrp`.
+///
+/// # Panics
+///
+/// Panics if any element of `data` is not a 5-bit value.
+pub fn encode(hrp: &str, data: &[u8], variant: Variant) -> String {
+    let hrp = hrp.to_lowercase();
+    let values = hrp_expand(&hrp).chain(data.iter().copied()).chain([0u8; 6]);
+    let checksum = polymod(values) ^ variant.constant();
+
+    let mut string = hrp;
+    string.push('1');
+    for value in data {
+        string.push(CHARSET[*value as usize] as char);
+    }
+    for i in 0..6 {
+        string.push(CHARSET[((checksum >> (5 * (5 - i))) & 0x1f) as usize] as char);
+    }
+    string
+}
+
+/// Decodes a Bech32 or Bech32m string into its lowercase human-readable part and 5-bit data.
+pub fn decode(string: &str) -> Result<(String, Vec<u8>, Variant), Error> {
+    if string.len() > MAX_LENGTH {
+        return Err(Error::InvalidLength(string.len()));
+    }
+
+    let has_lower = string.bytes().any(|b| b.is_ascii_lowercase());
+    let has_upper = string.bytes().any(|b| b.is_ascii_uppercase());
+    if has_lower && has_upper {
+        return Err(Error::MixedCase);
+    }
+
+    let string = string.to_lowercase();
+    let separator = string.rfind('1').ok_or(Error::MissingSeparator)?;
+    let (hrp, data) = (&string[..separator], &string[separator + 1..]);
+
+    if hrp.is_empty() || hrp.bytes().any(|b| !(33..=126).contains(&b)) {
+        return Err(Error::InvalidHrp);
+    }
+
+    if data.len() < 6 {
+        return Err(Error::InvalidLength(string.len()));
+    }
+
+    let data = data
+        .chars()
+        .enumerate()
+        .map(|(i, character)| {
+            CHARSET
+                .iter()
+                .position(|c| *c as char == character)
+                .map(|value| value as u8)
+                .ok_or(Error::InvalidCharacter {
+                    character,
+                    position: separator + 1 + i,
+                })
+        })
+        .collect::<Result<Vec<u8>, Error>>()?;
+
+    let variant = match polymod(hrp_expand(hrp).chain(data.iter().copied())) {
+        c if c == Variant::Bech32.constant() => Variant::Bech32,
+        c if c == Variant::Bech32m.constant() => Variant::Bech32m,
+        _ => return Err(Error::InvalidChecksum),
+    };
+
+    Ok((hrp.to_string(), data[..data.len() - 6].to_vec(), variant))
+}
+
+/// Regroups the bits of `data` from `from`-bit values into `to`-bit values.
+pub fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Result<Vec<u8>, Error> {
+    let mut acc = 0u32;
+    let mut bits = 0u32;
+    let mut ret = Vec::new();
+    let max = (1u32 << to) - 1;
+
+    for value in data {
+        acc = (acc << from) | *value as u32;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            ret.push(((acc >> bits) & max) as u8);
+        }
+    }
+
+    if pad {
+        if bits > 0 {
+            ret.push(((acc << (to - bits)) & max) as u8);
+        }
+    } else if bits >= from || ((acc << (to - bits)) & max) != 0 {
+        return Err(Error::InvalidPadding);
+    }
+
+    Ok(ret)
+}
+
+/// Encodes a segwit output as an address, using Bech32 for version 0 and Bech32m otherwise.
+///
+/// # Panics
+///
+/// Panics if `version` is greater than 16.
+pub fn encode_segwit_address(hrp: &str, version: u8, program: &[u8]) -> String {
+    assert!(version <= 16, "invalid witness version {}", version);
+
+    let variant = match version {
+        0 => Variant::Bech32,
+        _ => Variant::Bech32m,
+    };
+
+    let mut data = vec![version];
+    data.extend(convert_bits(program, 8, 5, true).expect("padding is allowed"));
+    encode(hrp, &data, variant)
+}
+
+/// Decodes a segwit address, returning its witness version and program.
+pub fn decode_segwit_address(hrp: &str, address: &str) -> Result<(u8, Vec<u8>), Error> {
+    let (actual_hrp, data, variant) = decode(address)?;
+
+    if actual_hrp != hrp {
+        return Err(Error::HrpMismatch(actual_hrp));
+    }
+
+    let (version, data) = data.split_first().ok_or(Error::InvalidLength(0))?;
+    if *version > 16 {
+        return Err(Error::InvalidWitnessVersion(*version));
+    }
+
+    let program = convert_bits(data, 5, 8, false)?;
+    if program.len() < 2 || program.len() > 40 {
+        return Err(Error::InvalidProgramLength(program.len()));
+    }
+
+    if *version == 0 && program.len() != 20 && program.len() != 32 {
+        return Err(Error::InvalidProgramLength(program.len()));
+    }
+
+    match (*version, variant) {
+        (0, Variant::Bech32) => {}
+        (0, _) | (_, Variant::Bech32) => return Err(Error::InvalidVariant(variant)),
+        _ => {}
+    }
+
+    Ok((*version, program))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_checksum_variants() {
+        assert_eq!(
+            decode("A12UEL5L"),
+            Ok(("a".to_string(), Vec::new(), Variant::Bech32))
+        );
+        assert_eq!(
+            decode("A1LQFN3A"),
+            Ok(("a".to_string(), Vec::new(), Variant::Bech32m))
+        );
+        assert_eq!(decode("A12UEL5l"), Err(Error::MixedCase));
+        assert_eq!(decode("a12uel5m"), Err(Error::InvalidChecksum));
+        assert_eq!(decode("pzry9x0s0muk"), Err(Error::MissingSeparator));
+        assert_eq!(decode("1pzry9x0s0muk"), Err(Error::InvalidHrp));
+    }
+
+    #[test]
+    fn test_encode_roundtrip() {
+        for variant in &[Variant::Bech32, Variant::Bech32m] {
+            let data = (0..32).collect::<Vec<u8>>();
+            let encoded = encode("test", &data, *variant);
+            assert_eq!(decode(&encoded), Ok(("test".to_string(), data, *variant)));
+        }
+    }
+
+    #[test]
+    fn test_segwit_address_rejects_wrong_variant() {
+        let program = [0u8; 20];
+        let v0 = encode_segwit_address("bc", 0, &program);
+        let mut data = vec![0];
+        data.extend(convert_bits(&program, 8, 5, true).unwrap());
+        let v0_bech32m = encode("bc", &data, Variant::Bech32m);
+
+        assert_eq!(decode_segwit_address("bc", &v0), Ok((0, program.to_vec())));
+        assert_eq!(
+            decode_segwit_address("bc", &v0_bech32m),
+            Err(Error::InvalidVariant(Variant::Bech32m))
+        );
+        assert_eq!(
+            decode_segwit_address("tb", &v0),
+            Err(Error::HrpMismatch("bc".to_string()))
+        );
+    }
+}
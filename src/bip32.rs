@@ -1,43 +1,81 @@
-use crate::base58check::Base58CheckString;
+use crate::base58check::{self, Base58CheckString};
+use crate::context;
 use crate::hash;
-use secp256k1::{PublicKey, Secp256k1, SecretKey};
+use crate::network::Network;
+use crate::wif::{self, PrivateKey};
+use secp256k1::{PublicKey, SecretKey};
+use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+use zeroize::{Zeroize, Zeroizing};
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Fingerprint([u8; 4]);
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct ChainCode([u8; 32]);
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum ChildNumber {
     Normal(u32),
     Hardened(u32),
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+/// A sequence of child numbers leading from one extended key to a descendant, written as
+/// `m/48'/0'/0'/2'`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct DerivationPath(Vec<ChildNumber>);
+
+/// Where a key was derived from: the fingerprint of its master key and its path from it.
+pub type KeySource = (Fingerprint, DerivationPath);
+
+/// An extended private key. Its private key and chain code are wiped when dropped and left out
+/// of the `Debug` output.
+#[derive(Clone, PartialEq)]
 pub struct ExtendedPrivateKey {
+    pub network: Network,
     pub depth: u8,
     pub parent_fingerprint: Fingerprint,
     pub child_number: ChildNumber,
     pub private_key: SecretKey,
     pub chain_code: ChainCode,
+    /// Where the key was derived from, if known. Master keys are their own origin and
+    /// derivation extends the parent's, so it's only unknown for keys decoded below the master.
+    pub origin: Option<KeySource>,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+/// An extended public key. Keys compare equal when their key material is equal, whether or not
+/// their origins are known.
+///
+/// It isn't `Copy`, since its origin holds a derivation path, so clone it where a copy is needed.
+#[derive(Clone, Debug)]
 pub struct ExtendedPublicKey {
+    pub network: Network,
     pub depth: u8,
     pub parent_fingerprint: Fingerprint,
     pub child_number: ChildNumber,
     pub public_key: PublicKey,
     pub chain_code: ChainCode,
+    /// Where the key was derived from, if known, as for `ExtendedPrivateKey::origin`.
+    pub origin: Option<KeySource>,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum Error {
+    Base58Check(base58check::Error),
     Secp256k1(secp256k1::Error),
     InvalidLength(usize),
     InvalidPrefix(Vec<u8>),
+    InvalidDerivationPath(String),
     ImpossibleDerivation,
+    NetworkMismatch { expected: Network, actual: Network },
+}
+
+impl From<base58check::Error> for Error {
+    fn from(err: base58check::Error) -> Error {
+        Error::Base58Check(err)
+    }
 }
 
 impl From<secp256k1::Error> for Error {
@@ -70,6 +108,28 @@ impl From<[u8; 32]> for ChainCode {
     }
 }
 
+impl Zeroize for ChainCode {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// The chains of an account's addresses, derived at its normal children 0 and 1.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Chain {
+    Receive,
+    Change,
+}
+
+impl Chain {
+    pub fn child_number(self) -> ChildNumber {
+        match self {
+            Chain::Receive => ChildNumber::Normal(0),
+            Chain::Change => ChildNumber::Normal(1),
+        }
+    }
+}
+
 impl From<u32> for ChildNumber {
     fn from(number: u32) -> ChildNumber {
         if number >> 31 == 1 {
@@ -89,12 +149,154 @@ impl From<ChildNumber> for u32 {
     }
 }
 
+impl fmt::Display for ChildNumber {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChildNumber::Normal(index) => write!(f, "{}", index),
+            ChildNumber::Hardened(index) => write!(f, "{}'", index),
+        }
+    }
+}
+
+impl DerivationPath {
+    /// Returns a new path with `child_number` appended.
+    pub fn child(&self, child_number: ChildNumber) -> DerivationPath {
+        let mut path = self.0.clone();
+        path.push(child_number);
+        DerivationPath(path)
+    }
+
+    /// Returns a new path with every element of `path` appended.
+    pub fn extend(&self, path: &[ChildNumber]) -> DerivationPath {
+        DerivationPath(self.0.iter().chain(path).copied().collect())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<Vec<ChildNumber>> for DerivationPath {
+    fn from(path: Vec<ChildNumber>) -> DerivationPath {
+        DerivationPath(path)
+    }
+}
+
+impl AsRef<[ChildNumber]> for DerivationPath {
+    fn as_ref(&self) -> &[ChildNumber] {
+        &self.0
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('/');
+
+        if parts.next() != Some("m") {
+            return Err(Error::InvalidDerivationPath(s.to_string()));
+        }
+
+        parts
+            .map(|part| {
+                let (index, hardened) = match part.strip_suffix(|c| c == '\'' || c == 'h') {
+                    Some(index) => (index, true),
+                    None => (part, false),
+                };
+
+                match index.parse::<u32>() {
+                    Ok(index) if index < (1 << 31) && hardened => Ok(ChildNumber::Hardened(index)),
+                    Ok(index) if index < (1 << 31) => Ok(ChildNumber::Normal(index)),
+                    _ => Err(Error::InvalidDerivationPath(s.to_string())),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(DerivationPath)
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "m")?;
+        for child_number in &self.0 {
+            write!(f, "/{}", child_number)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for ExtendedPrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtendedPrivateKey")
+            .field("network", &self.network)
+            .field("depth", &self.depth)
+            .field("parent_fingerprint", &self.parent_fingerprint)
+            .field("child_number", &self.child_number)
+            .field("private_key", &format_args!("[redacted]"))
+            .field("chain_code", &format_args!("[redacted]"))
+            .field("origin", &self.origin)
+            .finish()
+    }
+}
+
+impl Zeroize for ExtendedPrivateKey {
+    fn zeroize(&mut self) {
+        wif::zeroize_secret_key(&mut self.private_key);
+        self.chain_code.zeroize();
+    }
+}
+
+impl Drop for ExtendedPrivateKey {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
 impl ExtendedPrivateKey {
+    /// Creates the master key generated from `seed`, which is normally 16 to 64 bytes long.
+    pub fn new_master(seed: &[u8], network: Network) -> Result<ExtendedPrivateKey, Error> {
+        let hmac_result = hash::hmac_sha512(seed, b"Bitcoin seed");
+        ExtendedPrivateKey::from_master_parts(&hmac_result[..32], &hmac_result[32..], network)
+    }
+
+    /// Creates a master key from its private key and chain code.
+    pub(crate) fn from_master_parts(
+        private_key: &[u8],
+        chain_code: &[u8],
+        network: Network,
+    ) -> Result<ExtendedPrivateKey, Error> {
+        let mut master = ExtendedPrivateKey {
+            network,
+            depth: 0,
+            parent_fingerprint: Fingerprint([0u8; 4]),
+            child_number: ChildNumber::Normal(0),
+            chain_code: ChainCode([0u8; 32]),
+            private_key: SecretKey::from_slice(private_key)?,
+            origin: None,
+        };
+        master.chain_code.0.copy_from_slice(chain_code);
+        master.origin = Some((master.fingerprint(), DerivationPath::default()));
+
+        Ok(master)
+    }
+
+    /// Derives the descendant at `path`, relative to this key.
+    pub fn derive_private_path(&self, path: &[ChildNumber]) -> Result<ExtendedPrivateKey, Error> {
+        path.iter().try_fold(self.clone(), |xprv, child_number| {
+            xprv.derive_private(*child_number)
+        })
+    }
+
     pub fn derive_private(&self, child_number: ChildNumber) -> Result<ExtendedPrivateKey, Error> {
-        let secp = Secp256k1::new();
-        let public_key = PublicKey::from_secret_key(&secp, &self.private_key);
+        let secp = context::secp256k1();
+        let public_key = PublicKey::from_secret_key(secp, &self.private_key);
 
-        let mut hmac_data = [0u8; 37];
+        let mut hmac_data = Zeroizing::new([0u8; 37]);
 
         match child_number {
             ChildNumber::Hardened(index) => {
@@ -107,152 +309,343 @@ impl ExtendedPrivateKey {
             }
         }
 
-        let hmac_result = hash::hmac_sha512(&hmac_data, self.chain_code.as_bytes());
-        let chain_code = copy_from_slice!([0u8; 32], &hmac_result[32..]).into();
+        let hmac_result = hash::hmac_sha512(&hmac_data[..], self.chain_code.as_bytes());
 
-        let mut private_key = self.private_key;
-        private_key.add_assign(&hmac_result[..32])?;
+        // The child's secrets are written straight into it, so that no copies outlive it.
+        let mut child = ExtendedPrivateKey {
+            network: self.network,
+            depth: self.depth + 1,
+            parent_fingerprint: fingerprint(&public_key.serialize()),
+            child_number,
+            chain_code: ChainCode([0u8; 32]),
+            private_key: self.private_key,
+            origin: child_origin(&self.origin, child_number),
+        };
+        child.chain_code.0.copy_from_slice(&hmac_result[32..]);
+        child.private_key.add_assign(&hmac_result[..32])?;
 
-        let depth = self.depth + 1;
+        Ok(child)
+    }
 
-        let parent_fingerprint =
-            copy_from_slice!([0u8; 4], &hash::hash160(&public_key.serialize())[..4]).into();
+    /// Returns the HASH160 of this key's public key, which identifies it.
+    pub fn identifier(&self) -> [u8; 20] {
+        let secp = context::secp256k1();
+        identifier(&PublicKey::from_secret_key(secp, &self.private_key).serialize())
+    }
 
-        Ok(ExtendedPrivateKey {
-            depth,
-            parent_fingerprint,
-            child_number,
-            chain_code,
-            private_key,
-        })
+    /// Returns the first four bytes of this key's identifier, which its children record as
+    /// their parent fingerprint.
+    pub fn fingerprint(&self) -> Fingerprint {
+        copy_from_slice!([0u8; 4], &self.identifier()[..4]).into()
     }
-}
 
-impl TryFrom<&Base58CheckString> for ExtendedPrivateKey {
-    type Error = Error;
+    /// Exports this key's private key as compressed WIF, refusing to re-encode it for a network
+    /// other than the one it was created for.
+    pub fn to_wif(&self, network: Network) -> Result<Base58CheckString, Error> {
+        if network != self.network {
+            return Err(Error::NetworkMismatch {
+                expected: self.network,
+                actual: network,
+            });
+        }
 
-    fn try_from(v: &Base58CheckString) -> Result<Self, Self::Error> {
-        let data = v.into_bytes();
+        Ok(Base58CheckString::from(&PrivateKey::from(self)))
+    }
 
+    /// Decodes the 78 byte serialization which `xprv` strings encode.
+    pub(crate) fn deserialize(data: &[u8]) -> Result<ExtendedPrivateKey, Error> {
         if data.len() != 78 {
             return Err(Error::InvalidLength(data.len()));
         }
 
-        if data[0..4] != [0x04, 0x88, 0xad, 0xe4] {
-            return Err(Error::InvalidPrefix(data[0..4].to_vec()));
-        }
+        let network = Network::ALL
+            .iter()
+            .copied()
+            .find(|network| data[0..4] == network.xprv_version())
+            .ok_or_else(|| Error::InvalidPrefix(data[0..4].to_vec()))?;
 
         let private_key = SecretKey::from_slice(&data[46..])?;
+        let public_key = PublicKey::from_secret_key(context::secp256k1(), &private_key);
 
         Ok(ExtendedPrivateKey {
+            network,
             depth: data[4],
             parent_fingerprint: copy_from_slice!([0u8; 4], &data[5..9]).into(),
             child_number: u32::from_be_bytes(copy_from_slice!([0u8; 4], &data[9..13])).into(),
             chain_code: copy_from_slice!([0u8; 32], &data[13..45]).into(),
             private_key,
+            origin: decoded_origin(data[4], &public_key),
         })
     }
+
+    pub(crate) fn serialize(&self) -> Zeroizing<[u8; 78]> {
+        let mut data = Zeroizing::new([0u8; 78]);
+        data[..4].copy_from_slice(&self.network.xprv_version());
+        data[4] = self.depth;
+        data[5..9].copy_from_slice(self.parent_fingerprint.as_bytes());
+        data[9..13].copy_from_slice(&u32::from(self.child_number).to_be_bytes());
+        data[13..45].copy_from_slice(self.chain_code.as_bytes());
+        data[46..].copy_from_slice(&self.private_key[..]);
+        data
+    }
+}
+
+impl TryFrom<&Base58CheckString> for ExtendedPrivateKey {
+    type Error = Error;
+
+    fn try_from(v: &Base58CheckString) -> Result<Self, Self::Error> {
+        ExtendedPrivateKey::deserialize(&v.into_bytes()?)
+    }
 }
 
 impl From<&ExtendedPrivateKey> for Base58CheckString {
     fn from(v: &ExtendedPrivateKey) -> Base58CheckString {
-        let mut data = [0u8; 78];
-        data[..4].copy_from_slice(&[0x04, 0x88, 0xad, 0xe4]);
-        data[4] = v.depth;
-        data[5..9].copy_from_slice(&v.parent_fingerprint.as_bytes());
-        data[9..13].copy_from_slice(&u32::from(v.child_number).to_be_bytes());
-        data[13..45].copy_from_slice(&v.chain_code.as_bytes());
-        data[46..].copy_from_slice(&v.private_key[..]);
-        Base58CheckString::from_bytes(&data)
+        Base58CheckString::from_bytes(&v.serialize()[..])
     }
 }
 
 impl ExtendedPublicKey {
-    pub fn derive_public(&self, child_number: ChildNumber) -> Result<ExtendedPublicKey, Error> {
-        let mut hmac_data = [0u8; 37];
+    /// Derives the descendant at `path`, relative to this key.
+    pub fn derive_public_path(&self, path: &[ChildNumber]) -> Result<ExtendedPublicKey, Error> {
+        path.iter().try_fold(self.clone(), |xpub, child_number| {
+            xpub.derive_public(*child_number)
+        })
+    }
 
+    pub fn derive_public(&self, child_number: ChildNumber) -> Result<ExtendedPublicKey, Error> {
         match child_number {
             ChildNumber::Normal(index) => {
-                hmac_data[..33].copy_from_slice(&self.public_key.serialize());
-                hmac_data[33..].copy_from_slice(&index.to_be_bytes());
-            }
-            ChildNumber::Hardened(_) => {
-                return Err(Error::ImpossibleDerivation);
+                let public_key = self.public_key.serialize();
+                self.derive_normal(index, &public_key, fingerprint(&public_key))
             }
+            ChildNumber::Hardened(_) => Err(Error::ImpossibleDerivation),
+        }
+    }
+
+    /// Returns the HASH160 of this key's public key, which identifies it.
+    pub fn identifier(&self) -> [u8; 20] {
+        identifier(&self.public_key.serialize())
+    }
+
+    /// Returns the first four bytes of this key's identifier, which its children record as
+    /// their parent fingerprint.
+    pub fn fingerprint(&self) -> Fingerprint {
+        fingerprint(&self.public_key.serialize())
+    }
+
+    /// Derives the normal children with indices in `range`, serializing and fingerprinting this
+    /// key only once. Each child still costs a point multiplication, which dominates, so this is
+    /// about as fast as deriving them one at a time.
+    pub fn derive_range(&self, range: Range<u32>) -> Result<Vec<ExtendedPublicKey>, Error> {
+        if range.end > 1 << 31 {
+            return Err(Error::ImpossibleDerivation);
         }
 
+        let public_key = self.public_key.serialize();
+        let parent_fingerprint = fingerprint(&public_key);
+
+        range
+            .map(|index| self.derive_normal(index, &public_key, parent_fingerprint))
+            .collect()
+    }
+
+    /// Derives the normal child at `index`, given this key's serialized public key and its
+    /// fingerprint.
+    fn derive_normal(
+        &self,
+        index: u32,
+        public_key: &[u8; 33],
+        parent_fingerprint: Fingerprint,
+    ) -> Result<ExtendedPublicKey, Error> {
+        let mut hmac_data = [0u8; 37];
+        hmac_data[..33].copy_from_slice(public_key);
+        hmac_data[33..].copy_from_slice(&index.to_be_bytes());
+
         let hmac_result = hash::hmac_sha512(&hmac_data, self.chain_code.as_bytes());
         let chain_code = copy_from_slice!([0u8; 32], &hmac_result[32..]).into();
 
-        let secp = Secp256k1::new();
-        let mut public_key = self.public_key;
-        public_key.add_exp_assign(&secp, &hmac_result[..32])?;
-
-        let depth = self.depth + 1;
+        let mut child_key = self.public_key;
+        child_key.add_exp_assign(context::secp256k1(), &hmac_result[..32])?;
 
-        let parent_fingerprint =
-            copy_from_slice!([0u8; 4], &hash::hash160(&self.public_key.serialize())[..4]).into();
+        let child_number = ChildNumber::Normal(index);
 
         Ok(ExtendedPublicKey {
-            depth,
+            network: self.network,
+            depth: self.depth + 1,
             parent_fingerprint,
             child_number,
             chain_code,
-            public_key,
+            public_key: child_key,
+            origin: child_origin(&self.origin, child_number),
         })
     }
 }
 
-impl TryFrom<&Base58CheckString> for ExtendedPublicKey {
-    type Error = Error;
+fn identifier(public_key: &[u8; 33]) -> [u8; 20] {
+    hash::hash160(public_key)
+}
 
-    fn try_from(v: &Base58CheckString) -> Result<Self, Self::Error> {
-        let data = v.into_bytes();
+fn fingerprint(public_key: &[u8; 33]) -> Fingerprint {
+    copy_from_slice!([0u8; 4], &identifier(public_key)[..4]).into()
+}
+
+/// Returns the origin of a decoded key, which is only known if it's a master key.
+fn decoded_origin(depth: u8, public_key: &PublicKey) -> Option<KeySource> {
+    if depth == 0 {
+        Some((
+            fingerprint(&public_key.serialize()),
+            DerivationPath::default(),
+        ))
+    } else {
+        None
+    }
+}
+
+fn child_origin(origin: &Option<KeySource>, child_number: ChildNumber) -> Option<KeySource> {
+    origin.as_ref().map(|(master_fingerprint, path)| {
+        let mut path = path.clone();
+        path.0.push(child_number);
+        (*master_fingerprint, path)
+    })
+}
+
+/// Derives extended public keys below `root`, caching the keys along the way by their path from
+/// it. Deriving many keys with a common prefix, such as the receive addresses of an account,
+/// then only derives the prefix once.
+///
+/// Only ancestors of the requested keys are cached, so the cache stays small however many
+/// addresses are derived.
+#[derive(Clone, Debug)]
+pub struct DerivationCache {
+    root: ExtendedPublicKey,
+    keys: HashMap<Vec<ChildNumber>, ExtendedPublicKey>,
+}
+
+impl DerivationCache {
+    pub fn new(root: ExtendedPublicKey) -> DerivationCache {
+        DerivationCache {
+            root,
+            keys: HashMap::new(),
+        }
+    }
+
+    pub fn root(&self) -> &ExtendedPublicKey {
+        &self.root
+    }
+
+    /// Returns the number of cached keys.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Derives the descendant at `path`, relative to the root.
+    pub fn derive(&mut self, path: &[ChildNumber]) -> Result<ExtendedPublicKey, Error> {
+        match path.split_last() {
+            Some((child_number, parent)) => self.ancestor(parent)?.derive_public(*child_number),
+            None => Ok(self.root.clone()),
+        }
+    }
 
+    /// Derives the normal children with indices in `range` of the descendant at `parent`.
+    pub fn derive_range(
+        &mut self,
+        parent: &[ChildNumber],
+        range: Range<u32>,
+    ) -> Result<Vec<ExtendedPublicKey>, Error> {
+        self.ancestor(parent)?.derive_range(range)
+    }
+
+    fn ancestor(&mut self, path: &[ChildNumber]) -> Result<ExtendedPublicKey, Error> {
+        let (child_number, parent) = match path.split_last() {
+            Some(split) => split,
+            None => return Ok(self.root.clone()),
+        };
+
+        if let Some(key) = self.keys.get(path) {
+            return Ok(key.clone());
+        }
+
+        let key = self.ancestor(parent)?.derive_public(*child_number)?;
+        self.keys.insert(path.to_vec(), key.clone());
+        Ok(key)
+    }
+}
+
+impl ExtendedPublicKey {
+    /// Decodes the 78 byte serialization which `xpub` strings encode.
+    pub(crate) fn deserialize(data: &[u8]) -> Result<ExtendedPublicKey, Error> {
         if data.len() != 78 {
             return Err(Error::InvalidLength(data.len()));
         }
 
-        if data[0..4] != [0x04, 0x88, 0xb2, 0x1e] {
-            return Err(Error::InvalidPrefix(data[0..4].to_vec()));
-        }
+        let network = Network::ALL
+            .iter()
+            .copied()
+            .find(|network| data[0..4] == network.xpub_version())
+            .ok_or_else(|| Error::InvalidPrefix(data[0..4].to_vec()))?;
 
         let public_key = PublicKey::from_slice(&data[45..])?;
 
         Ok(ExtendedPublicKey {
+            network,
             depth: data[4],
             parent_fingerprint: copy_from_slice!([0u8; 4], &data[5..9]).into(),
             child_number: u32::from_be_bytes(copy_from_slice!([0u8; 4], &data[9..13])).into(),
             chain_code: copy_from_slice!([0u8; 32], &data[13..45]).into(),
+            origin: decoded_origin(data[4], &public_key),
             public_key,
         })
     }
+
+    pub(crate) fn serialize(&self) -> [u8; 78] {
+        let mut data = [0u8; 78];
+        data[..4].copy_from_slice(&self.network.xpub_version());
+        data[4] = self.depth;
+        data[5..9].copy_from_slice(self.parent_fingerprint.as_bytes());
+        data[9..13].copy_from_slice(&u32::from(self.child_number).to_be_bytes());
+        data[13..45].copy_from_slice(self.chain_code.as_bytes());
+        data[45..].copy_from_slice(&self.public_key.serialize());
+        data
+    }
+}
+
+impl PartialEq for ExtendedPublicKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.serialize() == other.serialize()
+    }
+}
+
+impl TryFrom<&Base58CheckString> for ExtendedPublicKey {
+    type Error = Error;
+
+    fn try_from(v: &Base58CheckString) -> Result<Self, Self::Error> {
+        ExtendedPublicKey::deserialize(&v.into_bytes()?)
+    }
 }
 
 impl From<&ExtendedPublicKey> for Base58CheckString {
     fn from(v: &ExtendedPublicKey) -> Self {
-        let mut data = [0u8; 78];
-        data[..4].copy_from_slice(&[0x04, 0x88, 0xb2, 0x1e]);
-        data[4] = v.depth;
-        data[5..9].copy_from_slice(&v.parent_fingerprint.as_bytes());
-        data[9..13].copy_from_slice(&u32::from(v.child_number).to_be_bytes());
-        data[13..45].copy_from_slice(&v.chain_code.as_bytes());
-        data[45..].copy_from_slice(&v.public_key.serialize());
-        Base58CheckString::from_bytes(&data)
+        Base58CheckString::from_public_bytes(v.serialize())
     }
 }
 
 impl From<&ExtendedPrivateKey> for ExtendedPublicKey {
     fn from(xprv: &ExtendedPrivateKey) -> Self {
-        let secp = Secp256k1::new();
-        let public_key = PublicKey::from_secret_key(&secp, &xprv.private_key);
+        let secp = context::secp256k1();
+        let public_key = PublicKey::from_secret_key(secp, &xprv.private_key);
 
         ExtendedPublicKey {
+            network: xprv.network,
             depth: xprv.depth,
             parent_fingerprint: xprv.parent_fingerprint,
             child_number: xprv.child_number,
             chain_code: xprv.chain_code,
             public_key,
+            origin: xprv.origin.clone(),
         }
     }
 }
@@ -260,10 +653,12 @@ impl From<&ExtendedPrivateKey> for ExtendedPublicKey {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::mem::ManuallyDrop;
 
     #[test]
     fn test_extendedprivatekey_from_base58check() {
         let expected = ExtendedPrivateKey {
+            network: Network::Mainnet,
             depth: 3,
             parent_fingerprint: Fingerprint([0x77, 0x92, 0x0d, 0x54]),
             child_number: ChildNumber::Normal(2),
@@ -278,6 +673,7 @@ mod tests {
                 0xe2, 0x5b, 0x94, 0xff, 0xa6, 0xff, 0x52, 0x47, 0x35, 0x69, 0x81, 0x9f, 0x3e, 0x5b,
                 0xa4, 0x72, 0xae, 0x6c,
             ]),
+            origin: None,
         };
 
         let actual = Base58CheckString::try_from("xprv9yYPeJbXz5c4y4UzEaAvWDdWExv2sFsXoU4EN9ERnnKasDbooSNM5kdsoCPh5UMvAvTqqh1oykDxqGsRouyn2xKW2eyEW7R2ie7K7jF9P85".to_string()).unwrap();
@@ -289,6 +685,7 @@ mod tests {
     #[test]
     fn test_extendedprivatekey_into_base58check() {
         let xprv = ExtendedPrivateKey {
+            network: Network::Mainnet,
             depth: 3,
             parent_fingerprint: Fingerprint([0x77, 0x92, 0x0d, 0x54]),
             child_number: ChildNumber::Normal(2),
@@ -303,6 +700,7 @@ mod tests {
                 0xe2, 0x5b, 0x94, 0xff, 0xa6, 0xff, 0x52, 0x47, 0x35, 0x69, 0x81, 0x9f, 0x3e, 0x5b,
                 0xa4, 0x72, 0xae, 0x6c,
             ]),
+            origin: None,
         };
 
         let expected = "xprv9yYPeJbXz5c4y4UzEaAvWDdWExv2sFsXoU4EN9ERnnKasDbooSNM5kdsoCPh5UMvAvTqqh1oykDxqGsRouyn2xKW2eyEW7R2ie7K7jF9P85";
@@ -334,6 +732,7 @@ mod tests {
     #[test]
     fn test_extendedpublickey_from_base58check() {
         let expected = ExtendedPublicKey {
+            network: Network::Mainnet,
             depth: 4,
             parent_fingerprint: Fingerprint([0xe9, 0x63, 0x32, 0x5c]),
             child_number: ChildNumber::Normal(5),
@@ -348,6 +747,7 @@ mod tests {
                 0x94, 0x3a, 0x3e, 0xa0, 0x03, 0x27, 0x7c, 0x6d, 0x8c, 0x13, 0x48, 0x29, 0x24, 0xf8,
                 0x6d, 0xcf, 0x46, 0x68,
             ]),
+            origin: None,
         };
 
         let actual = Base58CheckString::try_from("xpub6FFQ9VG4C9qhWBgoa6nURfEkYAbkE6pyScvERKKniwfxGqFabPGUo7uaiHfBb2vpKqdiFkKW1Wab9T2EJahdWXmHXXLV6F53xtaae4uaqR1".to_string()).unwrap();
@@ -359,6 +759,7 @@ mod tests {
     #[test]
     fn test_extendedpublickey_into_base58check() {
         let xpub = ExtendedPublicKey {
+            network: Network::Mainnet,
             depth: 4,
             parent_fingerprint: Fingerprint([0xe9, 0x63, 0x32, 0x5c]),
             child_number: ChildNumber::Normal(5),
@@ -373,6 +774,7 @@ mod tests {
                 0x94, 0x3a, 0x3e, 0xa0, 0x03, 0x27, 0x7c, 0x6d, 0x8c, 0x13, 0x48, 0x29, 0x24, 0xf8,
                 0x6d, 0xcf, 0x46, 0x68,
             ]),
+            origin: None,
         };
 
         let expected = "xpub6FFQ9VG4C9qhWBgoa6nURfEkYAbkE6pyScvERKKniwfxGqFabPGUo7uaiHfBb2vpKqdiFkKW1Wab9T2EJahdWXmHXXLV6F53xtaae4uaqR1";
@@ -401,6 +803,49 @@ mod tests {
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn test_derivation_path_roundtrip() {
+        let path = "m/48'/0'/0'/2'/0/7".parse::<DerivationPath>().unwrap();
+        assert_eq!(
+            path.as_ref(),
+            &[
+                ChildNumber::Hardened(48),
+                ChildNumber::Hardened(0),
+                ChildNumber::Hardened(0),
+                ChildNumber::Hardened(2),
+                ChildNumber::Normal(0),
+                ChildNumber::Normal(7),
+            ]
+        );
+        assert_eq!(path.to_string(), "m/48'/0'/0'/2'/0/7");
+        assert_eq!(
+            "m/1h".parse(),
+            Ok(DerivationPath(vec![ChildNumber::Hardened(1)]))
+        );
+        assert_eq!("m".parse(), Ok(DerivationPath::default()));
+        assert!("48'/0'".parse::<DerivationPath>().is_err());
+        assert!("m/2147483648".parse::<DerivationPath>().is_err());
+    }
+
+    #[test]
+    fn test_derive_path() {
+        let parent = Base58CheckString::try_from("xprv9yYPeJbXz5c4y4UzEaAvWDdWExv2sFsXoU4EN9ERnnKasDbooSNM5kdsoCPh5UMvAvTqqh1oykDxqGsRouyn2xKW2eyEW7R2ie7K7jF9P85".to_string()).unwrap();
+        let parent = ExtendedPrivateKey::try_from(&parent).unwrap();
+        let path = [ChildNumber::Hardened(1), ChildNumber::Normal(2)];
+
+        let expected = parent
+            .derive_private(path[0])
+            .and_then(|xprv| xprv.derive_private(path[1]))
+            .unwrap();
+        assert_eq!(parent.derive_private_path(&path), Ok(expected.clone()));
+
+        let xpub = ExtendedPublicKey::from(&parent.derive_private(path[0]).unwrap());
+        assert_eq!(
+            xpub.derive_public_path(&path[1..]),
+            Ok(ExtendedPublicKey::from(&expected))
+        );
+    }
+
     #[test]
     fn test_derive_hardened_public_from_extendedpublickey_fails() {
         let parent = Base58CheckString::try_from("xpub6FFQ9VG4C9qhWBgoa6nURfEkYAbkE6pyScvERKKniwfxGqFabPGUo7uaiHfBb2vpKqdiFkKW1Wab9T2EJahdWXmHXXLV6F53xtaae4uaqR1".to_string()).unwrap();
@@ -409,4 +854,211 @@ mod tests {
         let actual = parent.derive_public(ChildNumber::Hardened(0));
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn test_derive_range() {
+        let parent = Base58CheckString::try_from("xpub6FFQ9VG4C9qhWBgoa6nURfEkYAbkE6pyScvERKKniwfxGqFabPGUo7uaiHfBb2vpKqdiFkKW1Wab9T2EJahdWXmHXXLV6F53xtaae4uaqR1".to_string()).unwrap();
+        let parent = ExtendedPublicKey::try_from(&parent).unwrap();
+
+        let expected = (5..10)
+            .map(|i| parent.derive_public(ChildNumber::Normal(i)).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(parent.derive_range(5..10), Ok(expected));
+        assert_eq!(parent.derive_range(3..3), Ok(vec![]));
+
+        let last = (1 << 31) - 1;
+        assert_eq!(parent.derive_range(last..last + 1).unwrap().len(), 1);
+        assert_eq!(
+            parent.derive_range(last..last + 2),
+            Err(Error::ImpossibleDerivation)
+        );
+    }
+
+    #[test]
+    fn test_derivation_cache() {
+        let root = Base58CheckString::try_from("xpub6FFQ9VG4C9qhWBgoa6nURfEkYAbkE6pyScvERKKniwfxGqFabPGUo7uaiHfBb2vpKqdiFkKW1Wab9T2EJahdWXmHXXLV6F53xtaae4uaqR1".to_string()).unwrap();
+        let root = ExtendedPublicKey::try_from(&root).unwrap();
+        let mut cache = DerivationCache::new(root.clone());
+
+        assert_eq!(cache.derive(&[]), Ok(root.clone()));
+        assert!(cache.is_empty());
+
+        for i in 0..3 {
+            let path = [ChildNumber::Normal(1), ChildNumber::Normal(2), i.into()];
+            assert_eq!(cache.derive(&path), root.derive_public_path(&path));
+        }
+        // Only m/1 and m/1/2 are cached.
+        assert_eq!(cache.len(), 2);
+
+        let parent = [ChildNumber::Normal(1), ChildNumber::Normal(2)];
+        assert_eq!(
+            cache.derive_range(&parent, 0..3),
+            root.derive_public_path(&parent).unwrap().derive_range(0..3)
+        );
+        assert_eq!(cache.len(), 2);
+
+        assert_eq!(
+            cache.derive(&[ChildNumber::Normal(1), ChildNumber::Hardened(0)]),
+            Err(Error::ImpossibleDerivation)
+        );
+        assert_eq!(
+            cache.derive_range(&[ChildNumber::Hardened(0)], 0..1),
+            Err(Error::ImpossibleDerivation)
+        );
+        assert_eq!(cache.root(), &root);
+    }
+
+    #[test]
+    fn test_new_master() {
+        // BIP32 test vectors 1 and 3.
+        let master = ExtendedPrivateKey::new_master(
+            &hex::decode("000102030405060708090a0b0c0d0e0f").unwrap(),
+            Network::Mainnet,
+        )
+        .unwrap();
+        assert_eq!(Base58CheckString::from(&master).as_str(), "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi");
+        assert_eq!(
+            master.origin,
+            Some((master.fingerprint(), DerivationPath::default()))
+        );
+
+        let master = ExtendedPrivateKey::new_master(&hex::decode("4b381541583be4423346c643850da4b320e46a87ae3d2a4e6da11eba819cd4acba45d239319ac14f863b8d5ab5a0d0c64d2e8a1e7d1457df2e5a3c51c73235be").unwrap(), Network::Mainnet).unwrap();
+        assert_eq!(Base58CheckString::from(&master).as_str(), "xprv9s21ZrQH143K25QhxbucbDDuQ4naNntJRi4KUfWT7xo4EKsHt2QJDu7KXp1A3u7Bi1j8ph3EGsZ9Xvz9dGuVrtHHs7pXeTzjuxBrCmmhgC6");
+    }
+
+    #[test]
+    fn test_identifier_and_fingerprint() {
+        // BIP32 test vector 1.
+        let master = Base58CheckString::try_from("xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi".to_string()).unwrap();
+        let master = ExtendedPrivateKey::try_from(&master).unwrap();
+        assert_eq!(
+            hex::encode(master.identifier()),
+            "3442193e1bb70916e914552172cd4e2dbc9df811"
+        );
+        assert_eq!(master.fingerprint(), Fingerprint([0x34, 0x42, 0x19, 0x3e]));
+
+        let child =
+            ExtendedPublicKey::from(&master.derive_private(ChildNumber::Hardened(0)).unwrap());
+        assert_eq!(
+            hex::encode(child.identifier()),
+            "5c1bd648ed23aa5fd50ba52b2457c11e9e80a6a7"
+        );
+        assert_eq!(child.fingerprint(), Fingerprint([0x5c, 0x1b, 0xd6, 0x48]));
+        assert_eq!(
+            child
+                .derive_public(ChildNumber::Normal(1))
+                .unwrap()
+                .parent_fingerprint,
+            child.fingerprint()
+        );
+    }
+
+    #[test]
+    fn test_origin() {
+        let master = Base58CheckString::try_from("xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi".to_string()).unwrap();
+        let master = ExtendedPrivateKey::try_from(&master).unwrap();
+        let master_fingerprint = Fingerprint([0x34, 0x42, 0x19, 0x3e]);
+        assert_eq!(
+            master.origin,
+            Some((master_fingerprint, DerivationPath::default()))
+        );
+
+        let path = "m/84'/0'/0'".parse::<DerivationPath>().unwrap();
+        let account = master.derive_private_path(path.as_ref()).unwrap();
+        assert_eq!(account.origin, Some((master_fingerprint, path)));
+
+        let xpub = ExtendedPublicKey::from(&account);
+        assert_eq!(xpub.origin, account.origin);
+        assert_eq!(
+            xpub.derive_range(0..2).unwrap()[1].origin,
+            Some((master_fingerprint, "m/84'/0'/0'/1".parse().unwrap()))
+        );
+        assert_eq!(
+            DerivationCache::new(xpub.clone())
+                .derive(&[ChildNumber::Normal(0), ChildNumber::Normal(3)])
+                .unwrap()
+                .origin,
+            Some((master_fingerprint, "m/84'/0'/0'/0/3".parse().unwrap()))
+        );
+
+        // Only master keys carry their origin in their serialization.
+        let decoded = ExtendedPublicKey::try_from(&Base58CheckString::from(&xpub)).unwrap();
+        assert_eq!(decoded.origin, None);
+        // Keys are compared by their key material alone.
+        assert_eq!(decoded, xpub);
+        assert_ne!(decoded, xpub.derive_public(ChildNumber::Normal(0)).unwrap());
+        assert_eq!(
+            decoded
+                .derive_public(ChildNumber::Normal(0))
+                .unwrap()
+                .origin,
+            None
+        );
+        let decoded = ExtendedPublicKey::try_from(&Base58CheckString::from(
+            &ExtendedPublicKey::from(&master),
+        ))
+        .unwrap();
+        assert_eq!(decoded.origin, master.origin);
+    }
+
+    #[test]
+    fn test_testnet_extended_keys() {
+        let xprv = Base58CheckString::try_from("xprv9yYPeJbXz5c4y4UzEaAvWDdWExv2sFsXoU4EN9ERnnKasDbooSNM5kdsoCPh5UMvAvTqqh1oykDxqGsRouyn2xKW2eyEW7R2ie7K7jF9P85".to_string()).unwrap();
+        let mut xprv = ExtendedPrivateKey::try_from(&xprv).unwrap();
+        xprv.network = Network::Testnet;
+
+        let tprv = Base58CheckString::from(&xprv);
+        assert!(tprv.as_str().starts_with("tprv"));
+        assert_eq!(ExtendedPrivateKey::try_from(&tprv), Ok(xprv.clone()));
+
+        let tpub = Base58CheckString::from(&ExtendedPublicKey::from(&xprv));
+        assert!(tpub.as_str().starts_with("tpub"));
+        let tpub = ExtendedPublicKey::try_from(&tpub).unwrap();
+        assert_eq!(tpub.network, Network::Testnet);
+        assert_eq!(
+            tpub.derive_public(ChildNumber::Normal(0)).unwrap().network,
+            Network::Testnet
+        );
+    }
+
+    fn test_xprv() -> ExtendedPrivateKey {
+        let xprv = Base58CheckString::try_from("xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi".to_string()).unwrap();
+        ExtendedPrivateKey::try_from(&xprv).unwrap()
+    }
+
+    #[test]
+    fn test_extendedprivatekey_debug_is_redacted() {
+        let xprv = test_xprv();
+        let debug = format!("{:?}", xprv);
+
+        assert!(debug.contains("[redacted]"));
+        assert!(!debug.contains(&hex::encode(&xprv.private_key[..])));
+        assert!(!debug.contains(&hex::encode(xprv.chain_code.as_bytes())));
+    }
+
+    #[test]
+    fn test_extendedprivatekey_zeroize() {
+        let mut xprv = test_xprv();
+        xprv.zeroize();
+
+        assert_eq!(xprv.private_key[..], [0u8; 32]);
+        assert_eq!(xprv.chain_code.as_bytes(), &[0u8; 32]);
+    }
+
+    #[test]
+    fn test_extendedprivatekey_wiped_on_drop() {
+        let mut xprv = ManuallyDrop::new(
+            test_xprv()
+                .derive_private(ChildNumber::Hardened(0))
+                .unwrap(),
+        );
+        let private_key = xprv.private_key.as_ptr();
+        let chain_code = xprv.chain_code.as_bytes().as_ptr();
+
+        unsafe {
+            ManuallyDrop::drop(&mut xprv);
+            assert_eq!(std::slice::from_raw_parts(private_key, 32), &[0u8; 32]);
+            assert_eq!(std::slice::from_raw_parts(chain_code, 32), &[0u8; 32]);
+        }
+    }
 }
@@ -0,0 +1,801 @@
+//! BIP38 passphrase-protected private keys (`6P…`).
+//!
+//! Keys are either encrypted directly, or generated by a third party from an intermediate code
+//! without ever learning the key or passphrase (EC multiply mode). In the latter case a
+//! confirmation code lets the passphrase holder check which address was generated.
+
+use crate::base58check::{self, Base58CheckString};
+use crate::context;
+use crate::hash::{self, kdf, ScryptParams};
+use crate::network::Network;
+use crate::wif::{self, PrivateKey};
+use aes::{Aes256, BlockCipher, NewBlockCipher};
+use rand::Rng;
+use secp256k1::{PublicKey, SecretKey};
+use std::convert::TryFrom;
+use unicode_normalization::UnicodeNormalization;
+use zeroize::Zeroizing;
+
+const PREFIX_NON_EC: [u8; 2] = [0x01, 0x42];
+const PREFIX_EC: [u8; 2] = [0x01, 0x43];
+const MAGIC_INTERMEDIATE: [u8; 7] = [0x2c, 0xe9, 0xb3, 0xe1, 0xff, 0x39, 0xe2];
+const MAGIC_INTERMEDIATE_LOT_SEQUENCE: u8 = 0x51;
+const MAGIC_INTERMEDIATE_NO_LOT_SEQUENCE: u8 = 0x53;
+const MAGIC_CONFIRMATION: [u8; 5] = [0x64, 0x3b, 0xf6, 0xa8, 0x9a];
+
+const FLAG_NON_EC: u8 = 0xc0;
+const FLAG_COMPRESSED: u8 = 0x20;
+const FLAG_LOT_SEQUENCE: u8 = 0x04;
+
+/// The scrypt parameters used to derive keys from passphrases.
+const PASSPHRASE_SCRYPT: ScryptParams = ScryptParams {
+    log_n: 14,
+    r: 8,
+    p: 8,
+};
+
+/// The scrypt parameters used to derive keys from passpoints in EC multiply mode.
+const PASSPOINT_SCRYPT: ScryptParams = ScryptParams {
+    log_n: 10,
+    r: 1,
+    p: 1,
+};
+
+/// The largest lot number an intermediate code can embed.
+pub const MAX_LOT: u32 = 0xfffff;
+/// The largest sequence number an intermediate code can embed.
+pub const MAX_SEQUENCE: u32 = 0xfff;
+
+/// Error represents all of the possible errors that can arise while encrypting or decrypting a
+/// BIP38 key.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    Base58Check(base58check::Error),
+    Kdf(kdf::Error),
+    Secp256k1(secp256k1::Error),
+    InvalidLength(usize),
+    InvalidPrefix(Vec<u8>),
+    InvalidFlags(u8),
+    InvalidLotSequence {
+        lot: u32,
+        sequence: u32,
+    },
+    /// The decrypted key doesn't match the address hash, usually because the passphrase is
+    /// wrong.
+    IncorrectPassphrase,
+}
+
+impl From<base58check::Error> for Error {
+    fn from(err: base58check::Error) -> Error {
+        Error::Base58Check(err)
+    }
+}
+
+impl From<kdf::Error> for Error {
+    fn from(err: kdf::Error) -> Error {
+        Error::Kdf(err)
+    }
+}
+
+impl From<secp256k1::Error> for Error {
+    fn from(err: secp256k1::Error) -> Error {
+        Error::Secp256k1(err)
+    }
+}
+
+/// Encrypts `key` with `passphrase`, without EC multiplication.
+pub fn encrypt(key: &PrivateKey, passphrase: &str) -> Result<Base58CheckString, Error> {
+    let address_hash = address_hash(&key.public_key_bytes(), key.network);
+    let derived = derive_from_passphrase::<64>(passphrase, &address_hash)?;
+    let aes = cipher(&derived[32..]);
+
+    let mut data = Zeroizing::new(Vec::with_capacity(39));
+    data.extend(&PREFIX_NON_EC);
+    data.push(FLAG_NON_EC | if key.compressed { FLAG_COMPRESSED } else { 0 });
+    data.extend(&address_hash);
+
+    let mut block = Zeroizing::new([0u8; 16]);
+    for half in 0..2 {
+        xor(
+            &mut block[..],
+            &key.key[half * 16..][..16],
+            &derived[half * 16..][..16],
+        );
+        aes.encrypt_block((&mut block[..]).into());
+        data.extend(&block[..]);
+    }
+
+    Ok(Base58CheckString::from_bytes(&data[..]))
+}
+
+/// Decrypts a BIP38 key in either mode, for use on `network`.
+pub fn decrypt(
+    encrypted: &Base58CheckString,
+    passphrase: &str,
+    network: Network,
+) -> Result<PrivateKey, Error> {
+    let data = encrypted.into_bytes()?;
+    if data.len() != 39 {
+        return Err(Error::InvalidLength(data.len()));
+    }
+
+    let flags = data[2];
+    let address_hash = copy_from_slice!([0u8; 4], &data[3..7]);
+
+    let key = match copy_from_slice!([0u8; 2], &data[..2]) {
+        PREFIX_NON_EC => {
+            if flags & !FLAG_COMPRESSED != FLAG_NON_EC {
+                return Err(Error::InvalidFlags(flags));
+            }
+
+            let derived = derive_from_passphrase::<64>(passphrase, &address_hash)?;
+            let aes = cipher(&derived[32..]);
+
+            let mut key = Zeroizing::new([0u8; 32]);
+            for half in 0..2 {
+                let block = &mut key[half * 16..][..16];
+                block.copy_from_slice(&data[7 + half * 16..][..16]);
+                aes.decrypt_block(block.into());
+                xor_in_place(block, &derived[half * 16..][..16]);
+            }
+
+            SecretKey::from_slice(&key[..])?
+        }
+        PREFIX_EC => {
+            if flags & !(FLAG_COMPRESSED | FLAG_LOT_SEQUENCE) != 0 {
+                return Err(Error::InvalidFlags(flags));
+            }
+
+            let owner_entropy = copy_from_slice!([0u8; 8], &data[7..15]);
+            let lot_sequence = flags & FLAG_LOT_SEQUENCE != 0;
+            let passfactor = passfactor(passphrase, &owner_entropy, lot_sequence)?;
+            let passpoint = PublicKey::from_secret_key(context::secp256k1(), &passfactor);
+            let derived = derive_from_passpoint(&passpoint, &address_hash, &owner_entropy)?;
+            let aes = cipher(&derived[32..]);
+
+            // The second encrypted half covers the end of the first's plaintext, so decrypt it
+            // first.
+            let mut second = Zeroizing::new(copy_from_slice!([0u8; 16], &data[23..39]));
+            aes.decrypt_block((&mut second[..]).into());
+            xor_in_place(&mut second[..], &derived[16..32]);
+
+            let mut first = Zeroizing::new([0u8; 16]);
+            first[..8].copy_from_slice(&data[15..23]);
+            first[8..].copy_from_slice(&second[..8]);
+            aes.decrypt_block((&mut first[..]).into());
+            xor_in_place(&mut first[..], &derived[..16]);
+
+            let mut seed = Zeroizing::new([0u8; 24]);
+            seed[..16].copy_from_slice(&first[..]);
+            seed[16..].copy_from_slice(&second[8..]);
+
+            let mut key = passfactor;
+            key.mul_assign(&hash::double_sha256(&seed[..]))?;
+            key
+        }
+        prefix => return Err(Error::InvalidPrefix(prefix.to_vec())),
+    };
+
+    let key = PrivateKey {
+        network,
+        compressed: flags & FLAG_COMPRESSED != 0,
+        key,
+    };
+
+    if address_hash != self::address_hash(&key.public_key_bytes(), network) {
+        return Err(Error::IncorrectPassphrase);
+    }
+
+    Ok(key)
+}
+
+/// An intermediate code (`passphrase…`), which lets a third party generate keys encrypted with
+/// a passphrase they don't know.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IntermediateCode {
+    owner_entropy: [u8; 8],
+    lot_sequence: bool,
+    passpoint: PublicKey,
+}
+
+impl IntermediateCode {
+    /// Creates an intermediate code for `passphrase` with a random owner salt, optionally
+    /// embedding a lot and sequence number.
+    pub fn new(passphrase: &str, lot_sequence: Option<(u32, u32)>) -> Result<Self, Error> {
+        IntermediateCode::new_with_rng(passphrase, lot_sequence, &mut rand::thread_rng())
+    }
+
+    pub fn new_with_rng<R: Rng>(
+        passphrase: &str,
+        lot_sequence: Option<(u32, u32)>,
+        rng: &mut R,
+    ) -> Result<Self, Error> {
+        let mut owner_entropy = [0u8; 8];
+
+        match lot_sequence {
+            Some((lot, sequence)) => {
+                if lot > MAX_LOT || sequence > MAX_SEQUENCE {
+                    return Err(Error::InvalidLotSequence { lot, sequence });
+                }
+                rng.fill(&mut owner_entropy[..4]);
+                owner_entropy[4..].copy_from_slice(&(lot << 12 | sequence).to_be_bytes());
+            }
+            None => rng.fill(&mut owner_entropy[..]),
+        }
+
+        IntermediateCode::from_owner_entropy(passphrase, owner_entropy, lot_sequence.is_some())
+    }
+
+    /// Creates an intermediate code from its owner entropy: an 8 byte owner salt, or a 4 byte
+    /// owner salt followed by the lot and sequence number if `lot_sequence` is set.
+    pub fn from_owner_entropy(
+        passphrase: &str,
+        owner_entropy: [u8; 8],
+        lot_sequence: bool,
+    ) -> Result<Self, Error> {
+        let mut passfactor = passfactor(passphrase, &owner_entropy, lot_sequence)?;
+        let passpoint = PublicKey::from_secret_key(context::secp256k1(), &passfactor);
+        wif::zeroize_secret_key(&mut passfactor);
+
+        Ok(IntermediateCode {
+            owner_entropy,
+            lot_sequence,
+            passpoint,
+        })
+    }
+
+    /// Returns the lot and sequence number, if the code includes them.
+    pub fn lot_sequence(&self) -> Option<(u32, u32)> {
+        lot_sequence(&self.owner_entropy, self.lot_sequence)
+    }
+
+    /// Generates a new key for the passphrase holder, returning it encrypted along with its
+    /// confirmation code.
+    pub fn generate_encrypted_key(
+        &self,
+        compressed: bool,
+        network: Network,
+    ) -> Result<(Base58CheckString, ConfirmationCode), Error> {
+        self.generate_encrypted_key_with_rng(compressed, network, &mut rand::thread_rng())
+    }
+
+    pub fn generate_encrypted_key_with_rng<R: Rng>(
+        &self,
+        compressed: bool,
+        network: Network,
+        rng: &mut R,
+    ) -> Result<(Base58CheckString, ConfirmationCode), Error> {
+        loop {
+            let mut seed = Zeroizing::new([0u8; 24]);
+            rng.fill(&mut seed[..]);
+
+            match self.encrypt_seed(&seed, compressed, network) {
+                Err(Error::Secp256k1(_)) => continue,
+                result => return result,
+            }
+        }
+    }
+
+    /// Encrypts the key generated from `seed`, which fails if it doesn't hash to a valid secret
+    /// key.
+    fn encrypt_seed(
+        &self,
+        seed: &[u8; 24],
+        compressed: bool,
+        network: Network,
+    ) -> Result<(Base58CheckString, ConfirmationCode), Error> {
+        let secp = context::secp256k1();
+        let mut factor = SecretKey::from_slice(&Zeroizing::new(hash::double_sha256(seed))[..])?;
+
+        let mut public_key = self.passpoint;
+        public_key.mul_assign(secp, &factor[..])?;
+        let public_key = if compressed {
+            public_key.serialize().to_vec()
+        } else {
+            public_key.serialize_uncompressed().to_vec()
+        };
+
+        let mut flags = 0;
+        if compressed {
+            flags |= FLAG_COMPRESSED;
+        }
+        if self.lot_sequence {
+            flags |= FLAG_LOT_SEQUENCE;
+        }
+
+        let address_hash = address_hash(&public_key, network);
+        let derived = derive_from_passpoint(&self.passpoint, &address_hash, &self.owner_entropy)?;
+        let aes = cipher(&derived[32..]);
+
+        let mut first = Zeroizing::new([0u8; 16]);
+        xor(&mut first[..], &seed[..16], &derived[..16]);
+        aes.encrypt_block((&mut first[..]).into());
+
+        let mut second = Zeroizing::new([0u8; 16]);
+        second[..8].copy_from_slice(&first[8..]);
+        second[8..].copy_from_slice(&seed[16..]);
+        xor_in_place(&mut second[..], &derived[16..32]);
+        aes.encrypt_block((&mut second[..]).into());
+
+        let mut data = Vec::with_capacity(39);
+        data.extend(&PREFIX_EC);
+        data.push(flags);
+        data.extend(&address_hash);
+        data.extend(&self.owner_entropy);
+        data.extend(&first[..8]);
+        data.extend(&second[..]);
+
+        // The point `factor * G`, encrypted like the seed so that only the passphrase holder can
+        // recover the public key.
+        let point = PublicKey::from_secret_key(secp, &factor).serialize();
+        wif::zeroize_secret_key(&mut factor);
+        let mut encrypted_point = [0u8; 33];
+        encrypted_point[0] = point[0] ^ (derived[63] & 0x01);
+        for half in 0..2 {
+            let mut block = [0u8; 16];
+            xor(
+                &mut block,
+                &point[1 + half * 16..][..16],
+                &derived[half * 16..][..16],
+            );
+            aes.encrypt_block((&mut block[..]).into());
+            encrypted_point[1 + half * 16..][..16].copy_from_slice(&block);
+        }
+
+        let confirmation = ConfirmationCode {
+            flags,
+            address_hash,
+            owner_entropy: self.owner_entropy,
+            encrypted_point,
+        };
+
+        Ok((Base58CheckString::from_public_bytes(&data), confirmation))
+    }
+}
+
+impl TryFrom<&Base58CheckString> for IntermediateCode {
+    type Error = Error;
+
+    fn try_from(v: &Base58CheckString) -> Result<Self, Self::Error> {
+        let data = v.into_bytes()?;
+        if data.len() != 49 {
+            return Err(Error::InvalidLength(data.len()));
+        }
+
+        let lot_sequence = match (&data[..7], data[7]) {
+            (magic, MAGIC_INTERMEDIATE_LOT_SEQUENCE) if magic == MAGIC_INTERMEDIATE => true,
+            (magic, MAGIC_INTERMEDIATE_NO_LOT_SEQUENCE) if magic == MAGIC_INTERMEDIATE => false,
+            _ => return Err(Error::InvalidPrefix(data[..8].to_vec())),
+        };
+
+        Ok(IntermediateCode {
+            owner_entropy: copy_from_slice!([0u8; 8], &data[8..16]),
+            lot_sequence,
+            passpoint: PublicKey::from_slice(&data[16..])?,
+        })
+    }
+}
+
+impl From<&IntermediateCode> for Base58CheckString {
+    fn from(v: &IntermediateCode) -> Self {
+        let mut data = Vec::with_capacity(49);
+        data.extend(&MAGIC_INTERMEDIATE);
+        data.push(if v.lot_sequence {
+            MAGIC_INTERMEDIATE_LOT_SEQUENCE
+        } else {
+            MAGIC_INTERMEDIATE_NO_LOT_SEQUENCE
+        });
+        data.extend(&v.owner_entropy);
+        data.extend(&v.passpoint.serialize());
+        Base58CheckString::from_public_bytes(&data)
+    }
+}
+
+/// A confirmation code (`cfrm38…`), proving to the passphrase holder which address a key
+/// generated from their intermediate code has.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfirmationCode {
+    flags: u8,
+    address_hash: [u8; 4],
+    owner_entropy: [u8; 8],
+    encrypted_point: [u8; 33],
+}
+
+impl ConfirmationCode {
+    /// Returns the lot and sequence number, if the key's intermediate code included them.
+    pub fn lot_sequence(&self) -> Option<(u32, u32)> {
+        lot_sequence(&self.owner_entropy, self.flags & FLAG_LOT_SEQUENCE != 0)
+    }
+
+    /// Checks the code with `passphrase`, returning the P2PKH address of the generated key.
+    pub fn verify(&self, passphrase: &str, network: Network) -> Result<Base58CheckString, Error> {
+        let lot_sequence = self.flags & FLAG_LOT_SEQUENCE != 0;
+        let mut passfactor = passfactor(passphrase, &self.owner_entropy, lot_sequence)?;
+        let secp = context::secp256k1();
+        let passpoint = PublicKey::from_secret_key(secp, &passfactor);
+        let derived = derive_from_passpoint(&passpoint, &self.address_hash, &self.owner_entropy)?;
+        let aes = cipher(&derived[32..]);
+
+        let mut point = [0u8; 33];
+        point[0] = self.encrypted_point[0] ^ (derived[63] & 0x01);
+        for half in 0..2 {
+            let block = &mut point[1 + half * 16..][..16];
+            block.copy_from_slice(&self.encrypted_point[1 + half * 16..][..16]);
+            aes.decrypt_block(block.into());
+            xor_in_place(block, &derived[half * 16..][..16]);
+        }
+
+        let mut public_key =
+            PublicKey::from_slice(&point).map_err(|_| Error::IncorrectPassphrase)?;
+        let multiplied = public_key.mul_assign(secp, &passfactor[..]);
+        wif::zeroize_secret_key(&mut passfactor);
+        multiplied?;
+        let public_key = if self.flags & FLAG_COMPRESSED != 0 {
+            public_key.serialize().to_vec()
+        } else {
+            public_key.serialize_uncompressed().to_vec()
+        };
+
+        if self.address_hash != address_hash(&public_key, network) {
+            return Err(Error::IncorrectPassphrase);
+        }
+
+        Ok(p2pkh(&public_key, network))
+    }
+}
+
+impl TryFrom<&Base58CheckString> for ConfirmationCode {
+    type Error = Error;
+
+    fn try_from(v: &Base58CheckString) -> Result<Self, Self::Error> {
+        let data = v.into_bytes()?;
+        if data.len() != 51 {
+            return Err(Error::InvalidLength(data.len()));
+        }
+        if data[..5] != MAGIC_CONFIRMATION {
+            return Err(Error::InvalidPrefix(data[..5].to_vec()));
+        }
+
+        let flags = data[5];
+        if flags & !(FLAG_COMPRESSED | FLAG_LOT_SEQUENCE) != 0 {
+            return Err(Error::InvalidFlags(flags));
+        }
+
+        Ok(ConfirmationCode {
+            flags,
+            address_hash: copy_from_slice!([0u8; 4], &data[6..10]),
+            owner_entropy: copy_from_slice!([0u8; 8], &data[10..18]),
+            encrypted_point: copy_from_slice!([0u8; 33], &data[18..]),
+        })
+    }
+}
+
+impl From<&ConfirmationCode> for Base58CheckString {
+    fn from(v: &ConfirmationCode) -> Self {
+        let mut data = Vec::with_capacity(51);
+        data.extend(&MAGIC_CONFIRMATION);
+        data.push(v.flags);
+        data.extend(&v.address_hash);
+        data.extend(&v.owner_entropy);
+        data.extend(&v.encrypted_point);
+        Base58CheckString::from_public_bytes(&data)
+    }
+}
+
+fn p2pkh(public_key: &[u8], network: Network) -> Base58CheckString {
+    let mut data = vec![network.p2pkh_prefix()];
+    data.extend(&hash::hash160(public_key));
+    Base58CheckString::from_public_bytes(&data)
+}
+
+/// Returns the first four bytes of the double SHA256 of the key's P2PKH address, which checks
+/// the passphrase and salts the key derivation.
+fn address_hash(public_key: &[u8], network: Network) -> [u8; 4] {
+    let address = p2pkh(public_key, network);
+    copy_from_slice!(
+        [0u8; 4],
+        &hash::double_sha256(address.as_str().as_bytes())[..4]
+    )
+}
+
+fn derive_from_passphrase<const N: usize>(
+    passphrase: &str,
+    salt: &[u8],
+) -> Result<Zeroizing<[u8; N]>, Error> {
+    // Passphrases are normalized so that they can be typed the same on any system.
+    let passphrase = Zeroizing::new(passphrase.nfc().collect::<String>());
+    Ok(hash::scrypt(
+        passphrase.as_bytes(),
+        salt,
+        &PASSPHRASE_SCRYPT,
+    )?)
+}
+
+fn derive_from_passpoint(
+    passpoint: &PublicKey,
+    address_hash: &[u8; 4],
+    owner_entropy: &[u8; 8],
+) -> Result<Zeroizing<[u8; 64]>, Error> {
+    let mut salt = [0u8; 12];
+    salt[..4].copy_from_slice(address_hash);
+    salt[4..].copy_from_slice(owner_entropy);
+    Ok(hash::scrypt(
+        &passpoint.serialize(),
+        &salt,
+        &PASSPOINT_SCRYPT,
+    )?)
+}
+
+/// Derives the secret key whose public key is the passpoint.
+fn passfactor(
+    passphrase: &str,
+    owner_entropy: &[u8; 8],
+    lot_sequence: bool,
+) -> Result<SecretKey, Error> {
+    let owner_salt = if lot_sequence {
+        &owner_entropy[..4]
+    } else {
+        &owner_entropy[..]
+    };
+    let prefactor = derive_from_passphrase::<32>(passphrase, owner_salt)?;
+
+    let passfactor = if lot_sequence {
+        let mut data = Zeroizing::new([0u8; 40]);
+        data[..32].copy_from_slice(&prefactor[..]);
+        data[32..].copy_from_slice(owner_entropy);
+        SecretKey::from_slice(&Zeroizing::new(hash::double_sha256(&data[..]))[..])?
+    } else {
+        SecretKey::from_slice(&prefactor[..])?
+    };
+
+    Ok(passfactor)
+}
+
+fn lot_sequence(owner_entropy: &[u8; 8], lot_sequence: bool) -> Option<(u32, u32)> {
+    if !lot_sequence {
+        return None;
+    }
+    let n = u32::from_be_bytes(copy_from_slice!([0u8; 4], &owner_entropy[4..]));
+    Some((n >> 12, n & MAX_SEQUENCE))
+}
+
+fn cipher(key: &[u8]) -> Aes256 {
+    Aes256::new_varkey(key).expect("AES-256 keys are 32 bytes")
+}
+
+fn xor(output: &mut [u8], a: &[u8], b: &[u8]) {
+    for ((o, a), b) in output.iter_mut().zip(a).zip(b) {
+        *o = a ^ b;
+    }
+}
+
+fn xor_in_place(output: &mut [u8], other: &[u8]) {
+    for (o, b) in output.iter_mut().zip(other) {
+        *o ^= b;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn b58(s: &str) -> Base58CheckString {
+        Base58CheckString::try_from(s.to_string()).unwrap()
+    }
+
+    fn wif(s: &str) -> PrivateKey {
+        PrivateKey::try_from(&b58(s)).unwrap()
+    }
+
+    #[test]
+    fn test_non_ec_multiply() {
+        let vectors = [
+            (
+                "TestingOneTwoThree",
+                "6PRVWUbkzzsbcVac2qwfssoUJAN1Xhrg6bNk8J7Nzm5H7kxEbn2Nh2ZoGg",
+                "5KN7MzqK5wt2TP1fQCYyHBtDrXdJuXbUzm4A9rKAteGu3Qi5CVR",
+            ),
+            (
+                "Satoshi",
+                "6PRNFFkZc2NZ6dJqFfhRoFNMR9Lnyj7dYGrzdgXXVMXcxoKTePPX1dWByq",
+                "5HtasZ6ofTHP6HCwTqTkLDuLQisYPah7aUnSKfC7h4hMUVw2gi5",
+            ),
+            // A passphrase which isn't NFC normalized, with a null character and characters
+            // outside the basic multilingual plane.
+            (
+                "\u{03D2}\u{0301}\u{0000}\u{010400}\u{01F4A9}",
+                "6PRW5o9FLp4gJDDVqJQKJFTpMvdsSGJxMYHtHaQBF3ooa8mwD69bapcDQn",
+                "5Jajm8eQ22H3pGWLEVCXyvND8dQZhiQhoLJNKjYXk9roUFTMSZ4",
+            ),
+            (
+                "TestingOneTwoThree",
+                "6PYNKZ1EAgYgmQfmNVamxyXVWHzK5s6DGhwP4J5o44cvXdoY7sRzhtpUeo",
+                "L44B5gGEpqEDRS9vVPz7QT35jcBG2r3CZwSwQ4fCewXAhAhqGVpP",
+            ),
+            (
+                "Satoshi",
+                "6PYLtMnXvfG3oJde97zRyLYFZCYizPU5T3LwgdYJz1fRhh16bU7u6PPmY7",
+                "KwYgW8gcxj1JWJXhPSu4Fqwzfhp5Yfi42mdYmMa4XqK7NJxXUSK7",
+            ),
+        ];
+
+        for (passphrase, encrypted, key) in &vectors {
+            let key = wif(key);
+            assert_eq!(encrypt(&key, passphrase).unwrap(), b58(encrypted));
+            assert_eq!(
+                decrypt(&b58(encrypted), passphrase, Network::Mainnet),
+                Ok(key)
+            );
+        }
+    }
+
+    #[test]
+    fn test_ec_multiply() {
+        // The owner entropy and seed of each vector are recovered from the encrypted key.
+        let vectors = [
+            (
+                "TestingOneTwoThree",
+                "passphrasepxFy57B9v8HtUsszJYKReoNDV6VHjUSGt8EVJmux9n1J3Ltf1gRxyDGXqnf9qm",
+                "99241d58245c883896f80843d2846672d7312e6195ca1a6c",
+                "6PfQu77ygVyJLZjfvMLyhLMQbYnu5uguoJJ4kMCLqWwPEdfpwANVS76gTX",
+                "1PE6TQi6HTVNz5DLwB1LcpMBALubfuN2z2",
+                "5K4caxezwjGCGfnoPTZ8tMcJBLB7Jvyjv4xxeacadhq8nLisLR2",
+                None,
+                None,
+            ),
+            (
+                "Satoshi",
+                "passphraseoRDGAXTWzbp72eVbtUDdn1rwpgPUGjNZEc6CGBo8i5EC1FPW8wcnLdq4ThKzAS",
+                "49111e301d94eab339ff9f6822ee99d9f49606db3b47a497",
+                "6PfLGnQs6VZnrNpmVKfjotbnQuaJK4KZoPFrAjx1JMJUa1Ft8gnf5WxfKd",
+                "1CqzrtZC6mXSAhoxtFwVjz8LtwLJjDYU3V",
+                "5KJ51SgxWaAYR13zd9ReMhJpwrcX47xTJh2D3fGPG9CM8vkv5sH",
+                None,
+                None,
+            ),
+            (
+                "MOLON LABE",
+                "passphraseaB8feaLQDENqCgr4gKZpmf4VoaT6qdjJNJiv7fsKvjqavcJxvuR1hy25aTu5sX",
+                "87a13b07858fa753cd3ab3f1c5eafb5f12579b6c33c9a53f",
+                "6PgNBNNzDkKdhkT6uJntUXwwzQV8Rr2tZcbkDcuC9DZRsS6AtHts4Ypo1j",
+                "1Jscj8ALrYu2y9TD8NrpvDBugPedmbj4Yh",
+                "5JLdxTtcTHcfYcmJsNVy1v2PMDx432JPoYcBTVVRHpPaxUrdtf8",
+                Some("cfrm38V8aXBn7JWA1ESmFMUn6erxeBGZGAxJPY4e36S9QWkzZKtaVqLNMgnifETYw7BPwWC9aPD"),
+                Some((263183, 1)),
+            ),
+            (
+                "\u{039C}\u{039F}\u{039B}\u{03A9}\u{039D} \u{039B}\u{0391}\u{0392}\u{0395}",
+                "passphrased3z9rQJHSyBkNBwTRPkUGNVEVrUAcfAXDyRU1V28ie6hNFbqDwbFBvsTK7yWVK",
+                "03b06a1ea7f9219ae364560d7b985ab1fa27025aaa7e427a",
+                "6PgGWtx25kUg8QWvwuJAgorN6k9FbE25rv5dMRwu5SKMnfpfVe5mar2ngH",
+                "1Lurmih3KruL4xDB5FmHof38yawNtP9oGf",
+                "5KMKKuUmAkiNbA3DazMQiLfDq47qs8MAEThm4yL8R2PhV1ov33D",
+                Some("cfrm38V8G4qq2ywYEFfWLD5Cc6msj9UwsG2Mj4Z6QdGJAFQpdatZLavkgRd1i4iBMdRngDqDs51"),
+                Some((806938, 1)),
+            ),
+        ];
+
+        for (passphrase, encoded, seed, encrypted, address, key, confirmation, lot_sequence) in
+            &vectors
+        {
+            let parsed = IntermediateCode::try_from(&b58(encoded)).unwrap();
+            assert_eq!(parsed.lot_sequence(), *lot_sequence);
+
+            let code = IntermediateCode::from_owner_entropy(
+                passphrase,
+                parsed.owner_entropy,
+                parsed.lot_sequence,
+            )
+            .unwrap();
+            assert_eq!(code, parsed);
+            assert_eq!(Base58CheckString::from(&code), b58(encoded));
+
+            let seed = copy_from_slice!([0u8; 24], &hex::decode(seed).unwrap());
+            let (generated, generated_confirmation) =
+                code.encrypt_seed(&seed, false, Network::Mainnet).unwrap();
+            assert_eq!(generated, b58(encrypted));
+            if let Some(confirmation) = confirmation {
+                assert_eq!(
+                    Base58CheckString::from(&generated_confirmation),
+                    b58(confirmation)
+                );
+            }
+
+            let confirmation =
+                ConfirmationCode::try_from(&Base58CheckString::from(&generated_confirmation))
+                    .unwrap();
+            assert_eq!(confirmation.lot_sequence(), *lot_sequence);
+            assert_eq!(
+                confirmation.verify(passphrase, Network::Mainnet),
+                Ok(b58(address))
+            );
+
+            assert_eq!(
+                decrypt(&b58(encrypted), passphrase, Network::Mainnet),
+                Ok(wif(key))
+            );
+        }
+    }
+
+    #[test]
+    fn test_generate_round_trip() {
+        let code = IntermediateCode::new("correct horse", Some((1234, 56))).unwrap();
+        assert_eq!(code.lot_sequence(), Some((1234, 56)));
+
+        let code = IntermediateCode::try_from(&Base58CheckString::from(&code)).unwrap();
+        assert!(Base58CheckString::from(&code)
+            .as_str()
+            .starts_with("passphrase"));
+
+        let (encrypted, confirmation) =
+            code.generate_encrypted_key(true, Network::Testnet).unwrap();
+        assert!(encrypted.as_str().starts_with("6P"));
+        assert!(Base58CheckString::from(&confirmation)
+            .as_str()
+            .starts_with("cfrm38"));
+
+        let key = decrypt(&encrypted, "correct horse", Network::Testnet).unwrap();
+        assert!(key.compressed);
+        assert_eq!(
+            confirmation.verify("correct horse", Network::Testnet),
+            Ok(p2pkh(&key.public_key_bytes(), Network::Testnet))
+        );
+        assert_eq!(
+            confirmation.verify("incorrect horse", Network::Testnet),
+            Err(Error::IncorrectPassphrase)
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let encrypted = b58("6PRVWUbkzzsbcVac2qwfssoUJAN1Xhrg6bNk8J7Nzm5H7kxEbn2Nh2ZoGg");
+        assert_eq!(
+            decrypt(&encrypted, "TestingOneTwoFour", Network::Mainnet),
+            Err(Error::IncorrectPassphrase)
+        );
+
+        // The address hash commits to the network.
+        assert_eq!(
+            decrypt(&encrypted, "TestingOneTwoThree", Network::Testnet),
+            Err(Error::IncorrectPassphrase)
+        );
+
+        assert_eq!(
+            decrypt(
+                &Base58CheckString::from_public_bytes([0x01, 0x42, 0xc0]),
+                "",
+                Network::Mainnet
+            ),
+            Err(Error::InvalidLength(3))
+        );
+
+        let mut data = encrypted.into_bytes().unwrap();
+        data[2] = 0xe1;
+        assert_eq!(
+            decrypt(
+                &Base58CheckString::from_public_bytes(&data),
+                "",
+                Network::Mainnet
+            ),
+            Err(Error::InvalidFlags(0xe1))
+        );
+
+        data[1] = 0x44;
+        assert_eq!(
+            decrypt(
+                &Base58CheckString::from_public_bytes(&data),
+                "",
+                Network::Mainnet
+            ),
+            Err(Error::InvalidPrefix(vec![0x01, 0x44]))
+        );
+
+        assert_eq!(
+            IntermediateCode::new("", Some((MAX_LOT + 1, 0))),
+            Err(Error::InvalidLotSequence {
+                lot: MAX_LOT + 1,
+                sequence: 0
+            })
+        );
+    }
+}
@@ -0,0 +1,383 @@
+//! BIP39 mnemonic codes, which encode entropy as a phrase of words from a fixed list, in any of
+//! the languages BIP39 has wordlists for.
+
+use crate::hash;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use unicode_normalization::UnicodeNormalization;
+use zeroize::Zeroizing;
+
+/// Rounds of PBKDF2 stretching a mnemonic into a seed.
+const SEED_ROUNDS: u32 = 2048;
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    InvalidEntropyLength(usize),
+    InvalidWordCount(usize),
+    UnknownWord(String),
+    InvalidChecksum,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Language {
+    English,
+    Japanese,
+    Korean,
+    Spanish,
+    ChineseSimplified,
+    ChineseTraditional,
+    French,
+    Italian,
+    Czech,
+    Portuguese,
+}
+
+impl Language {
+    pub const ALL: [Language; 10] = [
+        Language::English,
+        Language::Japanese,
+        Language::Korean,
+        Language::Spanish,
+        Language::ChineseSimplified,
+        Language::ChineseTraditional,
+        Language::French,
+        Language::Italian,
+        Language::Czech,
+        Language::Portuguese,
+    ];
+
+    /// Returns the wordlist, in the order its words encode the values 0 to 2047. The words are
+    /// NFKD normalized.
+    pub fn wordlist(self) -> &'static [&'static str] {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const EMPTY: OnceLock<Vec<&'static str>> = OnceLock::new();
+        static WORDLISTS: [OnceLock<Vec<&'static str>>; 10] = [EMPTY; 10];
+        WORDLISTS[self as usize].get_or_init(|| {
+            let words = match self {
+                Language::English => include_str!("english.txt"),
+                Language::Japanese => include_str!("japanese.txt"),
+                Language::Korean => include_str!("korean.txt"),
+                Language::Spanish => include_str!("spanish.txt"),
+                Language::ChineseSimplified => include_str!("chinese_simplified.txt"),
+                Language::ChineseTraditional => include_str!("chinese_traditional.txt"),
+                Language::French => include_str!("french.txt"),
+                Language::Italian => include_str!("italian.txt"),
+                Language::Czech => include_str!("czech.txt"),
+                Language::Portuguese => include_str!("portuguese.txt"),
+            };
+            words.lines().collect()
+        })
+    }
+
+    /// Returns the separator between the words of a phrase, which is an ideographic space in
+    /// Japanese.
+    pub fn separator(self) -> &'static str {
+        match self {
+            Language::Japanese => "\u{3000}",
+            _ => " ",
+        }
+    }
+
+    fn index(self, word: &str) -> Option<usize> {
+        self.wordlist().iter().position(|w| *w == word)
+    }
+}
+
+/// A mnemonic, holding the entropy its phrase encodes. The entropy is wiped when dropped and left
+/// out of the `Debug` output.
+#[derive(Clone, PartialEq)]
+pub struct Mnemonic {
+    language: Language,
+    entropy: Zeroizing<Vec<u8>>,
+}
+
+/// Returns the English wordlist, in the order its words encode the values 0 to 2047.
+pub fn wordlist() -> &'static [&'static str] {
+    Language::English.wordlist()
+}
+
+impl Mnemonic {
+    /// Encodes `entropy` in English, which must be 16 to 32 bytes long and a multiple of 4 bytes.
+    pub fn from_entropy(entropy: &[u8]) -> Result<Mnemonic, Error> {
+        Mnemonic::from_entropy_in(Language::English, entropy)
+    }
+
+    /// Encodes `entropy` in `language`, as `from_entropy` does.
+    pub fn from_entropy_in(language: Language, entropy: &[u8]) -> Result<Mnemonic, Error> {
+        if entropy.len() < 16 || entropy.len() > 32 || entropy.len() % 4 != 0 {
+            return Err(Error::InvalidEntropyLength(entropy.len()));
+        }
+
+        Ok(Mnemonic {
+            language,
+            entropy: Zeroizing::new(entropy.to_vec()),
+        })
+    }
+
+    /// Decodes a phrase in `language` of whitespace separated words, checking its checksum.
+    pub fn parse_in(language: Language, phrase: &str) -> Result<Mnemonic, Error> {
+        let phrase = Zeroizing::new(phrase.nfkd().collect::<String>());
+        let words = phrase.split_whitespace().collect::<Vec<_>>();
+        if words.len() < 12 || words.len() > 24 || words.len() % 3 != 0 {
+            return Err(Error::InvalidWordCount(words.len()));
+        }
+
+        let mut bits = Zeroizing::new(vec![0u8; (words.len() * 11).div_ceil(8)]);
+        for (i, word) in words.iter().enumerate() {
+            let index = language
+                .index(word)
+                .ok_or_else(|| Error::UnknownWord(word.to_string()))?;
+
+            for bit in 0..11 {
+                if (index >> (10 - bit)) & 1 == 1 {
+                    let position = i * 11 + bit;
+                    bits[position / 8] |= 0x80 >> (position % 8);
+                }
+            }
+        }
+
+        let mnemonic = Mnemonic::from_entropy_in(language, &bits[..words.len() * 4 / 3])?;
+        if mnemonic.words() != words {
+            return Err(Error::InvalidChecksum);
+        }
+
+        Ok(mnemonic)
+    }
+
+    pub fn language(&self) -> Language {
+        self.language
+    }
+
+    pub fn entropy(&self) -> &[u8] {
+        &self.entropy
+    }
+
+    pub fn word_count(&self) -> usize {
+        self.entropy.len() * 3 / 4
+    }
+
+    /// Returns the words of the phrase.
+    pub fn words(&self) -> Vec<&'static str> {
+        // Each word encodes 11 bits of the entropy followed by its checksum, which is the first
+        // bit of its SHA256 hash for every 4 bytes of entropy.
+        let mut bits = Zeroizing::new(self.entropy.to_vec());
+        bits.push(hash::sha256(&self.entropy)[0]);
+
+        let bit = |index: usize| (bits[index / 8] >> (7 - index % 8)) & 1;
+        (0..self.word_count())
+            .map(|word| {
+                let index = (0..11).fold(0, |acc, i| (acc << 1) | bit(word * 11 + i) as usize);
+                self.language.wordlist()[index]
+            })
+            .collect()
+    }
+
+    /// Returns the phrase, with its words separated by its language's separator.
+    pub fn phrase(&self) -> Zeroizing<String> {
+        Zeroizing::new(self.words().join(self.language.separator()))
+    }
+
+    /// Stretches the mnemonic and an optional `passphrase` into the seed of a BIP32 master key.
+    pub fn to_seed(&self, passphrase: &str) -> Zeroizing<[u8; 64]> {
+        let phrase = Zeroizing::new(self.phrase().nfkd().collect::<String>());
+        let salt = Zeroizing::new(format!("mnemonic{}", passphrase).nfkd().collect::<String>());
+        hash::pbkdf2_hmac_sha512(phrase.as_bytes(), salt.as_bytes(), SEED_ROUNDS)
+    }
+}
+
+impl FromStr for Mnemonic {
+    type Err = Error;
+
+    /// Decodes a phrase in the first language whose wordlist has all of its words, as
+    /// `parse_in` does. Unknown words are reported in the language of the first word.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let phrase = Zeroizing::new(s.nfkd().collect::<String>());
+        let words = phrase.split_whitespace().collect::<Vec<_>>();
+        let language = Language::ALL
+            .iter()
+            .find(|language| words.iter().all(|word| language.index(word).is_some()))
+            .or_else(|| {
+                let first = words.first()?;
+                Language::ALL
+                    .iter()
+                    .find(|language| language.index(first).is_some())
+            })
+            .unwrap_or(&Language::English);
+
+        Mnemonic::parse_in(*language, s)
+    }
+}
+
+impl fmt::Debug for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mnemonic")
+            .field("language", &self.language)
+            .field("word_count", &self.word_count())
+            .field("entropy", &format_args!("[redacted]"))
+            .finish()
+    }
+}
+
+/// Returns the master key on `network` of "abandon abandon ... about" with no passphrase, the
+/// mnemonic behind the test vectors of BIP44, BIP49, BIP84 and BIP86 and of many wallets.
+#[cfg(test)]
+pub(crate) fn test_root(network: crate::network::Network) -> crate::bip32::ExtendedPrivateKey {
+    let mnemonic = Mnemonic::from_entropy(&[0u8; 16]).unwrap();
+    crate::bip32::ExtendedPrivateKey::new_master(&mnemonic.to_seed("")[..], network).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base58check::Base58CheckString;
+    use crate::bip32::ExtendedPrivateKey;
+    use crate::network::Network;
+
+    #[test]
+    fn test_wordlist() {
+        let wordlist = wordlist();
+        assert_eq!(wordlist.len(), 2048);
+        assert!(wordlist.windows(2).all(|words| words[0] < words[1]));
+    }
+
+    #[test]
+    fn test_vectors() {
+        // From the BIP39 reference implementation, all with the passphrase "TREZOR".
+        let tests = [
+            (
+                "00000000000000000000000000000000",
+                "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+                "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+                Some("xprv9s21ZrQH143K3h3fDYiay8mocZ3afhfULfb5GX8kCBdno77K4HiA15Tg23wpbeF1pLfs1c5SPmYHrEpTuuRhxMwvKDwqdKiGJS9XFKzUsAF"),
+            ),
+            (
+                "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+                "legal winner thank year wave sausage worth useful legal winner thank yellow",
+                "2e8905819b8723fe2c1d161860e5ee1830318dbf49a83bd451cfb8440c28bd6fa457fe1296106559a3c80937a1c1069be3a3a5bd381ee6260e8d9739fce1f607",
+                None,
+            ),
+            (
+                "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+                "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo vote",
+                "dd48c104698c30cfe2b6142103248622fb7bb0ff692eebb00089b32d22484e1613912f0a5b694407be899ffd31ed3992c456cdf60f5d4564b8ba3f05a69890ad",
+                None,
+            ),
+        ];
+
+        for (entropy, phrase, seed, xprv) in tests.iter() {
+            let mnemonic = Mnemonic::from_entropy(&hex::decode(entropy).unwrap()).unwrap();
+            assert_eq!(mnemonic.phrase().as_str(), *phrase);
+            assert_eq!(phrase.parse::<Mnemonic>(), Ok(mnemonic.clone()));
+
+            let mnemonic_seed = mnemonic.to_seed("TREZOR");
+            assert_eq!(hex::encode(&mnemonic_seed[..]), *seed);
+
+            if let Some(xprv) = xprv {
+                let master = ExtendedPrivateKey::new_master(&mnemonic_seed[..], Network::Mainnet);
+                assert_eq!(Base58CheckString::from(&master.unwrap()).as_str(), *xprv);
+            }
+        }
+    }
+
+    #[test]
+    fn test_japanese_vectors() {
+        // From the Japanese test vectors of bip32JP, with their ideographic spaces.
+        let passphrase = "㍍ガバヴァぱばぐゞちぢ十人十色";
+        let tests = [
+            (
+                "00000000000000000000000000000000",
+                "あいこくしん　あいこくしん　あいこくしん　あいこくしん　あいこくしん　あいこくしん　あいこくしん　あいこくしん　あいこくしん　あいこくしん　あいこくしん　あおぞら",
+                "a262d6fb6122ecf45be09c50492b31f92e9beb7d9a845987a02cefda57a15f9c467a17872029a9e92299b5cbdf306e3a0ee620245cbd508959b6cb7ca637bd55",
+            ),
+            (
+                "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+                "そつう　れきだい　ほんやく　わかす　りくつ　ばいか　ろせん　やちん　そつう　れきだい　ほんやく　わかめ",
+                "aee025cbe6ca256862f889e48110a6a382365142f7d16f2b9545285b3af64e542143a577e9c144e101a6bdca18f8d97ec3366ebf5b088b1c1af9bc31346e60d9",
+            ),
+        ];
+
+        for (entropy, phrase, seed) in tests.iter() {
+            let entropy = hex::decode(entropy).unwrap();
+            let mnemonic = Mnemonic::from_entropy_in(Language::Japanese, &entropy).unwrap();
+            assert!(mnemonic.phrase().contains('\u{3000}'));
+            assert_eq!(
+                mnemonic.phrase().nfkd().collect::<String>(),
+                phrase.nfkd().collect::<String>()
+            );
+            assert_eq!(hex::encode(&mnemonic.to_seed(passphrase)[..]), *seed);
+
+            assert_eq!(
+                Mnemonic::parse_in(Language::Japanese, phrase),
+                Ok(mnemonic.clone())
+            );
+            assert_eq!(phrase.parse::<Mnemonic>(), Ok(mnemonic));
+        }
+    }
+
+    #[test]
+    fn test_languages() {
+        for language in Language::ALL.iter() {
+            let wordlist = language.wordlist();
+            assert_eq!(wordlist.len(), 2048);
+            assert!(wordlist
+                .iter()
+                .all(|word| word.nfkd().collect::<String>() == *word));
+
+            let mnemonic = Mnemonic::from_entropy_in(*language, &[0xa5; 32]).unwrap();
+            assert_eq!(mnemonic.language(), *language);
+            assert_eq!(
+                Mnemonic::parse_in(*language, &mnemonic.phrase()),
+                Ok(mnemonic)
+            );
+        }
+
+        // Words are matched after normalization, so composed accents are accepted.
+        let mnemonic = Mnemonic::from_entropy_in(Language::Spanish, &[0u8; 16]).unwrap();
+        let composed = mnemonic.phrase().nfc().collect::<String>();
+        assert_ne!(composed, *mnemonic.phrase());
+        assert_eq!(composed.parse::<Mnemonic>(), Ok(mnemonic));
+    }
+
+    #[test]
+    fn test_root_key() {
+        assert_eq!(
+            Base58CheckString::from(&test_root(Network::Mainnet)).as_str(),
+            "xprv9s21ZrQH143K3GJpoapnV8SFfukcVBSfeCficPSGfubmSFDxo1kuHnLisriDvSnRRuL2Qrg5ggqHKNVpxR86QEC8w35uxmGoggxtQTPvfUu"
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(
+            Mnemonic::from_entropy(&[0u8; 15]),
+            Err(Error::InvalidEntropyLength(15))
+        );
+        assert_eq!(
+            Mnemonic::from_entropy(&[0u8; 36]),
+            Err(Error::InvalidEntropyLength(36))
+        );
+        assert_eq!(
+            "abandon abandon abandon".parse::<Mnemonic>(),
+            Err(Error::InvalidWordCount(3))
+        );
+        assert_eq!(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon"
+                .parse::<Mnemonic>(),
+            Err(Error::InvalidChecksum)
+        );
+        assert_eq!(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon bitcoin"
+                .parse::<Mnemonic>(),
+            Err(Error::UnknownWord("bitcoin".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_debug_is_redacted() {
+        let mnemonic = Mnemonic::from_entropy(&[0x7f; 16]).unwrap();
+        let debug = format!("{:?}", mnemonic);
+        assert!(!debug.contains("127"));
+        assert!(!debug.contains("legal"));
+    }
+}
@@ -0,0 +1,731 @@
+//! BIP47 reusable payment codes. A payment code publishes the public key and chain code of an
+//! account at `m/47'/coin_type'/account'`. Once a sender has notified a recipient of their own
+//! payment code, each of them can derive the addresses of the payments between them with ECDH,
+//! without the addresses being linkable to either payment code on chain.
+//!
+//! Only version 1 payment codes, whose payments are to P2PKH addresses, are supported.
+
+use crate::address::ScriptType;
+use crate::base58check::{self, Base58CheckString};
+use crate::bip32::{self, ChainCode, ChildNumber, ExtendedPrivateKey, ExtendedPublicKey};
+use crate::context;
+use crate::hash;
+use crate::network::Network;
+use crate::script::{opcodes, Builder, Script};
+use crate::transaction::{OutPoint, Transaction, TxIn, TxOut, SEQUENCE_FINAL};
+use crate::wallet::{self, FeeRate, InputType, Utxo};
+use crate::wif::PrivateKey;
+use secp256k1::{PublicKey, SecretKey};
+use std::convert::TryFrom;
+use zeroize::Zeroizing;
+
+/// The purpose BIP47 account paths start with.
+pub const PURPOSE: u32 = 47;
+
+/// The Base58Check version byte of payment codes, which makes them start with "PM8T".
+const PREFIX: u8 = 0x47;
+const VERSION: u8 = 0x01;
+const PAYMENT_CODE_LEN: usize = 80;
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    Base58Check(base58check::Error),
+    Bip32(bip32::Error),
+    Secp256k1(secp256k1::Error),
+    Wallet(wallet::Error),
+    InvalidLength(usize),
+    InvalidPrefix(u8),
+    InvalidVersion(u8),
+    /// The key isn't a master key, or the account key isn't at depth 3.
+    InvalidDepth(u8),
+    /// The shared secret of the payment at this index isn't a valid private key, so BIP47 skips
+    /// the index.
+    InvalidSharedSecret(u32),
+    /// The designated input of a notification transaction must expose its public key.
+    UnsupportedInputType(InputType),
+    /// The transaction doesn't pay to the notification address, or carries no payment code.
+    NotANotification,
+    /// None of the transaction's inputs expose a public key.
+    MissingDesignatedInput,
+}
+
+impl From<base58check::Error> for Error {
+    fn from(err: base58check::Error) -> Error {
+        Error::Base58Check(err)
+    }
+}
+
+impl From<bip32::Error> for Error {
+    fn from(err: bip32::Error) -> Error {
+        Error::Bip32(err)
+    }
+}
+
+impl From<secp256k1::Error> for Error {
+    fn from(err: secp256k1::Error) -> Error {
+        Error::Secp256k1(err)
+    }
+}
+
+impl From<wallet::Error> for Error {
+    fn from(err: wallet::Error) -> Error {
+        Error::Wallet(err)
+    }
+}
+
+/// Derives BIP47 account `index` from `master`, for the master key's network.
+pub fn derive_account(
+    master: &ExtendedPrivateKey,
+    index: u32,
+) -> Result<ExtendedPrivateKey, Error> {
+    if master.depth != 0 {
+        return Err(Error::InvalidDepth(master.depth));
+    }
+
+    Ok(master.derive_private_path(&[
+        ChildNumber::Hardened(PURPOSE),
+        ChildNumber::Hardened(master.network.coin_type()),
+        ChildNumber::Hardened(index),
+    ])?)
+}
+
+/// A version 1 payment code.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PaymentCode {
+    features: u8,
+    public_key: PublicKey,
+    chain_code: ChainCode,
+}
+
+impl PaymentCode {
+    /// Returns the payment code of a BIP47 account key, as derived by `derive_account`.
+    pub fn from_account(account: &ExtendedPrivateKey) -> Result<PaymentCode, Error> {
+        if account.depth != 3 {
+            return Err(Error::InvalidDepth(account.depth));
+        }
+
+        let xpub = ExtendedPublicKey::from(account);
+        Ok(PaymentCode {
+            features: 0,
+            public_key: xpub.public_key,
+            chain_code: xpub.chain_code,
+        })
+    }
+
+    /// Parses the 80 byte serialization which payment code strings encode.
+    pub fn from_bytes(data: &[u8]) -> Result<PaymentCode, Error> {
+        if data.len() != PAYMENT_CODE_LEN {
+            return Err(Error::InvalidLength(data.len()));
+        }
+        if data[0] != VERSION {
+            return Err(Error::InvalidVersion(data[0]));
+        }
+
+        Ok(PaymentCode {
+            features: data[1],
+            public_key: PublicKey::from_slice(&data[2..35])?,
+            chain_code: copy_from_slice!([0u8; 32], &data[35..67]).into(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; PAYMENT_CODE_LEN] {
+        let mut data = [0u8; PAYMENT_CODE_LEN];
+        data[0] = VERSION;
+        data[1] = self.features;
+        data[2..35].copy_from_slice(&self.public_key.serialize());
+        data[35..67].copy_from_slice(self.chain_code.as_bytes());
+        data
+    }
+
+    /// Returns the feature flags, none of which are defined for version 1.
+    pub fn features(&self) -> u8 {
+        self.features
+    }
+
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    pub fn chain_code(&self) -> &ChainCode {
+        &self.chain_code
+    }
+
+    /// Derives the public key at `index`, the key of the account's child `index`.
+    pub fn derive_public_key(&self, index: u32) -> Result<PublicKey, Error> {
+        // Payment codes aren't tied to a network, and the network doesn't affect derivation.
+        let xpub = ExtendedPublicKey {
+            network: Network::Mainnet,
+            depth: 3,
+            parent_fingerprint: [0u8; 4].into(),
+            child_number: ChildNumber::Hardened(0),
+            public_key: self.public_key,
+            chain_code: self.chain_code,
+            origin: None,
+        };
+        Ok(xpub.derive_public(ChildNumber::Normal(index))?.public_key)
+    }
+
+    /// Returns the public key of the notification address, which notifications are sent to.
+    pub fn notification_public_key(&self) -> Result<PublicKey, Error> {
+        self.derive_public_key(0)
+    }
+
+    pub fn notification_address(&self, network: Network) -> Result<String, Error> {
+        Ok(p2pkh_address(&self.notification_public_key()?, network))
+    }
+}
+
+impl From<&PaymentCode> for Base58CheckString {
+    fn from(payment_code: &PaymentCode) -> Base58CheckString {
+        let mut data = vec![PREFIX];
+        data.extend(&payment_code.to_bytes()[..]);
+        Base58CheckString::from_public_bytes(data)
+    }
+}
+
+impl TryFrom<&Base58CheckString> for PaymentCode {
+    type Error = Error;
+
+    fn try_from(v: &Base58CheckString) -> Result<Self, Self::Error> {
+        let data = v.into_bytes()?;
+        if data.len() != PAYMENT_CODE_LEN + 1 {
+            return Err(Error::InvalidLength(data.len()));
+        }
+        if data[0] != PREFIX {
+            return Err(Error::InvalidPrefix(data[0]));
+        }
+
+        PaymentCode::from_bytes(&data[1..])
+    }
+}
+
+fn p2pkh_address(public_key: &PublicKey, network: Network) -> String {
+    ScriptType::P2pkh
+        .address(public_key, network)
+        .expect("every network has P2PKH addresses")
+}
+
+/// Returns the SHA256 of the x coordinate of the ECDH point of `private_key` and `public_key`,
+/// which must be a valid private key for the payment at `index` to be made.
+fn shared_secret(
+    private_key: &SecretKey,
+    public_key: &PublicKey,
+    index: u32,
+) -> Result<Zeroizing<[u8; 32]>, Error> {
+    let mut point = *public_key;
+    point.mul_assign(context::secp256k1(), &private_key[..])?;
+
+    let secret = Zeroizing::new(hash::sha256(&point.serialize()[1..]));
+    SecretKey::from_slice(&secret[..]).map_err(|_| Error::InvalidSharedSecret(index))?;
+    Ok(secret)
+}
+
+/// Returns the public key `account` pays to in its payment at `index` to `recipient`.
+pub fn send_public_key(
+    account: &ExtendedPrivateKey,
+    recipient: &PaymentCode,
+    index: u32,
+) -> Result<PublicKey, Error> {
+    let notification_key = account.derive_private(ChildNumber::Normal(0))?;
+    let mut public_key = recipient.derive_public_key(index)?;
+    let secret = shared_secret(&notification_key.private_key, &public_key, index)?;
+
+    public_key.add_exp_assign(context::secp256k1(), &secret[..])?;
+    Ok(public_key)
+}
+
+/// Returns the address `account` pays to in its payment at `index` to `recipient`, on the
+/// account's network.
+pub fn send_address(
+    account: &ExtendedPrivateKey,
+    recipient: &PaymentCode,
+    index: u32,
+) -> Result<String, Error> {
+    Ok(p2pkh_address(
+        &send_public_key(account, recipient, index)?,
+        account.network,
+    ))
+}
+
+/// Returns the private key of the payment at `index` from `sender` to `account`.
+pub fn receive_private_key(
+    account: &ExtendedPrivateKey,
+    sender: &PaymentCode,
+    index: u32,
+) -> Result<PrivateKey, Error> {
+    let mut private_key = account
+        .derive_private(ChildNumber::Normal(index))?
+        .private_key;
+    let secret = shared_secret(&private_key, &sender.notification_public_key()?, index)?;
+
+    private_key.add_assign(&secret[..])?;
+    Ok(PrivateKey::new(private_key, account.network))
+}
+
+/// Returns the address of the payment at `index` from `sender` to `account`, on the account's
+/// network.
+pub fn receive_address(
+    account: &ExtendedPrivateKey,
+    sender: &PaymentCode,
+    index: u32,
+) -> Result<String, Error> {
+    let private_key = receive_private_key(account, sender, index)?;
+    Ok(p2pkh_address(&private_key.public_key(), account.network))
+}
+
+/// Returns the mask which blinds the payment code in a notification transaction, the
+/// HMAC-SHA512 keyed with the designated input's outpoint of the x coordinate of the ECDH point
+/// of the designated input's key and the notification key.
+fn blinding_mask(
+    private_key: &SecretKey,
+    public_key: &PublicKey,
+    outpoint: &OutPoint,
+) -> Result<Zeroizing<[u8; 64]>, Error> {
+    let mut point = *public_key;
+    point.mul_assign(context::secp256k1(), &private_key[..])?;
+
+    let mut key = Vec::with_capacity(36);
+    outpoint.consensus_encode(&mut key);
+    Ok(hash::hmac_sha512(&point.serialize()[1..], &key))
+}
+
+/// XORs the public key's x coordinate and the chain code with `mask`, which blinds or unblinds
+/// them.
+fn blind(payment_code: &mut [u8; PAYMENT_CODE_LEN], mask: &[u8; 64]) {
+    for (byte, mask) in payment_code[3..67].iter_mut().zip(mask.iter()) {
+        *byte ^= mask;
+    }
+}
+
+/// Builds the unsigned transaction notifying `recipient` of `sender`'s payment code. It spends
+/// only `designated`, whose private key is `designated_key`, and pays the dust limit to the
+/// recipient's notification address, the blinded payment code in an `OP_RETURN` output, and the
+/// rest, less fees, to `change_script` unless that would be dust.
+pub fn notification_transaction(
+    sender: &PaymentCode,
+    recipient: &PaymentCode,
+    designated: &Utxo,
+    designated_key: &SecretKey,
+    change_script: Script,
+    fee_rate: FeeRate,
+) -> Result<Transaction, Error> {
+    match designated.input_type {
+        InputType::P2pkh | InputType::P2shP2wpkh | InputType::P2wpkh => {}
+        input_type => return Err(Error::UnsupportedInputType(input_type)),
+    }
+
+    let notification_key = recipient.notification_public_key()?;
+    let mask = blinding_mask(designated_key, &notification_key, &designated.outpoint)?;
+    let mut payload = sender.to_bytes();
+    blind(&mut payload, &mask);
+
+    let notification_script = ScriptType::P2pkh.script_pubkey(&notification_key);
+    let notification_value = wallet::dust_limit(&notification_script);
+    let mut outputs = vec![
+        TxOut {
+            value: notification_value,
+            script_pubkey: notification_script,
+        },
+        TxOut {
+            value: 0,
+            script_pubkey: Builder::new()
+                .push_opcode(opcodes::OP_RETURN)
+                .push_slice(&payload)
+                .into_script(),
+        },
+    ];
+
+    let available = designated.txout.value;
+    let fee = fee_rate.fee(wallet::estimate_weight(&[designated.input_type], &outputs));
+    if available < notification_value + fee {
+        return Err(wallet::Error::InsufficientFunds {
+            needed: notification_value + fee,
+            available,
+        }
+        .into());
+    }
+
+    let mut with_change = outputs.clone();
+    with_change.push(TxOut {
+        value: 0,
+        script_pubkey: change_script,
+    });
+    let fee = fee_rate.fee(wallet::estimate_weight(
+        &[designated.input_type],
+        &with_change,
+    ));
+    let value = available.saturating_sub(notification_value + fee);
+    if value >= wallet::dust_limit(&with_change[2].script_pubkey) {
+        with_change[2].value = value;
+        outputs = with_change;
+    }
+
+    Ok(Transaction {
+        version: 2,
+        inputs: vec![TxIn {
+            previous_output: designated.outpoint,
+            script_sig: Script::new(),
+            sequence: SEQUENCE_FINAL,
+            witness: Vec::new(),
+        }],
+        outputs,
+        lock_time: 0,
+    })
+}
+
+/// Returns the public key the input exposes in its witness or, failing that, as the last push
+/// of its scriptSig.
+fn designated_public_key(input: &TxIn) -> Option<PublicKey> {
+    if input.witness.len() == 2 {
+        if let Ok(public_key) = PublicKey::from_slice(&input.witness[1]) {
+            return Some(public_key);
+        }
+    }
+
+    let data = input.script_sig.instructions().last()?.ok()?.data;
+    PublicKey::from_slice(data).ok()
+}
+
+/// Recovers the sender's payment code from a signed notification transaction to `account`.
+pub fn parse_notification_transaction(
+    tx: &Transaction,
+    account: &ExtendedPrivateKey,
+) -> Result<PaymentCode, Error> {
+    let notification_key = account.derive_private(ChildNumber::Normal(0))?;
+    let notification_script =
+        ScriptType::P2pkh.script_pubkey(&ExtendedPublicKey::from(&notification_key).public_key);
+    if !tx
+        .outputs
+        .iter()
+        .any(|output| output.script_pubkey == notification_script)
+    {
+        return Err(Error::NotANotification);
+    }
+
+    let mut payload = tx
+        .outputs
+        .iter()
+        .find_map(|output| {
+            let mut instructions = output.script_pubkey.instructions();
+            match (
+                instructions.next(),
+                instructions.next(),
+                instructions.next(),
+            ) {
+                (Some(Ok(op_return)), Some(Ok(push)), None)
+                    if op_return.opcode == opcodes::OP_RETURN
+                        && push.data.len() == PAYMENT_CODE_LEN =>
+                {
+                    Some(copy_from_slice!([0u8; PAYMENT_CODE_LEN], push.data))
+                }
+                _ => None,
+            }
+        })
+        .ok_or(Error::NotANotification)?;
+
+    let (input, designated_key) = tx
+        .inputs
+        .iter()
+        .find_map(|input| designated_public_key(input).map(|key| (input, key)))
+        .ok_or(Error::MissingDesignatedInput)?;
+
+    let mask = blinding_mask(
+        &notification_key.private_key,
+        &designated_key,
+        &input.previous_output,
+    )?;
+    blind(&mut payload, &mask);
+    PaymentCode::from_bytes(&payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address;
+    use crate::bip39::Mnemonic;
+    use crate::hash::Txid;
+    use std::str::FromStr;
+
+    // The wallets of BIP47's test vectors.
+    const ALICE: &str =
+        "response seminar brave tip suit recall often sound stick owner lottery motion";
+    const BOB: &str =
+        "reward upper indicate eight swift arch injury crystal super wrestle already dentist";
+    const ALICE_PAYMENT_CODE: &str = "PM8TJTLJbPRGxSbc8EJi42Wrr6QbNSaSSVJ5Y3E4pbCYiTHUskHg13935Ubb7q8tx9GVbh2UuRnBc3WSyJHhUrw8KhprKnn9eDznYGieTzFcwQRya4GA";
+    const BOB_PAYMENT_CODE: &str = "PM8TJS2JxQ5ztXUpBBRnpTbcUXbUHy2T1abfrb3KkAAtMEGNbey4oumH7Hc578WgQJhPjBxteQ5GHHToTYHE3A1w6p7tU6KSoFmWBVbFGjKPisZDbP97";
+
+    fn account(mnemonic: &str) -> ExtendedPrivateKey {
+        let seed = Mnemonic::from_str(mnemonic).unwrap().to_seed("");
+        let master = ExtendedPrivateKey::new_master(&seed[..], Network::Mainnet).unwrap();
+        derive_account(&master, 0).unwrap()
+    }
+
+    fn parse(payment_code: &str) -> PaymentCode {
+        PaymentCode::try_from(&Base58CheckString::try_from(payment_code.to_string()).unwrap())
+            .unwrap()
+    }
+
+    // The designated input of the test vectors' notification transaction.
+    fn designated() -> (SecretKey, OutPoint) {
+        let key = SecretKey::from_slice(
+            &hex::decode("1b7a10f45118e2519a8dd46ef81591c1ae501d082b6610fdda3de7a3c932880d")
+                .unwrap(),
+        )
+        .unwrap();
+        let outpoint = OutPoint {
+            txid: Txid::from_slice(
+                &hex::decode("86f411ab1c8e70ae8a0795ab7a6757aea6e4d5ae1826fc7b8f00c597d500609c")
+                    .unwrap(),
+            )
+            .unwrap(),
+            vout: 1,
+        };
+        (key, outpoint)
+    }
+
+    #[test]
+    fn test_payment_codes() {
+        let tests = [
+            (
+                ALICE,
+                ALICE_PAYMENT_CODE,
+                "1JDdmqFLhpzcUwPeinhJbUPw4Co3aWLyzW",
+            ),
+            (BOB, BOB_PAYMENT_CODE, "1ChvUUvht2hUQufHBXF8NgLhW8SwE2ecGV"),
+        ];
+
+        for (mnemonic, encoded, notification_address) in tests.iter() {
+            let payment_code = PaymentCode::from_account(&account(mnemonic)).unwrap();
+            assert_eq!(Base58CheckString::from(&payment_code).as_str(), *encoded);
+            assert_eq!(parse(encoded), payment_code);
+            assert_eq!(
+                payment_code.notification_address(Network::Mainnet),
+                Ok(notification_address.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_payment_addresses() {
+        let addresses = [
+            "141fi7TY3h936vRUKh1qfUZr8rSBuYbVBK",
+            "12u3Uued2fuko2nY4SoSFGCoGLCBUGPkk6",
+            "1FsBVhT5dQutGwaPePTYMe5qvYqqjxyftc",
+            "1CZAmrbKL6fJ7wUxb99aETwXhcGeG3CpeA",
+            "1KQvRShk6NqPfpr4Ehd53XUhpemBXtJPTL",
+            "1KsLV2F47JAe6f8RtwzfqhjVa8mZEnTM7t",
+            "1DdK9TknVwvBrJe7urqFmaxEtGF2TMWxzD",
+            "16DpovNuhQJH7JUSZQFLBQgQYS4QB9Wy8e",
+            "17qK2RPGZMDcci2BLQ6Ry2PDGJErrNojT5",
+            "1GxfdfP286uE24qLZ9YRP3EWk2urqXgC4s",
+        ];
+
+        let (alice, bob) = (account(ALICE), account(BOB));
+        let (alice_code, bob_code) = (parse(ALICE_PAYMENT_CODE), parse(BOB_PAYMENT_CODE));
+
+        for (index, address) in addresses.iter().enumerate() {
+            let index = index as u32;
+            assert_eq!(send_address(&alice, &bob_code, index).unwrap(), *address);
+            assert_eq!(receive_address(&bob, &alice_code, index).unwrap(), *address);
+        }
+
+        let private_key = receive_private_key(&bob, &alice_code, 0).unwrap();
+        assert_eq!(
+            hex::encode(&private_key.key[..]),
+            "d687f6b820e6e3d47296b01f3b73ccdc930eded39d559921a7dd8ed81b2c8f82"
+        );
+        assert_eq!(
+            private_key.public_key(),
+            send_public_key(&alice, &bob_code, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_blinded_payload() {
+        let (key, outpoint) = designated();
+        let mask = blinding_mask(
+            &key,
+            &parse(BOB_PAYMENT_CODE).notification_public_key().unwrap(),
+            &outpoint,
+        )
+        .unwrap();
+        assert_eq!(
+            hex::encode(&mask[..32]),
+            "be6e7a4256cac6f4d4ed4639b8c39c4cb8bece40010908e70d17ea9d77b4dc57"
+        );
+
+        let mut payload = parse(ALICE_PAYMENT_CODE).to_bytes();
+        blind(&mut payload, &mask);
+        assert_eq!(
+            hex::encode(&payload[..]),
+            "010002063e4eb95e62791b06c50e1a3a942e1ecaaa9afbbeb324d16ae6821e091611fa96c0cf048f607fe51a0327f5e2528979311c78cb2de0d682c61e1180fc3d543b00000000000000000000000000"
+        );
+    }
+
+    #[test]
+    fn test_notification_transaction() {
+        let (key, outpoint) = designated();
+        let public_key = PublicKey::from_secret_key(context::secp256k1(), &key);
+        let alice_code = parse(ALICE_PAYMENT_CODE);
+        let bob_code = parse(BOB_PAYMENT_CODE);
+        let change_script = ScriptType::P2wpkh.script_pubkey(&public_key);
+        let fee_rate = FeeRate::from_sat_per_vb(10);
+
+        for input_type in [InputType::P2pkh, InputType::P2wpkh].iter() {
+            let designated = Utxo {
+                outpoint,
+                txout: TxOut {
+                    value: 100_000,
+                    script_pubkey: Script::new(),
+                },
+                input_type: *input_type,
+            };
+            let mut tx = notification_transaction(
+                &alice_code,
+                &bob_code,
+                &designated,
+                &key,
+                change_script.clone(),
+                fee_rate,
+            )
+            .unwrap();
+
+            assert_eq!(tx.outputs.len(), 3);
+            assert_eq!(
+                address::from_script_pubkey(&tx.outputs[0].script_pubkey).as_deref(),
+                Some("1ChvUUvht2hUQufHBXF8NgLhW8SwE2ecGV")
+            );
+            assert_eq!(tx.outputs[0].value, 546);
+            assert_eq!(tx.outputs[2].script_pubkey, change_script);
+            let fee = 100_000 - tx.outputs.iter().map(|output| output.value).sum::<u64>();
+            assert_eq!(
+                fee,
+                fee_rate.fee(wallet::estimate_weight(&[*input_type], &tx.outputs))
+            );
+
+            // Signatures aren't checked, so a placeholder stands in for one.
+            let signature = [0x30; 72];
+            match input_type {
+                InputType::P2pkh => {
+                    tx.inputs[0].script_sig = Builder::new()
+                        .push_slice(&signature)
+                        .push_slice(&public_key.serialize())
+                        .into_script()
+                }
+                _ => {
+                    tx.inputs[0].witness = vec![signature.to_vec(), public_key.serialize().to_vec()]
+                }
+            }
+
+            assert_eq!(
+                parse_notification_transaction(&tx, &account(BOB)),
+                Ok(alice_code.clone())
+            );
+            assert_eq!(
+                parse_notification_transaction(&tx, &account(ALICE)),
+                Err(Error::NotANotification)
+            );
+
+            tx.inputs[0].script_sig = Script::new();
+            tx.inputs[0].witness = Vec::new();
+            assert_eq!(
+                parse_notification_transaction(&tx, &account(BOB)),
+                Err(Error::MissingDesignatedInput)
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_notifications() {
+        let (key, outpoint) = designated();
+        let (alice_code, bob_code) = (parse(ALICE_PAYMENT_CODE), parse(BOB_PAYMENT_CODE));
+        let change_script = ScriptType::P2pkh.script_pubkey(alice_code.public_key());
+        let mut designated = Utxo {
+            outpoint,
+            txout: TxOut {
+                value: 1_000,
+                script_pubkey: Script::new(),
+            },
+            input_type: InputType::P2pkh,
+        };
+
+        // Change below the dust limit is left to the fee.
+        let tx = notification_transaction(
+            &alice_code,
+            &bob_code,
+            &designated,
+            &key,
+            change_script.clone(),
+            FeeRate::from_sat_per_vb(1),
+        )
+        .unwrap();
+        assert_eq!(tx.outputs.len(), 2);
+
+        assert!(matches!(
+            notification_transaction(
+                &alice_code,
+                &bob_code,
+                &designated,
+                &key,
+                change_script.clone(),
+                FeeRate::from_sat_per_vb(10),
+            ),
+            Err(Error::Wallet(wallet::Error::InsufficientFunds {
+                available: 1_000,
+                ..
+            }))
+        ));
+
+        designated.input_type = InputType::P2trKeySpend;
+        assert_eq!(
+            notification_transaction(
+                &alice_code,
+                &bob_code,
+                &designated,
+                &key,
+                change_script,
+                FeeRate::from_sat_per_vb(1),
+            ),
+            Err(Error::UnsupportedInputType(InputType::P2trKeySpend))
+        );
+    }
+
+    #[test]
+    fn test_invalid_payment_codes() {
+        let mut data = parse(ALICE_PAYMENT_CODE).to_bytes();
+        assert_eq!(
+            PaymentCode::from_bytes(&data[..79]),
+            Err(Error::InvalidLength(79))
+        );
+
+        data[0] = 2;
+        assert_eq!(
+            PaymentCode::from_bytes(&data),
+            Err(Error::InvalidVersion(2))
+        );
+
+        data[0] = 1;
+        data[2] = 4;
+        assert!(matches!(
+            PaymentCode::from_bytes(&data),
+            Err(Error::Secp256k1(_))
+        ));
+
+        let xpub = Base58CheckString::from(&ExtendedPublicKey::from(&account(ALICE)));
+        assert_eq!(PaymentCode::try_from(&xpub), Err(Error::InvalidLength(78)));
+
+        let mut data = vec![0x48];
+        data.extend(&parse(ALICE_PAYMENT_CODE).to_bytes()[..]);
+        assert_eq!(
+            PaymentCode::try_from(&Base58CheckString::from_public_bytes(data)),
+            Err(Error::InvalidPrefix(0x48))
+        );
+
+        assert_eq!(
+            PaymentCode::from_account(
+                &ExtendedPrivateKey::new_master(&[0; 32], Network::Mainnet).unwrap()
+            ),
+            Err(Error::InvalidDepth(0))
+        );
+    }
+}
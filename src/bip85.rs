@@ -0,0 +1,559 @@
+//! BIP85 deterministic entropy, which derives independent secrets for other wallets and
+//! applications from a single BIP32 root key, so that backing up the root backs up all of them.
+//!
+//! BIP85 leaves the generation of RSA keys from its DRNG to the key generator, so they're only
+//! reproducible by generators using the algorithm described in `rsa`.
+
+use crate::bip32::{self, ChildNumber, ExtendedPrivateKey};
+use crate::bip39::{self, Language, Mnemonic};
+use crate::hash;
+use crate::rsa;
+use crate::wif::PrivateKey;
+use digest::{ExtendableOutput, Update, XofReader};
+use rand::{CryptoRng, RngCore};
+use secp256k1::SecretKey;
+use sha3::{Sha3XofReader, Shake256};
+use zeroize::Zeroizing;
+
+/// The purpose all BIP85 paths start with, which is "BIPS" on a phone keypad.
+pub const PURPOSE: u32 = 83696968;
+
+const APPLICATION_BIP39: u32 = 39;
+const APPLICATION_WIF: u32 = 2;
+const APPLICATION_XPRV: u32 = 32;
+const APPLICATION_HEX: u32 = 128169;
+const APPLICATION_PASSWORD_BASE64: u32 = 707764;
+const APPLICATION_PASSWORD_BASE85: u32 = 707785;
+const APPLICATION_DICE: u32 = 89101;
+const APPLICATION_RSA: u32 = 828365;
+
+/// The RFC 1924 alphabet, which BIP85 encodes base85 passwords with.
+const BASE85_ALPHABET: &[u8] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz!#$%&()*+-;<=>?@^_`{|}~";
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    Bip32(bip32::Error),
+    Bip39(bip39::Error),
+    Secp256k1(secp256k1::Error),
+    Rsa(rsa::Error),
+    /// A path index, which BIP85 always hardens, was out of range.
+    InvalidIndex(u32),
+    InvalidWordCount(usize),
+    InvalidLength(usize),
+    InvalidSides(u32),
+    InvalidRolls(u32),
+}
+
+impl From<bip32::Error> for Error {
+    fn from(err: bip32::Error) -> Error {
+        Error::Bip32(err)
+    }
+}
+
+impl From<bip39::Error> for Error {
+    fn from(err: bip39::Error) -> Error {
+        Error::Bip39(err)
+    }
+}
+
+impl From<secp256k1::Error> for Error {
+    fn from(err: secp256k1::Error) -> Error {
+        Error::Secp256k1(err)
+    }
+}
+
+impl From<rsa::Error> for Error {
+    fn from(err: rsa::Error) -> Error {
+        Error::Rsa(err)
+    }
+}
+
+/// The keys of a GPG key set, which BIP85 derives below its primary key's path.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GpgKey {
+    /// The primary key, which certifies the others.
+    Certify,
+    Encryption,
+    Authentication,
+    Signature,
+}
+
+/// Returns the code identifying `language` in BIP39 application paths.
+fn language_code(language: Language) -> u32 {
+    match language {
+        Language::English => 0,
+        Language::Japanese => 1,
+        Language::Korean => 2,
+        Language::Spanish => 3,
+        Language::ChineseSimplified => 4,
+        Language::ChineseTraditional => 5,
+        Language::French => 6,
+        Language::Italian => 7,
+        Language::Czech => 8,
+        Language::Portuguese => 9,
+    }
+}
+
+/// Derives the 64 bytes of entropy at `path` below `root`, which should be fully hardened.
+pub fn derive_entropy(
+    root: &ExtendedPrivateKey,
+    path: &[ChildNumber],
+) -> Result<Zeroizing<[u8; 64]>, Error> {
+    let key = root.derive_private_path(path)?;
+    Ok(hash::hmac_sha512(
+        &key.private_key[..],
+        b"bip-entropy-from-k",
+    ))
+}
+
+/// Derives the entropy of `application` at the hardened `indices` below it.
+fn derive_application(
+    root: &ExtendedPrivateKey,
+    application: u32,
+    indices: &[u32],
+) -> Result<Zeroizing<[u8; 64]>, Error> {
+    let path = [PURPOSE, application]
+        .iter()
+        .chain(indices)
+        .map(|index| match *index {
+            index if index < 1 << 31 => Ok(ChildNumber::Hardened(index)),
+            index => Err(Error::InvalidIndex(index)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    derive_entropy(root, &path)
+}
+
+/// Derives an English BIP39 mnemonic of 12, 18 or 24 words.
+pub fn mnemonic(root: &ExtendedPrivateKey, words: usize, index: u32) -> Result<Mnemonic, Error> {
+    mnemonic_in(root, Language::English, words, index)
+}
+
+/// Derives a BIP39 mnemonic in `language`, as `mnemonic` does. Each language has its own
+/// mnemonics, rather than translations of the English ones.
+pub fn mnemonic_in(
+    root: &ExtendedPrivateKey,
+    language: Language,
+    words: usize,
+    index: u32,
+) -> Result<Mnemonic, Error> {
+    if words != 12 && words != 18 && words != 24 {
+        return Err(Error::InvalidWordCount(words));
+    }
+
+    let indices = [language_code(language), words as u32, index];
+    let entropy = derive_application(root, APPLICATION_BIP39, &indices)?;
+    Ok(Mnemonic::from_entropy_in(
+        language,
+        &entropy[..words * 4 / 3],
+    )?)
+}
+
+/// Derives a private key for `root`'s network, to be imported into a wallet as WIF.
+pub fn wif(root: &ExtendedPrivateKey, index: u32) -> Result<PrivateKey, Error> {
+    let entropy = derive_application(root, APPLICATION_WIF, &[index])?;
+    Ok(PrivateKey::new(
+        SecretKey::from_slice(&entropy[..32])?,
+        root.network,
+    ))
+}
+
+/// Derives a master extended private key for `root`'s network.
+pub fn xprv(root: &ExtendedPrivateKey, index: u32) -> Result<ExtendedPrivateKey, Error> {
+    let entropy = derive_application(root, APPLICATION_XPRV, &[index])?;
+
+    // Unlike a BIP32 seed's, the entropy's chain code comes before its key.
+    Ok(ExtendedPrivateKey::from_master_parts(
+        &entropy[32..],
+        &entropy[..32],
+        root.network,
+    )?)
+}
+
+/// Derives 16 to 64 bytes of entropy, to be used as hex.
+pub fn hex(
+    root: &ExtendedPrivateKey,
+    length: usize,
+    index: u32,
+) -> Result<Zeroizing<Vec<u8>>, Error> {
+    if !(16..=64).contains(&length) {
+        return Err(Error::InvalidLength(length));
+    }
+
+    let entropy = derive_application(root, APPLICATION_HEX, &[length as u32, index])?;
+    Ok(Zeroizing::new(entropy[..length].to_vec()))
+}
+
+/// Derives a password of 20 to 86 characters from the base64 alphabet.
+pub fn password_base64(
+    root: &ExtendedPrivateKey,
+    length: usize,
+    index: u32,
+) -> Result<Zeroizing<String>, Error> {
+    if !(20..=86).contains(&length) {
+        return Err(Error::InvalidLength(length));
+    }
+
+    let entropy = derive_application(root, APPLICATION_PASSWORD_BASE64, &[length as u32, index])?;
+    let mut password = Zeroizing::new(base64::encode(&entropy[..]));
+    password.truncate(length);
+    Ok(password)
+}
+
+/// Derives a password of 10 to 80 characters from the base85 alphabet.
+pub fn password_base85(
+    root: &ExtendedPrivateKey,
+    length: usize,
+    index: u32,
+) -> Result<Zeroizing<String>, Error> {
+    if !(10..=80).contains(&length) {
+        return Err(Error::InvalidLength(length));
+    }
+
+    let entropy = derive_application(root, APPLICATION_PASSWORD_BASE85, &[length as u32, index])?;
+
+    // Every 4 bytes, read as a big-endian integer, are encoded as 5 digits, most significant
+    // first.
+    let mut password = Zeroizing::new(String::with_capacity(80));
+    for chunk in entropy.chunks(4) {
+        let mut value = u32::from_be_bytes(copy_from_slice!([0u8; 4], chunk));
+        let mut digits = [0u8; 5];
+        for digit in digits.iter_mut().rev() {
+            *digit = BASE85_ALPHABET[(value % 85) as usize];
+            value /= 85;
+        }
+        password.extend(digits.iter().map(|digit| *digit as char));
+    }
+
+    password.truncate(length);
+    Ok(password)
+}
+
+/// Derives `rolls` rolls of a die with `sides` sides, each from 0 to `sides - 1`.
+pub fn dice(
+    root: &ExtendedPrivateKey,
+    sides: u32,
+    rolls: u32,
+    index: u32,
+) -> Result<Vec<u32>, Error> {
+    if sides < 2 {
+        return Err(Error::InvalidSides(sides));
+    }
+    if rolls < 1 {
+        return Err(Error::InvalidRolls(rolls));
+    }
+
+    let entropy = derive_application(root, APPLICATION_DICE, &[sides, rolls, index])?;
+    let mut drng = Drng::new(&entropy);
+
+    // Each roll reads just enough whole bytes for the bits it needs, discarding any excess low
+    // bits and retrying until the roll is in range.
+    let bits = 32 - (sides - 1).leading_zeros();
+    let bytes = bits.div_ceil(8) as usize;
+
+    let mut history = Vec::with_capacity(rolls as usize);
+    while history.len() < rolls as usize {
+        let mut buf = [0u8; 4];
+        drng.fill_bytes(&mut buf[4 - bytes..]);
+        let roll = u32::from_be_bytes(buf) >> (bytes as u32 * 8 - bits);
+
+        if roll < sides {
+            history.push(roll);
+        }
+    }
+
+    Ok(history)
+}
+
+/// Derives an RSA key of `key_bits` bits, one of `rsa::KEY_SIZES`, generated from the DRNG.
+pub fn rsa(
+    root: &ExtendedPrivateKey,
+    key_bits: usize,
+    index: u32,
+) -> Result<rsa::PrivateKey, Error> {
+    derive_rsa(root, key_bits, &[index])
+}
+
+/// Derives one of the RSA keys of GPG key set `index`, as `rsa` does. The primary key is the
+/// RSA key of the same index, and the subkeys are derived below it.
+pub fn rsa_gpg(
+    root: &ExtendedPrivateKey,
+    key_bits: usize,
+    index: u32,
+    key: GpgKey,
+) -> Result<rsa::PrivateKey, Error> {
+    match key {
+        GpgKey::Certify => derive_rsa(root, key_bits, &[index]),
+        GpgKey::Encryption => derive_rsa(root, key_bits, &[index, 0]),
+        GpgKey::Authentication => derive_rsa(root, key_bits, &[index, 1]),
+        GpgKey::Signature => derive_rsa(root, key_bits, &[index, 2]),
+    }
+}
+
+fn derive_rsa(
+    root: &ExtendedPrivateKey,
+    key_bits: usize,
+    indices: &[u32],
+) -> Result<rsa::PrivateKey, Error> {
+    if !rsa::KEY_SIZES.contains(&key_bits) {
+        return Err(rsa::Error::UnsupportedKeySize(key_bits).into());
+    }
+
+    let indices = [key_bits as u32]
+        .iter()
+        .chain(indices)
+        .copied()
+        .collect::<Vec<_>>();
+    let entropy = derive_application(root, APPLICATION_RSA, &indices)?;
+    Ok(rsa::PrivateKey::generate_with_rng(
+        key_bits,
+        &mut Drng::new(&entropy),
+    )?)
+}
+
+/// The BIP85 deterministic random number generator, which reads the SHAKE256 output of 64
+/// bytes of derived entropy.
+pub struct Drng {
+    reader: Sha3XofReader,
+}
+
+impl Drng {
+    pub fn new(entropy: &[u8; 64]) -> Drng {
+        let mut shake = Shake256::default();
+        shake.update(&entropy[..]);
+        Drng {
+            reader: shake.finalize_xof(),
+        }
+    }
+}
+
+impl RngCore for Drng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.reader.read(dest);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl CryptoRng for Drng {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base58check::Base58CheckString;
+    use std::convert::TryFrom;
+    use unicode_normalization::UnicodeNormalization;
+
+    // The root key of BIP85's test vectors.
+    const ROOT_XPRV: &str = "xprv9s21ZrQH143K2LBWUUQRFXhucrQqBpKdRRxNVq2zBqsx8HVqFk2uYo8kmbaLLHRdqtQpUm98uKfu3vca1LqdGhUtyoFnCNkfmXRyPXLjbKb";
+
+    fn root() -> ExtendedPrivateKey {
+        ExtendedPrivateKey::try_from(&Base58CheckString::try_from(ROOT_XPRV.to_string()).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_derive_entropy() {
+        let tests = [
+            (
+                "m/83696968'/0'/0'",
+                "efecfbccffea313214232d29e71563d941229afb4338c21f9517c41aaa0d16f00b83d2a09ef747e7a64e8e2bd5a14869e693da66ce94ac2da570ab7ee48618f7",
+            ),
+            (
+                "m/83696968'/0'/1'",
+                "70c6e3e8ebee8dc4c0dbba66076819bb8c09672527c4277ca8729532ad711872218f826919f6b67218adde99018a6df9095ab2b58d803b5b93ec9802085a690e",
+            ),
+        ];
+
+        for (path, entropy) in tests.iter() {
+            let path = path.parse::<bip32::DerivationPath>().unwrap();
+            let actual = derive_entropy(&root(), path.as_ref()).unwrap();
+            assert_eq!(hex::encode(&actual[..]), *entropy);
+        }
+    }
+
+    #[test]
+    fn test_drng() {
+        let path = "m/83696968'/0'/0'"
+            .parse::<bip32::DerivationPath>()
+            .unwrap();
+        let mut drng = Drng::new(&derive_entropy(&root(), path.as_ref()).unwrap());
+
+        let mut output = [0u8; 80];
+        drng.fill_bytes(&mut output);
+        assert_eq!(hex::encode(&output[..]), "b78b1ee6b345eae6836c2d53d33c64cdaf9a696487be81b03e822dc84b3f1cd883d7559e53d175f243e4c349e822a957bbff9224bc5dde9492ef54e8a439f6bc8c7355b87a925a37ee405a7502991111");
+    }
+
+    #[test]
+    fn test_mnemonic() {
+        let tests = [
+            (12, "girl mad pet galaxy egg matter matrix prison refuse sense ordinary nose"),
+            (18, "near account window bike charge season chef number sketch tomorrow excuse sniff circle vital hockey outdoor supply token"),
+            (24, "puppy ocean match cereal symbol another shed magic wrap hammer bulb intact gadget divorce twin tonight reason outdoor destroy simple truth cigar social volcano"),
+        ];
+
+        for (words, phrase) in tests.iter() {
+            assert_eq!(
+                mnemonic(&root(), *words, 0).unwrap().phrase().as_str(),
+                *phrase
+            );
+        }
+        assert_eq!(mnemonic(&root(), 15, 0), Err(Error::InvalidWordCount(15)));
+    }
+
+    #[test]
+    fn test_mnemonic_languages() {
+        // Computed independently from the entropy at m/83696968'/39'/1'/12'/0'.
+        assert_eq!(
+            mnemonic_in(&root(), Language::Japanese, 12, 0)
+                .unwrap()
+                .phrase()
+                .nfc()
+                .collect::<String>(),
+            "おまいり　にんてい　こふん　ぎんいろ　にんい　ぜんご　ひめい　まほう　たたみ　さとう　ざいたく　あてな"
+        );
+
+        for language in Language::ALL.iter() {
+            let path = [PURPOSE, APPLICATION_BIP39, language_code(*language), 24, 0]
+                .iter()
+                .map(|index| ChildNumber::Hardened(*index))
+                .collect::<Vec<_>>();
+            let mnemonic = mnemonic_in(&root(), *language, 24, 0).unwrap();
+            assert_eq!(mnemonic.language(), *language);
+            assert_eq!(
+                mnemonic.entropy(),
+                &derive_entropy(&root(), &path).unwrap()[..32]
+            );
+        }
+        assert_eq!(
+            mnemonic_in(&root(), Language::Korean, 15, 0),
+            Err(Error::InvalidWordCount(15))
+        );
+    }
+
+    #[test]
+    fn test_rsa() {
+        // Generated independently with the algorithm described in `rsa`.
+        let tests = [
+            (1024, "afd2151ecf4524e756e8369b7f3e3190217e10f5a71ad48e44a02003b2dbcf0da3830528838b0723874c719c64d26822bf0b3687d53d60d2478c81c68728d002f353dc4dd0fb968de7a5d7862f3268708f84335b044ae096a1bea3b7ec5251f2cec937a3e5de83103a0dc5cc17f33bb431a8c9b5e4d71eff98474a726a8a84cf"),
+            (2048, "d8561115a08e16b3c491b634673f57e14e4c76a918fb052f64cf73da48e987246d6aa01e2ce6ec18cabfef03e2ae820266c524f6db6aad2bc6a0c2b94ab86483e01d99cf07432958a6f56c313e39bee25e30b4f5bf0576b65d4d1bd94dbb7f259e8e59c005f132968ea5b679906f82b9f3fb5febd5f6819ff200e3dbe06622b04fef08c8e5fa3f0a290dc0859521ee0879c7bdc27159d6486875c2c21237abfd96f8659779836a4fe5560c79918ab64df5f285677a27616b5f4523f3b6c3d867fedf4616d456dd4ad477ea06efa2bf8e72d0274d51a2058dc1d5ae97e238f7ab30ec168204d15d5e294be7942b1177398de2b0c19a5b89e9795958db56055319"),
+        ];
+
+        for (key_bits, modulus) in tests.iter() {
+            let key = rsa(&root(), *key_bits, 0).unwrap();
+            assert_eq!(key.bits(), *key_bits);
+            assert_eq!(hex::encode(key.modulus()), *modulus);
+        }
+
+        assert_eq!(
+            rsa(&root(), 1000, 0),
+            Err(Error::Rsa(rsa::Error::UnsupportedKeySize(1000)))
+        );
+        assert_eq!(
+            rsa(&root(), 1024, 1 << 31),
+            Err(Error::InvalidIndex(1 << 31))
+        );
+    }
+
+    #[test]
+    fn test_rsa_gpg() {
+        let primary = rsa_gpg(&root(), 1024, 0, GpgKey::Certify).unwrap();
+        assert_eq!(primary, rsa(&root(), 1024, 0).unwrap());
+
+        // The encryption subkey, at m/83696968'/828365'/1024'/0'/0'.
+        let encryption = rsa_gpg(&root(), 1024, 0, GpgKey::Encryption).unwrap();
+        assert_eq!(hex::encode(encryption.modulus()), "cd3a31a29e79b5976995c1fdb1324e1fecb93b3ca0d274d581e1f89a546a18a6aa0a279b9512595907c309cd72cfebf4d230fac0a039965794b41c03e09299c7a8150dd8800f7a9c4f5dd9c4ef887a7f6552dfd020704196bcfb7ed38a7d355c71e95ee723442493421b853096ae147616a8ee78c8d8a1dedd5e75c63956e191");
+
+        let authentication = rsa_gpg(&root(), 1024, 0, GpgKey::Authentication).unwrap();
+        let signature = rsa_gpg(&root(), 1024, 0, GpgKey::Signature).unwrap();
+        let mut moduli = [&primary, &encryption, &authentication, &signature]
+            .iter()
+            .map(|key| key.modulus().to_vec())
+            .collect::<Vec<_>>();
+        moduli.sort();
+        moduli.dedup();
+        assert_eq!(moduli.len(), 4);
+    }
+
+    #[test]
+    fn test_wif() {
+        let key = wif(&root(), 0).unwrap();
+        assert_eq!(
+            Base58CheckString::from(&key).as_str(),
+            "Kzyv4uF39d4Jrw2W7UryTHwZr1zQVNk4dAFyqE6BuMrMh1Za7uhp"
+        );
+    }
+
+    #[test]
+    fn test_xprv() {
+        let xprv = xprv(&root(), 0).unwrap();
+        assert_eq!(Base58CheckString::from(&xprv).as_str(), "xprv9s21ZrQH143K2srSbCSg4m4kLvPMzcWydgmKEnMmoZUurYuBuYG46c6P71UGXMzmriLzCCBvKQWBUv3vPB3m1SATMhp3uEjXHJ42jFg7myX");
+        assert_eq!(
+            xprv.origin,
+            Some((xprv.fingerprint(), bip32::DerivationPath::default()))
+        );
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(
+            hex::encode(&hex(&root(), 64, 0).unwrap()[..]),
+            "492db4698cf3b73a5a24998aa3e9d7fa96275d85724a91e71aa2d645442f878555d078fd1f1f67e368976f04137b1f7a0d19232136ca50c44614af72b5582a5c"
+        );
+        assert_eq!(hex(&root(), 15, 0), Err(Error::InvalidLength(15)));
+        assert_eq!(hex(&root(), 65, 0), Err(Error::InvalidLength(65)));
+    }
+
+    #[test]
+    fn test_passwords() {
+        assert_eq!(
+            password_base64(&root(), 21, 0).unwrap().as_str(),
+            "dKLoepugzdVJvdL56ogNV"
+        );
+        assert_eq!(
+            password_base85(&root(), 12, 0).unwrap().as_str(),
+            "_s`{TW89)i4`"
+        );
+        assert_eq!(password_base64(&root(), 86, 0).unwrap().len(), 86);
+        assert_eq!(password_base85(&root(), 80, 0).unwrap().len(), 80);
+        assert_eq!(
+            password_base64(&root(), 87, 0),
+            Err(Error::InvalidLength(87))
+        );
+        assert_eq!(password_base85(&root(), 9, 0), Err(Error::InvalidLength(9)));
+    }
+
+    #[test]
+    fn test_dice() {
+        assert_eq!(
+            dice(&root(), 6, 10, 0),
+            Ok(vec![1, 0, 0, 2, 0, 1, 5, 5, 2, 4])
+        );
+        assert!(dice(&root(), 1000, 50, 0)
+            .unwrap()
+            .iter()
+            .all(|roll| *roll < 1000));
+        assert_eq!(dice(&root(), 1, 10, 0), Err(Error::InvalidSides(1)));
+        assert_eq!(dice(&root(), 6, 0, 0), Err(Error::InvalidRolls(0)));
+        assert_eq!(
+            dice(&root(), 6, 10, 1 << 31),
+            Err(Error::InvalidIndex(1 << 31))
+        );
+    }
+}
@@ -0,0 +1,87 @@
+//! Block headers.
+
+use crate::encode::{self, Reader};
+use crate::hash::{BlockHash, Sha256d};
+
+/// The size of a serialized block header.
+pub const HEADER_SIZE: usize = 80;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BlockHeader {
+    pub version: i32,
+    pub prev_blockhash: BlockHash,
+    pub merkle_root: Sha256d,
+    pub time: u32,
+    pub bits: u32,
+    pub nonce: u32,
+}
+
+impl BlockHeader {
+    pub fn deserialize(data: &[u8]) -> Result<BlockHeader, encode::Error> {
+        let mut reader = Reader::new(data);
+        let header = BlockHeader::consensus_decode(&mut reader)?;
+        reader.finish()?;
+        Ok(header)
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_SIZE);
+        self.consensus_encode(&mut buf);
+        buf
+    }
+
+    pub fn block_hash(&self) -> BlockHash {
+        BlockHash::hash(&self.serialize())
+    }
+
+    pub fn consensus_encode(&self, buf: &mut Vec<u8>) {
+        buf.extend(&self.version.to_le_bytes());
+        buf.extend(self.prev_blockhash.as_bytes());
+        buf.extend(self.merkle_root.as_bytes());
+        buf.extend(&self.time.to_le_bytes());
+        buf.extend(&self.bits.to_le_bytes());
+        buf.extend(&self.nonce.to_le_bytes());
+    }
+
+    pub(crate) fn consensus_decode(reader: &mut Reader<'_>) -> Result<BlockHeader, encode::Error> {
+        Ok(BlockHeader {
+            version: reader.read_i32()?,
+            prev_blockhash: reader.read_hash()?.into(),
+            merkle_root: reader.read_hash()?.into(),
+            time: reader.read_u32()?,
+            bits: reader.read_u32()?,
+            nonce: reader.read_u32()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_genesis_header() {
+        let raw = hex::decode("0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c").unwrap();
+        let header = BlockHeader::deserialize(&raw).unwrap();
+
+        assert_eq!(header.version, 1);
+        assert_eq!(header.prev_blockhash, BlockHash::default());
+        assert_eq!(
+            header.merkle_root.to_string(),
+            "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b"
+        );
+        assert_eq!(header.time, 1231006505);
+        assert_eq!(header.bits, 0x1d00ffff);
+        assert_eq!(header.nonce, 2083236893);
+        assert_eq!(
+            header.block_hash().to_string(),
+            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"
+        );
+        assert_eq!(header.serialize(), raw);
+
+        assert_eq!(
+            BlockHeader::deserialize(&raw[..79]),
+            Err(encode::Error::UnexpectedEof)
+        );
+    }
+}
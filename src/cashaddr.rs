@@ -0,0 +1,270 @@
+//! CashAddr, the Bitcoin Cash address format. It uses Bech32's alphabet with a 40-bit BCH
+//! checksum over a version byte and the script or public key hash.
+
+use crate::bech32::{self, CHARSET};
+
+const GENERATOR: [u64; 5] = [
+    0x98_f2bc_8e61,
+    0x79_b76d_99e2,
+    0xf3_3e5f_b3c4,
+    0xae_2eab_e2a8,
+    0x1e_4f43_e470,
+];
+const CHECKSUM_LENGTH: usize = 8;
+
+/// The kind of output an address pays to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AddressType {
+    P2pkh,
+    P2sh,
+}
+
+/// Error represents all of the possible errors that can arise during CashAddr decoding.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    MixedCase,
+    PrefixMismatch(String),
+    InvalidCharacter { character: char, position: usize },
+    InvalidChecksum,
+    InvalidLength(usize),
+    InvalidPadding,
+    InvalidVersion(u8),
+}
+
+impl AddressType {
+    fn number(self) -> u8 {
+        match self {
+            AddressType::P2pkh => 0,
+            AddressType::P2sh => 1,
+        }
+    }
+}
+
+fn polymod(values: impl IntoIterator<Item = u8>) -> u64 {
+    let mut c = 1u64;
+    for value in values {
+        let c0 = c >> 35;
+        c = (c & 0x07_ffff_ffff) << 5 ^ value as u64;
+        for (i, g) in GENERATOR.iter().enumerate() {
+            if (c0 >> i) & 1 == 1 {
+                c ^= g;
+            }
+        }
+    }
+    c ^ 1
+}
+
+fn prefix_expand(prefix: &str) -> impl Iterator<Item = u8> + '_ {
+    prefix.bytes().map(|b| b & 0x1f).chain(std::iter::once(0))
+}
+
+fn size_code(len: usize) -> Option<u8> {
+    match len {
+        20 => Some(0),
+        24 => Some(1),
+        28 => Some(2),
+        32 => Some(3),
+        40 => Some(4),
+        48 => Some(5),
+        56 => Some(6),
+        64 => Some(7),
+        _ => None,
+    }
+}
+
+/// Encodes the address of type `address_type` paying to `hash`, with `prefix`.
+///
+/// # Panics
+///
+/// Panics if `hash` isn't one of the sizes CashAddr supports, from 20 to 64 bytes.
+pub fn encode(prefix: &str, address_type: AddressType, hash: &[u8]) -> String {
+    let size = size_code(hash.len()).expect("invalid CashAddr hash length");
+
+    let mut payload = vec![address_type.number() << 3 | size];
+    payload.extend(hash);
+    let data = bech32::convert_bits(&payload, 8, 5, true).expect("padding is allowed");
+
+    let prefix = prefix.to_lowercase();
+    let values = prefix_expand(&prefix)
+        .chain(data.iter().copied())
+        .chain([0u8; CHECKSUM_LENGTH]);
+    let checksum = polymod(values);
+
+    let mut string = prefix;
+    string.push(':');
+    for value in &data {
+        string.push(CHARSET[*value as usize] as char);
+    }
+    for i in 0..CHECKSUM_LENGTH {
+        let value = (checksum >> (5 * (CHECKSUM_LENGTH - 1 - i))) & 0x1f;
+        string.push(CHARSET[value as usize] as char);
+    }
+    string
+}
+
+/// Decodes an address with `prefix` into its type and hash. The prefix may be omitted from
+/// `address`, as wallets commonly display it without one.
+pub fn decode(prefix: &str, address: &str) -> Result<(AddressType, Vec<u8>), Error> {
+    let has_lower = address.bytes().any(|b| b.is_ascii_lowercase());
+    let has_upper = address.bytes().any(|b| b.is_ascii_uppercase());
+    if has_lower && has_upper {
+        return Err(Error::MixedCase);
+    }
+
+    let address = address.to_lowercase();
+    let prefix = prefix.to_lowercase();
+    let (offset, data) = match address.rfind(':') {
+        Some(separator) if address[..separator] != prefix => {
+            return Err(Error::PrefixMismatch(address[..separator].to_string()))
+        }
+        Some(separator) => (separator + 1, &address[separator + 1..]),
+        None => (0, &address[..]),
+    };
+
+    let data = data
+        .chars()
+        .enumerate()
+        .map(|(i, character)| {
+            CHARSET
+                .iter()
+                .position(|c| *c as char == character)
+                .map(|value| value as u8)
+                .ok_or(Error::InvalidCharacter {
+                    character,
+                    position: offset + i,
+                })
+        })
+        .collect::<Result<Vec<u8>, Error>>()?;
+
+    if data.len() <= CHECKSUM_LENGTH {
+        return Err(Error::InvalidLength(address.len()));
+    }
+
+    if polymod(prefix_expand(&prefix).chain(data.iter().copied())) != 0 {
+        return Err(Error::InvalidChecksum);
+    }
+
+    let payload = bech32::convert_bits(&data[..data.len() - CHECKSUM_LENGTH], 5, 8, false)
+        .map_err(|_| Error::InvalidPadding)?;
+    let (version, hash) = payload
+        .split_first()
+        .ok_or(Error::InvalidLength(address.len()))?;
+
+    let address_type = match version >> 3 {
+        0 => AddressType::P2pkh,
+        1 => AddressType::P2sh,
+        _ => return Err(Error::InvalidVersion(*version)),
+    };
+    if *version & 0x80 != 0 || size_code(hash.len()) != Some(version & 0x07) {
+        return Err(Error::InvalidVersion(*version));
+    }
+
+    Ok((address_type, hash.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spec_addresses() {
+        let tests = [
+            (
+                AddressType::P2pkh,
+                "76a04053bda0a88bda5177b86a15c3b29f559873",
+                "bitcoincash:qpm2qsznhks23z7629mms6s4cwef74vcwvy22gdx6a",
+            ),
+            (
+                AddressType::P2pkh,
+                "cb481232299cd5743151ac4b2d63ae198e7bb0a9",
+                "bitcoincash:qr95sy3j9xwd2ap32xkykttr4cvcu7as4y0qverfuy",
+            ),
+            (
+                AddressType::P2pkh,
+                "011f28e473c95f4013d7d53ec5fbc3b42df8ed10",
+                "bitcoincash:qqq3728yw0y47sqn6l2na30mcw6zm78dzqre909m2r",
+            ),
+            (
+                AddressType::P2sh,
+                "76a04053bda0a88bda5177b86a15c3b29f559873",
+                "bitcoincash:ppm2qsznhks23z7629mms6s4cwef74vcwvn0h829pq",
+            ),
+            (
+                AddressType::P2sh,
+                "cb481232299cd5743151ac4b2d63ae198e7bb0a9",
+                "bitcoincash:pr95sy3j9xwd2ap32xkykttr4cvcu7as4yc93ky28e",
+            ),
+            (
+                AddressType::P2sh,
+                "011f28e473c95f4013d7d53ec5fbc3b42df8ed10",
+                "bitcoincash:pqq3728yw0y47sqn6l2na30mcw6zm78dzq5ucqzc37",
+            ),
+        ];
+
+        for (address_type, hash, address) in tests.iter() {
+            let hash = hex::decode(hash).unwrap();
+            assert_eq!(encode("bitcoincash", *address_type, &hash), *address);
+            assert_eq!(
+                decode("bitcoincash", address),
+                Ok((*address_type, hash.clone()))
+            );
+            assert_eq!(
+                decode("bitcoincash", &address.to_uppercase()),
+                Ok((*address_type, hash.clone()))
+            );
+            assert_eq!(
+                decode("bitcoincash", &address["bitcoincash:".len()..]),
+                Ok((*address_type, hash))
+            );
+        }
+    }
+
+    #[test]
+    fn test_prefixes() {
+        let hash = hex::decode("f5bf48b397dae70be82b3cca4793f8eb2b6cdac9").unwrap();
+        let address = "bchtest:pr6m7j9njldwwzlg9v7v53unlr4jkmx6eyvwc0uz5t";
+
+        assert_eq!(encode("bchtest", AddressType::P2sh, &hash), address);
+        assert_eq!(
+            encode("bitcoincash", AddressType::P2pkh, &hash),
+            "bitcoincash:qr6m7j9njldwwzlg9v7v53unlr4jkmx6eylep8ekg2"
+        );
+        assert_eq!(
+            decode("bitcoincash", address),
+            Err(Error::PrefixMismatch("bchtest".to_string()))
+        );
+        // The checksum commits to the prefix, so omitting the wrong one fails.
+        assert_eq!(
+            decode("bitcoincash", &address["bchtest:".len()..]),
+            Err(Error::InvalidChecksum)
+        );
+    }
+
+    #[test]
+    fn test_invalid_addresses() {
+        let address = "bitcoincash:qpm2qsznhks23z7629mms6s4cwef74vcwvy22gdx6a";
+
+        assert_eq!(
+            decode(
+                "bitcoincash",
+                "bitcoincash:qpm2qsznhks23z7629mms6s4cwef74vcwvy22gdx6A"
+            ),
+            Err(Error::MixedCase)
+        );
+        assert_eq!(
+            decode("bitcoincash", &address.replace("6a", "6b")),
+            Err(Error::InvalidCharacter {
+                character: 'b',
+                position: address.len() - 1
+            })
+        );
+        assert_eq!(
+            decode("bitcoincash", &address.replace("6a", "6q")),
+            Err(Error::InvalidChecksum)
+        );
+        assert_eq!(
+            decode("bitcoincash", "bitcoincash:qqqqqq"),
+            Err(Error::InvalidLength(18))
+        );
+    }
+}
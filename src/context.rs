@@ -0,0 +1,13 @@
+//! A secp256k1 context shared by the whole crate.
+//!
+//! Creating a context allocates and fills its precomputed tables, which takes far longer than
+//! the operations it's used for.
+
+use secp256k1::{All, Secp256k1};
+use std::sync::OnceLock;
+
+/// Returns the shared context, creating it on first use.
+pub fn secp256k1() -> &'static Secp256k1<All> {
+    static CONTEXT: OnceLock<Secp256k1<All>> = OnceLock::new();
+    CONTEXT.get_or_init(Secp256k1::new)
+}
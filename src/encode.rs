@@ -0,0 +1,196 @@
+//! Bitcoin consensus serialization primitives.
+
+/// Largest length prefix accepted when decoding, matching Bitcoin Core's `MAX_SIZE`.
+const MAX_SIZE: u64 = 0x0200_0000;
+
+/// Error represents all of the possible errors that can arise while decoding consensus-encoded
+/// data.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    UnexpectedEof,
+    NonCanonicalCompactSize,
+    OversizedCompactSize(u64),
+    TrailingData(usize),
+    SuperfluousWitness,
+    UnknownOptionalData(u8),
+}
+
+/// Appends a CompactSize-encoded integer to `buf`.
+pub fn write_compact_size(buf: &mut Vec<u8>, n: u64) {
+    if n < 0xfd {
+        buf.push(n as u8);
+    } else if n <= 0xffff {
+        buf.push(0xfd);
+        buf.extend(&(n as u16).to_le_bytes());
+    } else if n <= 0xffff_ffff {
+        buf.push(0xfe);
+        buf.extend(&(n as u32).to_le_bytes());
+    } else {
+        buf.push(0xff);
+        buf.extend(&n.to_le_bytes());
+    }
+}
+
+/// Returns the number of bytes needed to CompactSize-encode `n`.
+pub fn compact_size_len(n: u64) -> usize {
+    match n {
+        0..=0xfc => 1,
+        0xfd..=0xffff => 3,
+        0x1_0000..=0xffff_ffff => 5,
+        _ => 9,
+    }
+}
+
+/// Appends a length-prefixed byte string to `buf`.
+pub fn write_var_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    write_compact_size(buf, bytes.len() as u64);
+    buf.extend(bytes);
+}
+
+/// A cursor over a byte slice which decodes consensus-encoded values.
+pub struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data, pos: 0 }
+    }
+
+    /// Returns the number of bytes which have not yet been consumed.
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    /// Returns an error if any bytes have not yet been consumed.
+    pub fn finish(&self) -> Result<(), Error> {
+        match self.remaining() {
+            0 => Ok(()),
+            n => Err(Error::TrailingData(n)),
+        }
+    }
+
+    pub fn peek_u8(&self) -> Result<u8, Error> {
+        self.data.get(self.pos).copied().ok_or(Error::UnexpectedEof)
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.remaining() < len {
+            return Err(Error::UnexpectedEof);
+        }
+
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, Error> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_le_bytes(copy_from_slice!(
+            [0u8; 2],
+            self.read_bytes(2)?
+        )))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(copy_from_slice!(
+            [0u8; 4],
+            self.read_bytes(4)?
+        )))
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32, Error> {
+        Ok(i32::from_le_bytes(copy_from_slice!(
+            [0u8; 4],
+            self.read_bytes(4)?
+        )))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(copy_from_slice!(
+            [0u8; 8],
+            self.read_bytes(8)?
+        )))
+    }
+
+    pub fn read_hash(&mut self) -> Result<[u8; 32], Error> {
+        Ok(copy_from_slice!([0u8; 32], self.read_bytes(32)?))
+    }
+
+    /// Reads a canonically encoded CompactSize integer no larger than `MAX_SIZE`.
+    pub fn read_compact_size(&mut self) -> Result<u64, Error> {
+        let n = match self.read_u8()? {
+            0xfd => {
+                let n = self.read_u16()? as u64;
+                if n < 0xfd {
+                    return Err(Error::NonCanonicalCompactSize);
+                }
+                n
+            }
+            0xfe => {
+                let n = self.read_u32()? as u64;
+                if n <= 0xffff {
+                    return Err(Error::NonCanonicalCompactSize);
+                }
+                n
+            }
+            0xff => {
+                let n = self.read_u64()?;
+                if n <= 0xffff_ffff {
+                    return Err(Error::NonCanonicalCompactSize);
+                }
+                n
+            }
+            n => n as u64,
+        };
+
+        if n > MAX_SIZE {
+            return Err(Error::OversizedCompactSize(n));
+        }
+
+        Ok(n)
+    }
+
+    pub fn read_var_bytes(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.read_compact_size()? as usize;
+        self.read_bytes(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compact_size_roundtrip() {
+        for n in &[0, 0xfc, 0xfd, 0xffff, 0x1_0000, MAX_SIZE] {
+            let mut buf = Vec::new();
+            write_compact_size(&mut buf, *n);
+            assert_eq!(buf.len(), compact_size_len(*n));
+
+            let mut reader = Reader::new(&buf);
+            assert_eq!(reader.read_compact_size(), Ok(*n));
+            assert_eq!(reader.finish(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_compact_size_rejects_non_canonical() {
+        assert_eq!(
+            Reader::new(&[0xfd, 0xfc, 0x00]).read_compact_size(),
+            Err(Error::NonCanonicalCompactSize)
+        );
+        assert_eq!(
+            Reader::new(&[0xfe, 0xff, 0xff, 0x00, 0x00]).read_compact_size(),
+            Err(Error::NonCanonicalCompactSize)
+        );
+        assert_eq!(
+            Reader::new(&[0xfe, 0x01, 0x00, 0x00, 0x04]).read_compact_size(),
+            Err(Error::OversizedCompactSize(0x0400_0001))
+        );
+    }
+}
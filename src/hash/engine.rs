@@ -0,0 +1,502 @@
+//! Incremental hash engines, for hashing data which is produced piece by piece, such as a large
+//! serialized block, without collecting it into a single buffer first.
+
+use crate::hash::{Hash160, Sha256d};
+use digest::Digest;
+use hmac::{Hmac, Mac, NewMac};
+use ripemd160::Ripemd160;
+use sha2::{Sha256, Sha512};
+use std::io;
+use zeroize::Zeroizing;
+
+/// The SHA256 initial hash value.
+const SHA256_INIT: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// The SHA256 state after `SHA256("TapLeaf") || SHA256("TapLeaf")`.
+pub const TAP_LEAF_MIDSTATE: Midstate = Midstate([
+    0x9c, 0xe0, 0xe4, 0xe6, 0x7c, 0x11, 0x6c, 0x39, 0x38, 0xb3, 0xca, 0xf2, 0xc3, 0x0f, 0x50, 0x89,
+    0xd3, 0xf3, 0x93, 0x6c, 0x47, 0x63, 0x6e, 0x60, 0x7d, 0xb3, 0x3e, 0xea, 0xdd, 0xc6, 0xf0, 0xc9,
+]);
+
+/// The SHA256 state after `SHA256("TapBranch") || SHA256("TapBranch")`.
+pub const TAP_BRANCH_MIDSTATE: Midstate = Midstate([
+    0x23, 0xa8, 0x65, 0xa9, 0xb8, 0xa4, 0x0d, 0xa7, 0x97, 0x7c, 0x1e, 0x04, 0xc4, 0x9e, 0x24, 0x6f,
+    0xb5, 0xbe, 0x13, 0x76, 0x9d, 0x24, 0xc9, 0xb7, 0xb5, 0x83, 0xb5, 0xd4, 0xa8, 0xd2, 0x26, 0xd2,
+]);
+
+/// The SHA256 state after `SHA256("TapTweak") || SHA256("TapTweak")`.
+pub const TAP_TWEAK_MIDSTATE: Midstate = Midstate([
+    0xd1, 0x29, 0xa2, 0xf3, 0x70, 0x1c, 0x65, 0x5d, 0x65, 0x83, 0xb6, 0xc3, 0xb9, 0x41, 0x97, 0x27,
+    0x95, 0xf4, 0xe2, 0x32, 0x94, 0xfd, 0x54, 0xf4, 0xa2, 0xae, 0x8d, 0x85, 0x47, 0xca, 0x59, 0x0b,
+]);
+
+/// The SHA256 state after `SHA256("TapSighash") || SHA256("TapSighash")`.
+pub const TAP_SIGHASH_MIDSTATE: Midstate = Midstate([
+    0xf5, 0x04, 0xa4, 0x25, 0xd7, 0xf8, 0x78, 0x3b, 0x13, 0x63, 0x86, 0x8a, 0xe3, 0xe5, 0x56, 0x58,
+    0x6e, 0xee, 0x94, 0x5d, 0xbc, 0x78, 0x88, 0xdd, 0x02, 0xa6, 0xe2, 0xc3, 0x18, 0x73, 0xfe, 0x9f,
+]);
+
+/// The SHA256 state after `SHA256("BIP0340/challenge") || SHA256("BIP0340/challenge")`.
+pub const BIP340_CHALLENGE_MIDSTATE: Midstate = Midstate([
+    0x9c, 0xec, 0xba, 0x11, 0x23, 0x92, 0x53, 0x81, 0x11, 0x67, 0x91, 0x12, 0xd1, 0x62, 0x7e, 0x0f,
+    0x97, 0xc8, 0x75, 0x50, 0x00, 0x3c, 0xc7, 0x65, 0x90, 0xf6, 0x11, 0x64, 0x33, 0xe9, 0xb6, 0x6a,
+]);
+
+/// A hash function which can be fed its input incrementally.
+///
+/// Engines also implement `io::Write`, so that anything which serializes to a writer can be
+/// hashed directly.
+pub trait HashEngine: io::Write {
+    type Output;
+
+    /// Feeds `data` into the hash.
+    fn input(&mut self, data: &[u8]);
+
+    /// Returns the hash of all of the input.
+    fn finalize(self) -> Self::Output;
+}
+
+/// The internal state of SHA256 after compressing a whole number of 64-byte blocks, serialized
+/// big-endian.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Midstate([u8; 32]);
+
+impl Midstate {
+    /// Computes the midstate of a BIP340 tagged hash, after `SHA256(tag) || SHA256(tag)`.
+    pub fn tagged(tag: &str) -> Midstate {
+        let tag_hash = Sha256::digest(tag.as_bytes());
+        let mut engine = Sha256Engine::new();
+        engine.input(&tag_hash);
+        engine.input(&tag_hash);
+        engine.midstate()
+    }
+
+    /// Returns the precomputed midstate of `tag`, if it's one of the tags used by Taproot.
+    pub fn precomputed(tag: &str) -> Option<Midstate> {
+        match tag {
+            "TapLeaf" => Some(TAP_LEAF_MIDSTATE),
+            "TapBranch" => Some(TAP_BRANCH_MIDSTATE),
+            "TapTweak" => Some(TAP_TWEAK_MIDSTATE),
+            "TapSighash" => Some(TAP_SIGHASH_MIDSTATE),
+            "BIP0340/challenge" => Some(BIP340_CHALLENGE_MIDSTATE),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl From<[u8; 32]> for Midstate {
+    fn from(bytes: [u8; 32]) -> Midstate {
+        Midstate(bytes)
+    }
+}
+
+/// A SHA256 engine whose internal state can be saved and restored as a `Midstate`, so that the
+/// cost of hashing a common prefix, like a tagged hash's tag, is only paid once.
+#[derive(Clone)]
+pub struct Sha256Engine {
+    state: [u32; 8],
+    buffer: [u8; 64],
+    length: u64,
+}
+
+impl Sha256Engine {
+    pub fn new() -> Sha256Engine {
+        Sha256Engine {
+            state: SHA256_INIT,
+            buffer: [0u8; 64],
+            length: 0,
+        }
+    }
+
+    /// Resumes hashing from `midstate`, after `length` bytes of input.
+    ///
+    /// Panics if `length` isn't a whole number of blocks.
+    pub fn from_midstate(midstate: Midstate, length: u64) -> Sha256Engine {
+        assert!(length % 64 == 0, "midstates lie on block boundaries");
+
+        let mut state = [0u32; 8];
+        for (word, bytes) in state.iter_mut().zip(midstate.0.chunks_exact(4)) {
+            *word = u32::from_be_bytes(copy_from_slice!([0u8; 4], bytes));
+        }
+
+        Sha256Engine {
+            state,
+            buffer: [0u8; 64],
+            length,
+        }
+    }
+
+    /// Starts a BIP340 tagged hash, using a precomputed midstate for the tags used by Taproot.
+    pub fn tagged(tag: &str) -> Sha256Engine {
+        let midstate = Midstate::precomputed(tag).unwrap_or_else(|| Midstate::tagged(tag));
+        Sha256Engine::from_midstate(midstate, 64)
+    }
+
+    /// Returns the state after the blocks compressed so far.
+    ///
+    /// Panics if the input so far isn't a whole number of blocks, since the buffered partial
+    /// block would be lost.
+    pub fn midstate(&self) -> Midstate {
+        assert!(self.length % 64 == 0, "midstates lie on block boundaries");
+
+        let mut midstate = [0u8; 32];
+        for (bytes, word) in midstate.chunks_exact_mut(4).zip(self.state.iter()) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+        Midstate(midstate)
+    }
+
+    fn compress(&mut self, block: &[u8]) {
+        sha2::compress256(&mut self.state, std::slice::from_ref(block.into()));
+    }
+}
+
+impl Default for Sha256Engine {
+    fn default() -> Sha256Engine {
+        Sha256Engine::new()
+    }
+}
+
+impl HashEngine for Sha256Engine {
+    type Output = [u8; 32];
+
+    fn input(&mut self, mut data: &[u8]) {
+        let buffered = (self.length % 64) as usize;
+        self.length += data.len() as u64;
+
+        if buffered > 0 {
+            let take = data.len().min(64 - buffered);
+            self.buffer[buffered..buffered + take].copy_from_slice(&data[..take]);
+            data = &data[take..];
+
+            if buffered + take < 64 {
+                return;
+            }
+            let block = self.buffer;
+            self.compress(&block);
+        }
+
+        let mut blocks = data.chunks_exact(64);
+        for block in &mut blocks {
+            self.compress(block);
+        }
+
+        let remainder = blocks.remainder();
+        self.buffer[..remainder.len()].copy_from_slice(remainder);
+    }
+
+    fn finalize(mut self) -> [u8; 32] {
+        let bit_length = self.length * 8;
+
+        // Pad with a one bit and then zeros up to 8 bytes short of a block boundary.
+        let padding = 64 - (self.length + 8) % 64;
+        let mut trailer = [0u8; 72];
+        trailer[0] = 0x80;
+        self.input(&trailer[..padding as usize]);
+        self.input(&bit_length.to_be_bytes());
+
+        let mut hash = [0u8; 32];
+        for (bytes, word) in hash.chunks_exact_mut(4).zip(self.state.iter()) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+        hash
+    }
+}
+
+/// A double SHA256 engine.
+#[derive(Clone, Default)]
+pub struct Sha256dEngine(Sha256Engine);
+
+impl Sha256dEngine {
+    pub fn new() -> Sha256dEngine {
+        Sha256dEngine::default()
+    }
+}
+
+impl HashEngine for Sha256dEngine {
+    type Output = Sha256d;
+
+    fn input(&mut self, data: &[u8]) {
+        self.0.input(data);
+    }
+
+    fn finalize(self) -> Sha256d {
+        let hash = Sha256::digest(&self.0.finalize());
+        Sha256d::from(copy_from_slice!([0u8; 32], &hash))
+    }
+}
+
+/// A `RIPEMD160(SHA256(data))` engine.
+#[derive(Clone, Default)]
+pub struct Hash160Engine(Sha256Engine);
+
+impl Hash160Engine {
+    pub fn new() -> Hash160Engine {
+        Hash160Engine::default()
+    }
+}
+
+impl HashEngine for Hash160Engine {
+    type Output = Hash160;
+
+    fn input(&mut self, data: &[u8]) {
+        self.0.input(data);
+    }
+
+    fn finalize(self) -> Hash160 {
+        let hash = Ripemd160::digest(&self.0.finalize());
+        Hash160::from(copy_from_slice!([0u8; 20], &hash))
+    }
+}
+
+/// A RIPEMD160 engine.
+#[derive(Clone, Default)]
+pub struct Ripemd160Engine(Ripemd160);
+
+impl Ripemd160Engine {
+    pub fn new() -> Ripemd160Engine {
+        Ripemd160Engine::default()
+    }
+}
+
+impl HashEngine for Ripemd160Engine {
+    type Output = [u8; 20];
+
+    fn input(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self) -> [u8; 20] {
+        copy_from_slice!([0u8; 20], &self.0.finalize())
+    }
+}
+
+/// A SHA512 engine.
+#[derive(Clone, Default)]
+pub struct Sha512Engine(Sha512);
+
+impl Sha512Engine {
+    pub fn new() -> Sha512Engine {
+        Sha512Engine::default()
+    }
+}
+
+impl HashEngine for Sha512Engine {
+    type Output = [u8; 64];
+
+    fn input(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self) -> [u8; 64] {
+        copy_from_slice!([0u8; 64], &self.0.finalize())
+    }
+}
+
+/// An HMAC-SHA256 engine, whose output is wiped when dropped.
+#[derive(Clone)]
+pub struct HmacSha256Engine(Hmac<Sha256>);
+
+impl HmacSha256Engine {
+    pub fn new(key: &[u8]) -> HmacSha256Engine {
+        HmacSha256Engine(Hmac::new_varkey(key).expect("HMAC accepts keys of any length"))
+    }
+}
+
+impl HashEngine for HmacSha256Engine {
+    type Output = Zeroizing<[u8; 32]>;
+
+    fn input(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self) -> Zeroizing<[u8; 32]> {
+        let mut output = Zeroizing::new([0u8; 32]);
+        output.copy_from_slice(&self.0.finalize().into_bytes());
+        output
+    }
+}
+
+/// An HMAC-SHA512 engine, whose output is wiped when dropped.
+#[derive(Clone)]
+pub struct HmacSha512Engine(Hmac<Sha512>);
+
+impl HmacSha512Engine {
+    pub fn new(key: &[u8]) -> HmacSha512Engine {
+        HmacSha512Engine(Hmac::new_varkey(key).expect("HMAC accepts keys of any length"))
+    }
+}
+
+impl HashEngine for HmacSha512Engine {
+    type Output = Zeroizing<[u8; 64]>;
+
+    fn input(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self) -> Zeroizing<[u8; 64]> {
+        let mut output = Zeroizing::new([0u8; 64]);
+        output.copy_from_slice(&self.0.finalize().into_bytes());
+        output
+    }
+}
+
+macro_rules! impl_write {
+    ($($engine:ty),*) => {
+        $(
+            impl io::Write for $engine {
+                fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                    self.input(buf);
+                    Ok(buf.len())
+                }
+
+                fn flush(&mut self) -> io::Result<()> {
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+impl_write!(
+    Sha256Engine,
+    Sha256dEngine,
+    Hash160Engine,
+    Ripemd160Engine,
+    Sha512Engine,
+    HmacSha256Engine,
+    HmacSha512Engine
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash;
+    use std::io::Write;
+
+    /// Feeds `data` to `engine` in pieces of `piece` bytes.
+    fn hash_in_pieces<E: HashEngine>(mut engine: E, data: &[u8], piece: usize) -> E::Output {
+        for chunk in data.chunks(piece.max(1)) {
+            engine.write_all(chunk).unwrap();
+        }
+        engine.finalize()
+    }
+
+    #[test]
+    fn test_sha256_engine() {
+        assert_eq!(
+            hex::encode(Sha256Engine::new().finalize()),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+
+        let mut engine = Sha256Engine::new();
+        engine.input(b"abc");
+        assert_eq!(
+            hex::encode(engine.finalize()),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        // Every length around the padding boundaries, fed in pieces which straddle blocks.
+        let data = (0..300).map(|i| (i * 7) as u8).collect::<Vec<_>>();
+        for len in 0..data.len() {
+            for piece in &[1, 13, 64, 100] {
+                assert_eq!(
+                    hash_in_pieces(Sha256Engine::new(), &data[..len], *piece),
+                    hash::sha256(&data[..len])
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_engines_match_one_shot_functions() {
+        let data = (0..1000).map(|i| (i * 31) as u8).collect::<Vec<_>>();
+
+        for piece in &[1, 63, 64, 65, 1000] {
+            assert_eq!(
+                hash_in_pieces(Sha256dEngine::new(), &data, *piece),
+                Sha256d::hash(&data)
+            );
+            assert_eq!(
+                hash_in_pieces(Hash160Engine::new(), &data, *piece),
+                Hash160::hash(&data)
+            );
+            assert_eq!(
+                hash_in_pieces(Ripemd160Engine::new(), &data, *piece),
+                hash::ripemd160(&data)
+            );
+            assert_eq!(
+                hash_in_pieces(Sha512Engine::new(), &data, *piece)[..],
+                Sha512::digest(&data)[..]
+            );
+            assert_eq!(
+                hash_in_pieces(HmacSha512Engine::new(b"key"), &data, *piece)[..],
+                hash::hmac_sha512(&data, b"key")[..]
+            );
+        }
+    }
+
+    #[test]
+    fn test_hmac_sha256_engine() {
+        // RFC 4231 test case 2.
+        let mut engine = HmacSha256Engine::new(b"Jefe");
+        write!(engine, "what do ya want for nothing?").unwrap();
+        assert_eq!(
+            hex::encode(&engine.finalize()[..]),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_midstate_round_trip() {
+        let data = (0..200).map(|i| i as u8).collect::<Vec<_>>();
+
+        let mut engine = Sha256Engine::new();
+        engine.input(&data[..128]);
+        let mut resumed = Sha256Engine::from_midstate(engine.midstate(), 128);
+        resumed.input(&data[128..]);
+
+        assert_eq!(resumed.finalize(), hash::sha256(&data));
+    }
+
+    #[test]
+    #[should_panic(expected = "midstates lie on block boundaries")]
+    fn test_midstate_requires_block_boundary() {
+        let mut engine = Sha256Engine::new();
+        engine.input(b"abc");
+        engine.midstate();
+    }
+
+    #[test]
+    fn test_precomputed_midstates() {
+        for tag in &[
+            "TapLeaf",
+            "TapBranch",
+            "TapTweak",
+            "TapSighash",
+            "BIP0340/challenge",
+        ] {
+            assert_eq!(Midstate::precomputed(tag), Some(Midstate::tagged(tag)));
+
+            let tag_hash = Sha256::digest(tag.as_bytes());
+            let expected = Sha256::new()
+                .chain(tag_hash)
+                .chain(tag_hash)
+                .chain(b"data")
+                .finalize();
+
+            let mut engine = Sha256Engine::tagged(tag);
+            engine.input(b"data");
+            assert_eq!(engine.finalize()[..], expected[..]);
+            assert_eq!(hash::tagged_hash(tag, b"data")[..], expected[..]);
+        }
+
+        assert_eq!(Midstate::precomputed("BIP0322-signed-message"), None);
+    }
+}
@@ -0,0 +1,238 @@
+//! Key derivation functions: PBKDF2 for BIP39 seeds and SLIP-39 shares, HKDF, and scrypt for
+//! encrypted keys.
+//!
+//! Derived keys are written to fixed-size arrays which are wiped when dropped.
+
+use hkdf::Hkdf;
+use hmac::Hmac;
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use zeroize::Zeroizing;
+
+/// Error represents all of the possible errors that can arise while deriving a key.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// The requested output is too long (or, for scrypt, empty).
+    InvalidLength(usize),
+    InvalidParams,
+}
+
+/// The cost parameters of scrypt: `N = 2^log_n`, the block size `r` and the parallelism `p`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ScryptParams {
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+}
+
+/// PBKDF2-HMAC-SHA512, as used to stretch BIP39 mnemonics into seeds.
+pub fn pbkdf2_hmac_sha512(password: &[u8], salt: &[u8], rounds: u32) -> Zeroizing<[u8; 64]> {
+    let mut output = Zeroizing::new([0u8; 64]);
+    pbkdf2::pbkdf2::<Hmac<Sha512>>(password, salt, rounds, &mut output[..]);
+    output
+}
+
+/// PBKDF2-HMAC-SHA256, deriving `N` bytes, as used by the SLIP-39 encryption rounds.
+pub fn pbkdf2_hmac_sha256<const N: usize>(
+    password: &[u8],
+    salt: &[u8],
+    rounds: u32,
+) -> Zeroizing<[u8; N]> {
+    let mut output = Zeroizing::new([0u8; N]);
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(password, salt, rounds, &mut output[..]);
+    output
+}
+
+/// PBKDF2-HMAC-SHA1, deriving `N` bytes, for older formats which predate the SHA2 variants.
+pub fn pbkdf2_hmac_sha1<const N: usize>(
+    password: &[u8],
+    salt: &[u8],
+    rounds: u32,
+) -> Zeroizing<[u8; N]> {
+    let mut output = Zeroizing::new([0u8; N]);
+    pbkdf2::pbkdf2::<Hmac<Sha1>>(password, salt, rounds, &mut output[..]);
+    output
+}
+
+/// HKDF-SHA256 (RFC 5869), extracting a pseudorandom key from `ikm` and `salt` and expanding it
+/// with `info` into `N` bytes.
+///
+/// Fails if `N` is more than `255 * 32` bytes.
+pub fn hkdf_sha256<const N: usize>(
+    ikm: &[u8],
+    salt: &[u8],
+    info: &[u8],
+) -> Result<Zeroizing<[u8; N]>, Error> {
+    let mut output = Zeroizing::new([0u8; N]);
+    Hkdf::<Sha256>::new(Some(salt), ikm)
+        .expand(info, &mut output[..])
+        .map_err(|_| Error::InvalidLength(N))?;
+    Ok(output)
+}
+
+/// scrypt (RFC 7914), deriving `N` bytes from `password` and `salt`.
+pub fn scrypt<const N: usize>(
+    password: &[u8],
+    salt: &[u8],
+    params: &ScryptParams,
+) -> Result<Zeroizing<[u8; N]>, Error> {
+    let params = scrypt::ScryptParams::new(params.log_n, params.r, params.p)
+        .map_err(|_| Error::InvalidParams)?;
+
+    let mut output = Zeroizing::new([0u8; N]);
+    scrypt::scrypt(password, salt, &params, &mut output[..])
+        .map_err(|_| Error::InvalidLength(N))?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pbkdf2_rfc6070() {
+        let vectors: &[(&[u8], &[u8], u32, &str)] = &[
+            (
+                b"password",
+                b"salt",
+                1,
+                "0c60c80f961f0e71f3a9b524af6012062fe037a6",
+            ),
+            (
+                b"password",
+                b"salt",
+                2,
+                "ea6c014dc72d6f8ccd1ed92ace1d41f0d8de8957",
+            ),
+            (
+                b"password",
+                b"salt",
+                4096,
+                "4b007901b765489abead49d926f721d065a429c1",
+            ),
+        ];
+
+        for (password, salt, rounds, expected) in vectors {
+            assert_eq!(
+                hex::encode(&pbkdf2_hmac_sha1::<20>(password, salt, *rounds)[..]),
+                *expected
+            );
+        }
+
+        assert_eq!(
+            hex::encode(
+                &pbkdf2_hmac_sha1::<25>(
+                    b"passwordPASSWORDpassword",
+                    b"saltSALTsaltSALTsaltSALTsaltSALTsalt",
+                    4096
+                )[..]
+            ),
+            "3d2eec4fe41c849b80c8d83662c0e44a8b291a964cf2f07038"
+        );
+        assert_eq!(
+            hex::encode(&pbkdf2_hmac_sha1::<16>(b"pass\0word", b"sa\0lt", 4096)[..]),
+            "56fa6aa75548099dcc37d7f03425e0c3"
+        );
+    }
+
+    #[test]
+    fn test_pbkdf2_hmac_sha512() {
+        // The RFC 6070 inputs with SHA512.
+        let vectors: &[(&[u8], &[u8], u32, &str)] = &[
+            (b"password", b"salt", 1, "867f70cf1ade02cff3752599a3a53dc4af34c7a669815ae5d513554e1c8cf252c02d470a285a0501bad999bfe943c08f050235d7d68b1da55e63f73b60a57fce"),
+            (b"password", b"salt", 2, "e1d9c16aa681708a45f5c7c4e215ceb66e011a2e9f0040713f18aefdb866d53cf76cab2868a39b9f7840edce4fef5a82be67335c77a6068e04112754f27ccf4e"),
+            (b"password", b"salt", 4096, "d197b1b33db0143e018b12f3d1d1479e6cdebdcc97c5c0f87f6902e072f457b5143f30602641b3d55cd335988cb36b84376060ecd532e039b742a239434af2d5"),
+            (b"passwordPASSWORDpassword", b"saltSALTsaltSALTsaltSALTsaltSALTsalt", 4096, "8c0511f4c6e597c6ac6315d8f0362e225f3c501495ba23b868c005174dc4ee71115b59f9e60cd9532fa33e0f75aefe30225c583a186cd82bd4daea9724a3d3b8"),
+        ];
+
+        for (password, salt, rounds, expected) in vectors {
+            assert_eq!(
+                hex::encode(&pbkdf2_hmac_sha512(password, salt, *rounds)[..]),
+                *expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_pbkdf2_hmac_sha256() {
+        assert_eq!(
+            hex::encode(&pbkdf2_hmac_sha256::<32>(b"password", b"salt", 1)[..]),
+            "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"
+        );
+        assert_eq!(
+            hex::encode(&pbkdf2_hmac_sha256::<20>(b"password", b"salt", 4096)[..]),
+            "c5e478d59288c841aa530db6845c4c8d962893a0"
+        );
+    }
+
+    #[test]
+    fn test_hkdf_rfc5869() {
+        let ikm = [0x0b; 22];
+        let salt = hex::decode("000102030405060708090a0b0c").unwrap();
+        let info = hex::decode("f0f1f2f3f4f5f6f7f8f9").unwrap();
+        let okm = hkdf_sha256::<42>(&ikm, &salt, &info).unwrap();
+        assert_eq!(
+            hex::encode(&okm[..]),
+            "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865"
+        );
+
+        // Test case 3, with an empty salt and info.
+        let okm = hkdf_sha256::<42>(&ikm, &[], &[]).unwrap();
+        assert_eq!(
+            hex::encode(&okm[..]),
+            "8da4e775a563c18f715f802a063c5a31b8a11f5c5ee1879ec3454e5f3c738d2d9d201395faa4b61a96c8"
+        );
+
+        assert!(hkdf_sha256::<{ 255 * 32 }>(&ikm, &salt, &info).is_ok());
+        assert_eq!(
+            hkdf_sha256::<{ 255 * 32 + 1 }>(&ikm, &salt, &info).map(|_| ()),
+            Err(Error::InvalidLength(255 * 32 + 1))
+        );
+    }
+
+    #[test]
+    fn test_scrypt_rfc7914() {
+        let params = ScryptParams {
+            log_n: 4,
+            r: 1,
+            p: 1,
+        };
+        assert_eq!(
+            hex::encode(&scrypt::<64>(b"", b"", &params).unwrap()[..]),
+            "77d6576238657b203b19ca42c18a0497f16b4844e3074ae8dfdffa3fede21442fcd0069ded0948f8326a753a0fc81f17e8d3e0fb2e0d3628cf35e20c38d18906"
+        );
+
+        let params = ScryptParams {
+            log_n: 10,
+            r: 8,
+            p: 16,
+        };
+        assert_eq!(
+            hex::encode(&scrypt::<64>(b"password", b"NaCl", &params).unwrap()[..]),
+            "fdbabe1c9d3472007856e7190d01e9fe7c6ad7cbc8237830e77376634b3731622eaf30d92e22a3886ff109279d9830dac727afb94a83ee6d8360cbdfa2cc0640"
+        );
+    }
+
+    #[test]
+    fn test_scrypt_errors() {
+        let params = ScryptParams {
+            log_n: 64,
+            r: 1,
+            p: 1,
+        };
+        assert_eq!(
+            scrypt::<32>(b"", b"", &params).map(|_| ()),
+            Err(Error::InvalidParams)
+        );
+
+        let params = ScryptParams {
+            log_n: 4,
+            r: 1,
+            p: 1,
+        };
+        assert_eq!(
+            scrypt::<0>(b"", b"", &params).map(|_| ()),
+            Err(Error::InvalidLength(0))
+        );
+    }
+}
@@ -0,0 +1,107 @@
+pub mod engine;
+pub mod kdf;
+pub mod types;
+
+pub use engine::{HashEngine, HmacSha256Engine, HmacSha512Engine, Midstate, Sha256Engine};
+pub use kdf::{
+    hkdf_sha256, pbkdf2_hmac_sha1, pbkdf2_hmac_sha256, pbkdf2_hmac_sha512, scrypt, ScryptParams,
+};
+pub use types::{BlockHash, Hash160, Sha256d, Txid, Wtxid};
+
+use digest::Digest;
+use ripemd160::Ripemd160;
+use sha1::Sha1;
+use sha2::Sha256;
+use sha3::Keccak256;
+use zeroize::Zeroizing;
+
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+    Sha1::digest(data).into()
+}
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+pub fn ripemd160(data: &[u8]) -> [u8; 20] {
+    Ripemd160::digest(data).into()
+}
+
+pub fn double_sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(&Sha256::digest(data)).into()
+}
+
+pub fn hash160(data: &[u8]) -> [u8; 20] {
+    Ripemd160::digest(&Sha256::digest(data)).into()
+}
+
+/// Keccak-256 as Ethereum uses it, which predates the padding change of the standard SHA3-256.
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+/// BIP340 tagged hash: `SHA256(SHA256(tag) || SHA256(tag) || data)`.
+pub fn tagged_hash(tag: &str, data: &[u8]) -> [u8; 32] {
+    let mut engine = Sha256Engine::tagged(tag);
+    engine.input(data);
+    engine.finalize()
+}
+
+/// HMAC-SHA256, whose output is wiped when dropped.
+pub fn hmac_sha256(data: &[u8], key: &[u8]) -> Zeroizing<[u8; 32]> {
+    let mut engine = HmacSha256Engine::new(key);
+    engine.input(data);
+    engine.finalize()
+}
+
+/// HMAC-SHA512, whose output is wiped when dropped since it's used to derive secret keys.
+pub fn hmac_sha512(data: &[u8], key: &[u8]) -> Zeroizing<[u8; 64]> {
+    let mut engine = HmacSha512Engine::new(key);
+    engine.input(data);
+    engine.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keccak256() {
+        assert_eq!(
+            hex::encode(keccak256(b"")),
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+        assert_eq!(
+            hex::encode(keccak256(b"abc")),
+            "4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45"
+        );
+    }
+
+    #[test]
+    fn test_hmac_rfc4231() {
+        // Test case 1.
+        let key = [0x0b; 20];
+        assert_eq!(
+            hex::encode(&hmac_sha256(b"Hi There", &key)[..]),
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+        );
+
+        // Test case 2.
+        assert_eq!(
+            hex::encode(&hmac_sha512(b"what do ya want for nothing?", b"Jefe")[..]),
+            "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea2505549758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737"
+        );
+
+        // Test case 6, with a key longer than the block size.
+        let key = [0xaa; 131];
+        assert_eq!(
+            hex::encode(
+                &hmac_sha256(
+                    b"Test Using Larger Than Block-Size Key - Hash Key First",
+                    &key
+                )[..]
+            ),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+}
@@ -0,0 +1,214 @@
+//! Fixed-size hash types.
+//!
+//! Bitcoin Core displays 256-bit hashes such as txids and block hashes as little-endian numbers,
+//! i.e. with their bytes reversed, while other hashes are displayed in byte order. The types
+//! here store bytes in internal (serialization) order and take care of the reversal.
+
+use crate::hash;
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+/// Error represents all of the possible errors that can arise while parsing a hash.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    Hex(hex::FromHexError),
+    InvalidLength(usize),
+}
+
+impl From<hex::FromHexError> for Error {
+    fn from(err: hex::FromHexError) -> Error {
+        Error::Hex(err)
+    }
+}
+
+macro_rules! hash_type {
+    ($(#[$doc:meta])* $name:ident, $len:expr, $reversed:expr, $hash:path) => {
+        $(#[$doc])*
+        #[derive(Copy, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub struct $name([u8; $len]);
+
+        impl $name {
+            /// Hashes `data`.
+            pub fn hash(data: &[u8]) -> $name {
+                $name($hash(data))
+            }
+
+            /// Returns the hash's bytes in internal byte order.
+            pub fn as_bytes(&self) -> &[u8; $len] {
+                &self.0
+            }
+
+            /// Parses a hash from bytes in internal byte order.
+            pub fn from_slice(bytes: &[u8]) -> Result<$name, Error> {
+                if bytes.len() != $len {
+                    return Err(Error::InvalidLength(bytes.len()));
+                }
+                Ok($name(copy_from_slice!([0u8; $len], bytes)))
+            }
+        }
+
+        impl From<[u8; $len]> for $name {
+            fn from(bytes: [u8; $len]) -> $name {
+                $name(bytes)
+            }
+        }
+
+        impl From<$name> for [u8; $len] {
+            fn from(hash: $name) -> [u8; $len] {
+                hash.0
+            }
+        }
+
+        impl TryFrom<&[u8]> for $name {
+            type Error = Error;
+
+            fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+                $name::from_slice(bytes)
+            }
+        }
+
+        impl AsRef<[u8]> for $name {
+            fn as_ref(&self) -> &[u8] {
+                &self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                let mut bytes = self.0;
+                if $reversed {
+                    bytes.reverse();
+                }
+                write!(f, "{}", hex::encode(bytes))
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}({})", stringify!($name), self)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = Error;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let mut bytes = $name::from_slice(&hex::decode(s)?)?;
+                if $reversed {
+                    bytes.0.reverse();
+                }
+                Ok(bytes)
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(&self.to_string())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let s = String::deserialize(deserializer)?;
+                s.parse()
+                    .map_err(|err| de::Error::custom(format!("invalid hash {}: {:?}", s, err)))
+            }
+        }
+    };
+}
+
+hash_type!(
+    /// A double SHA256 hash, displayed reversed like the hashes Bitcoin Core commits to.
+    Sha256d,
+    32,
+    true,
+    hash::double_sha256
+);
+
+hash_type!(
+    /// `RIPEMD160(SHA256(data))`, as committed to by P2PKH and P2WPKH outputs.
+    Hash160,
+    20,
+    false,
+    hash::hash160
+);
+
+hash_type!(
+    /// A transaction id, the double SHA256 hash of a transaction without its witness data.
+    Txid,
+    32,
+    true,
+    hash::double_sha256
+);
+
+hash_type!(
+    /// A witness transaction id, the double SHA256 hash of a transaction with its witness data.
+    Wtxid,
+    32,
+    true,
+    hash::double_sha256
+);
+
+hash_type!(
+    /// The double SHA256 hash of a block header.
+    BlockHash,
+    32,
+    true,
+    hash::double_sha256
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GENESIS_HEADER: &str = "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c";
+    const GENESIS_HASH: &str = "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
+
+    #[test]
+    fn test_reversed_display() {
+        let hash = BlockHash::hash(&hex::decode(GENESIS_HEADER).unwrap());
+
+        assert_eq!(hash.to_string(), GENESIS_HASH);
+        assert_eq!(hash.as_bytes()[31], 0x00);
+        assert_eq!(GENESIS_HASH.parse::<BlockHash>(), Ok(hash));
+        assert_eq!(
+            format!("{:?}", hash),
+            format!("BlockHash({})", GENESIS_HASH)
+        );
+    }
+
+    #[test]
+    fn test_forward_display() {
+        let pubkey =
+            hex::decode("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+                .unwrap();
+        let hash = Hash160::hash(&pubkey);
+
+        assert_eq!(hash.to_string(), "751e76e8199196d454941c45d1b3a323f1433bd6");
+        assert_eq!(hash.as_bytes()[0], 0x75);
+        assert_eq!(hash.to_string().parse::<Hash160>(), Ok(hash));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!("00".parse::<Txid>(), Err(Error::InvalidLength(1)));
+        assert!(matches!("zz".parse::<Txid>(), Err(Error::Hex(_))));
+        assert_eq!(
+            Hash160::from_slice(&[0u8; 32]),
+            Err(Error::InvalidLength(32))
+        );
+    }
+
+    #[test]
+    fn test_serde() {
+        let hash: BlockHash = GENESIS_HASH.parse().unwrap();
+        let json = serde_json::to_string(&hash).unwrap();
+
+        assert_eq!(json, format!("\"{}\"", GENESIS_HASH));
+        assert_eq!(serde_json::from_str::<BlockHash>(&json).unwrap(), hash);
+        assert!(serde_json::from_str::<BlockHash>("\"00\"").is_err());
+    }
+}
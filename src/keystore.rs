@@ -0,0 +1,535 @@
+//! Password-encrypted keystore files for extended private keys.
+//!
+//! A keystore is a JSON document holding an extended private key together with the fingerprint
+//! of its master key and its derivation path from it. The key material is encrypted with
+//! XChaCha20-Poly1305 under a key derived from the password with scrypt. The format version and
+//! KDF parameters are authenticated too, so tampering with any part of the file makes decryption
+//! fail.
+
+use crate::bip32::{self, ChildNumber, DerivationPath, ExtendedPrivateKey, Fingerprint, KeySource};
+use crate::hash::{self, kdf, ScryptParams};
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::XChaCha20Poly1305;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+use unicode_normalization::UnicodeNormalization;
+use zeroize::Zeroizing;
+
+pub const VERSION: u32 = 1;
+
+const KDF: &str = "scrypt";
+const CIPHER: &str = "xchacha20poly1305";
+const SALT_SIZE: usize = 32;
+const NONCE_SIZE: usize = 24;
+
+/// The scrypt parameters new keystores are encrypted with, which take about 64 MiB of memory.
+pub const DEFAULT_SCRYPT: ScryptParams = ScryptParams {
+    log_n: 16,
+    r: 8,
+    p: 1,
+};
+
+/// The most expensive scrypt parameters a keystore may specify, so that a crafted file can't
+/// exhaust memory.
+const MAX_LOG_N: u8 = 22;
+const MAX_MEMORY: u64 = 1 << 30;
+const MAX_PARALLELISM: u32 = 16;
+
+/// Error represents all of the possible errors that can arise while reading, writing or
+/// decrypting a keystore.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Hex(hex::FromHexError),
+    Kdf(kdf::Error),
+    Bip32(bip32::Error),
+    UnsupportedVersion(u32),
+    UnsupportedKdf(String),
+    UnsupportedCipher(String),
+    /// The KDF parameters exceed the limits on the memory or time decryption may take.
+    KdfTooExpensive(ScryptParams),
+    InvalidLength(usize),
+    /// The key's origin is unknown, or its path doesn't lead to a key of its depth.
+    InvalidOrigin(Option<KeySource>),
+    /// The password is wrong, or the file has been tampered with.
+    DecryptionFailed,
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Error {
+        Error::Json(err)
+    }
+}
+
+impl From<hex::FromHexError> for Error {
+    fn from(err: hex::FromHexError) -> Error {
+        Error::Hex(err)
+    }
+}
+
+impl From<kdf::Error> for Error {
+    fn from(err: kdf::Error) -> Error {
+        Error::Kdf(err)
+    }
+}
+
+impl From<bip32::Error> for Error {
+    fn from(err: bip32::Error) -> Error {
+        Error::Bip32(err)
+    }
+}
+
+/// The decrypted contents of a keystore: an extended private key whose origin is known.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keystore {
+    xprv: ExtendedPrivateKey,
+}
+
+impl Keystore {
+    /// Creates a keystore for `xprv`, which records its origin. Keys decoded below their master
+    /// key need their origin set first.
+    pub fn new(xprv: ExtendedPrivateKey) -> Result<Keystore, Error> {
+        match &xprv.origin {
+            Some((_, path)) if path.len() == xprv.depth as usize => Ok(Keystore { xprv }),
+            origin => Err(Error::InvalidOrigin(origin.clone())),
+        }
+    }
+
+    pub fn xprv(&self) -> &ExtendedPrivateKey {
+        &self.xprv
+    }
+
+    pub fn master_fingerprint(&self) -> Fingerprint {
+        self.origin().0
+    }
+
+    pub fn derivation_path(&self) -> &DerivationPath {
+        &self.origin().1
+    }
+
+    fn origin(&self) -> &KeySource {
+        self.xprv.origin.as_ref().expect("checked by Keystore::new")
+    }
+
+    /// Reads and decrypts the keystore at `path`.
+    pub fn load<P: AsRef<Path>>(path: P, password: &str) -> Result<Keystore, Error> {
+        EncryptedKeystore::load(path)?.decrypt(password)
+    }
+
+    /// Encrypts the keystore with `password` and writes it to `path`, replacing any existing
+    /// file.
+    pub fn save<P: AsRef<Path>>(&self, path: P, password: &str) -> Result<(), Error> {
+        self.encrypt(password)?.save(path)
+    }
+
+    /// Encrypts the keystore with `password`, using the default scrypt parameters.
+    pub fn encrypt(&self, password: &str) -> Result<EncryptedKeystore, Error> {
+        self.encrypt_with_rng(password, DEFAULT_SCRYPT, &mut rand::thread_rng())
+    }
+
+    pub fn encrypt_with_rng<R: Rng>(
+        &self,
+        password: &str,
+        params: ScryptParams,
+        rng: &mut R,
+    ) -> Result<EncryptedKeystore, Error> {
+        let mut salt = [0u8; SALT_SIZE];
+        rng.fill(&mut salt[..]);
+        let mut nonce = [0u8; NONCE_SIZE];
+        rng.fill(&mut nonce[..]);
+
+        let mut encrypted = EncryptedKeystore {
+            version: VERSION,
+            kdf: KdfParams {
+                name: KDF.to_string(),
+                log_n: params.log_n,
+                r: params.r,
+                p: params.p,
+                salt: hex::encode(salt),
+            },
+            cipher: CIPHER.to_string(),
+            nonce: hex::encode(nonce),
+            ciphertext: String::new(),
+        };
+
+        let key = derive_key(password, &salt, &params)?;
+        let ciphertext = cipher(&key)
+            .encrypt(
+                (&nonce[..]).into(),
+                Payload {
+                    msg: &self.serialize()[..],
+                    aad: &encrypted.associated_data(),
+                },
+            )
+            .expect("keystores are far shorter than the cipher's limit");
+        encrypted.ciphertext = hex::encode(ciphertext);
+
+        Ok(encrypted)
+    }
+
+    fn serialize(&self) -> Zeroizing<Vec<u8>> {
+        let (master_fingerprint, derivation_path) = self.origin();
+        let mut data = Zeroizing::new(Vec::with_capacity(82 + 4 * derivation_path.len()));
+        data.extend(&self.xprv.serialize()[..]);
+        data.extend(master_fingerprint.as_bytes());
+        for child_number in derivation_path.as_ref() {
+            data.extend(&u32::from(*child_number).to_be_bytes());
+        }
+        data
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Keystore, Error> {
+        if data.len() < 82 || (data.len() - 82) % 4 != 0 {
+            return Err(Error::InvalidLength(data.len()));
+        }
+
+        let derivation_path = data[82..]
+            .chunks_exact(4)
+            .map(|chunk| ChildNumber::from(u32::from_be_bytes(copy_from_slice!([0u8; 4], chunk))))
+            .collect::<Vec<_>>();
+
+        let master_fingerprint = copy_from_slice!([0u8; 4], &data[78..82]).into();
+        let derivation_path = DerivationPath::from(derivation_path);
+
+        let origin = Some((master_fingerprint, derivation_path));
+
+        // Master keys are their own origin, which the stored one must agree with.
+        let mut xprv = ExtendedPrivateKey::deserialize(&data[..78])?;
+        if xprv.origin.is_some() && xprv.origin != origin {
+            return Err(Error::InvalidOrigin(origin));
+        }
+        xprv.origin = origin;
+
+        Keystore::new(xprv)
+    }
+}
+
+/// Changes the password of the keystore at `path`, re-encrypting it with a fresh salt and nonce.
+pub fn change_password<P: AsRef<Path>>(
+    path: P,
+    old_password: &str,
+    new_password: &str,
+) -> Result<(), Error> {
+    EncryptedKeystore::load(&path)?
+        .change_password(old_password, new_password)?
+        .save(&path)
+}
+
+/// A keystore as stored on disk.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EncryptedKeystore {
+    version: u32,
+    kdf: KdfParams,
+    cipher: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct KdfParams {
+    name: String,
+    log_n: u8,
+    r: u32,
+    p: u32,
+    salt: String,
+}
+
+impl EncryptedKeystore {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<EncryptedKeystore, Error> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    /// Writes the keystore to `path`, replacing any existing file.
+    ///
+    /// The keystore is written to a temporary file which is then renamed, so that an existing
+    /// keystore isn't lost if writing fails partway. On Unix, the file is only accessible to its
+    /// owner.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let mut file = options.open(&temp)?;
+        serde_json::to_writer_pretty(&mut file, self)?;
+        file.sync_all()?;
+        fs::rename(&temp, path)?;
+        Ok(())
+    }
+
+    /// The scrypt parameters the keystore is encrypted with.
+    pub fn scrypt_params(&self) -> ScryptParams {
+        ScryptParams {
+            log_n: self.kdf.log_n,
+            r: self.kdf.r,
+            p: self.kdf.p,
+        }
+    }
+
+    pub fn decrypt(&self, password: &str) -> Result<Keystore, Error> {
+        if self.version != VERSION {
+            return Err(Error::UnsupportedVersion(self.version));
+        }
+        if self.kdf.name != KDF {
+            return Err(Error::UnsupportedKdf(self.kdf.name.clone()));
+        }
+        if self.cipher != CIPHER {
+            return Err(Error::UnsupportedCipher(self.cipher.clone()));
+        }
+
+        let params = self.scrypt_params();
+        if params.log_n > MAX_LOG_N
+            || params.p > MAX_PARALLELISM
+            || (128 * u64::from(params.r)) << params.log_n > MAX_MEMORY
+        {
+            return Err(Error::KdfTooExpensive(params));
+        }
+
+        let nonce = hex::decode(&self.nonce)?;
+        if nonce.len() != NONCE_SIZE {
+            return Err(Error::InvalidLength(nonce.len()));
+        }
+
+        let key = derive_key(password, &hex::decode(&self.kdf.salt)?, &params)?;
+        let plaintext = cipher(&key)
+            .decrypt(
+                (&nonce[..]).into(),
+                Payload {
+                    msg: &hex::decode(&self.ciphertext)?,
+                    aad: &self.associated_data(),
+                },
+            )
+            .map_err(|_| Error::DecryptionFailed)?;
+
+        Keystore::deserialize(&Zeroizing::new(plaintext))
+    }
+
+    /// Re-encrypts the keystore with `new_password` and a fresh salt and nonce, keeping its
+    /// scrypt parameters.
+    pub fn change_password(
+        &self,
+        old_password: &str,
+        new_password: &str,
+    ) -> Result<EncryptedKeystore, Error> {
+        self.decrypt(old_password)?.encrypt_with_rng(
+            new_password,
+            self.scrypt_params(),
+            &mut rand::thread_rng(),
+        )
+    }
+
+    /// Everything but the nonce and ciphertext, which are authenticated by the cipher itself.
+    fn associated_data(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend(&self.version.to_be_bytes());
+        for field in &[&self.kdf.name, &self.cipher, &self.kdf.salt] {
+            data.extend(&(field.len() as u32).to_be_bytes());
+            data.extend(field.as_bytes());
+        }
+        data.push(self.kdf.log_n);
+        data.extend(&self.kdf.r.to_be_bytes());
+        data.extend(&self.kdf.p.to_be_bytes());
+        data
+    }
+}
+
+fn derive_key(
+    password: &str,
+    salt: &[u8],
+    params: &ScryptParams,
+) -> Result<Zeroizing<[u8; 32]>, Error> {
+    let password = Zeroizing::new(password.nfc().collect::<String>());
+    Ok(hash::scrypt(password.as_bytes(), salt, params)?)
+}
+
+fn cipher(key: &[u8; 32]) -> XChaCha20Poly1305 {
+    XChaCha20Poly1305::new((&key[..]).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base58check::Base58CheckString;
+    use std::convert::TryFrom;
+
+    const MASTER_XPRV: &str = "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi";
+
+    /// Cheap parameters, so that the tests run quickly.
+    const TEST_SCRYPT: ScryptParams = ScryptParams {
+        log_n: 4,
+        r: 8,
+        p: 1,
+    };
+
+    fn keystore() -> Keystore {
+        let master = Base58CheckString::try_from(MASTER_XPRV.to_string()).unwrap();
+        let master = ExtendedPrivateKey::try_from(&master).unwrap();
+        let path: DerivationPath = "m/84'/0'/0'".parse().unwrap();
+        let xprv = master.derive_private_path(path.as_ref()).unwrap();
+
+        Keystore::new(xprv).unwrap()
+    }
+
+    fn encrypt(keystore: &Keystore, password: &str) -> EncryptedKeystore {
+        keystore
+            .encrypt_with_rng(password, TEST_SCRYPT, &mut rand::thread_rng())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let keystore = keystore();
+        let encrypted = encrypt(&keystore, "hunter2");
+
+        let json = serde_json::to_string(&encrypted).unwrap();
+        assert!(!json.contains(&hex::encode(&keystore.xprv().private_key[..])));
+
+        let decrypted = serde_json::from_str::<EncryptedKeystore>(&json)
+            .unwrap()
+            .decrypt("hunter2")
+            .unwrap();
+        assert_eq!(decrypted, keystore);
+
+        // Each encryption uses a fresh salt and nonce.
+        assert_ne!(encrypt(&keystore, "hunter2"), encrypted);
+    }
+
+    #[test]
+    fn test_origin() {
+        let keystore = keystore();
+        assert_eq!(
+            keystore.master_fingerprint(),
+            [0x34, 0x42, 0x19, 0x3e].into()
+        );
+        assert_eq!(keystore.derivation_path().to_string(), "m/84'/0'/0'");
+
+        // Keys decoded below their master key don't know where they came from.
+        let mut xprv = keystore.xprv().clone();
+        xprv.origin = None;
+        assert!(matches!(
+            Keystore::new(xprv.clone()),
+            Err(Error::InvalidOrigin(None))
+        ));
+
+        let origin = Some(([0x34, 0x42, 0x19, 0x3e].into(), "m/84'/0'".parse().unwrap()));
+        xprv.origin = origin.clone();
+        assert!(matches!(
+            Keystore::new(xprv),
+            Err(Error::InvalidOrigin(o)) if o == origin
+        ));
+    }
+
+    #[test]
+    fn test_wrong_password() {
+        let encrypted = encrypt(&keystore(), "hunter2");
+        assert!(matches!(
+            encrypted.decrypt("hunter3"),
+            Err(Error::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_tamper_detection() {
+        let encrypted = encrypt(&keystore(), "hunter2");
+
+        let mut ciphertext = hex::decode(&encrypted.ciphertext).unwrap();
+        ciphertext[0] ^= 0x01;
+        let mut tampered = encrypted.clone();
+        tampered.ciphertext = hex::encode(ciphertext);
+        assert!(matches!(
+            tampered.decrypt("hunter2"),
+            Err(Error::DecryptionFailed)
+        ));
+
+        let mut tampered = encrypted.clone();
+        tampered.nonce = hex::encode([0u8; NONCE_SIZE]);
+        assert!(matches!(
+            tampered.decrypt("hunter2"),
+            Err(Error::DecryptionFailed)
+        ));
+
+        // Changing the KDF parameters changes the derived key, but the parameters are also
+        // authenticated directly.
+        let mut tampered = encrypted.clone();
+        tampered.kdf.r = 4;
+        assert!(matches!(
+            tampered.decrypt("hunter2"),
+            Err(Error::DecryptionFailed)
+        ));
+
+        let mut tampered = encrypted.clone();
+        tampered.version = 2;
+        assert!(matches!(
+            tampered.decrypt("hunter2"),
+            Err(Error::UnsupportedVersion(2))
+        ));
+
+        let mut tampered = encrypted.clone();
+        tampered.cipher = "aes-128-ctr".to_string();
+        assert!(matches!(
+            tampered.decrypt("hunter2"),
+            Err(Error::UnsupportedCipher(_))
+        ));
+
+        let mut tampered = encrypted;
+        tampered.kdf.log_n = 40;
+        assert!(matches!(
+            tampered.decrypt("hunter2"),
+            Err(Error::KdfTooExpensive(_))
+        ));
+    }
+
+    #[test]
+    fn test_save_load_and_change_password() {
+        let dir = std::env::temp_dir().join(format!("keystore-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("wallet.json");
+
+        let keystore = keystore();
+        keystore.save(&path, "hunter2").unwrap();
+        assert_eq!(
+            EncryptedKeystore::load(&path).unwrap().scrypt_params(),
+            DEFAULT_SCRYPT
+        );
+        assert_eq!(Keystore::load(&path, "hunter2").unwrap(), keystore);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        change_password(&path, "hunter2", "correct horse").unwrap();
+        assert!(matches!(
+            Keystore::load(&path, "hunter2"),
+            Err(Error::DecryptionFailed)
+        ));
+        assert_eq!(Keystore::load(&path, "correct horse").unwrap(), keystore);
+
+        assert!(matches!(
+            change_password(&path, "hunter2", "battery staple"),
+            Err(Error::DecryptionFailed)
+        ));
+        assert!(matches!(
+            Keystore::load(dir.join("missing.json"), "hunter2"),
+            Err(Error::Io(_))
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
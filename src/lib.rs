@@ -1,8 +1,34 @@
+// Their suggestions need a newer compiler than this crate supports.
+#![allow(clippy::manual_is_multiple_of, clippy::unnecessary_map_or)]
+
 #[macro_use]
 mod macros;
 
+pub mod account;
 pub mod address;
 pub mod base58check;
+pub mod bech32;
 pub mod bip32;
+pub mod bip38;
+pub mod bip39;
+pub mod bip47;
+pub mod bip85;
+pub mod block;
+pub mod cashaddr;
+pub mod context;
+pub mod encode;
 pub mod hash;
+pub mod keystore;
+pub mod merkle;
+pub mod message;
+pub mod multisig;
+pub mod network;
+pub mod psbt;
 pub mod rpc;
+pub mod rsa;
+pub mod script;
+pub mod slip10;
+pub mod slip39;
+pub mod transaction;
+pub mod wallet;
+pub mod wif;
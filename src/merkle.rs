@@ -0,0 +1,562 @@
+//! Transaction merkle trees, merkle branches, and the partial merkle trees returned by Bitcoin
+//! Core's `gettxoutproof` (`CMerkleBlock`).
+
+use crate::block::BlockHeader;
+use crate::encode::{self, Reader};
+use crate::hash::{Sha256d, Txid};
+use crate::transaction::MAX_BLOCK_WEIGHT;
+
+/// The weight of the smallest possible transaction, which bounds the number of transactions in a
+/// block.
+pub const MIN_TRANSACTION_WEIGHT: usize = 4 * 60;
+
+/// Error represents all of the ways in which a partial merkle tree can be malformed.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    Encode(encode::Error),
+    NoTransactions,
+    TooManyTransactions(u32),
+    /// There are more hashes than transactions.
+    TooManyHashes,
+    /// The tree's flag bits ran out before it was fully traversed.
+    NotEnoughBits,
+    /// The tree's hashes ran out before it was fully traversed.
+    NotEnoughHashes,
+    /// Some flag bits beyond the final partial byte were left unused.
+    UnusedBits,
+    UnusedHashes,
+    /// A node's two children are identical, as in CVE-2012-2459.
+    DuplicateChildren,
+    /// The tree doesn't commit to the block header's merkle root.
+    MerkleRootMismatch,
+}
+
+impl From<encode::Error> for Error {
+    fn from(err: encode::Error) -> Error {
+        Error::Encode(err)
+    }
+}
+
+fn hash_pair(left: &Sha256d, right: &Sha256d) -> Sha256d {
+    let mut data = [0u8; 64];
+    data[..32].copy_from_slice(left.as_bytes());
+    data[32..].copy_from_slice(right.as_bytes());
+    Sha256d::hash(&data)
+}
+
+fn leaf(txid: &Txid) -> Sha256d {
+    Sha256d::from(*txid.as_bytes())
+}
+
+/// Computes the merkle root of `txids`, duplicating the last node of levels with an odd number
+/// of nodes.
+///
+/// Also returns whether the list is mutated, i.e. whether some level ends in two identical
+/// nodes, so that a different list of transactions has the same root (CVE-2012-2459). Blocks
+/// whose transactions are mutated must be treated as invalid rather than as having that root.
+pub fn merkle_root(txids: &[Txid]) -> (Sha256d, bool) {
+    let mut hashes = txids.iter().map(leaf).collect::<Vec<_>>();
+    let mut mutated = false;
+
+    if hashes.is_empty() {
+        return (Sha256d::default(), false);
+    }
+
+    while hashes.len() > 1 {
+        for pair in hashes.chunks_exact(2) {
+            mutated |= pair[0] == pair[1];
+        }
+
+        if hashes.len() % 2 != 0 {
+            hashes.push(hashes[hashes.len() - 1]);
+        }
+
+        hashes = hashes
+            .chunks_exact(2)
+            .map(|pair| hash_pair(&pair[0], &pair[1]))
+            .collect();
+    }
+
+    (hashes[0], mutated)
+}
+
+/// Returns the merkle branch proving that the transaction at `index` is committed to by the
+/// merkle root of `txids`: the sibling of each node on the path from it to the root.
+///
+/// Panics if `index` is out of bounds.
+pub fn merkle_branch(txids: &[Txid], mut index: usize) -> Vec<Sha256d> {
+    assert!(index < txids.len(), "transaction index out of bounds");
+
+    let mut hashes = txids.iter().map(leaf).collect::<Vec<_>>();
+    let mut branch = Vec::new();
+
+    while hashes.len() > 1 {
+        if hashes.len() % 2 != 0 {
+            hashes.push(hashes[hashes.len() - 1]);
+        }
+
+        branch.push(hashes[index ^ 1]);
+        index /= 2;
+
+        hashes = hashes
+            .chunks_exact(2)
+            .map(|pair| hash_pair(&pair[0], &pair[1]))
+            .collect();
+    }
+
+    branch
+}
+
+/// Computes the merkle root committing to `txid` at `index` through `branch`. The branch is
+/// valid if this equals the block's merkle root.
+pub fn root_from_branch(txid: &Txid, branch: &[Sha256d], mut index: usize) -> Sha256d {
+    let mut hash = leaf(txid);
+
+    for sibling in branch {
+        hash = if index & 1 == 1 {
+            hash_pair(sibling, &hash)
+        } else {
+            hash_pair(&hash, sibling)
+        };
+        index /= 2;
+    }
+
+    hash
+}
+
+/// A merkle tree pruned down to the paths to some matched transactions, as in Bitcoin Core's
+/// `CPartialMerkleTree`.
+///
+/// The tree is traversed depth-first. Each node has a flag bit which is set if it's an ancestor
+/// of a matched transaction or is one itself. The hashes of nodes whose bit is clear, and of
+/// matched transactions, are included, and everything else is recomputed from them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartialMerkleTree {
+    transactions: u32,
+    bits: Vec<bool>,
+    hashes: Vec<Sha256d>,
+}
+
+impl PartialMerkleTree {
+    /// Builds the partial tree proving which of `txids` are flagged in `matches`.
+    ///
+    /// Panics if `txids` and `matches` differ in length.
+    pub fn new(txids: &[Txid], matches: &[bool]) -> PartialMerkleTree {
+        assert_eq!(txids.len(), matches.len(), "a match flag for every txid");
+
+        let mut tree = PartialMerkleTree {
+            transactions: txids.len() as u32,
+            bits: Vec::new(),
+            hashes: Vec::new(),
+        };
+
+        let leaves = txids.iter().map(leaf).collect::<Vec<_>>();
+        tree.build(tree.height(), 0, &leaves, matches);
+        tree
+    }
+
+    /// The number of transactions in the block.
+    pub fn transactions(&self) -> u32 {
+        self.transactions
+    }
+
+    pub fn deserialize(data: &[u8]) -> Result<PartialMerkleTree, Error> {
+        let mut reader = Reader::new(data);
+        let tree = PartialMerkleTree::consensus_decode(&mut reader)?;
+        reader.finish()?;
+        Ok(tree)
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.consensus_encode(&mut buf);
+        buf
+    }
+
+    pub fn consensus_encode(&self, buf: &mut Vec<u8>) {
+        buf.extend(&self.transactions.to_le_bytes());
+
+        encode::write_compact_size(buf, self.hashes.len() as u64);
+        for hash in &self.hashes {
+            buf.extend(hash.as_bytes());
+        }
+
+        let mut flags = vec![0u8; self.bits.len().div_ceil(8)];
+        for (i, bit) in self.bits.iter().enumerate() {
+            flags[i / 8] |= (*bit as u8) << (i % 8);
+        }
+        encode::write_var_bytes(buf, &flags);
+    }
+
+    fn consensus_decode(reader: &mut Reader<'_>) -> Result<PartialMerkleTree, Error> {
+        let transactions = reader.read_u32()?;
+
+        let mut hashes = Vec::new();
+        for _ in 0..reader.read_compact_size()? {
+            hashes.push(reader.read_hash()?.into());
+        }
+
+        let bits = reader
+            .read_var_bytes()?
+            .iter()
+            .flat_map(|byte| (0..8).map(move |i| byte >> i & 1 == 1))
+            .collect();
+
+        Ok(PartialMerkleTree {
+            transactions,
+            bits,
+            hashes,
+        })
+    }
+
+    /// Validates the tree, returning the merkle root it commits to and the matched transactions
+    /// with their positions in the block.
+    pub fn extract_matches(&self) -> Result<(Sha256d, Vec<(Txid, usize)>), Error> {
+        if self.transactions == 0 {
+            return Err(Error::NoTransactions);
+        }
+        if self.transactions as usize > MAX_BLOCK_WEIGHT / MIN_TRANSACTION_WEIGHT {
+            return Err(Error::TooManyTransactions(self.transactions));
+        }
+        if self.hashes.len() > self.transactions as usize {
+            return Err(Error::TooManyHashes);
+        }
+        if self.bits.len() < self.hashes.len() {
+            return Err(Error::NotEnoughBits);
+        }
+
+        let mut cursor = Cursor::default();
+        let root = self.extract(self.height(), 0, &mut cursor)?;
+
+        // Only padding in the final byte of flags may be left over.
+        if cursor.bits.div_ceil(8) != self.bits.len().div_ceil(8) {
+            return Err(Error::UnusedBits);
+        }
+        if cursor.hashes != self.hashes.len() {
+            return Err(Error::UnusedHashes);
+        }
+
+        Ok((root, cursor.matches))
+    }
+
+    /// Returns the number of nodes at `height` above the leaves.
+    fn width(&self, height: u32) -> usize {
+        ((self.transactions as usize) + (1 << height) - 1) >> height
+    }
+
+    fn height(&self) -> u32 {
+        let mut height = 0;
+        while self.width(height) > 1 {
+            height += 1;
+        }
+        height
+    }
+
+    fn node_hash(&self, height: u32, pos: usize, leaves: &[Sha256d]) -> Sha256d {
+        if height == 0 {
+            return leaves[pos];
+        }
+
+        let left = self.node_hash(height - 1, pos * 2, leaves);
+        let right = if pos * 2 + 1 < self.width(height - 1) {
+            self.node_hash(height - 1, pos * 2 + 1, leaves)
+        } else {
+            left
+        };
+
+        hash_pair(&left, &right)
+    }
+
+    fn build(&mut self, height: u32, pos: usize, leaves: &[Sha256d], matches: &[bool]) {
+        let first = pos << height;
+        let last = ((pos + 1) << height).min(leaves.len());
+        let parent_of_match = matches[first..last].iter().any(|matched| *matched);
+
+        self.bits.push(parent_of_match);
+
+        if height == 0 || !parent_of_match {
+            let hash = self.node_hash(height, pos, leaves);
+            self.hashes.push(hash);
+        } else {
+            self.build(height - 1, pos * 2, leaves, matches);
+            if pos * 2 + 1 < self.width(height - 1) {
+                self.build(height - 1, pos * 2 + 1, leaves, matches);
+            }
+        }
+    }
+
+    fn extract(&self, height: u32, pos: usize, cursor: &mut Cursor) -> Result<Sha256d, Error> {
+        let parent_of_match = *self.bits.get(cursor.bits).ok_or(Error::NotEnoughBits)?;
+        cursor.bits += 1;
+
+        if height == 0 || !parent_of_match {
+            let hash = *self
+                .hashes
+                .get(cursor.hashes)
+                .ok_or(Error::NotEnoughHashes)?;
+            cursor.hashes += 1;
+
+            if height == 0 && parent_of_match {
+                cursor.matches.push((Txid::from(*hash.as_bytes()), pos));
+            }
+            return Ok(hash);
+        }
+
+        let left = self.extract(height - 1, pos * 2, cursor)?;
+        let right = if pos * 2 + 1 < self.width(height - 1) {
+            let right = self.extract(height - 1, pos * 2 + 1, cursor)?;
+            if right == left {
+                return Err(Error::DuplicateChildren);
+            }
+            right
+        } else {
+            left
+        };
+
+        Ok(hash_pair(&left, &right))
+    }
+}
+
+/// Progress through a partial merkle tree's bits and hashes while extracting its matches.
+#[derive(Default)]
+struct Cursor {
+    bits: usize,
+    hashes: usize,
+    matches: Vec<(Txid, usize)>,
+}
+
+/// A block header with a partial merkle tree proving that some transactions are in the block,
+/// as returned by `gettxoutproof`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleBlock {
+    pub header: BlockHeader,
+    pub txn: PartialMerkleTree,
+}
+
+impl MerkleBlock {
+    /// Builds a proof that the transactions of the block with `header` which are flagged in
+    /// `matches` are in it.
+    pub fn new(header: BlockHeader, txids: &[Txid], matches: &[bool]) -> MerkleBlock {
+        MerkleBlock {
+            header,
+            txn: PartialMerkleTree::new(txids, matches),
+        }
+    }
+
+    pub fn deserialize(data: &[u8]) -> Result<MerkleBlock, Error> {
+        let mut reader = Reader::new(data);
+        let header = BlockHeader::consensus_decode(&mut reader)?;
+        let txn = PartialMerkleTree::consensus_decode(&mut reader)?;
+        reader.finish()?;
+        Ok(MerkleBlock { header, txn })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = self.header.serialize();
+        self.txn.consensus_encode(&mut buf);
+        buf
+    }
+
+    /// Verifies that the partial merkle tree commits to the header's merkle root, returning the
+    /// transactions it proves are in the block with their positions.
+    pub fn extract_matches(&self) -> Result<Vec<(Txid, usize)>, Error> {
+        let (root, matches) = self.txn.extract_matches()?;
+        if root != self.header.merkle_root {
+            return Err(Error::MerkleRootMismatch);
+        }
+        Ok(matches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The transactions of block 100000.
+    const BLOCK_100000_TXIDS: [&str; 4] = [
+        "8c14f0db3df150123e6f3dbbf30f8b955a8249b62ac1d1ff16284aefa3d06d87",
+        "fff2525b8931402dd09222c50775608f75787bd2b87e56995a7bdd30f79702c4",
+        "6359f0868171b1d194cbee1af2f16ea598ae8fad666d9b012c8ed2b79a236ec4",
+        "e9a66845e05d5abc0ad04ec80f774a7e585c6e8db975962d069a522137b80c1d",
+    ];
+    const BLOCK_100000_MERKLE_ROOT: &str =
+        "f3e94742aca4b5ef85488dc37c06c3282295ffec960994b2c0d5ac2a25a95766";
+
+    fn block_100000_txids() -> Vec<Txid> {
+        BLOCK_100000_TXIDS
+            .iter()
+            .map(|txid| txid.parse().unwrap())
+            .collect()
+    }
+
+    fn block_100000_header() -> BlockHeader {
+        BlockHeader {
+            version: 1,
+            prev_blockhash: "000000000002d01c1fccc21636b607dfd930d31d01c3a62104612a1719011250"
+                .parse()
+                .unwrap(),
+            merkle_root: BLOCK_100000_MERKLE_ROOT.parse().unwrap(),
+            time: 1293623863,
+            bits: 0x1b04864c,
+            nonce: 274148111,
+        }
+    }
+
+    /// Distinct txids for synthetic blocks of `n` transactions.
+    fn synthetic_txids(n: usize) -> Vec<Txid> {
+        (0..n)
+            .map(|i| Txid::hash(&(i as u32).to_le_bytes()))
+            .collect()
+    }
+
+    #[test]
+    fn test_merkle_root() {
+        let txids = block_100000_txids();
+        assert_eq!(
+            merkle_root(&txids),
+            (BLOCK_100000_MERKLE_ROOT.parse().unwrap(), false)
+        );
+        assert_eq!(
+            block_100000_header().block_hash().to_string(),
+            "000000000003ba27aa200b1cecaad478d2b00432346c3f1f3986da1afd33e506"
+        );
+
+        // A single transaction is its own root.
+        assert_eq!(merkle_root(&txids[..1]), (leaf(&txids[0]), false));
+        assert_eq!(merkle_root(&[]), (Sha256d::default(), false));
+    }
+
+    #[test]
+    fn test_merkle_root_mutation() {
+        // Duplicating the last transaction of a level with an odd number of nodes gives the
+        // same root, but is detected.
+        let txids = synthetic_txids(3);
+        let mut mutated = txids.clone();
+        mutated.push(txids[2]);
+
+        let (root, is_mutated) = merkle_root(&txids);
+        assert!(!is_mutated);
+        assert_eq!(merkle_root(&mutated), (root, true));
+
+        // Likewise for duplicated subtrees further up.
+        let txids = synthetic_txids(6);
+        let mut mutated = txids.clone();
+        mutated.extend_from_slice(&txids[4..6]);
+        assert_eq!(merkle_root(&mutated), (merkle_root(&txids).0, true));
+    }
+
+    #[test]
+    fn test_merkle_branch() {
+        for n in 1..20 {
+            let txids = synthetic_txids(n);
+            let (root, _) = merkle_root(&txids);
+
+            for (index, txid) in txids.iter().enumerate() {
+                let branch = merkle_branch(&txids, index);
+                assert_eq!(root_from_branch(txid, &branch, index), root);
+
+                // A node hashed with its own duplicate is the same on either side.
+                if index ^ 1 < n {
+                    assert_ne!(root_from_branch(txid, &branch, index ^ 1), root);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_partial_merkle_tree() {
+        for n in &[1, 4, 7, 17, 56, 100] {
+            let txids = synthetic_txids(*n);
+            let (root, _) = merkle_root(&txids);
+
+            for pattern in 0..8 {
+                let matches = (0..*n)
+                    .map(|i| (i * 7 + pattern) % (pattern + 2) == 0)
+                    .collect::<Vec<_>>();
+
+                let tree = PartialMerkleTree::new(&txids, &matches);
+                let tree = PartialMerkleTree::deserialize(&tree.serialize()).unwrap();
+
+                let expected = txids
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| matches[*i])
+                    .map(|(i, txid)| (*txid, i))
+                    .collect::<Vec<_>>();
+                assert_eq!(tree.extract_matches(), Ok((root, expected)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_merkle_block() {
+        let txids = block_100000_txids();
+        let block = MerkleBlock::new(block_100000_header(), &txids, &[false, true, false, true]);
+
+        let block = MerkleBlock::deserialize(&block.serialize()).unwrap();
+        assert_eq!(block.txn.transactions(), 4);
+        assert_eq!(
+            block.extract_matches(),
+            Ok(vec![(txids[1], 1), (txids[3], 3)])
+        );
+
+        let mut header = block_100000_header();
+        header.merkle_root = Sha256d::default();
+        let forged = MerkleBlock {
+            header,
+            txn: block.txn.clone(),
+        };
+        assert_eq!(forged.extract_matches(), Err(Error::MerkleRootMismatch));
+    }
+
+    #[test]
+    fn test_malformed_partial_merkle_trees() {
+        let txids = synthetic_txids(7);
+        let tree =
+            PartialMerkleTree::new(&txids, &[false, false, true, false, false, false, false]);
+
+        let mut empty = tree.clone();
+        empty.transactions = 0;
+        assert_eq!(empty.extract_matches(), Err(Error::NoTransactions));
+
+        let mut oversized = tree.clone();
+        oversized.transactions = 1_000_000;
+        assert_eq!(
+            oversized.extract_matches(),
+            Err(Error::TooManyTransactions(1_000_000))
+        );
+
+        let mut truncated = tree.clone();
+        truncated.hashes.pop();
+        assert_eq!(truncated.extract_matches(), Err(Error::NotEnoughHashes));
+
+        let mut extra_hash = tree.clone();
+        extra_hash.hashes.push(Sha256d::default());
+        assert_eq!(extra_hash.extract_matches(), Err(Error::UnusedHashes));
+
+        let mut extra_bits = tree.clone();
+        extra_bits.bits.extend_from_slice(&[false; 8]);
+        assert_eq!(extra_bits.extract_matches(), Err(Error::UnusedBits));
+
+        let mut missing_bits = tree.clone();
+        missing_bits.bits.truncate(2);
+        assert_eq!(missing_bits.extract_matches(), Err(Error::NotEnoughBits));
+
+        assert!(matches!(
+            PartialMerkleTree::deserialize(&tree.serialize()[..10]),
+            Err(Error::Encode(_))
+        ));
+    }
+
+    #[test]
+    fn test_duplicate_children_rejected() {
+        // A tree over [a, b, c, c] proving the last transaction has the same root as one over
+        // [a, b, c], but must be rejected.
+        let txids = synthetic_txids(3);
+        let mut mutated = txids.clone();
+        mutated.push(txids[2]);
+
+        let tree = PartialMerkleTree::new(&mutated, &[false, false, false, true]);
+        assert_eq!(tree.extract_matches(), Err(Error::DuplicateChildren));
+    }
+}
@@ -0,0 +1,408 @@
+//! Generic signed messages (BIP322), which prove control of an address by signing a virtual
+//! transaction spending from it.
+
+use super::{p2pkh_script, p2wpkh_script, Error};
+use crate::address;
+use crate::context;
+use crate::encode::{self, Reader};
+use crate::hash;
+use crate::network::Network;
+use crate::script::interpreter::{self, VerifyFlags};
+use crate::script::{opcodes, Builder, Script};
+use crate::transaction::sighash::{self, SIGHASH_ALL, SIGHASH_DEFAULT};
+use crate::transaction::{OutPoint, Transaction, TxIn, TxOut};
+use secp256k1::schnorrsig::{self, KeyPair};
+use secp256k1::{Message, PublicKey, SecretKey};
+
+const MESSAGE_TAG: &str = "BIP0322-signed-message";
+
+/// How a signature is encoded.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Only the witness of the `to_sign` transaction, for segwit addresses.
+    Simple,
+    /// The entire `to_sign` transaction.
+    Full,
+}
+
+/// Returns the BIP340 tagged hash of `message` committed to by `to_spend`.
+pub fn message_hash(message: &[u8]) -> [u8; 32] {
+    hash::tagged_hash(MESSAGE_TAG, message)
+}
+
+/// Builds the virtual transaction paying to `script_pubkey` which a signature over `message`
+/// spends.
+pub fn to_spend(script_pubkey: &Script, message: &[u8]) -> Transaction {
+    Transaction {
+        version: 0,
+        inputs: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: Builder::new()
+                .push_opcode(opcodes::OP_0)
+                .push_slice(&message_hash(message))
+                .into_script(),
+            sequence: 0,
+            witness: Vec::new(),
+        }],
+        outputs: vec![TxOut {
+            value: 0,
+            script_pubkey: script_pubkey.clone(),
+        }],
+        lock_time: 0,
+    }
+}
+
+/// Builds the unsigned virtual transaction spending `to_spend`.
+pub fn to_sign(to_spend: &Transaction) -> Transaction {
+    Transaction {
+        version: 0,
+        inputs: vec![TxIn {
+            previous_output: OutPoint {
+                txid: to_spend.txid(),
+                vout: 0,
+            },
+            script_sig: Script::new(),
+            sequence: 0,
+            witness: Vec::new(),
+        }],
+        outputs: vec![TxOut {
+            value: 0,
+            script_pubkey: Builder::new().push_opcode(opcodes::OP_RETURN).into_script(),
+        }],
+        lock_time: 0,
+    }
+}
+
+/// Signs `message` for the mainnet `address`, which must be a P2PKH, P2SH-P2WPKH, P2WPKH or
+/// BIP86 P2TR address controlled by `secret_key`. Only segwit v0 and taproot addresses have a
+/// simple encoding.
+pub fn sign(
+    secret_key: &SecretKey,
+    address: &str,
+    message: &[u8],
+    format: Format,
+) -> Result<String, Error> {
+    sign_on(secret_key, address, message, format, Network::Mainnet)
+}
+
+/// Signs `message` for `address` on `network`, as `sign` does for mainnet addresses.
+pub fn sign_on(
+    secret_key: &SecretKey,
+    address: &str,
+    message: &[u8],
+    format: Format,
+    network: Network,
+) -> Result<String, Error> {
+    let to_spend = to_spend(&address::to_script_pubkey_on(address, network)?, message);
+    let mut to_sign = to_sign(&to_spend);
+    satisfy(&mut to_sign, &to_spend.outputs[0], secret_key)?;
+
+    match format {
+        Format::Simple if !to_sign.inputs[0].script_sig.is_empty() => {
+            Err(Error::UnsupportedAddress)
+        }
+        Format::Simple => Ok(base64::encode(encode_witness(&to_sign.inputs[0].witness))),
+        Format::Full => Ok(base64::encode(to_sign.serialize())),
+    }
+}
+
+/// Verifies a base64 BIP322 signature, in either format, over `message` for the mainnet
+/// `address`.
+///
+/// Returns `Err(Error::Script)` if the signature doesn't satisfy the address's script.
+pub fn verify(address: &str, message: &[u8], signature: &str) -> Result<(), Error> {
+    verify_on(address, message, signature, Network::Mainnet)
+}
+
+/// Verifies a base64 BIP322 signature over `message` for `address` on `network`, as `verify`
+/// does for mainnet addresses.
+pub fn verify_on(
+    address: &str,
+    message: &[u8],
+    signature: &str,
+    network: Network,
+) -> Result<(), Error> {
+    let to_spend = to_spend(&address::to_script_pubkey_on(address, network)?, message);
+    let data = base64::decode(signature)?;
+
+    let to_sign = match Transaction::deserialize(&data) {
+        Ok(to_sign) => {
+            check_to_sign(&to_sign, &to_spend)?;
+            to_sign
+        }
+        Err(_) => {
+            let mut to_sign = to_sign(&to_spend);
+            to_sign.inputs[0].witness = decode_witness(&data)?;
+            to_sign
+        }
+    };
+
+    interpreter::verify_input_with_prevouts(&to_sign, 0, &to_spend.outputs, VerifyFlags::STANDARD)?;
+    Ok(())
+}
+
+/// Checks a full signature's `to_sign` transaction spends `to_spend` and nothing else.
+fn check_to_sign(to_sign: &Transaction, to_spend: &Transaction) -> Result<(), Error> {
+    if to_sign.inputs.len() > 1 {
+        return Err(Error::UnsupportedProof);
+    }
+
+    let spends_to_spend = to_sign.inputs.first().map(|input| input.previous_output)
+        == Some(OutPoint {
+            txid: to_spend.txid(),
+            vout: 0,
+        });
+    let burns_nothing = to_sign.outputs.len() == 1
+        && to_sign.outputs[0].value == 0
+        && to_sign.outputs[0].script_pubkey.as_bytes() == [opcodes::OP_RETURN];
+
+    if !spends_to_spend || !burns_nothing {
+        return Err(Error::InvalidToSign);
+    }
+
+    Ok(())
+}
+
+/// Fills in the scriptSig and witness of `to_sign`'s input so it spends `prevout`.
+fn satisfy(
+    to_sign: &mut Transaction,
+    prevout: &TxOut,
+    secret_key: &SecretKey,
+) -> Result<(), Error> {
+    let secp = context::secp256k1();
+    let pubkey = PublicKey::from_secret_key(secp, secret_key);
+    let pubkey_hash = hash::hash160(&pubkey.serialize());
+    let script_pubkey = &prevout.script_pubkey;
+
+    let sign_ecdsa = |hash: [u8; 32]| {
+        let msg = Message::from_slice(&hash).expect("hash is 32 bytes");
+        let mut signature = secp.sign_low_r(&msg, secret_key).serialize_der().to_vec();
+        signature.push(SIGHASH_ALL);
+        signature
+    };
+
+    let input = 0;
+    if *script_pubkey == p2pkh_script(&pubkey_hash) {
+        let hash = sighash::legacy(to_sign, input, script_pubkey.as_bytes(), SIGHASH_ALL as u32);
+        to_sign.inputs[input].script_sig = Builder::new()
+            .push_slice(&sign_ecdsa(hash))
+            .push_slice(&pubkey.serialize())
+            .into_script();
+    } else if *script_pubkey == p2wpkh_script(&pubkey_hash)
+        || *script_pubkey == p2wpkh_script(&pubkey_hash).to_p2sh()
+    {
+        let script_code = p2pkh_script(&pubkey_hash);
+        let hash = sighash::segwit_v0(
+            to_sign,
+            input,
+            script_code.as_bytes(),
+            prevout.value,
+            SIGHASH_ALL as u32,
+        )?;
+
+        if script_pubkey.is_p2sh() {
+            to_sign.inputs[input].script_sig = Builder::new()
+                .push_slice(p2wpkh_script(&pubkey_hash).as_bytes())
+                .into_script();
+        }
+        to_sign.inputs[input].witness = vec![sign_ecdsa(hash), pubkey.serialize().to_vec()];
+    } else if let Some((1, program)) = script_pubkey.witness_program() {
+        let mut keypair = KeyPair::from_secret_key(secp, *secret_key);
+        let internal_key = schnorrsig::PublicKey::from_keypair(secp, &keypair);
+        keypair.tweak_add_assign(
+            secp,
+            &interpreter::taptweak_hash(&internal_key.serialize(), None),
+        )?;
+
+        if schnorrsig::PublicKey::from_keypair(secp, &keypair).serialize()[..] != *program {
+            return Err(Error::KeyMismatch);
+        }
+
+        let hash = sighash::taproot(
+            to_sign,
+            input,
+            std::slice::from_ref(prevout),
+            SIGHASH_DEFAULT,
+            None,
+            None,
+        )?;
+        let msg = Message::from_slice(&hash).expect("hash is 32 bytes");
+        let signature = secp.schnorrsig_sign_no_aux_rand(&msg, &keypair);
+        to_sign.inputs[input].witness = vec![signature[..].to_vec()];
+    } else if script_pubkey.is_p2pkh()
+        || script_pubkey.is_p2sh()
+        || script_pubkey.witness_program().is_some()
+    {
+        return Err(Error::KeyMismatch);
+    } else {
+        return Err(Error::UnsupportedAddress);
+    }
+
+    Ok(())
+}
+
+fn encode_witness(witness: &[Vec<u8>]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    encode::write_compact_size(&mut buf, witness.len() as u64);
+    for item in witness {
+        encode::write_var_bytes(&mut buf, item);
+    }
+    buf
+}
+
+fn decode_witness(data: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+    let mut reader = Reader::new(data);
+    let count = reader.read_compact_size()?;
+
+    let witness = (0..count)
+        .map(|_| reader.read_var_bytes().map(|item| item.to_vec()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    reader.finish()?;
+    Ok(witness)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base58check::Base58CheckString;
+    use crate::wif::PrivateKey;
+    use std::convert::TryFrom;
+
+    // From BIP322.
+    const PRIVATE_KEY: &str = "L3VFeEujGtevx9w18HD1fhRbCH67Az2dpCymeRE1SoPK6XQtaN2k";
+    const ADDRESS: &str = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l";
+
+    fn secret_key() -> SecretKey {
+        let wif = Base58CheckString::try_from(PRIVATE_KEY.to_string()).unwrap();
+        PrivateKey::from_wif(&wif, Network::Mainnet).unwrap().key
+    }
+
+    #[test]
+    fn test_message_hash() {
+        assert_eq!(
+            hex::encode(message_hash(b"")),
+            "c90c269c4f8fcbe6880f72a721ddfbf1914268a794cbb21cfafee13770ae19f1"
+        );
+        assert_eq!(
+            hex::encode(message_hash(b"Hello World")),
+            "f0eb03b1a75ac6d9847f55c624a99169b5dccba2a31f5b23bea77ba270de0a7a"
+        );
+    }
+
+    #[test]
+    fn test_transaction_ids() {
+        let script_pubkey = address::to_script_pubkey(ADDRESS).unwrap();
+
+        let empty = to_spend(&script_pubkey, b"");
+        assert_eq!(
+            empty.txid().to_string(),
+            "c5680aa69bb8d860bf82d4e9cd3504b55dde018de765a91bb566283c545a99a7"
+        );
+        assert_eq!(
+            to_sign(&empty).txid().to_string(),
+            "1e9654e951a5ba44c8604c4de6c67fd78a27e81dcadcfe1edf638ba3aaebaed6"
+        );
+
+        let hello = to_spend(&script_pubkey, b"Hello World");
+        assert_eq!(
+            hello.txid().to_string(),
+            "b79d196740ad5217771c1098fc4a4b51e0535c32236c71f1ea4d61a2d603352b"
+        );
+        assert_eq!(
+            to_sign(&hello).txid().to_string(),
+            "88737ae86f2077145f93cc4b153ae9a1cb8d56afa511988c149c5c8c9d93bddf"
+        );
+    }
+
+    #[test]
+    fn test_bip322_simple_vectors() {
+        let empty = "AkcwRAIgM2gBAQqvZX15ZiysmKmQpDrG83avLIT492QBzLnQIxYCIBaTpOaD20qRlEylyxFSeEA2ba9YOixpX8z46TSDtS40ASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=";
+        let hello = "AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=";
+
+        assert_eq!(verify(ADDRESS, b"", empty), Ok(()));
+        assert_eq!(verify(ADDRESS, b"Hello World", hello), Ok(()));
+        assert!(matches!(
+            verify(ADDRESS, b"Hello World", empty),
+            Err(Error::Script(_))
+        ));
+
+        assert_eq!(
+            sign(&secret_key(), ADDRESS, b"Hello World", Format::Simple),
+            Ok(hello.to_string())
+        );
+    }
+
+    #[test]
+    fn test_sign_verify_roundtrip() {
+        let secp = context::secp256k1();
+        let secret_key = secret_key();
+        let pubkey = PublicKey::from_secret_key(secp, &secret_key);
+        let internal_key =
+            schnorrsig::PublicKey::from_keypair(secp, &KeyPair::from_secret_key(secp, secret_key));
+
+        let segwit = [address::p2wpkh(&pubkey), address::p2tr(&internal_key)];
+        let legacy = [
+            address::p2pkh(&pubkey).as_str().to_string(),
+            address::p2sh_p2wpkh(&pubkey).as_str().to_string(),
+        ];
+
+        for address in segwit.iter().chain(&legacy) {
+            let signature = sign(&secret_key, address, b"proof", Format::Full).unwrap();
+            assert_eq!(verify(address, b"proof", &signature), Ok(()));
+            assert!(verify(address, b"other", &signature).is_err());
+        }
+
+        for address in &segwit {
+            let signature = sign(&secret_key, address, b"proof", Format::Simple).unwrap();
+            assert_eq!(verify(address, b"proof", &signature), Ok(()));
+        }
+
+        for address in &legacy {
+            assert_eq!(
+                sign(&secret_key, address, b"proof", Format::Simple),
+                Err(Error::UnsupportedAddress)
+            );
+        }
+
+        let other_key = SecretKey::from_slice(&[0x01; 32]).unwrap();
+        assert_eq!(
+            sign(&other_key, ADDRESS, b"proof", Format::Simple),
+            Err(Error::KeyMismatch)
+        );
+    }
+
+    #[test]
+    fn test_testnet_roundtrip() {
+        let secp = context::secp256k1();
+        let secret_key = secret_key();
+        let pubkey = PublicKey::from_secret_key(secp, &secret_key);
+        let script_pubkey = address::to_script_pubkey(ADDRESS).unwrap();
+        let testnet = address::from_script_pubkey_on(&script_pubkey, Network::Testnet).unwrap();
+        assert_eq!(
+            address::to_script_pubkey_on(&testnet, Network::Testnet),
+            Ok(address::ScriptType::P2wpkh.script_pubkey(&pubkey))
+        );
+
+        let signature = sign_on(
+            &secret_key,
+            &testnet,
+            b"proof",
+            Format::Simple,
+            Network::Testnet,
+        )
+        .unwrap();
+        assert_eq!(
+            signature,
+            sign(&secret_key, ADDRESS, b"proof", Format::Simple).unwrap()
+        );
+        assert_eq!(
+            verify_on(&testnet, b"proof", &signature, Network::Testnet),
+            Ok(())
+        );
+        assert!(matches!(
+            verify(&testnet, b"proof", &signature),
+            Err(Error::Address(_))
+        ));
+    }
+}
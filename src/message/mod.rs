@@ -0,0 +1,311 @@
+//! Signed messages proving control of an address: the legacy "Bitcoin Signed Message" format
+//! (BIP137) and its generalisation to any script (BIP322).
+
+pub mod bip322;
+
+use crate::address;
+use crate::context;
+use crate::encode;
+use crate::hash;
+use crate::network::Network;
+use crate::script::{interpreter, opcodes, Builder, Script};
+use crate::transaction::sighash;
+use secp256k1::recovery::{RecoverableSignature, RecoveryId};
+use secp256k1::{Message, PublicKey, SecretKey};
+
+pub const MESSAGE_MAGIC: &str = "Bitcoin Signed Message:\n";
+
+/// Error represents all of the possible errors that can arise while signing or verifying a
+/// message.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    Address(address::Error),
+    Base64(base64::DecodeError),
+    Encode(encode::Error),
+    Script(interpreter::Error),
+    Secp256k1(secp256k1::Error),
+    Sighash(sighash::Error),
+    InvalidSignatureLength(usize),
+    InvalidHeader(u8),
+    /// The signing key doesn't control the address.
+    KeyMismatch,
+    UnsupportedAddress,
+    /// BIP322 proofs of funds, which spend additional inputs, aren't supported.
+    UnsupportedProof,
+    InvalidToSign,
+    /// The signature is valid, but not for this message and address.
+    NotSigned,
+}
+
+impl From<address::Error> for Error {
+    fn from(err: address::Error) -> Error {
+        Error::Address(err)
+    }
+}
+
+impl From<base64::DecodeError> for Error {
+    fn from(err: base64::DecodeError) -> Error {
+        Error::Base64(err)
+    }
+}
+
+impl From<encode::Error> for Error {
+    fn from(err: encode::Error) -> Error {
+        Error::Encode(err)
+    }
+}
+
+impl From<interpreter::Error> for Error {
+    fn from(err: interpreter::Error) -> Error {
+        Error::Script(err)
+    }
+}
+
+impl From<secp256k1::Error> for Error {
+    fn from(err: secp256k1::Error) -> Error {
+        Error::Secp256k1(err)
+    }
+}
+
+impl From<sighash::Error> for Error {
+    fn from(err: sighash::Error) -> Error {
+        Error::Sighash(err)
+    }
+}
+
+/// The address types a BIP137 signature's header byte can commit to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AddressType {
+    P2pkhUncompressed,
+    P2pkh,
+    P2shP2wpkh,
+    P2wpkh,
+}
+
+impl AddressType {
+    fn header_base(self) -> u8 {
+        match self {
+            AddressType::P2pkhUncompressed => 27,
+            AddressType::P2pkh => 31,
+            AddressType::P2shP2wpkh => 35,
+            AddressType::P2wpkh => 39,
+        }
+    }
+
+    fn from_header(header: u8) -> Option<AddressType> {
+        match header {
+            27..=30 => Some(AddressType::P2pkhUncompressed),
+            31..=34 => Some(AddressType::P2pkh),
+            35..=38 => Some(AddressType::P2shP2wpkh),
+            39..=42 => Some(AddressType::P2wpkh),
+            _ => None,
+        }
+    }
+
+    /// Returns the output script of this type paying to `pubkey`.
+    fn script_pubkey(self, pubkey: &PublicKey) -> Script {
+        let pubkey_hash = match self {
+            AddressType::P2pkhUncompressed => hash::hash160(&pubkey.serialize_uncompressed()),
+            _ => hash::hash160(&pubkey.serialize()),
+        };
+
+        match self {
+            AddressType::P2pkhUncompressed | AddressType::P2pkh => p2pkh_script(&pubkey_hash),
+            AddressType::P2shP2wpkh => p2wpkh_script(&pubkey_hash).to_p2sh(),
+            AddressType::P2wpkh => p2wpkh_script(&pubkey_hash),
+        }
+    }
+}
+
+/// Returns the hash signed by a BIP137 signature over `message`.
+pub fn message_hash(message: &[u8]) -> [u8; 32] {
+    let mut data = Vec::new();
+    encode::write_var_bytes(&mut data, MESSAGE_MAGIC.as_bytes());
+    encode::write_var_bytes(&mut data, message);
+    hash::double_sha256(&data)
+}
+
+/// Signs `message` with `secret_key`, returning the base64 signature for the address of type
+/// `address_type` controlled by the key.
+pub fn sign(secret_key: &SecretKey, message: &[u8], address_type: AddressType) -> String {
+    let secp = context::secp256k1();
+    let msg = Message::from_slice(&message_hash(message)).expect("hash is 32 bytes");
+
+    let (recovery_id, signature) = secp.sign_recoverable(&msg, secret_key).serialize_compact();
+
+    let mut data = vec![address_type.header_base() + recovery_id.to_i32() as u8];
+    data.extend(&signature[..]);
+    base64::encode(&data)
+}
+
+/// Verifies a base64 BIP137 signature over `message` by the key controlling the mainnet
+/// `address`.
+pub fn verify(address: &str, signature: &str, message: &[u8]) -> Result<(), Error> {
+    verify_on(address, signature, message, Network::Mainnet)
+}
+
+/// Verifies a base64 BIP137 signature over `message` by the key controlling `address` on
+/// `network`.
+pub fn verify_on(
+    address: &str,
+    signature: &str,
+    message: &[u8],
+    network: Network,
+) -> Result<(), Error> {
+    let script_pubkey = address::to_script_pubkey_on(address, network)?;
+
+    let data = base64::decode(signature)?;
+    if data.len() != 65 {
+        return Err(Error::InvalidSignatureLength(data.len()));
+    }
+
+    let address_type = AddressType::from_header(data[0]).ok_or(Error::InvalidHeader(data[0]))?;
+    let recovery_id = RecoveryId::from_i32(((data[0] - 27) % 4) as i32)?;
+    let signature = RecoverableSignature::from_compact(&data[1..], recovery_id)?;
+
+    let secp = context::secp256k1();
+    let msg = Message::from_slice(&message_hash(message)).expect("hash is 32 bytes");
+    let pubkey = secp.recover(&msg, &signature)?;
+
+    if address_type.script_pubkey(&pubkey) != script_pubkey {
+        return Err(Error::NotSigned);
+    }
+
+    Ok(())
+}
+
+fn p2pkh_script(pubkey_hash: &[u8]) -> Script {
+    Builder::new()
+        .push_opcode(opcodes::OP_DUP)
+        .push_opcode(opcodes::OP_HASH160)
+        .push_slice(pubkey_hash)
+        .push_opcode(opcodes::OP_EQUALVERIFY)
+        .push_opcode(opcodes::OP_CHECKSIG)
+        .into_script()
+}
+
+fn p2wpkh_script(pubkey_hash: &[u8]) -> Script {
+    Builder::new()
+        .push_opcode(opcodes::OP_0)
+        .push_slice(pubkey_hash)
+        .into_script()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // From Bitcoin Core's util_tests.
+    const CORE_PRIVATE_KEY: &str =
+        "d97f5108f11cda6eeebaaa420fef0726b1f898060b98489fa3098463c0032866";
+    const CORE_ADDRESS: &str = "15CRxFdyRpGZLW9w8HnHvVduizdL5jKNbs";
+    const CORE_SIGNATURE: &str =
+        "IPojfrX2dfPnH26UegfbGQQLrdK844DlHq5157/P6h57WyuS/Qsl+h/WSVGDF4MUi4rWSswW38oimDYfNNUBUOk=";
+
+    #[test]
+    fn test_bitcoin_core_sign() {
+        let secret_key = SecretKey::from_slice(&hex::decode(CORE_PRIVATE_KEY).unwrap()).unwrap();
+        assert_eq!(
+            sign(&secret_key, b"Trust no one", AddressType::P2pkh),
+            CORE_SIGNATURE
+        );
+    }
+
+    #[test]
+    fn test_bitcoin_core_verify() {
+        assert_eq!(
+            verify(CORE_ADDRESS, CORE_SIGNATURE, b"Trust no one"),
+            Ok(())
+        );
+        assert_eq!(
+            verify(CORE_ADDRESS, CORE_SIGNATURE, b"I never signed this"),
+            Err(Error::NotSigned)
+        );
+        assert_eq!(
+            verify(
+                "11canuhp9X2NocwCq7xNrQYTmUgZAnLK3",
+                "IIcaIENoYW5jZWxsb3Igb24gYnJpbmsgb2Ygc2Vjb25kIGJhaWxvdXQgZm9yIGJhbmtzIAaHRtbCeDZINyavx14=",
+                b"Trust me"
+            ),
+            Ok(())
+        );
+
+        assert!(matches!(
+            verify(CORE_ADDRESS, "not base64!", b"Trust no one"),
+            Err(Error::Base64(_))
+        ));
+        assert!(verify(
+            CORE_ADDRESS,
+            "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=",
+            b"Trust no one"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_segwit_address_types() {
+        let secp = context::secp256k1();
+        let secret_key = SecretKey::from_slice(&hex::decode(CORE_PRIVATE_KEY).unwrap()).unwrap();
+        let pubkey = PublicKey::from_secret_key(secp, &secret_key);
+
+        let addresses = [
+            (
+                AddressType::P2pkh,
+                address::p2pkh(&pubkey).as_str().to_string(),
+            ),
+            (
+                AddressType::P2shP2wpkh,
+                address::p2sh_p2wpkh(&pubkey).as_str().to_string(),
+            ),
+            (AddressType::P2wpkh, address::p2wpkh(&pubkey)),
+        ];
+
+        for (address_type, address) in &addresses {
+            let signature = sign(&secret_key, b"message", *address_type);
+            assert_eq!(verify(address, &signature, b"message"), Ok(()));
+
+            // The header commits to the address type.
+            for (other_type, other_address) in &addresses {
+                if other_type != address_type {
+                    assert_eq!(
+                        verify(other_address, &signature, b"message"),
+                        Err(Error::NotSigned)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_other_networks() {
+        let secp = context::secp256k1();
+        let secret_key = SecretKey::from_slice(&hex::decode(CORE_PRIVATE_KEY).unwrap()).unwrap();
+        let pubkey = PublicKey::from_secret_key(secp, &secret_key);
+
+        for network in &[Network::Testnet, Network::Litecoin] {
+            for address_type in &[
+                AddressType::P2pkh,
+                AddressType::P2shP2wpkh,
+                AddressType::P2wpkh,
+            ] {
+                let address =
+                    address::from_script_pubkey_on(&address_type.script_pubkey(&pubkey), *network)
+                        .unwrap();
+                let signature = sign(&secret_key, b"message", *address_type);
+
+                assert_eq!(
+                    verify_on(&address, &signature, b"message", *network),
+                    Ok(())
+                );
+                assert_eq!(
+                    verify_on(&address, &signature, b"other", *network),
+                    Err(Error::NotSigned)
+                );
+                assert!(matches!(
+                    verify(&address, &signature, b"message"),
+                    Err(Error::Address(_))
+                ));
+            }
+        }
+    }
+}
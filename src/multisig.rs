@@ -0,0 +1,827 @@
+//! m-of-n multisig wallets built from BIP32 extended public keys, with BIP67 key sorting and
+//! BIP48 derivation paths.
+
+use crate::address;
+use crate::bip32::{self, ChildNumber, DerivationPath, ExtendedPublicKey, Fingerprint};
+use crate::network::Network;
+use crate::psbt;
+use crate::script::{interpreter, opcodes, Builder, Instruction, Script};
+use secp256k1::PublicKey;
+
+/// Most keys a P2SH multisig may have while keeping its redeem script within 520 bytes.
+pub const MAX_P2SH_PUBKEYS: usize = 15;
+
+/// Most keys `OP_CHECKMULTISIG` accepts.
+pub const MAX_PUBKEYS: usize = 20;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ScriptType {
+    P2sh,
+    P2shP2wsh,
+    P2wsh,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    Bip32(bip32::Error),
+    InvalidThreshold { threshold: usize, keys: usize },
+    TooManyKeys(usize),
+    DuplicateKey(PublicKey),
+    NoBip48ScriptType(ScriptType),
+    NetworkMismatch { expected: Network, actual: Network },
+    UnsupportedScriptType(ScriptType, Network),
+}
+
+impl From<bip32::Error> for Error {
+    fn from(err: bip32::Error) -> Error {
+        Error::Bip32(err)
+    }
+}
+
+impl ScriptType {
+    /// Returns the BIP48 account path `m/48'/coin_type'/account'/script_type'`, with the coin
+    /// type of `network`.
+    ///
+    /// BIP48 only assigns script types to the segwit variants.
+    pub fn bip48_path(self, network: Network, account: u32) -> Result<DerivationPath, Error> {
+        let script_type = match self {
+            ScriptType::P2shP2wsh => 1,
+            ScriptType::P2wsh => 2,
+            ScriptType::P2sh => return Err(Error::NoBip48ScriptType(self)),
+        };
+
+        Ok(DerivationPath::from(vec![
+            ChildNumber::Hardened(48),
+            ChildNumber::Hardened(network.coin_type()),
+            ChildNumber::Hardened(account),
+            ChildNumber::Hardened(script_type),
+        ]))
+    }
+
+    /// Returns whether `network` has addresses of this type, which for the segwit types means
+    /// it has segwit.
+    pub fn is_supported(self, network: Network) -> bool {
+        self == ScriptType::P2sh || network.bech32_hrp().is_some()
+    }
+
+    fn max_pubkeys(self) -> usize {
+        match self {
+            ScriptType::P2sh => MAX_P2SH_PUBKEYS,
+            _ => MAX_PUBKEYS,
+        }
+    }
+}
+
+/// An account-level extended public key contributed by one participant, along with where it
+/// was derived from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cosigner {
+    pub xpub: ExtendedPublicKey,
+    pub master_fingerprint: Fingerprint,
+    pub account_path: DerivationPath,
+}
+
+impl Cosigner {
+    /// Takes the cosigner's key origin from `xpub`, or returns `None` if it's unknown.
+    pub fn from_origin(xpub: ExtendedPublicKey) -> Option<Cosigner> {
+        let (master_fingerprint, account_path) = xpub.origin.clone()?;
+        Some(Cosigner {
+            xpub,
+            master_fingerprint,
+            account_path,
+        })
+    }
+}
+
+impl From<ExtendedPublicKey> for Cosigner {
+    /// Treats `xpub` as its own master key, for participants who don't disclose key origins.
+    fn from(xpub: ExtendedPublicKey) -> Cosigner {
+        Cosigner {
+            master_fingerprint: xpub.fingerprint(),
+            account_path: DerivationPath::default(),
+            xpub,
+        }
+    }
+}
+
+/// A wallet requiring `threshold` of its cosigners' keys to spend, on the network of their
+/// keys.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MultisigWallet {
+    threshold: usize,
+    cosigners: Vec<Cosigner>,
+    script_type: ScriptType,
+    network: Network,
+}
+
+/// The scripts and address of one multisig output.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MultisigAddress {
+    /// Public keys in BIP67 order, as they appear in the script.
+    pub pubkeys: Vec<PublicKey>,
+    pub redeem_script: Option<Script>,
+    pub witness_script: Option<Script>,
+    pub script_pubkey: Script,
+    pub address: String,
+}
+
+/// Sorts public keys lexicographically by their compressed encoding, as specified by BIP67.
+pub fn sort_pubkeys(pubkeys: &mut [PublicKey]) {
+    pubkeys.sort_by_key(|pubkey| pubkey.serialize());
+}
+
+/// Builds the BIP67 `threshold <pubkeys...> n OP_CHECKMULTISIG` script for `pubkeys`.
+pub fn multisig_script(threshold: usize, pubkeys: &[PublicKey]) -> Result<Script, Error> {
+    if threshold == 0 || threshold > pubkeys.len() {
+        return Err(Error::InvalidThreshold {
+            threshold,
+            keys: pubkeys.len(),
+        });
+    }
+
+    if pubkeys.len() > MAX_PUBKEYS {
+        return Err(Error::TooManyKeys(pubkeys.len()));
+    }
+
+    let mut pubkeys = pubkeys.to_vec();
+    sort_pubkeys(&mut pubkeys);
+
+    if let Some(pair) = pubkeys.windows(2).find(|pair| pair[0] == pair[1]) {
+        return Err(Error::DuplicateKey(pair[0]));
+    }
+
+    let builder = pubkeys.iter().fold(
+        Builder::new().push_int(threshold as i64),
+        |builder, pubkey| builder.push_slice(&pubkey.serialize()),
+    );
+
+    Ok(builder
+        .push_int(pubkeys.len() as i64)
+        .push_opcode(opcodes::OP_CHECKMULTISIG)
+        .into_script())
+}
+
+/// Parses a `threshold <pubkeys...> n OP_CHECKMULTISIG` script, returning the threshold and the
+/// public keys in script order.
+pub fn parse_multisig_script(script: &Script) -> Option<(usize, Vec<PublicKey>)> {
+    let instructions = script.instructions().collect::<Result<Vec<_>, _>>().ok()?;
+
+    let (first, rest) = instructions.split_first()?;
+    let (last, rest) = rest.split_last()?;
+    let (count, keys) = rest.split_last()?;
+
+    let threshold = parse_count(first)?;
+    if last.opcode != opcodes::OP_CHECKMULTISIG || parse_count(count)? != keys.len() {
+        return None;
+    }
+
+    let pubkeys = keys
+        .iter()
+        .map(|ins| PublicKey::from_slice(ins.data).ok())
+        .collect::<Option<Vec<_>>>()?;
+
+    if threshold > pubkeys.len() {
+        return None;
+    }
+
+    Some((threshold, pubkeys))
+}
+
+/// Parses a key count or threshold, pushed as `multisig_script` does: with `OP_1` to `OP_16`, or
+/// as a minimally encoded number above 16.
+fn parse_count(ins: &Instruction) -> Option<usize> {
+    match ins.opcode {
+        opcodes::OP_1..=opcodes::OP_16 => Some((ins.opcode - opcodes::OP_1 + 1) as usize),
+        len if len as usize == ins.data.len() && !ins.data.is_empty() => {
+            let n = interpreter::decode_num(ins.data, true, 4).ok()?;
+            Some(n as usize).filter(|n| (17..=MAX_PUBKEYS).contains(n))
+        }
+        _ => None,
+    }
+}
+
+impl MultisigWallet {
+    pub fn new(
+        threshold: usize,
+        cosigners: Vec<Cosigner>,
+        script_type: ScriptType,
+    ) -> Result<MultisigWallet, Error> {
+        if cosigners.len() > script_type.max_pubkeys() {
+            return Err(Error::TooManyKeys(cosigners.len()));
+        }
+
+        if threshold == 0 || threshold > cosigners.len() {
+            return Err(Error::InvalidThreshold {
+                threshold,
+                keys: cosigners.len(),
+            });
+        }
+
+        let network = cosigners[0].xpub.network;
+        if let Some(cosigner) = cosigners
+            .iter()
+            .find(|cosigner| cosigner.xpub.network != network)
+        {
+            return Err(Error::NetworkMismatch {
+                expected: network,
+                actual: cosigner.xpub.network,
+            });
+        }
+
+        if !script_type.is_supported(network) {
+            return Err(Error::UnsupportedScriptType(script_type, network));
+        }
+
+        for (i, cosigner) in cosigners.iter().enumerate() {
+            if cosigners[..i]
+                .iter()
+                .any(|other| other.xpub.public_key == cosigner.xpub.public_key)
+            {
+                return Err(Error::DuplicateKey(cosigner.xpub.public_key));
+            }
+        }
+
+        Ok(MultisigWallet {
+            threshold,
+            cosigners,
+            script_type,
+            network,
+        })
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    pub fn cosigners(&self) -> &[Cosigner] {
+        &self.cosigners
+    }
+
+    pub fn script_type(&self) -> ScriptType {
+        self.script_type
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// Derives the receive (or, if `change`, change) address at `index` on the wallet's network,
+    /// following the BIP48 `.../change/index` layout below each cosigner's account key.
+    pub fn derive(&self, change: bool, index: u32) -> Result<MultisigAddress, Error> {
+        let mut pubkeys = self
+            .cosigners
+            .iter()
+            .map(|cosigner| {
+                cosigner
+                    .xpub
+                    .derive_public_path(&child_path(change, index))
+                    .map(|xpub| xpub.public_key)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        sort_pubkeys(&mut pubkeys);
+
+        let script = multisig_script(self.threshold, &pubkeys)?;
+
+        let (redeem_script, witness_script, script_pubkey) = match self.script_type {
+            ScriptType::P2sh => (Some(script.clone()), None, script.to_p2sh()),
+            ScriptType::P2shP2wsh => (
+                Some(script.to_p2wsh()),
+                Some(script.clone()),
+                script.to_p2wsh().to_p2sh(),
+            ),
+            ScriptType::P2wsh => (None, Some(script.clone()), script.to_p2wsh()),
+        };
+        let address = address::from_script_pubkey_on(&script_pubkey, self.network)
+            .expect("supported script types have addresses");
+
+        Ok(MultisigAddress {
+            pubkeys,
+            redeem_script,
+            witness_script,
+            script_pubkey,
+            address,
+        })
+    }
+
+    /// Adds the scripts and key derivations a cosigner needs to sign a PSBT input spending the
+    /// address at (`change`, `index`).
+    pub fn update_psbt_input(
+        &self,
+        input: &mut psbt::Input,
+        change: bool,
+        index: u32,
+    ) -> Result<(), Error> {
+        let derived = self.derive(change, index)?;
+        input.redeem_script = derived.redeem_script;
+        input.witness_script = derived.witness_script;
+        input
+            .bip32_derivation
+            .extend(self.key_sources(change, index)?);
+        Ok(())
+    }
+
+    /// Adds the scripts and key derivations identifying a PSBT output as paying to the address
+    /// at (`change`, `index`).
+    pub fn update_psbt_output(
+        &self,
+        output: &mut psbt::Output,
+        change: bool,
+        index: u32,
+    ) -> Result<(), Error> {
+        let derived = self.derive(change, index)?;
+        output.redeem_script = derived.redeem_script;
+        output.witness_script = derived.witness_script;
+        output
+            .bip32_derivation
+            .extend(self.key_sources(change, index)?);
+        Ok(())
+    }
+
+    fn key_sources(
+        &self,
+        change: bool,
+        index: u32,
+    ) -> Result<Vec<(PublicKey, psbt::KeySource)>, Error> {
+        let path = child_path(change, index);
+
+        self.cosigners
+            .iter()
+            .map(|cosigner| {
+                let pubkey = cosigner.xpub.derive_public_path(&path)?.public_key;
+                let source = (
+                    cosigner.master_fingerprint,
+                    cosigner.account_path.extend(&path),
+                );
+                Ok((pubkey, source))
+            })
+            .collect()
+    }
+}
+
+fn child_path(change: bool, index: u32) -> [ChildNumber; 2] {
+    [
+        ChildNumber::Normal(change as u32),
+        ChildNumber::Normal(index),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base58check::Base58CheckString;
+    use crate::bip32::ExtendedPrivateKey;
+    use crate::psbt::Psbt;
+    use crate::script::interpreter::{self, VerifyFlags};
+    use crate::transaction::sighash;
+    use crate::transaction::{OutPoint, Transaction, TxIn, TxOut};
+    use std::convert::TryFrom;
+
+    const MASTER_XPRV: &str = "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi";
+
+    /// Returns three unrelated signing keys and the matching 2-of-3 wallet.
+    fn wallet(script_type: ScriptType) -> (Vec<ExtendedPrivateKey>, MultisigWallet) {
+        wallet_on(script_type, Network::Mainnet)
+    }
+
+    fn wallet_on(
+        script_type: ScriptType,
+        network: Network,
+    ) -> (Vec<ExtendedPrivateKey>, MultisigWallet) {
+        wallet_of(3, script_type, network)
+    }
+
+    /// Returns `keys` unrelated signing keys and the matching 2-of-`keys` wallet.
+    fn wallet_of(
+        keys: u32,
+        script_type: ScriptType,
+        network: Network,
+    ) -> (Vec<ExtendedPrivateKey>, MultisigWallet) {
+        let master = Base58CheckString::try_from(MASTER_XPRV.to_string()).unwrap();
+        let mut master = ExtendedPrivateKey::try_from(&master).unwrap();
+        master.network = network;
+        let account_path = ScriptType::P2wsh.bip48_path(network, 0).unwrap();
+
+        // Each signer stands in for a separate master key.
+        let signers = (0..keys)
+            .map(|i| {
+                let mut signer = master.derive_private(ChildNumber::Hardened(i)).unwrap();
+                signer.origin = Some((signer.fingerprint(), DerivationPath::default()));
+                signer
+            })
+            .collect::<Vec<_>>();
+
+        let cosigners = signers
+            .iter()
+            .map(|signer| {
+                let account = signer.derive_private_path(account_path.as_ref()).unwrap();
+                let xpub = ExtendedPublicKey::from(&account);
+                assert_eq!(
+                    Cosigner::from(xpub.clone()).account_path,
+                    DerivationPath::default()
+                );
+
+                let cosigner = Cosigner::from_origin(xpub).unwrap();
+                assert_eq!(cosigner.master_fingerprint, signer.fingerprint());
+                assert_eq!(cosigner.account_path, account_path);
+                cosigner
+            })
+            .collect();
+
+        (
+            signers,
+            MultisigWallet::new(2, cosigners, script_type).unwrap(),
+        )
+    }
+
+    /// Builds a PSBT spending a single output paid to the wallet's first receive address.
+    fn spending_psbt(wallet: &MultisigWallet) -> (Psbt, TxOut) {
+        let derived = wallet.derive(false, 0).unwrap();
+        let prevout = TxOut {
+            value: 100_000,
+            script_pubkey: derived.script_pubkey,
+        };
+
+        let tx = Transaction {
+            version: 2,
+            inputs: vec![TxIn {
+                previous_output: OutPoint {
+                    txid: [0x11; 32].into(),
+                    vout: 0,
+                },
+                script_sig: Script::new(),
+                sequence: 0xffff_fffd,
+                witness: Vec::new(),
+            }],
+            outputs: vec![TxOut {
+                value: 90_000,
+                script_pubkey: wallet.derive(true, 0).unwrap().script_pubkey,
+            }],
+            lock_time: 0,
+        };
+
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(prevout.clone());
+        wallet
+            .update_psbt_input(&mut psbt.inputs[0], false, 0)
+            .unwrap();
+        wallet
+            .update_psbt_output(&mut psbt.outputs[0], true, 0)
+            .unwrap();
+
+        (psbt, prevout)
+    }
+
+    #[test]
+    fn test_bip48_path() {
+        assert_eq!(
+            ScriptType::P2wsh
+                .bip48_path(Network::Mainnet, 0)
+                .unwrap()
+                .to_string(),
+            "m/48'/0'/0'/2'"
+        );
+        assert_eq!(
+            ScriptType::P2shP2wsh
+                .bip48_path(Network::Mainnet, 3)
+                .unwrap()
+                .to_string(),
+            "m/48'/0'/3'/1'"
+        );
+        assert_eq!(
+            ScriptType::P2wsh
+                .bip48_path(Network::Testnet, 0)
+                .unwrap()
+                .to_string(),
+            "m/48'/1'/0'/2'"
+        );
+        assert_eq!(
+            ScriptType::P2wsh
+                .bip48_path(Network::Litecoin, 0)
+                .unwrap()
+                .to_string(),
+            "m/48'/2'/0'/2'"
+        );
+        assert_eq!(
+            ScriptType::P2sh.bip48_path(Network::Mainnet, 0),
+            Err(Error::NoBip48ScriptType(ScriptType::P2sh))
+        );
+    }
+
+    #[test]
+    fn test_multisig_script_sorts_keys() {
+        let a = PublicKey::from_slice(
+            &hex::decode("02fe6f0a5a297eb38c391581c4413e084773ea23954d93f7753db7dc0adc188b2f")
+                .unwrap(),
+        )
+        .unwrap();
+        let b = PublicKey::from_slice(
+            &hex::decode("02ff12471208c14bd580709cb2358d98975247d8765f92bc25eab3b2763ed605f8")
+                .unwrap(),
+        )
+        .unwrap();
+
+        let script = multisig_script(2, &[b, a]).unwrap();
+        assert_eq!(script, multisig_script(2, &[a, b]).unwrap());
+        assert_eq!(parse_multisig_script(&script), Some((2, vec![a, b])));
+
+        assert_eq!(
+            multisig_script(3, &[a, b]),
+            Err(Error::InvalidThreshold {
+                threshold: 3,
+                keys: 2
+            })
+        );
+        assert_eq!(multisig_script(1, &[a, a]), Err(Error::DuplicateKey(a)));
+    }
+
+    #[test]
+    fn test_parse_large_multisig_script() {
+        let secp = crate::context::secp256k1();
+        let pubkeys = (1..=MAX_PUBKEYS as u8)
+            .map(|i| {
+                let key = secp256k1::SecretKey::from_slice(&[i; 32]).unwrap();
+                PublicKey::from_secret_key(secp, &key)
+            })
+            .collect::<Vec<_>>();
+        let mut sorted = pubkeys.clone();
+        sort_pubkeys(&mut sorted);
+
+        for (threshold, n) in &[(2, 16), (2, 17), (17, 17), (20, 20)] {
+            let script = multisig_script(*threshold, &pubkeys[..*n]).unwrap();
+            let (parsed_threshold, parsed) = parse_multisig_script(&script).unwrap();
+            assert_eq!(parsed_threshold, *threshold);
+            assert_eq!(parsed.len(), *n);
+        }
+        let script = multisig_script(20, &pubkeys).unwrap();
+        assert_eq!(parse_multisig_script(&script), Some((20, sorted)));
+
+        // Counts of 16 or less must use the small integer opcodes.
+        let script = Builder::new()
+            .push_opcode(opcodes::OP_1)
+            .push_slice(&pubkeys[0].serialize())
+            .push_slice(&[1])
+            .push_opcode(opcodes::OP_CHECKMULTISIG)
+            .into_script();
+        assert_eq!(parse_multisig_script(&script), None);
+    }
+
+    #[test]
+    fn test_bip67_vectors() {
+        let vectors: &[(&[&str], &str)] = &[
+            (
+                &[
+                    "02ff12471208c14bd580709cb2358d98975247d8765f92bc25eab3b2763ed605f8",
+                    "02fe6f0a5a297eb38c391581c4413e084773ea23954d93f7753db7dc0adc188b2f",
+                ],
+                "39bgKC7RFbpoCRbtD5KEdkYKtNyhpsNa3Z",
+            ),
+            (
+                &[
+                    "02632b12f4ac5b1d1b72b2a3b508c19172de44f6f46bcee50ba33f3f9291e47ed0",
+                    "027735a29bae7780a9755fae7a1c4374c656ac6a69ea9f3697fda61bb99a4f3e77",
+                    "02e2cc6bd5f45edd43bebe7cb9b675f0ce9ed3efe613b177588290ad188d11b404",
+                ],
+                "3CKHTjBKxCARLzwABMu9yD85kvtm7WnMfH",
+            ),
+            (
+                &[
+                    "030000000000000000000000000000000000004141414141414141414141414141",
+                    "020000000000000000000000000000000000004141414141414141414141414141",
+                    "020000000000000000000000000000000000004141414141414141414141414140",
+                    "030000000000000000000000000000000000004141414141414141414141414140",
+                ],
+                "32V85igBri9zcfBRVupVvwK18NFtS37FuD",
+            ),
+            (
+                &[
+                    "022df8750480ad5b26950b25c7ba79d3e37d75f640f8e5d9bcd5b150a0f85014da",
+                    "03e3818b65bcc73a7d64064106a859cc1a5a728c4345ff0b641209fba0d90de6e9",
+                    "021f2f6e1e50cb6a953935c3601284925decd3fd21bc445712576873fb8c6ebc18",
+                ],
+                "3Q4sF6tv9wsdqu2NtARzNCpQgwifm2rAba",
+            ),
+        ];
+
+        for (pubkeys, expected) in vectors {
+            let pubkeys = pubkeys
+                .iter()
+                .map(|pubkey| PublicKey::from_slice(&hex::decode(pubkey).unwrap()).unwrap())
+                .collect::<Vec<_>>();
+            let script = multisig_script(2, &pubkeys).unwrap();
+            assert_eq!(address::p2sh(&script).as_str(), *expected);
+        }
+    }
+
+    #[test]
+    fn test_cosigner_origin() {
+        let (_, wallet) = wallet(ScriptType::P2wsh);
+        let xpub = wallet.cosigners[0].xpub.clone();
+
+        // Serialized account keys don't carry their origin.
+        let decoded = ExtendedPublicKey::try_from(&Base58CheckString::from(&xpub)).unwrap();
+        assert_eq!(Cosigner::from_origin(decoded.clone()), None);
+
+        let cosigner = Cosigner::from(decoded);
+        assert_eq!(cosigner.master_fingerprint, xpub.fingerprint());
+        assert_eq!(cosigner.account_path, DerivationPath::default());
+    }
+
+    #[test]
+    fn test_derive_addresses() {
+        for script_type in &[ScriptType::P2sh, ScriptType::P2shP2wsh, ScriptType::P2wsh] {
+            let (_, wallet) = wallet(*script_type);
+            let derived = wallet.derive(false, 0).unwrap();
+
+            let script = derived
+                .witness_script
+                .clone()
+                .or_else(|| derived.redeem_script.clone())
+                .unwrap();
+            assert_eq!(
+                parse_multisig_script(&script),
+                Some((2, derived.pubkeys.clone()))
+            );
+
+            let mut sorted = derived.pubkeys.clone();
+            sort_pubkeys(&mut sorted);
+            assert_eq!(sorted, derived.pubkeys);
+
+            assert_eq!(
+                address::to_script_pubkey(&derived.address),
+                Ok(derived.script_pubkey.clone())
+            );
+            assert_ne!(derived, wallet.derive(true, 0).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_testnet_addresses() {
+        for (script_type, prefix) in &[
+            (ScriptType::P2sh, "2"),
+            (ScriptType::P2shP2wsh, "2"),
+            (ScriptType::P2wsh, "tb1q"),
+        ] {
+            let (_, testnet) = wallet_on(*script_type, Network::Testnet);
+            assert_eq!(testnet.network(), Network::Testnet);
+            assert_eq!(
+                testnet.cosigners[0].account_path,
+                ScriptType::P2wsh.bip48_path(Network::Testnet, 0).unwrap()
+            );
+
+            let derived = testnet.derive(false, 0).unwrap();
+            assert!(derived.address.starts_with(prefix), "{}", derived.address);
+            assert_eq!(
+                address::to_script_pubkey_on(&derived.address, Network::Testnet),
+                Ok(derived.script_pubkey.clone())
+            );
+        }
+    }
+
+    #[test]
+    fn test_network_mismatch() {
+        let (_, wallet) = wallet(ScriptType::P2wsh);
+        let mut cosigners = wallet.cosigners.clone();
+        cosigners[2].xpub.network = Network::Testnet;
+        assert_eq!(
+            MultisigWallet::new(2, cosigners, ScriptType::P2wsh),
+            Err(Error::NetworkMismatch {
+                expected: Network::Mainnet,
+                actual: Network::Testnet
+            })
+        );
+
+        let mut cosigners = wallet.cosigners.clone();
+        for cosigner in &mut cosigners {
+            cosigner.xpub.network = Network::BitcoinCash;
+        }
+        assert_eq!(
+            MultisigWallet::new(2, cosigners.clone(), ScriptType::P2wsh),
+            Err(Error::UnsupportedScriptType(
+                ScriptType::P2wsh,
+                Network::BitcoinCash
+            ))
+        );
+        let wallet = MultisigWallet::new(2, cosigners, ScriptType::P2sh).unwrap();
+        assert!(wallet
+            .derive(false, 0)
+            .unwrap()
+            .address
+            .starts_with("bitcoincash:p"));
+    }
+
+    #[test]
+    fn test_cosigners_sign_psbt() {
+        for script_type in &[ScriptType::P2sh, ScriptType::P2shP2wsh, ScriptType::P2wsh] {
+            let (signers, wallet) = wallet(*script_type);
+            let (psbt, prevout) = spending_psbt(&wallet);
+            let psbt = psbt.to_string().parse::<Psbt>().unwrap();
+
+            // Cosigners sign independently, and their PSBTs are then combined.
+            let mut first = psbt.clone();
+            assert_eq!(first.sign(&signers[0]), Ok(1));
+            let mut second = psbt.clone();
+            assert_eq!(second.sign(&signers[2]), Ok(1));
+
+            // An account key signs too, since its origin locates it among the derivations.
+            let account = signers[1]
+                .derive_private_path(
+                    ScriptType::P2wsh
+                        .bip48_path(Network::Mainnet, 0)
+                        .unwrap()
+                        .as_ref(),
+                )
+                .unwrap();
+            assert_eq!(psbt.clone().sign(&account), Ok(1));
+            let mut unknown_origin = account.clone();
+            unknown_origin.origin = None;
+            assert_eq!(psbt.clone().sign(&unknown_origin), Ok(0));
+
+            let mut incomplete = first.clone();
+            assert_eq!(
+                incomplete.finalize(),
+                Err(psbt::Error::InsufficientSignatures {
+                    input: 0,
+                    required: 2,
+                    available: 1
+                })
+            );
+
+            first.combine(second).unwrap();
+            first.finalize().unwrap();
+            let tx = first.extract_tx().unwrap();
+
+            assert_eq!(
+                interpreter::verify_input_with_prevouts(&tx, 0, &[prevout], VerifyFlags::STANDARD),
+                Ok(())
+            );
+        }
+    }
+
+    #[test]
+    fn test_sign_sighash_types() {
+        let (signers, wallet) = wallet(ScriptType::P2wsh);
+        let (psbt, prevout) = spending_psbt(&wallet);
+
+        // A coordinator can't collect signatures which don't commit to the outputs.
+        let none = (sighash::SIGHASH_NONE | sighash::SIGHASH_ANYONECANPAY) as u32;
+        let mut requested = psbt.clone();
+        requested.inputs[0].sighash_type = Some(none);
+        assert_eq!(
+            requested.clone().sign(&signers[0]),
+            Err(psbt::Error::SighashTypeNotAllowed {
+                input: 0,
+                hash_type: none
+            })
+        );
+
+        // Unless the signer agrees to them.
+        let mut first = requested.clone();
+        assert_eq!(
+            first.sign_with_sighash_types(&signers[0], &[none as u8]),
+            Ok(1)
+        );
+        let mut second = requested.clone();
+        assert_eq!(
+            second.sign_with_sighash_types(&signers[1], &[none as u8]),
+            Ok(1)
+        );
+        first.combine(second).unwrap();
+        first.finalize().unwrap();
+        let tx = first.extract_tx().unwrap();
+        assert_eq!(
+            interpreter::verify_input_with_prevouts(&tx, 0, &[prevout], VerifyFlags::STANDARD),
+            Ok(())
+        );
+
+        // Types which don't fit in the signature's sighash byte are never signed.
+        let mut oversized = psbt.clone();
+        oversized.inputs[0].sighash_type = Some(0x101);
+        assert_eq!(
+            oversized.sign_with_sighash_types(&signers[0], &[0x01]),
+            Err(psbt::Error::SighashTypeNotAllowed {
+                input: 0,
+                hash_type: 0x101
+            })
+        );
+
+        let mut explicit_all = psbt;
+        explicit_all.inputs[0].sighash_type = Some(sighash::SIGHASH_ALL as u32);
+        assert_eq!(explicit_all.sign(&signers[0]), Ok(1));
+    }
+
+    #[test]
+    fn test_finalize_large_wallet() {
+        let (signers, wallet) = wallet_of(17, ScriptType::P2wsh, Network::Mainnet);
+        let (mut psbt, prevout) = spending_psbt(&wallet);
+
+        assert_eq!(psbt.sign(&signers[4]), Ok(1));
+        assert_eq!(psbt.sign(&signers[16]), Ok(1));
+        psbt.finalize().unwrap();
+        let tx = psbt.extract_tx().unwrap();
+
+        assert_eq!(
+            interpreter::verify_input_with_prevouts(&tx, 0, &[prevout], VerifyFlags::STANDARD),
+            Ok(())
+        );
+    }
+}
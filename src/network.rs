@@ -0,0 +1,161 @@
+/// The networks keys and addresses can be encoded for. `Mainnet` and `Testnet` are bitcoin's,
+/// and testnet's prefixes are shared by signet and regtest.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Litecoin,
+    Dogecoin,
+    BitcoinCash,
+}
+
+/// The prefixes and version bytes a chain encodes keys and addresses with, and its SLIP-44 coin
+/// type.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct ChainParams {
+    pub name: &'static str,
+    pub wif_prefix: u8,
+    pub p2pkh_prefix: u8,
+    pub p2sh_prefix: u8,
+    /// The human-readable part of segwit addresses, if the chain has segwit.
+    pub bech32_hrp: Option<&'static str>,
+    /// The prefix of CashAddr addresses, if the chain uses them.
+    pub cashaddr_prefix: Option<&'static str>,
+    pub xprv_version: [u8; 4],
+    pub xpub_version: [u8; 4],
+    pub coin_type: u32,
+}
+
+pub const BITCOIN: ChainParams = ChainParams {
+    name: "bitcoin",
+    wif_prefix: 0x80,
+    p2pkh_prefix: 0x00,
+    p2sh_prefix: 0x05,
+    bech32_hrp: Some("bc"),
+    cashaddr_prefix: None,
+    xprv_version: [0x04, 0x88, 0xad, 0xe4],
+    xpub_version: [0x04, 0x88, 0xb2, 0x1e],
+    coin_type: 0,
+};
+
+pub const BITCOIN_TESTNET: ChainParams = ChainParams {
+    name: "testnet",
+    wif_prefix: 0xef,
+    p2pkh_prefix: 0x6f,
+    p2sh_prefix: 0xc4,
+    // Regtest's differs, and isn't supported.
+    bech32_hrp: Some("tb"),
+    cashaddr_prefix: None,
+    xprv_version: [0x04, 0x35, 0x83, 0x94],
+    xpub_version: [0x04, 0x35, 0x87, 0xcf],
+    coin_type: 1,
+};
+
+/// Litecoin, whose extended keys use the SLIP-132 `Ltpv`/`Ltub` versions as hardware wallets
+/// do. Litecoin Core encodes them as `xprv`/`xpub`.
+pub const LITECOIN: ChainParams = ChainParams {
+    name: "litecoin",
+    wif_prefix: 0xb0,
+    p2pkh_prefix: 0x30,
+    p2sh_prefix: 0x32,
+    bech32_hrp: Some("ltc"),
+    cashaddr_prefix: None,
+    xprv_version: [0x01, 0x9d, 0x9c, 0xfe],
+    xpub_version: [0x01, 0x9d, 0xa4, 0x62],
+    coin_type: 2,
+};
+
+pub const DOGECOIN: ChainParams = ChainParams {
+    name: "dogecoin",
+    wif_prefix: 0x9e,
+    p2pkh_prefix: 0x1e,
+    p2sh_prefix: 0x16,
+    bech32_hrp: None,
+    cashaddr_prefix: None,
+    xprv_version: [0x02, 0xfa, 0xc3, 0x98],
+    xpub_version: [0x02, 0xfa, 0xca, 0xfd],
+    coin_type: 3,
+};
+
+/// Bitcoin Cash, which shares bitcoin's base58 prefixes and extended key versions, so its keys
+/// decode as mainnet ones.
+pub const BITCOIN_CASH: ChainParams = ChainParams {
+    name: "bitcoincash",
+    wif_prefix: 0x80,
+    p2pkh_prefix: 0x00,
+    p2sh_prefix: 0x05,
+    bech32_hrp: None,
+    cashaddr_prefix: Some("bitcoincash"),
+    xprv_version: [0x04, 0x88, 0xad, 0xe4],
+    xpub_version: [0x04, 0x88, 0xb2, 0x1e],
+    coin_type: 145,
+};
+
+impl Network {
+    /// Every network, in the order decoders try them in.
+    pub const ALL: [Network; 5] = [
+        Network::Mainnet,
+        Network::Testnet,
+        Network::Litecoin,
+        Network::Dogecoin,
+        Network::BitcoinCash,
+    ];
+
+    pub fn params(self) -> &'static ChainParams {
+        match self {
+            Network::Mainnet => &BITCOIN,
+            Network::Testnet => &BITCOIN_TESTNET,
+            Network::Litecoin => &LITECOIN,
+            Network::Dogecoin => &DOGECOIN,
+            Network::BitcoinCash => &BITCOIN_CASH,
+        }
+    }
+
+    /// Looks up a network by the name of its parameters.
+    pub fn from_name(name: &str) -> Option<Network> {
+        Network::ALL
+            .iter()
+            .copied()
+            .find(|network| network.params().name == name)
+    }
+
+    /// Returns the version byte prefixed to WIF private keys.
+    pub fn wif_prefix(self) -> u8 {
+        self.params().wif_prefix
+    }
+
+    /// Returns the version byte of P2PKH addresses.
+    pub fn p2pkh_prefix(self) -> u8 {
+        self.params().p2pkh_prefix
+    }
+
+    /// Returns the version byte of P2SH addresses.
+    pub fn p2sh_prefix(self) -> u8 {
+        self.params().p2sh_prefix
+    }
+
+    /// Returns the human-readable part of segwit addresses, if the network has segwit.
+    pub fn bech32_hrp(self) -> Option<&'static str> {
+        self.params().bech32_hrp
+    }
+
+    /// Returns the prefix of CashAddr addresses, if the network uses them.
+    pub fn cashaddr_prefix(self) -> Option<&'static str> {
+        self.params().cashaddr_prefix
+    }
+
+    /// Returns the version bytes of BIP32 extended private keys (`xprv`/`tprv`).
+    pub fn xprv_version(self) -> [u8; 4] {
+        self.params().xprv_version
+    }
+
+    /// Returns the version bytes of BIP32 extended public keys (`xpub`/`tpub`).
+    pub fn xpub_version(self) -> [u8; 4] {
+        self.params().xpub_version
+    }
+
+    /// Returns the SLIP-44 coin type of BIP44 accounts.
+    pub fn coin_type(self) -> u32 {
+        self.params().coin_type
+    }
+}
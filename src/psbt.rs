@@ -0,0 +1,931 @@
+//! Partially Signed Bitcoin Transactions (BIP174).
+
+use crate::base58check::Base58CheckString;
+use crate::bip32::{
+    self, ChildNumber, DerivationPath, ExtendedPrivateKey, ExtendedPublicKey, Fingerprint,
+};
+use crate::context;
+use crate::encode::{self, Reader};
+use crate::hash;
+use crate::multisig;
+use crate::script::{opcodes, Builder, Script};
+use crate::transaction::{sighash, Transaction, TxOut};
+use secp256k1::{Message, PublicKey};
+use std::collections::{BTreeMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+const MAGIC: &[u8] = b"psbt\xff";
+
+const PSBT_GLOBAL_UNSIGNED_TX: u8 = 0x00;
+const PSBT_GLOBAL_XPUB: u8 = 0x01;
+const PSBT_GLOBAL_VERSION: u8 = 0xfb;
+
+const PSBT_IN_NON_WITNESS_UTXO: u8 = 0x00;
+const PSBT_IN_WITNESS_UTXO: u8 = 0x01;
+const PSBT_IN_PARTIAL_SIG: u8 = 0x02;
+const PSBT_IN_SIGHASH_TYPE: u8 = 0x03;
+const PSBT_IN_REDEEM_SCRIPT: u8 = 0x04;
+const PSBT_IN_WITNESS_SCRIPT: u8 = 0x05;
+const PSBT_IN_BIP32_DERIVATION: u8 = 0x06;
+const PSBT_IN_FINAL_SCRIPTSIG: u8 = 0x07;
+const PSBT_IN_FINAL_SCRIPTWITNESS: u8 = 0x08;
+
+const PSBT_OUT_REDEEM_SCRIPT: u8 = 0x00;
+const PSBT_OUT_WITNESS_SCRIPT: u8 = 0x01;
+const PSBT_OUT_BIP32_DERIVATION: u8 = 0x02;
+
+pub use crate::bip32::KeySource;
+
+/// Error represents all of the possible errors that can arise while decoding, signing or
+/// finalizing a PSBT.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    Encode(encode::Error),
+    Base64(base64::DecodeError),
+    Secp256k1(secp256k1::Error),
+    Bip32(bip32::Error),
+    Sighash(sighash::Error),
+    InvalidMagic,
+    MissingUnsignedTx,
+    UnsignedTxHasScriptSigs,
+    DuplicateKey(Vec<u8>),
+    InvalidKey(Vec<u8>),
+    InvalidValue(Vec<u8>),
+    MissingUtxo(usize),
+    UtxoMismatch(usize),
+    MissingRedeemScript(usize),
+    MissingWitnessScript(usize),
+    ScriptMismatch(usize),
+    UnsupportedScript(usize),
+    InsufficientSignatures {
+        input: usize,
+        required: usize,
+        available: usize,
+    },
+    NotFinalized(usize),
+    TransactionMismatch,
+    InputIndexOutOfRange(usize),
+    SighashTypeNotAllowed {
+        input: usize,
+        hash_type: u32,
+    },
+}
+
+impl From<encode::Error> for Error {
+    fn from(err: encode::Error) -> Error {
+        Error::Encode(err)
+    }
+}
+
+impl From<base64::DecodeError> for Error {
+    fn from(err: base64::DecodeError) -> Error {
+        Error::Base64(err)
+    }
+}
+
+impl From<secp256k1::Error> for Error {
+    fn from(err: secp256k1::Error) -> Error {
+        Error::Secp256k1(err)
+    }
+}
+
+impl From<bip32::Error> for Error {
+    fn from(err: bip32::Error) -> Error {
+        Error::Bip32(err)
+    }
+}
+
+impl From<sighash::Error> for Error {
+    fn from(err: sighash::Error) -> Error {
+        Error::Sighash(err)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Input {
+    pub non_witness_utxo: Option<Transaction>,
+    pub witness_utxo: Option<TxOut>,
+    pub partial_sigs: BTreeMap<PublicKey, Vec<u8>>,
+    pub sighash_type: Option<u32>,
+    pub redeem_script: Option<Script>,
+    pub witness_script: Option<Script>,
+    pub bip32_derivation: BTreeMap<PublicKey, KeySource>,
+    pub final_script_sig: Option<Script>,
+    pub final_script_witness: Option<Vec<Vec<u8>>>,
+    pub unknown: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Output {
+    pub redeem_script: Option<Script>,
+    pub witness_script: Option<Script>,
+    pub bip32_derivation: BTreeMap<PublicKey, KeySource>,
+    pub unknown: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Psbt {
+    pub unsigned_tx: Transaction,
+    pub xpubs: Vec<(ExtendedPublicKey, KeySource)>,
+    pub version: Option<u32>,
+    pub unknown: BTreeMap<Vec<u8>, Vec<u8>>,
+    pub inputs: Vec<Input>,
+    pub outputs: Vec<Output>,
+}
+
+impl Psbt {
+    /// Creates a PSBT with empty input and output maps for `tx`, whose inputs must not yet have
+    /// any scriptSigs or witnesses.
+    pub fn from_unsigned_tx(tx: Transaction) -> Result<Psbt, Error> {
+        if tx
+            .inputs
+            .iter()
+            .any(|input| !input.script_sig.is_empty() || !input.witness.is_empty())
+        {
+            return Err(Error::UnsignedTxHasScriptSigs);
+        }
+
+        Ok(Psbt {
+            inputs: vec![Input::default(); tx.inputs.len()],
+            outputs: vec![Output::default(); tx.outputs.len()],
+            unsigned_tx: tx,
+            xpubs: Vec::new(),
+            version: None,
+            unknown: BTreeMap::new(),
+        })
+    }
+
+    pub fn deserialize(data: &[u8]) -> Result<Psbt, Error> {
+        let mut reader = Reader::new(data);
+
+        if reader.read_bytes(MAGIC.len())? != MAGIC {
+            return Err(Error::InvalidMagic);
+        }
+
+        let mut unsigned_tx = None;
+        let mut xpubs = Vec::new();
+        let mut version = None;
+        let mut unknown = BTreeMap::new();
+
+        for (key, value) in read_map(&mut reader)? {
+            match key[0] {
+                PSBT_GLOBAL_UNSIGNED_TX if key.len() == 1 => {
+                    let tx = Transaction::deserialize(value)?;
+                    if tx
+                        .inputs
+                        .iter()
+                        .any(|input| !input.script_sig.is_empty() || !input.witness.is_empty())
+                    {
+                        return Err(Error::UnsignedTxHasScriptSigs);
+                    }
+                    unsigned_tx = Some(tx);
+                }
+                PSBT_GLOBAL_XPUB if key.len() == 79 => {
+                    let xpub = Base58CheckString::from_public_bytes(&key[1..]);
+                    let xpub = ExtendedPublicKey::try_from(&xpub)?;
+                    xpubs.push((xpub, decode_key_source(value)?));
+                }
+                PSBT_GLOBAL_VERSION if key.len() == 1 => {
+                    let mut value = Reader::new(value);
+                    version = Some(value.read_u32()?);
+                    value.finish()?;
+                }
+                PSBT_GLOBAL_UNSIGNED_TX | PSBT_GLOBAL_XPUB | PSBT_GLOBAL_VERSION => {
+                    return Err(Error::InvalidKey(key.to_vec()));
+                }
+                _ => {
+                    unknown.insert(key.to_vec(), value.to_vec());
+                }
+            }
+        }
+
+        let unsigned_tx = unsigned_tx.ok_or(Error::MissingUnsignedTx)?;
+
+        let inputs = (0..unsigned_tx.inputs.len())
+            .map(|_| Input::decode(&mut reader))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let outputs = (0..unsigned_tx.outputs.len())
+            .map(|_| Output::decode(&mut reader))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        reader.finish()?;
+
+        Ok(Psbt {
+            unsigned_tx,
+            xpubs,
+            version,
+            unknown,
+            inputs,
+            outputs,
+        })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::from(MAGIC);
+
+        write_pair(
+            &mut buf,
+            &[PSBT_GLOBAL_UNSIGNED_TX],
+            &self.unsigned_tx.serialize_no_witness(),
+        );
+
+        for (xpub, source) in &self.xpubs {
+            let mut key = vec![PSBT_GLOBAL_XPUB];
+            key.extend(&xpub.serialize());
+            write_pair(&mut buf, &key, &encode_key_source(source));
+        }
+
+        if let Some(version) = self.version {
+            write_pair(&mut buf, &[PSBT_GLOBAL_VERSION], &version.to_le_bytes());
+        }
+
+        write_unknown(&mut buf, &self.unknown);
+        buf.push(0x00);
+
+        for input in &self.inputs {
+            input.encode(&mut buf);
+        }
+
+        for output in &self.outputs {
+            output.encode(&mut buf);
+        }
+
+        buf
+    }
+
+    /// Merges the signatures and metadata of another PSBT for the same transaction into this one.
+    pub fn combine(&mut self, other: Psbt) -> Result<(), Error> {
+        if self.unsigned_tx != other.unsigned_tx {
+            return Err(Error::TransactionMismatch);
+        }
+
+        for (xpub, source) in other.xpubs {
+            if !self.xpubs.iter().any(|(existing, _)| *existing == xpub) {
+                self.xpubs.push((xpub, source));
+            }
+        }
+        self.version = self.version.or(other.version);
+        self.unknown.extend(other.unknown);
+
+        for (input, other) in self.inputs.iter_mut().zip(other.inputs) {
+            input.non_witness_utxo = input.non_witness_utxo.take().or(other.non_witness_utxo);
+            input.witness_utxo = input.witness_utxo.take().or(other.witness_utxo);
+            input.partial_sigs.extend(other.partial_sigs);
+            input.sighash_type = input.sighash_type.or(other.sighash_type);
+            input.redeem_script = input.redeem_script.take().or(other.redeem_script);
+            input.witness_script = input.witness_script.take().or(other.witness_script);
+            input.bip32_derivation.extend(other.bip32_derivation);
+            input.final_script_sig = input.final_script_sig.take().or(other.final_script_sig);
+            input.final_script_witness = input
+                .final_script_witness
+                .take()
+                .or(other.final_script_witness);
+            input.unknown.extend(other.unknown);
+        }
+
+        for (output, other) in self.outputs.iter_mut().zip(other.outputs) {
+            output.redeem_script = output.redeem_script.take().or(other.redeem_script);
+            output.witness_script = output.witness_script.take().or(other.witness_script);
+            output.bip32_derivation.extend(other.bip32_derivation);
+            output.unknown.extend(other.unknown);
+        }
+
+        Ok(())
+    }
+
+    /// Signs every input with a key derived from `xprv`, returning the number of signatures
+    /// added.
+    ///
+    /// Keys are located through the inputs' BIP32 derivations passing through `xprv`, which must
+    /// be a master key unless its origin is known. Taproot inputs are not supported.
+    ///
+    /// Only `SIGHASH_ALL` signatures are made, so that every signature commits to all inputs and
+    /// outputs. Inputs asking for another sighash type are an error.
+    pub fn sign(&mut self, xprv: &ExtendedPrivateKey) -> Result<usize, Error> {
+        self.sign_with_sighash_types(xprv, &[sighash::SIGHASH_ALL])
+    }
+
+    /// Signs like `sign`, but also makes signatures of the sighash types in `allowed` for inputs
+    /// which ask for them.
+    pub fn sign_with_sighash_types(
+        &mut self,
+        xprv: &ExtendedPrivateKey,
+        allowed: &[u8],
+    ) -> Result<usize, Error> {
+        let secp = context::secp256k1();
+        let (fingerprint, origin_path) = xprv
+            .origin
+            .clone()
+            .unwrap_or_else(|| (xprv.fingerprint(), DerivationPath::default()));
+
+        let mut signed = 0;
+
+        for index in 0..self.inputs.len() {
+            let keys = self.inputs[index]
+                .bip32_derivation
+                .iter()
+                .filter(|(_, (source, _))| *source == fingerprint)
+                .filter_map(|(pubkey, (_, path))| {
+                    let path = path.as_ref().strip_prefix(origin_path.as_ref())?;
+                    Some((*pubkey, path.to_vec()))
+                })
+                .collect::<Vec<_>>();
+
+            for (pubkey, path) in keys {
+                let child = xprv.derive_private_path(&path)?;
+                if PublicKey::from_secret_key(secp, &child.private_key) != pubkey {
+                    continue;
+                }
+
+                let hash_type = self.inputs[index]
+                    .sighash_type
+                    .unwrap_or(sighash::SIGHASH_ALL as u32);
+                if hash_type != sighash::SIGHASH_ALL as u32
+                    && !allowed.iter().any(|allowed| *allowed as u32 == hash_type)
+                {
+                    return Err(Error::SighashTypeNotAllowed {
+                        input: index,
+                        hash_type,
+                    });
+                }
+
+                let sighash = self.signature_hash(index, &pubkey, hash_type)?;
+                let msg = Message::from_slice(&sighash).expect("sighash is 32 bytes");
+
+                let mut sig = secp.sign(&msg, &child.private_key).serialize_der().to_vec();
+                sig.push(hash_type as u8);
+
+                self.inputs[index].partial_sigs.insert(pubkey, sig);
+                signed += 1;
+            }
+        }
+
+        Ok(signed)
+    }
+
+    /// Builds the final scriptSig and witness of every input from its partial signatures.
+    ///
+    /// Single-key (P2PKH, P2WPKH, P2SH-P2WPKH) and multisig (P2SH, P2SH-P2WSH, P2WSH) inputs are
+    /// supported. Inputs which are already final are left untouched.
+    pub fn finalize(&mut self) -> Result<(), Error> {
+        for index in 0..self.inputs.len() {
+            let input = &self.inputs[index];
+            if input.final_script_sig.is_some() || input.final_script_witness.is_some() {
+                continue;
+            }
+
+            let (script_sig, witness) = self.finalize_input(index)?;
+
+            let input = &mut self.inputs[index];
+            input.final_script_sig = Some(script_sig).filter(|script| !script.is_empty());
+            input.final_script_witness = Some(witness).filter(|witness| !witness.is_empty());
+            input.partial_sigs.clear();
+            input.sighash_type = None;
+            input.redeem_script = None;
+            input.witness_script = None;
+            input.bip32_derivation.clear();
+        }
+
+        Ok(())
+    }
+
+    /// Returns the signed transaction once every input has been finalized.
+    pub fn extract_tx(&self) -> Result<Transaction, Error> {
+        let mut tx = self.unsigned_tx.clone();
+
+        for (index, (txin, input)) in tx.inputs.iter_mut().zip(&self.inputs).enumerate() {
+            if input.final_script_sig.is_none() && input.final_script_witness.is_none() {
+                return Err(Error::NotFinalized(index));
+            }
+
+            txin.script_sig = input.final_script_sig.clone().unwrap_or_default();
+            txin.witness = input.final_script_witness.clone().unwrap_or_default();
+        }
+
+        Ok(tx)
+    }
+
+    /// Returns the output spent by input `index`.
+    pub fn spent_output(&self, index: usize) -> Result<TxOut, Error> {
+        let input = self
+            .inputs
+            .get(index)
+            .ok_or(Error::InputIndexOutOfRange(index))?;
+
+        if let Some(utxo) = &input.witness_utxo {
+            return Ok(utxo.clone());
+        }
+
+        let tx = input
+            .non_witness_utxo
+            .as_ref()
+            .ok_or(Error::MissingUtxo(index))?;
+        let prevout = self.unsigned_tx.inputs[index].previous_output;
+
+        if tx.txid() != prevout.txid {
+            return Err(Error::UtxoMismatch(index));
+        }
+
+        tx.outputs
+            .get(prevout.vout as usize)
+            .cloned()
+            .ok_or(Error::UtxoMismatch(index))
+    }
+
+    /// Resolves the script input `index` ultimately executes: the redeem script for P2SH, and
+    /// then the witness script or implied P2PKH script for segwit v0.
+    fn signing_script(&self, index: usize) -> Result<(Script, Option<u64>), Error> {
+        let input = &self.inputs[index];
+        let utxo = self.spent_output(index)?;
+        let mut script = utxo.script_pubkey;
+
+        if script.is_p2sh() {
+            let redeem_script = input
+                .redeem_script
+                .clone()
+                .ok_or(Error::MissingRedeemScript(index))?;
+            if redeem_script.to_p2sh() != script {
+                return Err(Error::ScriptMismatch(index));
+            }
+            script = redeem_script;
+        }
+
+        match script.witness_program() {
+            Some((0, program)) if program.len() == 32 => {
+                let witness_script = input
+                    .witness_script
+                    .clone()
+                    .ok_or(Error::MissingWitnessScript(index))?;
+                if witness_script.to_p2wsh() != script {
+                    return Err(Error::ScriptMismatch(index));
+                }
+                Ok((witness_script, Some(utxo.value)))
+            }
+            Some((0, program)) if program.len() == 20 => {
+                Ok((p2pkh_script(program), Some(utxo.value)))
+            }
+            Some(_) => Err(Error::UnsupportedScript(index)),
+            None => Ok((script, None)),
+        }
+    }
+
+    fn signature_hash(
+        &self,
+        index: usize,
+        pubkey: &PublicKey,
+        hash_type: u32,
+    ) -> Result<[u8; 32], Error> {
+        let (script, value) = self.signing_script(index)?;
+
+        // Only sign for keys the script actually checks.
+        if !script
+            .instructions()
+            .any(|ins| matches!(ins, Ok(ins) if ins.data == &pubkey.serialize()[..] || ins.data == &hash::hash160(&pubkey.serialize())[..]))
+        {
+            return Err(Error::ScriptMismatch(index));
+        }
+
+        match value {
+            Some(value) => Ok(sighash::segwit_v0(
+                &self.unsigned_tx,
+                index,
+                script.as_bytes(),
+                value,
+                hash_type,
+            )?),
+            None => Ok(sighash::legacy(
+                &self.unsigned_tx,
+                index,
+                script.as_bytes(),
+                hash_type,
+            )),
+        }
+    }
+
+    fn finalize_input(&self, index: usize) -> Result<(Script, Vec<Vec<u8>>), Error> {
+        let input = &self.inputs[index];
+        let utxo = self.spent_output(index)?;
+        let (script, value) = self.signing_script(index)?;
+        let mut stack = self.satisfy(index, &script)?;
+
+        let is_p2sh = utxo.script_pubkey.is_p2sh();
+        let is_segwit = value.is_some();
+        let inner = match &input.redeem_script {
+            Some(redeem_script) if is_p2sh => redeem_script.clone(),
+            _ => utxo.script_pubkey,
+        };
+        let is_p2wsh = matches!(inner.witness_program(), Some((0, program)) if program.len() == 32);
+
+        // Scripts committed to by hash must be revealed after their satisfaction.
+        if is_p2wsh || (is_p2sh && !is_segwit) {
+            stack.push(script.into_bytes());
+        }
+
+        if !is_segwit {
+            let script_sig = stack
+                .iter()
+                .fold(Builder::new(), |builder, item| match item.len() {
+                    0 => builder.push_opcode(opcodes::OP_0),
+                    _ => builder.push_slice(item),
+                })
+                .into_script();
+            return Ok((script_sig, Vec::new()));
+        }
+
+        let script_sig = match is_p2sh {
+            true => Builder::new().push_slice(inner.as_bytes()).into_script(),
+            false => Script::new(),
+        };
+        Ok((script_sig, stack))
+    }
+
+    /// Returns the stack items which satisfy `script` using the input's partial signatures.
+    fn satisfy(&self, index: usize, script: &Script) -> Result<Vec<Vec<u8>>, Error> {
+        let input = &self.inputs[index];
+
+        if let Some((threshold, pubkeys)) = multisig::parse_multisig_script(script) {
+            let sigs = pubkeys
+                .iter()
+                .filter_map(|pubkey| input.partial_sigs.get(pubkey))
+                .take(threshold)
+                .cloned()
+                .collect::<Vec<_>>();
+
+            if sigs.len() < threshold {
+                return Err(Error::InsufficientSignatures {
+                    input: index,
+                    required: threshold,
+                    available: sigs.len(),
+                });
+            }
+
+            // CHECKMULTISIG consumes an extra, unused stack item.
+            return Ok(std::iter::once(Vec::new()).chain(sigs).collect());
+        }
+
+        if script.is_p2pkh() {
+            return input
+                .partial_sigs
+                .iter()
+                .find(|(pubkey, _)| p2pkh_script(&hash::hash160(&pubkey.serialize())) == *script)
+                .map(|(pubkey, sig)| vec![sig.clone(), pubkey.serialize().to_vec()])
+                .ok_or(Error::InsufficientSignatures {
+                    input: index,
+                    required: 1,
+                    available: 0,
+                });
+        }
+
+        Err(Error::UnsupportedScript(index))
+    }
+}
+
+impl fmt::Display for Psbt {
+    /// Formats the PSBT as base64, the standard text encoding.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", base64::encode(self.serialize()))
+    }
+}
+
+impl FromStr for Psbt {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Psbt::deserialize(&base64::decode(s)?)
+    }
+}
+
+impl Input {
+    fn decode(reader: &mut Reader<'_>) -> Result<Input, Error> {
+        let mut input = Input::default();
+
+        for (key, value) in read_map(reader)? {
+            match (key[0], key.len()) {
+                (PSBT_IN_NON_WITNESS_UTXO, 1) => {
+                    input.non_witness_utxo = Some(Transaction::deserialize(value)?);
+                }
+                (PSBT_IN_WITNESS_UTXO, 1) => {
+                    let mut value = Reader::new(value);
+                    input.witness_utxo = Some(TxOut {
+                        value: value.read_u64()?,
+                        script_pubkey: Script::from(value.read_var_bytes()?),
+                    });
+                    value.finish()?;
+                }
+                (PSBT_IN_PARTIAL_SIG, 34) | (PSBT_IN_PARTIAL_SIG, 66) => {
+                    input
+                        .partial_sigs
+                        .insert(PublicKey::from_slice(&key[1..])?, value.to_vec());
+                }
+                (PSBT_IN_SIGHASH_TYPE, 1) => {
+                    let mut value = Reader::new(value);
+                    input.sighash_type = Some(value.read_u32()?);
+                    value.finish()?;
+                }
+                (PSBT_IN_REDEEM_SCRIPT, 1) => input.redeem_script = Some(Script::from(value)),
+                (PSBT_IN_WITNESS_SCRIPT, 1) => input.witness_script = Some(Script::from(value)),
+                (PSBT_IN_BIP32_DERIVATION, 34) | (PSBT_IN_BIP32_DERIVATION, 66) => {
+                    input
+                        .bip32_derivation
+                        .insert(PublicKey::from_slice(&key[1..])?, decode_key_source(value)?);
+                }
+                (PSBT_IN_FINAL_SCRIPTSIG, 1) => input.final_script_sig = Some(Script::from(value)),
+                (PSBT_IN_FINAL_SCRIPTWITNESS, 1) => {
+                    let mut value = Reader::new(value);
+                    let witness = (0..value.read_compact_size()?)
+                        .map(|_| value.read_var_bytes().map(<[u8]>::to_vec))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    value.finish()?;
+                    input.final_script_witness = Some(witness);
+                }
+                (PSBT_IN_NON_WITNESS_UTXO..=PSBT_IN_FINAL_SCRIPTWITNESS, _) => {
+                    return Err(Error::InvalidKey(key.to_vec()));
+                }
+                _ => {
+                    input.unknown.insert(key.to_vec(), value.to_vec());
+                }
+            }
+        }
+
+        Ok(input)
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        if let Some(tx) = &self.non_witness_utxo {
+            write_pair(buf, &[PSBT_IN_NON_WITNESS_UTXO], &tx.serialize());
+        }
+
+        if let Some(utxo) = &self.witness_utxo {
+            let mut value = Vec::new();
+            utxo.consensus_encode(&mut value);
+            write_pair(buf, &[PSBT_IN_WITNESS_UTXO], &value);
+        }
+
+        for (pubkey, sig) in &self.partial_sigs {
+            write_pair(buf, &pubkey_key(PSBT_IN_PARTIAL_SIG, pubkey), sig);
+        }
+
+        if let Some(hash_type) = self.sighash_type {
+            write_pair(buf, &[PSBT_IN_SIGHASH_TYPE], &hash_type.to_le_bytes());
+        }
+
+        if let Some(script) = &self.redeem_script {
+            write_pair(buf, &[PSBT_IN_REDEEM_SCRIPT], script.as_bytes());
+        }
+
+        if let Some(script) = &self.witness_script {
+            write_pair(buf, &[PSBT_IN_WITNESS_SCRIPT], script.as_bytes());
+        }
+
+        for (pubkey, source) in &self.bip32_derivation {
+            let key = pubkey_key(PSBT_IN_BIP32_DERIVATION, pubkey);
+            write_pair(buf, &key, &encode_key_source(source));
+        }
+
+        if let Some(script) = &self.final_script_sig {
+            write_pair(buf, &[PSBT_IN_FINAL_SCRIPTSIG], script.as_bytes());
+        }
+
+        if let Some(witness) = &self.final_script_witness {
+            let mut value = Vec::new();
+            encode::write_compact_size(&mut value, witness.len() as u64);
+            for item in witness {
+                encode::write_var_bytes(&mut value, item);
+            }
+            write_pair(buf, &[PSBT_IN_FINAL_SCRIPTWITNESS], &value);
+        }
+
+        write_unknown(buf, &self.unknown);
+        buf.push(0x00);
+    }
+}
+
+impl Output {
+    fn decode(reader: &mut Reader<'_>) -> Result<Output, Error> {
+        let mut output = Output::default();
+
+        for (key, value) in read_map(reader)? {
+            match (key[0], key.len()) {
+                (PSBT_OUT_REDEEM_SCRIPT, 1) => output.redeem_script = Some(Script::from(value)),
+                (PSBT_OUT_WITNESS_SCRIPT, 1) => output.witness_script = Some(Script::from(value)),
+                (PSBT_OUT_BIP32_DERIVATION, 34) | (PSBT_OUT_BIP32_DERIVATION, 66) => {
+                    output
+                        .bip32_derivation
+                        .insert(PublicKey::from_slice(&key[1..])?, decode_key_source(value)?);
+                }
+                (PSBT_OUT_REDEEM_SCRIPT..=PSBT_OUT_BIP32_DERIVATION, _) => {
+                    return Err(Error::InvalidKey(key.to_vec()));
+                }
+                _ => {
+                    output.unknown.insert(key.to_vec(), value.to_vec());
+                }
+            }
+        }
+
+        Ok(output)
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        if let Some(script) = &self.redeem_script {
+            write_pair(buf, &[PSBT_OUT_REDEEM_SCRIPT], script.as_bytes());
+        }
+
+        if let Some(script) = &self.witness_script {
+            write_pair(buf, &[PSBT_OUT_WITNESS_SCRIPT], script.as_bytes());
+        }
+
+        for (pubkey, source) in &self.bip32_derivation {
+            let key = pubkey_key(PSBT_OUT_BIP32_DERIVATION, pubkey);
+            write_pair(buf, &key, &encode_key_source(source));
+        }
+
+        write_unknown(buf, &self.unknown);
+        buf.push(0x00);
+    }
+}
+
+/// A raw key and value from a PSBT map.
+type Pair<'a> = (&'a [u8], &'a [u8]);
+
+/// Reads key-value pairs up to and including the map's terminating zero-length key.
+fn read_map<'a>(reader: &mut Reader<'a>) -> Result<Vec<Pair<'a>>, Error> {
+    let mut pairs = Vec::new();
+    let mut keys = HashSet::new();
+
+    loop {
+        let key = reader.read_var_bytes()?;
+        if key.is_empty() {
+            return Ok(pairs);
+        }
+
+        if !keys.insert(key) {
+            return Err(Error::DuplicateKey(key.to_vec()));
+        }
+
+        pairs.push((key, reader.read_var_bytes()?));
+    }
+}
+
+fn write_pair(buf: &mut Vec<u8>, key: &[u8], value: &[u8]) {
+    encode::write_var_bytes(buf, key);
+    encode::write_var_bytes(buf, value);
+}
+
+fn write_unknown(buf: &mut Vec<u8>, unknown: &BTreeMap<Vec<u8>, Vec<u8>>) {
+    for (key, value) in unknown {
+        write_pair(buf, key, value);
+    }
+}
+
+fn pubkey_key(key_type: u8, pubkey: &PublicKey) -> Vec<u8> {
+    let mut key = vec![key_type];
+    key.extend(&pubkey.serialize());
+    key
+}
+
+fn decode_key_source(value: &[u8]) -> Result<KeySource, Error> {
+    if value.len() < 4 || value.len() % 4 != 0 {
+        return Err(Error::InvalidValue(value.to_vec()));
+    }
+
+    let fingerprint = Fingerprint::from(copy_from_slice!([0u8; 4], &value[..4]));
+    let path = value[4..]
+        .chunks(4)
+        .map(|index| ChildNumber::from(u32::from_le_bytes(copy_from_slice!([0u8; 4], index))))
+        .collect::<Vec<_>>();
+
+    Ok((fingerprint, path.into()))
+}
+
+fn encode_key_source((fingerprint, path): &KeySource) -> Vec<u8> {
+    let mut value = fingerprint.as_bytes().to_vec();
+    for child_number in path.as_ref() {
+        value.extend(&u32::from(*child_number).to_le_bytes());
+    }
+    value
+}
+
+fn p2pkh_script(pubkey_hash: &[u8]) -> Script {
+    Builder::new()
+        .push_opcode(opcodes::OP_DUP)
+        .push_opcode(opcodes::OP_HASH160)
+        .push_slice(pubkey_hash)
+        .push_opcode(opcodes::OP_EQUALVERIFY)
+        .push_opcode(opcodes::OP_CHECKSIG)
+        .into_script()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::{OutPoint, TxIn};
+    use serde_json::Value;
+
+    fn unsigned_tx() -> Transaction {
+        Transaction {
+            version: 2,
+            inputs: vec![TxIn {
+                previous_output: OutPoint {
+                    txid: [0x22; 32].into(),
+                    vout: 1,
+                },
+                script_sig: Script::new(),
+                sequence: 0xffff_ffff,
+                witness: Vec::new(),
+            }],
+            outputs: vec![TxOut {
+                value: 50_000,
+                script_pubkey: p2pkh_script(&[0x33; 20]),
+            }],
+            lock_time: 0,
+        }
+    }
+
+    #[test]
+    fn test_serialize_roundtrip() {
+        let mut psbt = Psbt::from_unsigned_tx(unsigned_tx()).unwrap();
+        psbt.version = Some(0);
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: 60_000,
+            script_pubkey: p2pkh_script(&[0x44; 20]),
+        });
+        psbt.inputs[0].sighash_type = Some(1);
+        psbt.outputs[0].unknown.insert(vec![0xfc, 0x01], vec![0x02]);
+
+        assert_eq!(Psbt::deserialize(&psbt.serialize()), Ok(psbt.clone()));
+        assert_eq!(psbt.to_string().parse::<Psbt>(), Ok(psbt));
+    }
+
+    #[test]
+    fn test_deserialize_rejects_malformed() {
+        let serialized = Psbt::from_unsigned_tx(unsigned_tx()).unwrap().serialize();
+
+        let mut bad_magic = serialized.clone();
+        bad_magic[0] = b'q';
+        assert_eq!(Psbt::deserialize(&bad_magic), Err(Error::InvalidMagic));
+
+        // Repeat the unsigned transaction pair, which is followed by the three map separators.
+        let pair_len = serialized.len() - MAGIC.len() - 3;
+        let mut duplicated = serialized[..MAGIC.len() + pair_len].to_vec();
+        duplicated.extend_from_slice(&serialized[MAGIC.len()..]);
+        assert_eq!(
+            Psbt::deserialize(&duplicated),
+            Err(Error::DuplicateKey(vec![PSBT_GLOBAL_UNSIGNED_TX]))
+        );
+
+        let mut signed = unsigned_tx();
+        signed.inputs[0].script_sig = Builder::new().push_int(1).into_script();
+        assert_eq!(
+            Psbt::from_unsigned_tx(signed),
+            Err(Error::UnsignedTxHasScriptSigs)
+        );
+    }
+
+    #[test]
+    fn test_spent_output_index_out_of_range() {
+        let mut psbt = Psbt::from_unsigned_tx(unsigned_tx()).unwrap();
+        let utxo = TxOut {
+            value: 60_000,
+            script_pubkey: p2pkh_script(&[0x44; 20]),
+        };
+        psbt.inputs[0].witness_utxo = Some(utxo.clone());
+
+        assert_eq!(psbt.spent_output(0), Ok(utxo));
+        assert_eq!(psbt.spent_output(1), Err(Error::InputIndexOutOfRange(1)));
+    }
+
+    #[test]
+    fn test_bip174_vectors() {
+        let vectors: Value =
+            serde_json::from_str(include_str!("../testdata/bip174_vectors.json")).unwrap();
+
+        let errors = vec![
+            Error::InvalidMagic,
+            Error::Encode(encode::Error::UnexpectedEof),
+            Error::UnsignedTxHasScriptSigs,
+            Error::MissingUnsignedTx,
+            Error::DuplicateKey(vec![PSBT_IN_NON_WITNESS_UTXO]),
+        ];
+        let invalid = vectors["invalid"].as_array().unwrap();
+        assert_eq!(invalid.len(), errors.len());
+
+        for (vector, error) in invalid.iter().zip(errors) {
+            let data = hex::decode(vector["psbt"].as_str().unwrap()).unwrap();
+            assert_eq!(
+                Psbt::deserialize(&data),
+                Err(error),
+                "{}",
+                vector["comment"]
+            );
+        }
+
+        for vector in vectors["valid"].as_array().unwrap() {
+            let data = hex::decode(vector["psbt"].as_str().unwrap()).unwrap();
+            let psbt = Psbt::deserialize(&data).unwrap();
+            assert_eq!(psbt.serialize(), data, "{}", vector["comment"]);
+        }
+    }
+}
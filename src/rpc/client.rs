@@ -24,7 +24,7 @@ impl<E: BlockingExecute> BlockingClient<E> {
     where
         T: Into<JsonRpcRequest>,
     {
-        Ok(self.executer.execute(self.builder.build(request))?)
+        self.executer.execute(self.builder.build(request))
     }
 }
 
@@ -40,9 +40,9 @@ impl BlockingExecute for reqwest::blocking::Client {
     }
 }
 
-impl Into<reqwest::blocking::Body> for JsonRpcRequest {
-    fn into(self) -> reqwest::blocking::Body {
-        let string: String = self.into();
+impl From<JsonRpcRequest> for reqwest::blocking::Body {
+    fn from(request: JsonRpcRequest) -> Self {
+        let string: String = request.into();
         string.into()
     }
 }
@@ -11,7 +11,7 @@ impl From<GetBlockRequest> for JsonRpcRequest {
     fn from(req: GetBlockRequest) -> JsonRpcRequest {
         JsonRpcRequest {
             method: "getblock",
-            params: vec![req.hash.into(), 2.into()],
+            params: vec![req.hash.to_string().into(), 2.into()],
         }
     }
 }
@@ -21,13 +21,13 @@ pub struct JsonRpcRequest {
     pub params: Vec<serde_json::Value>,
 }
 
-impl Into<String> for JsonRpcRequest {
-    fn into(self) -> String {
+impl From<JsonRpcRequest> for String {
+    fn from(request: JsonRpcRequest) -> Self {
         json!({
             "jsonrpc": "1.0",
             "id": "rust-crypto",
-            "method": self.method,
-            "params": self.params,
+            "method": request.method,
+            "params": request.params,
         })
         .to_string()
     }
@@ -1,7 +1,6 @@
 use serde::Deserialize;
 
-pub type BlockHash = String;
-pub type TransactionHash = String;
+pub use crate::hash::{BlockHash, Txid as TransactionHash};
 
 #[derive(Deserialize, PartialEq, Debug)]
 pub struct Block {
@@ -64,22 +63,30 @@ mod tests {
             }
         "#;
 
-        let block: Block = serde_json::from_str(&data).unwrap();
+        let block: Block = serde_json::from_str(data).unwrap();
 
         assert_eq!(
             block,
             Block {
                 height: 1,
-                hash: String::from(
-                    "00000000839a8e6886ab5951d76f411475428afc90947ee320161bbf18eb6048"
-                ),
+                hash: "00000000839a8e6886ab5951d76f411475428afc90947ee320161bbf18eb6048"
+                    .parse()
+                    .unwrap(),
                 tx: vec![String::from(
                     "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff0704ffff001d0104ffffffff0100f2052a0100000043410496b538e853519c726a2c91e61ec11600ae1390813a627c66fb8be7947be63c52da7589379515d4e0a604f8141781e62294721166bf621e73a82cbf2342c858eeac00000000"
                 )],
-                next_block_hash: Some(String::from(
+                next_block_hash: Some(
                     "000000006a625f06636b8bb6ac7b960a8d03705d1ace08b1a19da3fdcc99ddbd"
-                )),
+                        .parse()
+                        .unwrap()
+                ),
             }
         );
     }
+
+    #[test]
+    fn test_block_deserialization_rejects_invalid_hash() {
+        let data = r#"{"hash": "not a hash", "height": 1, "tx": []}"#;
+        assert!(serde_json::from_str::<Block>(data).is_err());
+    }
 }
@@ -0,0 +1,277 @@
+//! RSA key generation, from any RNG, so that keys can be derived deterministically by BIP85.
+//!
+//! Keys have the public exponent 65537 and a modulus of exactly the requested size. Each prime is
+//! the first candidate read from the RNG, as a big-endian integer of half the modulus's size with
+//! its top two and bottom bits set, which has no small factors, passes Miller-Rabin with the
+//! first `MILLER_RABIN_BASES` primes as bases, and is one more than a number coprime to the
+//! public exponent. The private exponent is the inverse of the public one modulo `(p-1)(q-1)`.
+
+use crypto_bigint::modular::runtime_mod::{DynResidue, DynResidueParams};
+use crypto_bigint::{Limb, NonZero, Uint, U1024, U1536, U2048, U3072, U4096, U512};
+use rand::{CryptoRng, RngCore};
+use std::fmt;
+use zeroize::Zeroizing;
+
+pub const PUBLIC_EXPONENT: u32 = 65537;
+
+/// The sizes of the keys which can be generated, in bits.
+pub const KEY_SIZES: [usize; 4] = [1024, 2048, 3072, 4096];
+
+/// The odd primes below 1000, which trial division rules out as factors before Miller-Rabin.
+const SMALL_PRIMES: [u32; 167] = [
+    3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311, 313, 317, 331, 337, 347, 349, 353, 359, 367, 373, 379, 383, 389, 397, 401, 409, 419, 421,
+    431, 433, 439, 443, 449, 457, 461, 463, 467, 479, 487, 491, 499, 503, 509, 521, 523, 541, 547,
+    557, 563, 569, 571, 577, 587, 593, 599, 601, 607, 613, 617, 619, 631, 641, 643, 647, 653, 659,
+    661, 673, 677, 683, 691, 701, 709, 719, 727, 733, 739, 743, 751, 757, 761, 769, 773, 787, 797,
+    809, 811, 821, 823, 827, 829, 839, 853, 857, 859, 863, 877, 881, 883, 887, 907, 911, 919, 929,
+    937, 941, 947, 953, 967, 971, 977, 983, 991, 997,
+];
+
+const MILLER_RABIN_BASES: usize = 16;
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    UnsupportedKeySize(usize),
+}
+
+/// An RSA private key, whose parameters are big-endian integers padded to their full size. The
+/// private parameters are wiped when dropped and left out of the `Debug` output.
+#[derive(Clone, PartialEq)]
+pub struct PrivateKey {
+    modulus: Vec<u8>,
+    private_exponent: Zeroizing<Vec<u8>>,
+    p: Zeroizing<Vec<u8>>,
+    q: Zeroizing<Vec<u8>>,
+}
+
+impl PrivateKey {
+    /// Generates a key of `bits` bits, which must be one of `KEY_SIZES`.
+    pub fn generate(bits: usize) -> Result<PrivateKey, Error> {
+        PrivateKey::generate_with_rng(bits, &mut rand::thread_rng())
+    }
+
+    pub fn generate_with_rng<R: RngCore + CryptoRng>(
+        bits: usize,
+        rng: &mut R,
+    ) -> Result<PrivateKey, Error> {
+        Ok(match bits {
+            1024 => generate::<{ U512::LIMBS }, { U1024::LIMBS }, _>(rng),
+            2048 => generate::<{ U1024::LIMBS }, { U2048::LIMBS }, _>(rng),
+            3072 => generate::<{ U1536::LIMBS }, { U3072::LIMBS }, _>(rng),
+            4096 => generate::<{ U2048::LIMBS }, { U4096::LIMBS }, _>(rng),
+            _ => return Err(Error::UnsupportedKeySize(bits)),
+        })
+    }
+
+    pub fn bits(&self) -> usize {
+        self.modulus.len() * 8
+    }
+
+    pub fn modulus(&self) -> &[u8] {
+        &self.modulus
+    }
+
+    pub fn public_exponent(&self) -> u32 {
+        PUBLIC_EXPONENT
+    }
+
+    pub fn private_exponent(&self) -> &[u8] {
+        &self.private_exponent
+    }
+
+    /// Returns the primes `p` and `q`, in the order they were generated.
+    pub fn primes(&self) -> (&[u8], &[u8]) {
+        (&self.p, &self.q)
+    }
+}
+
+impl fmt::Debug for PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PrivateKey")
+            .field("modulus", &hex::encode(&self.modulus))
+            .field("public_exponent", &PUBLIC_EXPONENT)
+            .field("private_exponent", &format_args!("[redacted]"))
+            .field("primes", &format_args!("[redacted]"))
+            .finish()
+    }
+}
+
+/// Generates a key whose primes have `P` limbs and whose modulus has `N`, twice as many.
+fn generate<const P: usize, const N: usize, R: RngCore + CryptoRng>(rng: &mut R) -> PrivateKey {
+    let p = Zeroizing::new(generate_prime::<P, R>(rng));
+    let q = loop {
+        let q = Zeroizing::new(generate_prime::<P, R>(rng));
+        if *q != *p {
+            break q;
+        }
+    };
+
+    let modulus = p.resize::<N>().wrapping_mul(&q.resize::<N>());
+    let phi = Zeroizing::new(
+        p.wrapping_sub(&Uint::ONE)
+            .resize::<N>()
+            .wrapping_mul(&q.wrapping_sub(&Uint::ONE).resize::<N>()),
+    );
+
+    // The public exponent is coprime to both p-1 and q-1, so it has an inverse.
+    let (private_exponent, exists) = Uint::<N>::from_u32(PUBLIC_EXPONENT).inv_mod(&phi);
+    assert!(bool::from(exists), "the public exponent is invertible");
+    let private_exponent = Zeroizing::new(private_exponent);
+
+    PrivateKey {
+        modulus: to_be_bytes(&modulus).to_vec(),
+        private_exponent: to_be_bytes(&private_exponent),
+        p: to_be_bytes(&p),
+        q: to_be_bytes(&q),
+    }
+}
+
+fn generate_prime<const L: usize, R: RngCore + CryptoRng>(rng: &mut R) -> Uint<L> {
+    let mut bytes = Zeroizing::new(vec![0u8; L * Limb::BYTES]);
+    loop {
+        rng.fill_bytes(&mut bytes);
+        bytes[0] |= 0xc0;
+        *bytes.last_mut().unwrap() |= 1;
+
+        let candidate = Zeroizing::new(Uint::<L>::from_be_slice(&bytes));
+        if has_small_factor(&candidate)
+            || remainder(&candidate.wrapping_sub(&Uint::ONE), PUBLIC_EXPONENT) == 0
+        {
+            continue;
+        }
+
+        if is_probable_prime(&candidate) {
+            return *candidate;
+        }
+    }
+}
+
+fn has_small_factor<const L: usize>(n: &Uint<L>) -> bool {
+    SMALL_PRIMES.iter().any(|prime| remainder(n, *prime) == 0)
+}
+
+fn remainder<const L: usize>(n: &Uint<L>, divisor: u32) -> u32 {
+    let divisor = NonZero::new(Limb::from(divisor)).unwrap();
+    n.div_rem_limb(divisor).1 .0 as u32
+}
+
+/// Runs Miller-Rabin on the odd `n` with the first `MILLER_RABIN_BASES` primes as bases.
+fn is_probable_prime<const L: usize>(n: &Uint<L>) -> bool {
+    let params = DynResidueParams::new(n);
+    let n_minus_one = n.wrapping_sub(&Uint::ONE);
+    let s = n_minus_one.trailing_zeros();
+    let d = n_minus_one.shr(s);
+
+    let one = DynResidue::one(params);
+    let minus_one = DynResidue::new(&n_minus_one, params);
+
+    [2].iter()
+        .chain(SMALL_PRIMES.iter())
+        .take(MILLER_RABIN_BASES)
+        .all(|base| {
+            let mut x = DynResidue::new(&Uint::from_u32(*base), params).pow(&d);
+            if x == one || x == minus_one {
+                return true;
+            }
+
+            for _ in 1..s {
+                x = x.square();
+                if x == minus_one {
+                    return true;
+                }
+            }
+            false
+        })
+}
+
+fn to_be_bytes<const L: usize>(n: &Uint<L>) -> Zeroizing<Vec<u8>> {
+    let mut bytes = Zeroizing::new(Vec::with_capacity(L * Limb::BYTES));
+    for word in n.as_words().iter().rev() {
+        bytes.extend_from_slice(&word.to_be_bytes());
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    /// Checks that `key`'s primes make up its modulus and that its exponents are inverses, by
+    /// encrypting and decrypting a message.
+    fn check_key<const N: usize>(key: &PrivateKey) {
+        let (p, q) = key.primes();
+        assert_eq!(p.len() * 2, key.modulus().len());
+        assert!(p[0] >= 0xc0 && q[0] >= 0xc0);
+
+        let modulus = Uint::<N>::from_be_slice(key.modulus());
+        let widen = |prime: &[u8]| {
+            Uint::<N>::from_be_slice(&[vec![0u8; prime.len()], prime.to_vec()].concat())
+        };
+        let product = widen(p).wrapping_mul(&widen(q));
+        assert_eq!(product, modulus);
+        assert_eq!(modulus.bits(), key.bits());
+
+        let params = DynResidueParams::new(&modulus);
+        let message = DynResidue::new(&Uint::from_u64(0x0123_4567_89ab_cdef), params);
+        let ciphertext = message.pow(&Uint::<N>::from_u32(key.public_exponent()));
+        assert_ne!(ciphertext, message);
+        assert_eq!(
+            ciphertext.pow(&Uint::<N>::from_be_slice(key.private_exponent())),
+            message
+        );
+    }
+
+    #[test]
+    fn test_generate() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let key = PrivateKey::generate_with_rng(1024, &mut rng).unwrap();
+        check_key::<{ U1024::LIMBS }>(&key);
+
+        let key = PrivateKey::generate(2048).unwrap();
+        check_key::<{ U2048::LIMBS }>(&key);
+
+        assert_eq!(
+            PrivateKey::generate(1000),
+            Err(Error::UnsupportedKeySize(1000))
+        );
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let key = |seed| {
+            PrivateKey::generate_with_rng(1024, &mut rand::rngs::StdRng::seed_from_u64(seed))
+                .unwrap()
+        };
+        assert_eq!(key(1), key(1));
+        assert_ne!(key(1), key(2));
+    }
+
+    #[test]
+    fn test_miller_rabin() {
+        // 2^521 - 1 is a Mersenne prime, and 561 and 2465 Carmichael numbers.
+        let mersenne = Uint::<{ U1024::LIMBS }>::ONE
+            .shl_vartime(521)
+            .wrapping_sub(&Uint::ONE);
+        assert!(is_probable_prime(&mersenne));
+        assert!(!is_probable_prime(
+            &mersenne.wrapping_add(&Uint::from_u32(2))
+        ));
+        assert!(!is_probable_prime(&U512::from_u32(561)));
+        assert!(!is_probable_prime(&U512::from_u32(2465)));
+        assert!(is_probable_prime(&U512::from_u32(1009)));
+    }
+
+    #[test]
+    fn test_debug_is_redacted() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let key = PrivateKey::generate_with_rng(1024, &mut rng).unwrap();
+        let debug = format!("{:?}", key);
+        assert!(debug.contains(&hex::encode(key.modulus())));
+        assert!(!debug.contains(&hex::encode(key.primes().0)));
+        assert!(!debug.contains(&hex::encode(key.private_exponent())));
+    }
+}
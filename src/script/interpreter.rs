@@ -0,0 +1,2158 @@
+//! Script execution engine, following Bitcoin Core's `EvalScript` and `VerifyScript`.
+
+use crate::context;
+use crate::encode;
+use crate::hash;
+use crate::script::opcodes::*;
+use crate::script::{self, Script};
+use crate::transaction::{self, sighash, Transaction, TxOut};
+use secp256k1::{schnorrsig, All, Message, PublicKey, Secp256k1, Signature};
+use std::ops::{BitOr, BitOrAssign};
+
+pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+pub const MAX_OPS_PER_SCRIPT: usize = 201;
+pub const MAX_PUBKEYS_PER_MULTISIG: i64 = 20;
+pub const MAX_SCRIPT_SIZE: usize = 10_000;
+pub const MAX_STACK_SIZE: usize = 1000;
+
+/// Leaf version of BIP342 tapscript leaves.
+pub const TAPROOT_LEAF_TAPSCRIPT: u8 = 0xc0;
+
+const TAPROOT_LEAF_MASK: u8 = 0xfe;
+const TAPROOT_CONTROL_BASE_SIZE: usize = 33;
+const TAPROOT_CONTROL_NODE_SIZE: usize = 32;
+const TAPROOT_CONTROL_MAX_NODE_COUNT: usize = 128;
+const ANNEX_TAG: u8 = 0x50;
+const VALIDATION_WEIGHT_PER_SIGOP_PASSED: i64 = 50;
+const VALIDATION_WEIGHT_OFFSET: i64 = 50;
+
+/// Script verification flags, with the same meanings and bit values as Bitcoin Core's
+/// `SCRIPT_VERIFY_*` constants.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct VerifyFlags(u32);
+
+impl VerifyFlags {
+    pub const NONE: VerifyFlags = VerifyFlags(0);
+    pub const P2SH: VerifyFlags = VerifyFlags(1 << 0);
+    pub const STRICTENC: VerifyFlags = VerifyFlags(1 << 1);
+    pub const DERSIG: VerifyFlags = VerifyFlags(1 << 2);
+    pub const LOW_S: VerifyFlags = VerifyFlags(1 << 3);
+    pub const NULLDUMMY: VerifyFlags = VerifyFlags(1 << 4);
+    pub const SIGPUSHONLY: VerifyFlags = VerifyFlags(1 << 5);
+    pub const MINIMALDATA: VerifyFlags = VerifyFlags(1 << 6);
+    pub const DISCOURAGE_UPGRADABLE_NOPS: VerifyFlags = VerifyFlags(1 << 7);
+    pub const CLEANSTACK: VerifyFlags = VerifyFlags(1 << 8);
+    pub const CHECKLOCKTIMEVERIFY: VerifyFlags = VerifyFlags(1 << 9);
+    pub const CHECKSEQUENCEVERIFY: VerifyFlags = VerifyFlags(1 << 10);
+    pub const WITNESS: VerifyFlags = VerifyFlags(1 << 11);
+    pub const DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM: VerifyFlags = VerifyFlags(1 << 12);
+    pub const MINIMALIF: VerifyFlags = VerifyFlags(1 << 13);
+    pub const NULLFAIL: VerifyFlags = VerifyFlags(1 << 14);
+    pub const WITNESS_PUBKEYTYPE: VerifyFlags = VerifyFlags(1 << 15);
+    pub const CONST_SCRIPTCODE: VerifyFlags = VerifyFlags(1 << 16);
+    pub const TAPROOT: VerifyFlags = VerifyFlags(1 << 17);
+    pub const DISCOURAGE_UPGRADABLE_TAPROOT_VERSION: VerifyFlags = VerifyFlags(1 << 18);
+    pub const DISCOURAGE_OP_SUCCESS: VerifyFlags = VerifyFlags(1 << 19);
+    pub const DISCOURAGE_UPGRADABLE_PUBKEYTYPE: VerifyFlags = VerifyFlags(1 << 20);
+
+    /// Flags enforced by consensus for blocks after the taproot activation.
+    pub const CONSENSUS: VerifyFlags = VerifyFlags(
+        VerifyFlags::P2SH.0
+            | VerifyFlags::DERSIG.0
+            | VerifyFlags::NULLDUMMY.0
+            | VerifyFlags::CHECKLOCKTIMEVERIFY.0
+            | VerifyFlags::CHECKSEQUENCEVERIFY.0
+            | VerifyFlags::WITNESS.0
+            | VerifyFlags::TAPROOT.0,
+    );
+
+    /// Flags Bitcoin Core applies to transactions before relaying them.
+    pub const STANDARD: VerifyFlags = VerifyFlags(
+        VerifyFlags::CONSENSUS.0
+            | VerifyFlags::STRICTENC.0
+            | VerifyFlags::MINIMALDATA.0
+            | VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS.0
+            | VerifyFlags::CLEANSTACK.0
+            | VerifyFlags::MINIMALIF.0
+            | VerifyFlags::NULLFAIL.0
+            | VerifyFlags::LOW_S.0
+            | VerifyFlags::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM.0
+            | VerifyFlags::WITNESS_PUBKEYTYPE.0
+            | VerifyFlags::CONST_SCRIPTCODE.0
+            | VerifyFlags::DISCOURAGE_UPGRADABLE_TAPROOT_VERSION.0
+            | VerifyFlags::DISCOURAGE_OP_SUCCESS.0
+            | VerifyFlags::DISCOURAGE_UPGRADABLE_PUBKEYTYPE.0,
+    );
+
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub fn from_bits(bits: u32) -> VerifyFlags {
+        VerifyFlags(bits)
+    }
+
+    pub fn contains(self, other: VerifyFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn difference(self, other: VerifyFlags) -> VerifyFlags {
+        VerifyFlags(self.0 & !other.0)
+    }
+}
+
+impl BitOr for VerifyFlags {
+    type Output = VerifyFlags;
+
+    fn bitor(self, rhs: VerifyFlags) -> VerifyFlags {
+        VerifyFlags(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for VerifyFlags {
+    fn bitor_assign(&mut self, rhs: VerifyFlags) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// The rule set a script is executed under.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SigVersion {
+    Base,
+    WitnessV0,
+    Taproot,
+    Tapscript,
+}
+
+/// Error represents all of the reasons script verification can fail. Variants correspond to
+/// Bitcoin Core's `SCRIPT_ERR_*` codes, plus a few describing missing transaction context.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Error {
+    EvalFalse,
+    OpReturn,
+    ScriptSize,
+    PushSize,
+    OpCount,
+    StackSize,
+    SigCount,
+    PubkeyCount,
+    Verify,
+    EqualVerify,
+    CheckMultisigVerify,
+    CheckSigVerify,
+    NumEqualVerify,
+    BadOpcode,
+    DisabledOpcode,
+    InvalidStackOperation,
+    InvalidAltstackOperation,
+    UnbalancedConditional,
+    NegativeLocktime,
+    UnsatisfiedLocktime,
+    SigHashType,
+    SigDer,
+    MinimalData,
+    SigPushOnly,
+    SigHighS,
+    SigNullDummy,
+    PubkeyType,
+    CleanStack,
+    MinimalIf,
+    NullFail,
+    DiscourageUpgradableNops,
+    DiscourageUpgradableWitnessProgram,
+    DiscourageUpgradableTaprootVersion,
+    DiscourageOpSuccess,
+    DiscourageUpgradablePubkeyType,
+    WitnessProgramWrongLength,
+    WitnessProgramWitnessEmpty,
+    WitnessProgramMismatch,
+    WitnessMalleated,
+    WitnessMalleatedP2sh,
+    WitnessUnexpected,
+    WitnessPubkeyType,
+    SchnorrSigSize,
+    SchnorrSigHashType,
+    SchnorrSig,
+    TaprootWrongControlSize,
+    TapscriptValidationWeight,
+    TapscriptCheckMultisig,
+    TapscriptMinimalIf,
+    TapscriptEmptyPubkey,
+    OpCodeSeparator,
+    SigFindAndDelete,
+    ScriptNum,
+    /// A taproot signature hash needs every spent output, but only one was provided.
+    MissingPrevouts,
+    InputIndexOutOfRange(usize),
+}
+
+/// Per-input state consulted when computing BIP341/BIP342 signature hashes.
+#[derive(Clone, Debug, Default)]
+pub struct ExecData {
+    /// Opcode position of the last executed `OP_CODESEPARATOR`, or `0xffffffff`.
+    pub codeseparator_pos: u32,
+    /// The annex, including its `0x50` tag, if the witness has one.
+    pub annex: Option<Vec<u8>>,
+    /// Hash of the tapscript leaf being executed.
+    pub tapleaf_hash: Option<[u8; 32]>,
+    /// Remaining BIP342 signature validation budget.
+    pub validation_weight_left: i64,
+}
+
+/// Provides the transaction-dependent checks needed while executing a script.
+///
+/// The default implementations fail every check, which is suitable for evaluating scripts
+/// outside of any transaction.
+pub trait SignatureChecker {
+    fn check_ecdsa_signature(
+        &self,
+        _sig: &[u8],
+        _pubkey: &[u8],
+        _script_code: &[u8],
+        _sig_version: SigVersion,
+    ) -> bool {
+        false
+    }
+
+    fn check_schnorr_signature(
+        &self,
+        _sig: &[u8],
+        _pubkey: &[u8],
+        _sig_version: SigVersion,
+        _exec_data: &ExecData,
+    ) -> Result<(), Error> {
+        Err(Error::SchnorrSig)
+    }
+
+    fn check_lock_time(&self, _lock_time: i64) -> bool {
+        false
+    }
+
+    fn check_sequence(&self, _sequence: i64) -> bool {
+        false
+    }
+}
+
+/// Checks signatures and lock times against a specific input of a transaction.
+pub struct TransactionChecker<'a> {
+    tx: &'a Transaction,
+    input_index: usize,
+    prevout: &'a TxOut,
+    prevouts: Option<&'a [TxOut]>,
+    secp: &'static Secp256k1<All>,
+}
+
+impl<'a> TransactionChecker<'a> {
+    /// Creates a checker which knows only the output spent by the input being verified.
+    ///
+    /// Taproot signatures commit to every spent output, so they can only be checked this way
+    /// when the transaction has a single input.
+    pub fn new(tx: &'a Transaction, input_index: usize, prevout: &'a TxOut) -> Self {
+        TransactionChecker {
+            tx,
+            input_index,
+            prevout,
+            prevouts: None,
+            secp: context::secp256k1(),
+        }
+    }
+
+    /// Creates a checker from the outputs spent by every input of the transaction.
+    pub fn with_prevouts(
+        tx: &'a Transaction,
+        input_index: usize,
+        prevouts: &'a [TxOut],
+    ) -> Result<Self, Error> {
+        let prevout = prevouts
+            .get(input_index)
+            .ok_or(Error::InputIndexOutOfRange(input_index))?;
+
+        Ok(TransactionChecker {
+            tx,
+            input_index,
+            prevout,
+            prevouts: Some(prevouts),
+            secp: context::secp256k1(),
+        })
+    }
+
+    fn all_prevouts(&self) -> Option<&'a [TxOut]> {
+        match self.prevouts {
+            Some(prevouts) => Some(prevouts),
+            None if self.tx.inputs.len() == 1 => Some(std::slice::from_ref(self.prevout)),
+            None => None,
+        }
+    }
+}
+
+impl SignatureChecker for TransactionChecker<'_> {
+    fn check_ecdsa_signature(
+        &self,
+        sig: &[u8],
+        pubkey: &[u8],
+        script_code: &[u8],
+        sig_version: SigVersion,
+    ) -> bool {
+        if !is_valid_pubkey_size(pubkey) {
+            return false;
+        }
+
+        let pubkey = match PublicKey::from_slice(pubkey) {
+            Ok(pubkey) => pubkey,
+            Err(_) => return false,
+        };
+
+        let (hash_type, sig) = match sig.split_last() {
+            Some((hash_type, sig)) => (*hash_type as u32, sig),
+            None => return false,
+        };
+
+        let sighash = match sig_version {
+            SigVersion::WitnessV0 => match sighash::segwit_v0(
+                self.tx,
+                self.input_index,
+                script_code,
+                self.prevout.value,
+                hash_type,
+            ) {
+                Ok(sighash) => sighash,
+                Err(_) => return false,
+            },
+            _ => sighash::legacy(self.tx, self.input_index, script_code, hash_type),
+        };
+
+        let mut sig = match Signature::from_der_lax(sig) {
+            Ok(sig) => sig,
+            Err(_) => return false,
+        };
+        sig.normalize_s();
+
+        let msg = Message::from_slice(&sighash).expect("sighash is 32 bytes");
+        self.secp.verify(&msg, &sig, &pubkey).is_ok()
+    }
+
+    fn check_schnorr_signature(
+        &self,
+        sig: &[u8],
+        pubkey: &[u8],
+        sig_version: SigVersion,
+        exec_data: &ExecData,
+    ) -> Result<(), Error> {
+        if sig.len() != 64 && sig.len() != 65 {
+            return Err(Error::SchnorrSigSize);
+        }
+
+        let (sig, hash_type) = match sig.len() {
+            65 if sig[64] == sighash::SIGHASH_DEFAULT => return Err(Error::SchnorrSigHashType),
+            65 => (&sig[..64], sig[64]),
+            _ => (sig, sighash::SIGHASH_DEFAULT),
+        };
+
+        let prevouts = self.all_prevouts().ok_or(Error::MissingPrevouts)?;
+
+        let script_path = match sig_version {
+            SigVersion::Tapscript => Some(sighash::ScriptPath {
+                leaf_hash: exec_data
+                    .tapleaf_hash
+                    .expect("tapleaf hash is set for tapscript"),
+                codeseparator_pos: exec_data.codeseparator_pos,
+            }),
+            _ => None,
+        };
+
+        let sighash = sighash::taproot(
+            self.tx,
+            self.input_index,
+            prevouts,
+            hash_type,
+            exec_data.annex.as_deref(),
+            script_path,
+        )
+        .map_err(|err| match err {
+            sighash::Error::PrevoutsMismatch { .. } => Error::MissingPrevouts,
+            _ => Error::SchnorrSigHashType,
+        })?;
+
+        let msg = Message::from_slice(&sighash).expect("sighash is 32 bytes");
+        let pubkey = schnorrsig::PublicKey::from_slice(pubkey).map_err(|_| Error::SchnorrSig)?;
+        let sig = schnorrsig::Signature::from_slice(sig).map_err(|_| Error::SchnorrSig)?;
+
+        self.secp
+            .schnorrsig_verify(&sig, &msg, &pubkey)
+            .map_err(|_| Error::SchnorrSig)
+    }
+
+    fn check_lock_time(&self, lock_time: i64) -> bool {
+        let tx_lock_time = self.tx.lock_time as i64;
+        let threshold = transaction::LOCKTIME_THRESHOLD as i64;
+
+        // Heights can only be compared against heights and times against times.
+        if (tx_lock_time < threshold) != (lock_time < threshold) {
+            return false;
+        }
+
+        if lock_time > tx_lock_time {
+            return false;
+        }
+
+        // A final input disables the transaction's lock time, and with it this check.
+        self.tx.inputs[self.input_index].sequence != transaction::SEQUENCE_FINAL
+    }
+
+    fn check_sequence(&self, sequence: i64) -> bool {
+        let tx_sequence = self.tx.inputs[self.input_index].sequence as i64;
+
+        if (self.tx.version as u32) < 2 {
+            return false;
+        }
+
+        if tx_sequence & transaction::SEQUENCE_LOCKTIME_DISABLE_FLAG as i64 != 0 {
+            return false;
+        }
+
+        let mask =
+            (transaction::SEQUENCE_LOCKTIME_TYPE_FLAG | transaction::SEQUENCE_LOCKTIME_MASK) as i64;
+        let type_flag = transaction::SEQUENCE_LOCKTIME_TYPE_FLAG as i64;
+        let tx_sequence = tx_sequence & mask;
+        let sequence = sequence & mask;
+
+        if (tx_sequence < type_flag) != (sequence < type_flag) {
+            return false;
+        }
+
+        sequence <= tx_sequence
+    }
+}
+
+/// Verifies input `index` of `tx` against the output it spends using the standard flags.
+///
+/// Taproot inputs of transactions with more than one input need every spent output; use
+/// `verify_input_with_prevouts` for those.
+pub fn verify_input(tx: &Transaction, index: usize, prevout: &TxOut) -> Result<(), Error> {
+    let input = tx
+        .inputs
+        .get(index)
+        .ok_or(Error::InputIndexOutOfRange(index))?;
+    let checker = TransactionChecker::new(tx, index, prevout);
+
+    verify_script(
+        &input.script_sig,
+        &prevout.script_pubkey,
+        &input.witness,
+        VerifyFlags::STANDARD,
+        &checker,
+    )
+}
+
+/// Verifies input `index` of `tx` given the outputs spent by all of its inputs.
+pub fn verify_input_with_prevouts(
+    tx: &Transaction,
+    index: usize,
+    prevouts: &[TxOut],
+    flags: VerifyFlags,
+) -> Result<(), Error> {
+    let input = tx
+        .inputs
+        .get(index)
+        .ok_or(Error::InputIndexOutOfRange(index))?;
+    let checker = TransactionChecker::with_prevouts(tx, index, prevouts)?;
+
+    verify_script(
+        &input.script_sig,
+        &prevouts[index].script_pubkey,
+        &input.witness,
+        flags,
+        &checker,
+    )
+}
+
+/// Verifies that `script_sig` and `witness` satisfy `script_pubkey`.
+///
+/// # Panics
+///
+/// As in Bitcoin Core, `CLEANSTACK` requires both `P2SH` and `WITNESS`, and `WITNESS` requires
+/// `P2SH`; any other combination is a programming error.
+pub fn verify_script<C: SignatureChecker>(
+    script_sig: &Script,
+    script_pubkey: &Script,
+    witness: &[Vec<u8>],
+    flags: VerifyFlags,
+    checker: &C,
+) -> Result<(), Error> {
+    if flags.contains(VerifyFlags::CLEANSTACK) {
+        assert!(flags.contains(VerifyFlags::P2SH | VerifyFlags::WITNESS));
+    }
+    if flags.contains(VerifyFlags::WITNESS) {
+        assert!(flags.contains(VerifyFlags::P2SH));
+    }
+
+    let mut had_witness = false;
+
+    if flags.contains(VerifyFlags::SIGPUSHONLY) && !script_sig.is_push_only() {
+        return Err(Error::SigPushOnly);
+    }
+
+    // The scriptSig and scriptPubKey are evaluated sequentially on the same stack rather than
+    // being concatenated (see CVE-2010-5141).
+    let mut stack = Vec::new();
+    let mut exec_data = ExecData::default();
+    eval_script(
+        &mut stack,
+        script_sig.as_bytes(),
+        flags,
+        checker,
+        SigVersion::Base,
+        &mut exec_data,
+    )?;
+
+    let stack_copy = if flags.contains(VerifyFlags::P2SH) {
+        stack.clone()
+    } else {
+        Vec::new()
+    };
+
+    eval_script(
+        &mut stack,
+        script_pubkey.as_bytes(),
+        flags,
+        checker,
+        SigVersion::Base,
+        &mut exec_data,
+    )?;
+
+    match stack.last() {
+        Some(top) if cast_to_bool(top) => {}
+        _ => return Err(Error::EvalFalse),
+    }
+
+    if flags.contains(VerifyFlags::WITNESS) {
+        if let Some((version, program)) = script_pubkey.witness_program() {
+            had_witness = true;
+
+            // The scriptSig must be exactly empty, otherwise malleability is reintroduced.
+            if !script_sig.is_empty() {
+                return Err(Error::WitnessMalleated);
+            }
+
+            verify_witness_program(witness, version, program, flags, checker, false)?;
+
+            // The stack is obviously not clean for witness programs, so bypass that check.
+            stack.truncate(1);
+        }
+    }
+
+    if flags.contains(VerifyFlags::P2SH) && script_pubkey.is_p2sh() {
+        if !script_sig.is_push_only() {
+            return Err(Error::SigPushOnly);
+        }
+
+        stack = stack_copy;
+
+        // The stack cannot be empty, since the P2SH scriptPubKey would then have failed.
+        let redeem_script = Script::from(stack.pop().expect("P2SH stack is not empty"));
+
+        eval_script(
+            &mut stack,
+            redeem_script.as_bytes(),
+            flags,
+            checker,
+            SigVersion::Base,
+            &mut exec_data,
+        )?;
+
+        match stack.last() {
+            Some(top) if cast_to_bool(top) => {}
+            _ => return Err(Error::EvalFalse),
+        }
+
+        if flags.contains(VerifyFlags::WITNESS) {
+            if let Some((version, program)) = redeem_script.witness_program() {
+                had_witness = true;
+
+                // The scriptSig must be exactly a single push of the redeem script.
+                let mut expected = Vec::new();
+                script::push_data(&mut expected, redeem_script.as_bytes());
+                if script_sig.as_bytes() != &expected[..] {
+                    return Err(Error::WitnessMalleatedP2sh);
+                }
+
+                verify_witness_program(witness, version, program, flags, checker, true)?;
+
+                stack.truncate(1);
+            }
+        }
+    }
+
+    // Only performed after P2SH and witness evaluation, which obviously leave a dirty stack.
+    if flags.contains(VerifyFlags::CLEANSTACK) && stack.len() != 1 {
+        return Err(Error::CleanStack);
+    }
+
+    if flags.contains(VerifyFlags::WITNESS) && !had_witness && !witness.is_empty() {
+        return Err(Error::WitnessUnexpected);
+    }
+
+    Ok(())
+}
+
+fn verify_witness_program<C: SignatureChecker>(
+    witness: &[Vec<u8>],
+    version: u8,
+    program: &[u8],
+    flags: VerifyFlags,
+    checker: &C,
+    is_p2sh: bool,
+) -> Result<(), Error> {
+    let mut exec_data = ExecData::default();
+
+    if version == 0 {
+        if program.len() == 32 {
+            // P2WSH: the last witness item is the script, committed to by its SHA256.
+            let (script, stack) = witness
+                .split_last()
+                .ok_or(Error::WitnessProgramWitnessEmpty)?;
+
+            if hash::sha256(script) != program {
+                return Err(Error::WitnessProgramMismatch);
+            }
+
+            execute_witness_script(
+                stack,
+                script,
+                flags,
+                SigVersion::WitnessV0,
+                checker,
+                &mut exec_data,
+            )
+        } else if program.len() == 20 {
+            // P2WPKH: exactly a signature and a public key, checked by the implied P2PKH script.
+            if witness.len() != 2 {
+                return Err(Error::WitnessProgramMismatch);
+            }
+
+            let script = script::Builder::new()
+                .push_opcode(OP_DUP)
+                .push_opcode(OP_HASH160)
+                .push_slice(program)
+                .push_opcode(OP_EQUALVERIFY)
+                .push_opcode(OP_CHECKSIG)
+                .into_script();
+
+            execute_witness_script(
+                witness,
+                script.as_bytes(),
+                flags,
+                SigVersion::WitnessV0,
+                checker,
+                &mut exec_data,
+            )
+        } else {
+            Err(Error::WitnessProgramWrongLength)
+        }
+    } else if version == 1 && program.len() == 32 && !is_p2sh {
+        if !flags.contains(VerifyFlags::TAPROOT) {
+            return Ok(());
+        }
+
+        let mut stack = witness;
+        if stack.is_empty() {
+            return Err(Error::WitnessProgramWitnessEmpty);
+        }
+
+        if stack.len() >= 2 {
+            if let Some((annex, rest)) = stack.split_last() {
+                if annex.first() == Some(&ANNEX_TAG) {
+                    exec_data.annex = Some(annex.clone());
+                    stack = rest;
+                }
+            }
+        }
+
+        if stack.len() == 1 {
+            // Key path spending.
+            return checker.check_schnorr_signature(
+                &stack[0],
+                program,
+                SigVersion::Taproot,
+                &exec_data,
+            );
+        }
+
+        // Script path spending.
+        let (control, stack) = stack.split_last().unwrap();
+        let (script, stack) = stack.split_last().unwrap();
+
+        if control.len() < TAPROOT_CONTROL_BASE_SIZE
+            || control.len()
+                > TAPROOT_CONTROL_BASE_SIZE
+                    + TAPROOT_CONTROL_NODE_SIZE * TAPROOT_CONTROL_MAX_NODE_COUNT
+            || (control.len() - TAPROOT_CONTROL_BASE_SIZE) % TAPROOT_CONTROL_NODE_SIZE != 0
+        {
+            return Err(Error::TaprootWrongControlSize);
+        }
+
+        let leaf_hash = tapleaf_hash(control[0] & TAPROOT_LEAF_MASK, script);
+        if !verify_taproot_commitment(control, program, &leaf_hash) {
+            return Err(Error::WitnessProgramMismatch);
+        }
+        exec_data.tapleaf_hash = Some(leaf_hash);
+
+        if control[0] & TAPROOT_LEAF_MASK == TAPROOT_LEAF_TAPSCRIPT {
+            exec_data.validation_weight_left =
+                witness_serialized_size(witness) as i64 + VALIDATION_WEIGHT_OFFSET;
+
+            return execute_witness_script(
+                stack,
+                script,
+                flags,
+                SigVersion::Tapscript,
+                checker,
+                &mut exec_data,
+            );
+        }
+
+        if flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_TAPROOT_VERSION) {
+            return Err(Error::DiscourageUpgradableTaprootVersion);
+        }
+
+        Ok(())
+    } else if version == 1 && program == [0x4e, 0x73] && !is_p2sh {
+        // Pay-to-anchor outputs are anyone-can-spend.
+        Ok(())
+    } else {
+        if flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM) {
+            return Err(Error::DiscourageUpgradableWitnessProgram);
+        }
+
+        // Other version, size and P2SH combinations are reserved for future soft forks.
+        Ok(())
+    }
+}
+
+fn execute_witness_script<C: SignatureChecker>(
+    stack: &[Vec<u8>],
+    script: &[u8],
+    flags: VerifyFlags,
+    sig_version: SigVersion,
+    checker: &C,
+    exec_data: &mut ExecData,
+) -> Result<(), Error> {
+    if sig_version == SigVersion::Tapscript {
+        // OP_SUCCESSx processing overrides everything, including stack element size limits.
+        let mut pc = 0;
+        while pc < script.len() {
+            let ins = script::get_op(script, &mut pc).ok_or(Error::BadOpcode)?;
+
+            if is_op_success(ins.opcode) {
+                if flags.contains(VerifyFlags::DISCOURAGE_OP_SUCCESS) {
+                    return Err(Error::DiscourageOpSuccess);
+                }
+                return Ok(());
+            }
+        }
+
+        if stack.len() > MAX_STACK_SIZE {
+            return Err(Error::StackSize);
+        }
+    }
+
+    if stack
+        .iter()
+        .any(|item| item.len() > MAX_SCRIPT_ELEMENT_SIZE)
+    {
+        return Err(Error::PushSize);
+    }
+
+    let mut stack = stack.to_vec();
+    eval_script(&mut stack, script, flags, checker, sig_version, exec_data)?;
+
+    // Scripts inside a witness implicitly require a clean stack.
+    if stack.len() != 1 {
+        return Err(Error::CleanStack);
+    }
+
+    if !cast_to_bool(&stack[0]) {
+        return Err(Error::EvalFalse);
+    }
+
+    Ok(())
+}
+
+/// Computes the BIP341 hash of a script leaf.
+pub fn tapleaf_hash(leaf_version: u8, script: &[u8]) -> [u8; 32] {
+    let mut data = vec![leaf_version];
+    encode::write_var_bytes(&mut data, script);
+    hash::tagged_hash("TapLeaf", &data)
+}
+
+/// Computes the BIP341 hash of an inner node of a script tree.
+pub fn tapbranch_hash(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let (left, right) = if a < b { (a, b) } else { (b, a) };
+    let mut data = [0u8; 64];
+    data[..32].copy_from_slice(left);
+    data[32..].copy_from_slice(right);
+    hash::tagged_hash("TapBranch", &data)
+}
+
+/// Computes the BIP341 tweak committing `internal_key` to an optional script tree root.
+pub fn taptweak_hash(internal_key: &[u8; 32], merkle_root: Option<&[u8; 32]>) -> [u8; 32] {
+    let mut data = internal_key.to_vec();
+    if let Some(root) = merkle_root {
+        data.extend(root);
+    }
+    hash::tagged_hash("TapTweak", &data)
+}
+
+fn verify_taproot_commitment(control: &[u8], program: &[u8], leaf_hash: &[u8; 32]) -> bool {
+    let secp = context::secp256k1();
+
+    let internal_key =
+        match schnorrsig::PublicKey::from_slice(&control[1..TAPROOT_CONTROL_BASE_SIZE]) {
+            Ok(key) => key,
+            Err(_) => return false,
+        };
+
+    let output_key = match schnorrsig::PublicKey::from_slice(program) {
+        Ok(key) => key,
+        Err(_) => return false,
+    };
+
+    let merkle_root = control[TAPROOT_CONTROL_BASE_SIZE..]
+        .chunks(TAPROOT_CONTROL_NODE_SIZE)
+        .fold(*leaf_hash, |k, node| {
+            tapbranch_hash(&k, &copy_from_slice!([0u8; 32], node))
+        });
+
+    let tweak = taptweak_hash(&internal_key.serialize(), Some(&merkle_root));
+    internal_key.tweak_add_check(secp, &output_key, control[0] & 1 == 1, tweak)
+}
+
+fn witness_serialized_size(witness: &[Vec<u8>]) -> usize {
+    encode::compact_size_len(witness.len() as u64)
+        + witness
+            .iter()
+            .map(|item| encode::compact_size_len(item.len() as u64) + item.len())
+            .sum::<usize>()
+}
+
+fn is_op_success(opcode: u8) -> bool {
+    matches!(
+        opcode,
+        80 | 98 | 126..=129 | 131..=134 | 137..=138 | 141..=142 | 149..=153 | 187..=254
+    )
+}
+
+/// Tracks nested `OP_IF` branches without storing each branch's value.
+struct ConditionStack {
+    size: usize,
+    first_false_pos: Option<usize>,
+}
+
+impl ConditionStack {
+    fn new() -> ConditionStack {
+        ConditionStack {
+            size: 0,
+            first_false_pos: None,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    fn all_true(&self) -> bool {
+        self.first_false_pos.is_none()
+    }
+
+    fn push(&mut self, value: bool) {
+        if self.first_false_pos.is_none() && !value {
+            self.first_false_pos = Some(self.size);
+        }
+        self.size += 1;
+    }
+
+    fn pop(&mut self) {
+        self.size -= 1;
+        if self.first_false_pos == Some(self.size) {
+            self.first_false_pos = None;
+        }
+    }
+
+    fn toggle_top(&mut self) {
+        if self.first_false_pos.is_none() {
+            self.first_false_pos = Some(self.size - 1);
+        } else if self.first_false_pos == Some(self.size - 1) {
+            self.first_false_pos = None;
+        }
+    }
+}
+
+/// Executes `script` on `stack`.
+pub fn eval_script<C: SignatureChecker>(
+    stack: &mut Vec<Vec<u8>>,
+    script: &[u8],
+    flags: VerifyFlags,
+    checker: &C,
+    sig_version: SigVersion,
+    exec_data: &mut ExecData,
+) -> Result<(), Error> {
+    let is_legacy_or_v0 = sig_version == SigVersion::Base || sig_version == SigVersion::WitnessV0;
+
+    if is_legacy_or_v0 && script.len() > MAX_SCRIPT_SIZE {
+        return Err(Error::ScriptSize);
+    }
+
+    let require_minimal = flags.contains(VerifyFlags::MINIMALDATA);
+    let mut pc = 0;
+    let mut begin_code_hash = 0;
+    let mut op_count = 0;
+    let mut opcode_pos: u32 = 0;
+    let mut exec_stack = ConditionStack::new();
+    let mut altstack: Vec<Vec<u8>> = Vec::new();
+    exec_data.codeseparator_pos = 0xffff_ffff;
+
+    while pc < script.len() {
+        let exec = exec_stack.all_true();
+
+        let ins = script::get_op(script, &mut pc).ok_or(Error::BadOpcode)?;
+        let opcode = ins.opcode;
+
+        if ins.data.len() > MAX_SCRIPT_ELEMENT_SIZE {
+            return Err(Error::PushSize);
+        }
+
+        // Note how OP_RESERVED does not count towards the opcode limit.
+        if is_legacy_or_v0 && opcode > OP_16 {
+            op_count += 1;
+            if op_count > MAX_OPS_PER_SCRIPT {
+                return Err(Error::OpCount);
+            }
+        }
+
+        if is_disabled(opcode) {
+            return Err(Error::DisabledOpcode);
+        }
+
+        // With CONST_SCRIPTCODE, OP_CODESEPARATOR in legacy scripts is rejected even when
+        // unexecuted.
+        if opcode == OP_CODESEPARATOR
+            && sig_version == SigVersion::Base
+            && flags.contains(VerifyFlags::CONST_SCRIPTCODE)
+        {
+            return Err(Error::OpCodeSeparator);
+        }
+
+        if exec && opcode <= OP_PUSHDATA4 {
+            if require_minimal && !is_minimal_push(ins.data, opcode) {
+                return Err(Error::MinimalData);
+            }
+            stack.push(ins.data.to_vec());
+        } else if exec || (OP_IF..=OP_ENDIF).contains(&opcode) {
+            match opcode {
+                OP_1NEGATE | OP_1..=OP_16 => {
+                    let n = opcode as i64 - (OP_1 as i64 - 1);
+                    stack.push(script::encode_num(n));
+                }
+
+                OP_NOP => {}
+
+                OP_CHECKLOCKTIMEVERIFY => {
+                    if !flags.contains(VerifyFlags::CHECKLOCKTIMEVERIFY) {
+                        // Not enabled; treat as OP_NOP2.
+                        continue_after_op(stack, &altstack)?;
+                        opcode_pos += 1;
+                        continue;
+                    }
+
+                    // Lock times are 32-bit unsigned, so 5-byte operands are allowed here.
+                    let lock_time = decode_num(top(stack, 1)?, require_minimal, 5)?;
+
+                    if lock_time < 0 {
+                        return Err(Error::NegativeLocktime);
+                    }
+
+                    if !checker.check_lock_time(lock_time) {
+                        return Err(Error::UnsatisfiedLocktime);
+                    }
+                }
+
+                OP_CHECKSEQUENCEVERIFY => {
+                    if !flags.contains(VerifyFlags::CHECKSEQUENCEVERIFY) {
+                        // Not enabled; treat as OP_NOP3.
+                        continue_after_op(stack, &altstack)?;
+                        opcode_pos += 1;
+                        continue;
+                    }
+
+                    let sequence = decode_num(top(stack, 1)?, require_minimal, 5)?;
+
+                    if sequence < 0 {
+                        return Err(Error::NegativeLocktime);
+                    }
+
+                    // With the disable flag set, CHECKSEQUENCEVERIFY behaves as a NOP, leaving
+                    // room for future soft forks.
+                    if sequence & transaction::SEQUENCE_LOCKTIME_DISABLE_FLAG as i64 == 0
+                        && !checker.check_sequence(sequence)
+                    {
+                        return Err(Error::UnsatisfiedLocktime);
+                    }
+                }
+
+                OP_NOP1 | OP_NOP4..=OP_NOP10 => {
+                    if flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS) {
+                        return Err(Error::DiscourageUpgradableNops);
+                    }
+                }
+
+                OP_IF | OP_NOTIF => {
+                    let mut value = false;
+
+                    if exec {
+                        let top = top(stack, 1)?;
+
+                        if sig_version == SigVersion::Tapscript
+                            && (top.len() > 1 || (top.len() == 1 && top[0] != 1))
+                        {
+                            return Err(Error::TapscriptMinimalIf);
+                        }
+
+                        if sig_version == SigVersion::WitnessV0
+                            && flags.contains(VerifyFlags::MINIMALIF)
+                            && (top.len() > 1 || (top.len() == 1 && top[0] != 1))
+                        {
+                            return Err(Error::MinimalIf);
+                        }
+
+                        value = cast_to_bool(top);
+                        if opcode == OP_NOTIF {
+                            value = !value;
+                        }
+                        stack.pop();
+                    }
+
+                    exec_stack.push(value);
+                }
+
+                OP_ELSE => {
+                    if exec_stack.is_empty() {
+                        return Err(Error::UnbalancedConditional);
+                    }
+                    exec_stack.toggle_top();
+                }
+
+                OP_ENDIF => {
+                    if exec_stack.is_empty() {
+                        return Err(Error::UnbalancedConditional);
+                    }
+                    exec_stack.pop();
+                }
+
+                OP_VERIFY => {
+                    if !cast_to_bool(top(stack, 1)?) {
+                        return Err(Error::Verify);
+                    }
+                    stack.pop();
+                }
+
+                OP_RETURN => return Err(Error::OpReturn),
+
+                OP_TOALTSTACK => {
+                    let item = stack.pop().ok_or(Error::InvalidStackOperation)?;
+                    altstack.push(item);
+                }
+
+                OP_FROMALTSTACK => {
+                    let item = altstack.pop().ok_or(Error::InvalidAltstackOperation)?;
+                    stack.push(item);
+                }
+
+                OP_2DROP => {
+                    require(stack, 2)?;
+                    stack.truncate(stack.len() - 2);
+                }
+
+                OP_2DUP => {
+                    require(stack, 2)?;
+                    let n = stack.len();
+                    stack.extend_from_within(n - 2..);
+                }
+
+                OP_3DUP => {
+                    require(stack, 3)?;
+                    let n = stack.len();
+                    stack.extend_from_within(n - 3..);
+                }
+
+                OP_2OVER => {
+                    require(stack, 4)?;
+                    let n = stack.len();
+                    stack.extend_from_within(n - 4..n - 2);
+                }
+
+                OP_2ROT => {
+                    require(stack, 6)?;
+                    let n = stack.len();
+                    let moved = stack.drain(n - 6..n - 4).collect::<Vec<_>>();
+                    stack.extend(moved);
+                }
+
+                OP_2SWAP => {
+                    require(stack, 4)?;
+                    let n = stack.len();
+                    stack.swap(n - 4, n - 2);
+                    stack.swap(n - 3, n - 1);
+                }
+
+                OP_IFDUP => {
+                    let top = top(stack, 1)?;
+                    if cast_to_bool(top) {
+                        let top = top.clone();
+                        stack.push(top);
+                    }
+                }
+
+                OP_DEPTH => {
+                    let depth = stack.len() as i64;
+                    stack.push(script::encode_num(depth));
+                }
+
+                OP_DROP => {
+                    stack.pop().ok_or(Error::InvalidStackOperation)?;
+                }
+
+                OP_DUP => {
+                    let top = top(stack, 1)?.clone();
+                    stack.push(top);
+                }
+
+                OP_NIP => {
+                    require(stack, 2)?;
+                    stack.remove(stack.len() - 2);
+                }
+
+                OP_OVER => {
+                    let item = top(stack, 2)?.clone();
+                    stack.push(item);
+                }
+
+                OP_PICK | OP_ROLL => {
+                    require(stack, 2)?;
+                    let n = clamp_int(decode_num(top(stack, 1)?, require_minimal, 4)?);
+                    stack.pop();
+
+                    if n < 0 || n as usize >= stack.len() {
+                        return Err(Error::InvalidStackOperation);
+                    }
+
+                    let index = stack.len() - n as usize - 1;
+                    let item = if opcode == OP_ROLL {
+                        stack.remove(index)
+                    } else {
+                        stack[index].clone()
+                    };
+                    stack.push(item);
+                }
+
+                OP_ROT => {
+                    require(stack, 3)?;
+                    let n = stack.len();
+                    stack.swap(n - 3, n - 2);
+                    stack.swap(n - 2, n - 1);
+                }
+
+                OP_SWAP => {
+                    require(stack, 2)?;
+                    let n = stack.len();
+                    stack.swap(n - 2, n - 1);
+                }
+
+                OP_TUCK => {
+                    let item = top(stack, 1)?.clone();
+                    require(stack, 2)?;
+                    stack.insert(stack.len() - 2, item);
+                }
+
+                OP_SIZE => {
+                    let size = top(stack, 1)?.len() as i64;
+                    stack.push(script::encode_num(size));
+                }
+
+                OP_EQUAL | OP_EQUALVERIFY => {
+                    require(stack, 2)?;
+                    let a = stack.pop().unwrap();
+                    let b = stack.pop().unwrap();
+                    let equal = a == b;
+
+                    if opcode == OP_EQUALVERIFY {
+                        if !equal {
+                            stack.push(bool_to_vec(equal));
+                            return Err(Error::EqualVerify);
+                        }
+                    } else {
+                        stack.push(bool_to_vec(equal));
+                    }
+                }
+
+                OP_1ADD | OP_1SUB | OP_NEGATE | OP_ABS | OP_NOT | OP_0NOTEQUAL => {
+                    let n = decode_num(top(stack, 1)?, require_minimal, 4)?;
+                    let result = match opcode {
+                        OP_1ADD => n + 1,
+                        OP_1SUB => n - 1,
+                        OP_NEGATE => -n,
+                        OP_ABS => n.abs(),
+                        OP_NOT => (n == 0) as i64,
+                        _ => (n != 0) as i64,
+                    };
+                    stack.pop();
+                    stack.push(script::encode_num(result));
+                }
+
+                OP_ADD
+                | OP_SUB
+                | OP_BOOLAND
+                | OP_BOOLOR
+                | OP_NUMEQUAL
+                | OP_NUMEQUALVERIFY
+                | OP_NUMNOTEQUAL
+                | OP_LESSTHAN
+                | OP_GREATERTHAN
+                | OP_LESSTHANOREQUAL
+                | OP_GREATERTHANOREQUAL
+                | OP_MIN
+                | OP_MAX => {
+                    require(stack, 2)?;
+                    let a = decode_num(top(stack, 2)?, require_minimal, 4)?;
+                    let b = decode_num(top(stack, 1)?, require_minimal, 4)?;
+                    let result = match opcode {
+                        OP_ADD => a + b,
+                        OP_SUB => a - b,
+                        OP_BOOLAND => (a != 0 && b != 0) as i64,
+                        OP_BOOLOR => (a != 0 || b != 0) as i64,
+                        OP_NUMEQUAL | OP_NUMEQUALVERIFY => (a == b) as i64,
+                        OP_NUMNOTEQUAL => (a != b) as i64,
+                        OP_LESSTHAN => (a < b) as i64,
+                        OP_GREATERTHAN => (a > b) as i64,
+                        OP_LESSTHANOREQUAL => (a <= b) as i64,
+                        OP_GREATERTHANOREQUAL => (a >= b) as i64,
+                        OP_MIN => a.min(b),
+                        _ => a.max(b),
+                    };
+                    stack.truncate(stack.len() - 2);
+                    stack.push(script::encode_num(result));
+
+                    if opcode == OP_NUMEQUALVERIFY {
+                        if result == 0 {
+                            return Err(Error::NumEqualVerify);
+                        }
+                        stack.pop();
+                    }
+                }
+
+                OP_WITHIN => {
+                    require(stack, 3)?;
+                    let x = decode_num(top(stack, 3)?, require_minimal, 4)?;
+                    let min = decode_num(top(stack, 2)?, require_minimal, 4)?;
+                    let max = decode_num(top(stack, 1)?, require_minimal, 4)?;
+                    stack.truncate(stack.len() - 3);
+                    stack.push(bool_to_vec(min <= x && x < max));
+                }
+
+                OP_RIPEMD160 | OP_SHA1 | OP_SHA256 | OP_HASH160 | OP_HASH256 => {
+                    let item = stack.pop().ok_or(Error::InvalidStackOperation)?;
+                    let digest = match opcode {
+                        OP_RIPEMD160 => hash::ripemd160(&item).to_vec(),
+                        OP_SHA1 => hash::sha1(&item).to_vec(),
+                        OP_SHA256 => hash::sha256(&item).to_vec(),
+                        OP_HASH160 => hash::hash160(&item).to_vec(),
+                        _ => hash::double_sha256(&item).to_vec(),
+                    };
+                    stack.push(digest);
+                }
+
+                OP_CODESEPARATOR => {
+                    // Signatures commit to the script following the last executed separator.
+                    begin_code_hash = pc;
+                    exec_data.codeseparator_pos = opcode_pos;
+                }
+
+                OP_CHECKSIG | OP_CHECKSIGVERIFY => {
+                    require(stack, 2)?;
+                    let pubkey = stack.pop().unwrap();
+                    let sig = stack.pop().unwrap();
+
+                    let success = eval_checksig(
+                        &sig,
+                        &pubkey,
+                        &script[begin_code_hash..],
+                        exec_data,
+                        flags,
+                        checker,
+                        sig_version,
+                    )?;
+
+                    if opcode == OP_CHECKSIGVERIFY {
+                        if !success {
+                            stack.push(bool_to_vec(success));
+                            return Err(Error::CheckSigVerify);
+                        }
+                    } else {
+                        stack.push(bool_to_vec(success));
+                    }
+                }
+
+                OP_CHECKSIGADD => {
+                    if is_legacy_or_v0 {
+                        return Err(Error::BadOpcode);
+                    }
+
+                    require(stack, 3)?;
+                    let n = decode_num(top(stack, 2)?, require_minimal, 4)?;
+                    let pubkey = stack.pop().unwrap();
+                    stack.pop();
+                    let sig = stack.pop().unwrap();
+
+                    let success = eval_checksig(
+                        &sig,
+                        &pubkey,
+                        &script[begin_code_hash..],
+                        exec_data,
+                        flags,
+                        checker,
+                        sig_version,
+                    )?;
+
+                    stack.push(script::encode_num(n + success as i64));
+                }
+
+                OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => {
+                    if sig_version == SigVersion::Tapscript {
+                        return Err(Error::TapscriptCheckMultisig);
+                    }
+
+                    let success = eval_checkmultisig(
+                        stack,
+                        &script[begin_code_hash..],
+                        flags,
+                        checker,
+                        sig_version,
+                        &mut op_count,
+                    )?;
+
+                    if opcode == OP_CHECKMULTISIGVERIFY {
+                        if !success {
+                            return Err(Error::CheckMultisigVerify);
+                        }
+                        stack.pop();
+                    }
+                }
+
+                _ => return Err(Error::BadOpcode),
+            }
+        }
+
+        continue_after_op(stack, &altstack)?;
+        opcode_pos += 1;
+    }
+
+    if !exec_stack.is_empty() {
+        return Err(Error::UnbalancedConditional);
+    }
+
+    Ok(())
+}
+
+/// Enforces the combined stack size limit after each operation.
+fn continue_after_op(stack: &[Vec<u8>], altstack: &[Vec<u8>]) -> Result<(), Error> {
+    if stack.len() + altstack.len() > MAX_STACK_SIZE {
+        return Err(Error::StackSize);
+    }
+    Ok(())
+}
+
+fn eval_checksig<C: SignatureChecker>(
+    sig: &[u8],
+    pubkey: &[u8],
+    script_code: &[u8],
+    exec_data: &mut ExecData,
+    flags: VerifyFlags,
+    checker: &C,
+    sig_version: SigVersion,
+) -> Result<bool, Error> {
+    match sig_version {
+        SigVersion::Base | SigVersion::WitnessV0 => {
+            let mut script_code = script_code.to_vec();
+
+            // Legacy signatures cannot sign themselves, so drop them from the script code.
+            if sig_version == SigVersion::Base {
+                let found = find_and_delete(&mut script_code, sig);
+                if found > 0 && flags.contains(VerifyFlags::CONST_SCRIPTCODE) {
+                    return Err(Error::SigFindAndDelete);
+                }
+            }
+
+            check_signature_encoding(sig, flags)?;
+            check_pubkey_encoding(pubkey, flags, sig_version)?;
+
+            let success = checker.check_ecdsa_signature(sig, pubkey, &script_code, sig_version);
+
+            if !success && flags.contains(VerifyFlags::NULLFAIL) && !sig.is_empty() {
+                return Err(Error::NullFail);
+            }
+
+            Ok(success)
+        }
+        SigVersion::Tapscript => {
+            // The order of these checks is consensus critical: upgradable public key types
+            // precede other rules, an empty signature with an invalid key fails execution, and
+            // a non-empty invalid signature fails execution.
+            let success = !sig.is_empty();
+
+            if success {
+                exec_data.validation_weight_left -= VALIDATION_WEIGHT_PER_SIGOP_PASSED;
+                if exec_data.validation_weight_left < 0 {
+                    return Err(Error::TapscriptValidationWeight);
+                }
+            }
+
+            match pubkey.len() {
+                0 => return Err(Error::TapscriptEmptyPubkey),
+                32 => {
+                    if success {
+                        checker.check_schnorr_signature(sig, pubkey, sig_version, exec_data)?;
+                    }
+                }
+                _ => {
+                    if flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_PUBKEYTYPE) {
+                        return Err(Error::DiscourageUpgradablePubkeyType);
+                    }
+                }
+            }
+
+            Ok(success)
+        }
+        SigVersion::Taproot => unreachable!("taproot key paths execute no script"),
+    }
+}
+
+fn eval_checkmultisig<C: SignatureChecker>(
+    stack: &mut Vec<Vec<u8>>,
+    script_code: &[u8],
+    flags: VerifyFlags,
+    checker: &C,
+    sig_version: SigVersion,
+    op_count: &mut usize,
+) -> Result<bool, Error> {
+    let require_minimal = flags.contains(VerifyFlags::MINIMALDATA);
+
+    // ([sig ...] num_of_signatures [pubkey ...] num_of_pubkeys -- bool)
+    let mut i = 1;
+    require(stack, i)?;
+
+    let mut keys_count = clamp_int(decode_num(top(stack, i)?, require_minimal, 4)?);
+    if !(0..=MAX_PUBKEYS_PER_MULTISIG).contains(&keys_count) {
+        return Err(Error::PubkeyCount);
+    }
+
+    *op_count += keys_count as usize;
+    if *op_count > MAX_OPS_PER_SCRIPT {
+        return Err(Error::OpCount);
+    }
+
+    i += 1;
+    let mut ikey = i;
+    // Position of the last non-signature item, used to enforce NULLFAIL during cleanup.
+    let mut ikey2 = keys_count as usize + 2;
+    i += keys_count as usize;
+    require(stack, i)?;
+
+    let mut sigs_count = clamp_int(decode_num(top(stack, i)?, require_minimal, 4)?);
+    if sigs_count < 0 || sigs_count > keys_count {
+        return Err(Error::SigCount);
+    }
+
+    i += 1;
+    let mut isig = i;
+    i += sigs_count as usize;
+    require(stack, i)?;
+
+    let mut script_code = script_code.to_vec();
+    if sig_version == SigVersion::Base {
+        for k in 0..sigs_count as usize {
+            let sig = top(stack, isig + k)?.clone();
+            let found = find_and_delete(&mut script_code, &sig);
+            if found > 0 && flags.contains(VerifyFlags::CONST_SCRIPTCODE) {
+                return Err(Error::SigFindAndDelete);
+            }
+        }
+    }
+
+    let mut success = true;
+    while success && sigs_count > 0 {
+        let sig = top(stack, isig)?;
+        let pubkey = top(stack, ikey)?;
+
+        // This makes the exact order of evaluation observable through CHECKMULTISIG NOT when
+        // STRICTENC is set.
+        check_signature_encoding(sig, flags)?;
+        check_pubkey_encoding(pubkey, flags, sig_version)?;
+
+        if checker.check_ecdsa_signature(sig, pubkey, &script_code, sig_version) {
+            isig += 1;
+            sigs_count -= 1;
+        }
+        ikey += 1;
+        keys_count -= 1;
+
+        // Too many signatures have failed once more remain than there are keys to check.
+        if sigs_count > keys_count {
+            success = false;
+        }
+    }
+
+    while i > 1 {
+        i -= 1;
+
+        if !success
+            && flags.contains(VerifyFlags::NULLFAIL)
+            && ikey2 == 0
+            && !stack.last().unwrap().is_empty()
+        {
+            return Err(Error::NullFail);
+        }
+
+        ikey2 = ikey2.saturating_sub(1);
+
+        stack.pop();
+    }
+
+    // A bug causes CHECKMULTISIG to consume one extra, unchecked argument, so NULLDUMMY
+    // requires it to be empty to remove that source of malleability.
+    let dummy = stack.pop().ok_or(Error::InvalidStackOperation)?;
+    if flags.contains(VerifyFlags::NULLDUMMY) && !dummy.is_empty() {
+        return Err(Error::SigNullDummy);
+    }
+
+    stack.push(bool_to_vec(success));
+    Ok(success)
+}
+
+/// Returns the `n`th item from the top of the stack, where 1 is the top.
+fn top(stack: &[Vec<u8>], n: usize) -> Result<&Vec<u8>, Error> {
+    if n == 0 || stack.len() < n {
+        return Err(Error::InvalidStackOperation);
+    }
+    Ok(&stack[stack.len() - n])
+}
+
+fn require(stack: &[Vec<u8>], n: usize) -> Result<(), Error> {
+    if stack.len() < n {
+        return Err(Error::InvalidStackOperation);
+    }
+    Ok(())
+}
+
+fn bool_to_vec(value: bool) -> Vec<u8> {
+    if value {
+        vec![1]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Interprets a stack item as a boolean, where any non-zero value other than negative zero is
+/// true.
+pub fn cast_to_bool(item: &[u8]) -> bool {
+    match item.iter().rposition(|b| *b != 0) {
+        Some(i) => !(i == item.len() - 1 && item[i] == 0x80),
+        None => false,
+    }
+}
+
+/// Decodes a script number of at most `max_size` bytes.
+pub fn decode_num(bytes: &[u8], require_minimal: bool, max_size: usize) -> Result<i64, Error> {
+    if bytes.len() > max_size {
+        return Err(Error::ScriptNum);
+    }
+
+    let (last, rest) = match bytes.split_last() {
+        Some(split) => split,
+        None => return Ok(0),
+    };
+
+    // The most significant byte may only be zero (other than the sign bit) if the next byte
+    // needs its high bit to represent the magnitude.
+    if require_minimal && last & 0x7f == 0 && rest.last().map_or(true, |b| b & 0x80 == 0) {
+        return Err(Error::ScriptNum);
+    }
+
+    let mut result = 0i64;
+    for (i, byte) in bytes.iter().enumerate() {
+        result |= (*byte as i64) << (8 * i);
+    }
+
+    if last & 0x80 != 0 {
+        Ok(-(result & !(0x80i64 << (8 * rest.len()))))
+    } else {
+        Ok(result)
+    }
+}
+
+fn clamp_int(n: i64) -> i64 {
+    n.max(i32::MIN as i64).min(i32::MAX as i64)
+}
+
+fn is_disabled(opcode: u8) -> bool {
+    matches!(
+        opcode,
+        OP_CAT
+            | OP_SUBSTR
+            | OP_LEFT
+            | OP_RIGHT
+            | OP_INVERT
+            | OP_AND
+            | OP_OR
+            | OP_XOR
+            | OP_2MUL
+            | OP_2DIV
+            | OP_MUL
+            | OP_DIV
+            | OP_MOD
+            | OP_LSHIFT
+            | OP_RSHIFT
+    )
+}
+
+fn is_minimal_push(data: &[u8], opcode: u8) -> bool {
+    match data.len() {
+        0 => opcode == OP_0,
+        1 if data[0] >= 1 && data[0] <= 16 => false,
+        1 if data[0] == 0x81 => false,
+        len if len <= 75 => opcode as usize == len,
+        len if len <= 255 => opcode == OP_PUSHDATA1,
+        len if len <= 65535 => opcode == OP_PUSHDATA2,
+        _ => true,
+    }
+}
+
+/// Removes every occurrence of a canonical push of `sig` from `script`, returning how many were
+/// found. Occurrences are only matched at opcode boundaries.
+fn find_and_delete(script: &mut Vec<u8>, sig: &[u8]) -> usize {
+    let mut pattern = Vec::new();
+    script::push_data(&mut pattern, sig);
+
+    let mut found = 0;
+    let mut result = Vec::new();
+    let mut pc = 0;
+    let mut pc2 = 0;
+
+    loop {
+        result.extend(&script[pc2..pc]);
+
+        while script.len() - pc >= pattern.len() && script[pc..].starts_with(&pattern) {
+            pc += pattern.len();
+            found += 1;
+        }
+
+        pc2 = pc;
+
+        if script::get_op(script, &mut pc).is_none() {
+            break;
+        }
+    }
+
+    if found > 0 {
+        result.extend(&script[pc2..]);
+        *script = result;
+    }
+
+    found
+}
+
+/// Checks a signature's encoding against the DERSIG, LOW_S and STRICTENC rules.
+fn check_signature_encoding(sig: &[u8], flags: VerifyFlags) -> Result<(), Error> {
+    // An empty signature is a compact way to provide an invalid signature to CHECK(MULTI)SIG.
+    if sig.is_empty() {
+        return Ok(());
+    }
+
+    let strict = VerifyFlags::DERSIG | VerifyFlags::LOW_S | VerifyFlags::STRICTENC;
+    if flags.0 & strict.0 != 0 && !is_valid_signature_encoding(sig) {
+        return Err(Error::SigDer);
+    }
+
+    if flags.contains(VerifyFlags::LOW_S) && !is_low_s(&sig[..sig.len() - 1]) {
+        return Err(Error::SigHighS);
+    }
+
+    if flags.contains(VerifyFlags::STRICTENC) {
+        let hash_type = sig[sig.len() - 1] & !sighash::SIGHASH_ANYONECANPAY;
+        if !(sighash::SIGHASH_ALL..=sighash::SIGHASH_SINGLE).contains(&hash_type) {
+            return Err(Error::SigHashType);
+        }
+    }
+
+    Ok(())
+}
+
+fn check_pubkey_encoding(
+    pubkey: &[u8],
+    flags: VerifyFlags,
+    sig_version: SigVersion,
+) -> Result<(), Error> {
+    let compressed = pubkey.len() == 33 && (pubkey[0] == 0x02 || pubkey[0] == 0x03);
+    let uncompressed = pubkey.len() == 65 && pubkey[0] == 0x04;
+
+    if flags.contains(VerifyFlags::STRICTENC) && !compressed && !uncompressed {
+        return Err(Error::PubkeyType);
+    }
+
+    // Only compressed keys are accepted in segwit.
+    if flags.contains(VerifyFlags::WITNESS_PUBKEYTYPE)
+        && sig_version == SigVersion::WitnessV0
+        && !compressed
+    {
+        return Err(Error::WitnessPubkeyType);
+    }
+
+    Ok(())
+}
+
+/// Returns true if `pubkey` has the length its header byte calls for.
+fn is_valid_pubkey_size(pubkey: &[u8]) -> bool {
+    match pubkey.first() {
+        Some(0x02) | Some(0x03) => pubkey.len() == 33,
+        Some(0x04) | Some(0x06) | Some(0x07) => pubkey.len() == 65,
+        _ => false,
+    }
+}
+
+fn is_low_s(sig: &[u8]) -> bool {
+    match Signature::from_der_lax(sig) {
+        Ok(sig) => {
+            let mut normalized = sig;
+            normalized.normalize_s();
+            normalized == sig
+        }
+        Err(_) => false,
+    }
+}
+
+/// Checks strict DER encoding of a signature followed by a hash type byte, per BIP66.
+fn is_valid_signature_encoding(sig: &[u8]) -> bool {
+    // Format: 0x30 [total-length] 0x02 [R-length] [R] 0x02 [S-length] [S] [sighash]
+    if sig.len() < 9 || sig.len() > 73 {
+        return false;
+    }
+
+    if sig[0] != 0x30 || sig[1] as usize != sig.len() - 3 {
+        return false;
+    }
+
+    let len_r = sig[3] as usize;
+    if 5 + len_r >= sig.len() {
+        return false;
+    }
+
+    let len_s = sig[5 + len_r] as usize;
+    if len_r + len_s + 7 != sig.len() {
+        return false;
+    }
+
+    // R must be a positive, minimally encoded integer.
+    if sig[2] != 0x02 || len_r == 0 || sig[4] & 0x80 != 0 {
+        return false;
+    }
+    if len_r > 1 && sig[4] == 0x00 && sig[5] & 0x80 == 0 {
+        return false;
+    }
+
+    // S must be a positive, minimally encoded integer.
+    if sig[len_r + 4] != 0x02 || len_s == 0 || sig[len_r + 6] & 0x80 != 0 {
+        return false;
+    }
+    if len_s > 1 && sig[len_r + 6] == 0x00 && sig[len_r + 7] & 0x80 == 0 {
+        return false;
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::{opcodes, Builder};
+    use crate::transaction::{OutPoint, TxIn};
+    use serde_json::Value;
+    use std::collections::HashMap;
+
+    /// The BIP341 "nothing up my sleeve" point, used as the internal key of taproot test outputs.
+    const NUMS_H: &str = "50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0";
+
+    /// Parses the script notation used by Bitcoin Core's JSON test vectors.
+    fn parse_script(asm: &str) -> Script {
+        let mut script = Vec::new();
+
+        for word in asm.split_whitespace() {
+            let is_number = word.strip_prefix('-').unwrap_or(word);
+            if !is_number.is_empty() && is_number.bytes().all(|b| b.is_ascii_digit()) {
+                let n: i64 = word.parse().unwrap();
+                assert!(
+                    (-0xffff_ffff..=0xffff_ffff).contains(&n),
+                    "out of range: {}",
+                    word
+                );
+                script.extend(Builder::new().push_int(n).into_script().into_bytes());
+            } else if word.len() > 2 && word.starts_with("0x") {
+                script.extend(hex::decode(&word[2..]).unwrap());
+            } else if word.len() >= 2 && word.starts_with('\'') && word.ends_with('\'') {
+                script::push_data(&mut script, &word.as_bytes()[1..word.len() - 1]);
+            } else {
+                script.push(parse_opcode(word).unwrap_or_else(|| panic!("bad opcode {}", word)));
+            }
+        }
+
+        Script::from(script)
+    }
+
+    fn parse_opcode(word: &str) -> Option<u8> {
+        (0..=OP_CHECKSIGADD)
+            .filter(|op| *op >= OP_NOP || *op == OP_RESERVED)
+            .find(|op| {
+                let name = opcodes::name(*op);
+                name != "OP_UNKNOWN" && (name == word || &name[3..] == word)
+            })
+    }
+
+    fn parse_flags(flags: &str) -> VerifyFlags {
+        let mut result = VerifyFlags::NONE;
+
+        for flag in flags.split(',').filter(|flag| !flag.is_empty()) {
+            result |= match flag {
+                "NONE" => VerifyFlags::NONE,
+                "P2SH" => VerifyFlags::P2SH,
+                "STRICTENC" => VerifyFlags::STRICTENC,
+                "DERSIG" => VerifyFlags::DERSIG,
+                "LOW_S" => VerifyFlags::LOW_S,
+                "SIGPUSHONLY" => VerifyFlags::SIGPUSHONLY,
+                "MINIMALDATA" => VerifyFlags::MINIMALDATA,
+                "NULLDUMMY" => VerifyFlags::NULLDUMMY,
+                "DISCOURAGE_UPGRADABLE_NOPS" => VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS,
+                "CLEANSTACK" => VerifyFlags::CLEANSTACK,
+                "MINIMALIF" => VerifyFlags::MINIMALIF,
+                "NULLFAIL" => VerifyFlags::NULLFAIL,
+                "CHECKLOCKTIMEVERIFY" => VerifyFlags::CHECKLOCKTIMEVERIFY,
+                "CHECKSEQUENCEVERIFY" => VerifyFlags::CHECKSEQUENCEVERIFY,
+                "WITNESS" => VerifyFlags::WITNESS,
+                "DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM" => {
+                    VerifyFlags::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM
+                }
+                "WITNESS_PUBKEYTYPE" => VerifyFlags::WITNESS_PUBKEYTYPE,
+                "CONST_SCRIPTCODE" => VerifyFlags::CONST_SCRIPTCODE,
+                "TAPROOT" => VerifyFlags::TAPROOT,
+                "DISCOURAGE_UPGRADABLE_PUBKEYTYPE" => VerifyFlags::DISCOURAGE_UPGRADABLE_PUBKEYTYPE,
+                "DISCOURAGE_OP_SUCCESS" => VerifyFlags::DISCOURAGE_OP_SUCCESS,
+                "DISCOURAGE_UPGRADABLE_TAPROOT_VERSION" => {
+                    VerifyFlags::DISCOURAGE_UPGRADABLE_TAPROOT_VERSION
+                }
+                _ => panic!("unknown flag {}", flag),
+            };
+        }
+
+        result
+    }
+
+    fn all_flags() -> VerifyFlags {
+        VerifyFlags::from_bits((1 << 21) - 1)
+    }
+
+    /// Drops flags which may not be set without the flags they depend on.
+    fn trim_flags(mut flags: VerifyFlags) -> VerifyFlags {
+        if !flags.contains(VerifyFlags::P2SH) {
+            flags = flags.difference(VerifyFlags::WITNESS);
+        }
+        if !flags.contains(VerifyFlags::WITNESS) {
+            flags = flags.difference(VerifyFlags::CLEANSTACK);
+        }
+        flags
+    }
+
+    fn error_name(err: Error) -> &'static str {
+        match err {
+            Error::EvalFalse => "EVAL_FALSE",
+            Error::OpReturn => "OP_RETURN",
+            Error::ScriptSize => "SCRIPT_SIZE",
+            Error::PushSize => "PUSH_SIZE",
+            Error::OpCount => "OP_COUNT",
+            Error::StackSize => "STACK_SIZE",
+            Error::SigCount => "SIG_COUNT",
+            Error::PubkeyCount => "PUBKEY_COUNT",
+            Error::Verify => "VERIFY",
+            Error::EqualVerify => "EQUALVERIFY",
+            Error::CheckMultisigVerify => "CHECKMULTISIGVERIFY",
+            Error::CheckSigVerify => "CHECKSIGVERIFY",
+            Error::NumEqualVerify => "NUMEQUALVERIFY",
+            Error::BadOpcode => "BAD_OPCODE",
+            Error::DisabledOpcode => "DISABLED_OPCODE",
+            Error::InvalidStackOperation => "INVALID_STACK_OPERATION",
+            Error::InvalidAltstackOperation => "INVALID_ALTSTACK_OPERATION",
+            Error::UnbalancedConditional => "UNBALANCED_CONDITIONAL",
+            Error::NegativeLocktime => "NEGATIVE_LOCKTIME",
+            Error::UnsatisfiedLocktime => "UNSATISFIED_LOCKTIME",
+            Error::SigHashType => "SIG_HASHTYPE",
+            Error::SigDer => "SIG_DER",
+            Error::MinimalData => "MINIMALDATA",
+            Error::SigPushOnly => "SIG_PUSHONLY",
+            Error::SigHighS => "SIG_HIGH_S",
+            Error::SigNullDummy => "SIG_NULLDUMMY",
+            Error::PubkeyType => "PUBKEYTYPE",
+            Error::CleanStack => "CLEANSTACK",
+            Error::MinimalIf => "MINIMALIF",
+            Error::NullFail => "NULLFAIL",
+            Error::DiscourageUpgradableNops => "DISCOURAGE_UPGRADABLE_NOPS",
+            Error::DiscourageUpgradableWitnessProgram => "DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM",
+            Error::DiscourageUpgradableTaprootVersion => "DISCOURAGE_UPGRADABLE_TAPROOT_VERSION",
+            Error::DiscourageOpSuccess => "DISCOURAGE_OP_SUCCESS",
+            Error::DiscourageUpgradablePubkeyType => "DISCOURAGE_UPGRADABLE_PUBKEYTYPE",
+            Error::WitnessProgramWrongLength => "WITNESS_PROGRAM_WRONG_LENGTH",
+            Error::WitnessProgramWitnessEmpty => "WITNESS_PROGRAM_WITNESS_EMPTY",
+            Error::WitnessProgramMismatch => "WITNESS_PROGRAM_MISMATCH",
+            Error::WitnessMalleated => "WITNESS_MALLEATED",
+            Error::WitnessMalleatedP2sh => "WITNESS_MALLEATED_P2SH",
+            Error::WitnessUnexpected => "WITNESS_UNEXPECTED",
+            Error::WitnessPubkeyType => "WITNESS_PUBKEYTYPE",
+            Error::SchnorrSigSize => "SCHNORR_SIG_SIZE",
+            Error::SchnorrSigHashType => "SCHNORR_SIG_HASHTYPE",
+            Error::SchnorrSig => "SCHNORR_SIG",
+            Error::TaprootWrongControlSize => "TAPROOT_WRONG_CONTROL_SIZE",
+            Error::TapscriptValidationWeight => "TAPSCRIPT_VALIDATION_WEIGHT",
+            Error::TapscriptCheckMultisig => "TAPSCRIPT_CHECKMULTISIG",
+            Error::TapscriptMinimalIf => "TAPSCRIPT_MINIMALIF",
+            Error::TapscriptEmptyPubkey => "TAPSCRIPT_EMPTY_PUBKEY",
+            Error::OpCodeSeparator => "OP_CODESEPARATOR",
+            Error::SigFindAndDelete => "SIG_FINDANDDELETE",
+            Error::ScriptNum => "SCRIPTNUM",
+            Error::MissingPrevouts | Error::InputIndexOutOfRange(_) => "UNKNOWN_ERROR",
+        }
+    }
+
+    /// Builds the single-leaf tapscript control block for `script` under the NUMS internal key,
+    /// returning it along with the output key.
+    fn taproot_single_leaf(script: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let secp = Secp256k1::new();
+        let internal = copy_from_slice!([0u8; 32], &hex::decode(NUMS_H).unwrap());
+        let merkle_root = tapleaf_hash(TAPROOT_LEAF_TAPSCRIPT, script);
+        let tweak = taptweak_hash(&internal, Some(&merkle_root));
+
+        let mut output_key = schnorrsig::PublicKey::from_slice(&internal).unwrap();
+        let parity = output_key.tweak_add_assign(&secp, &tweak).unwrap();
+
+        let mut control = vec![TAPROOT_LEAF_TAPSCRIPT | parity as u8];
+        control.extend(&internal);
+        (control, output_key.serialize().to_vec())
+    }
+
+    fn crediting_tx(script_pubkey: Script, value: u64) -> Transaction {
+        Transaction {
+            version: 1,
+            inputs: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: Script::from(vec![OP_0, OP_0]),
+                sequence: transaction::SEQUENCE_FINAL,
+                witness: Vec::new(),
+            }],
+            outputs: vec![TxOut {
+                value,
+                script_pubkey,
+            }],
+            lock_time: 0,
+        }
+    }
+
+    fn spending_tx(credit: &Transaction, script_sig: Script, witness: Vec<Vec<u8>>) -> Transaction {
+        Transaction {
+            version: 1,
+            inputs: vec![TxIn {
+                previous_output: OutPoint {
+                    txid: credit.txid(),
+                    vout: 0,
+                },
+                script_sig,
+                sequence: transaction::SEQUENCE_FINAL,
+                witness,
+            }],
+            outputs: vec![TxOut {
+                value: credit.outputs[0].value,
+                script_pubkey: Script::new(),
+            }],
+            lock_time: 0,
+        }
+    }
+
+    #[test]
+    fn test_bitcoin_core_script_tests() {
+        let tests: Vec<Value> =
+            serde_json::from_str(include_str!("../../testdata/script_tests.json")).unwrap();
+
+        for test in tests
+            .iter()
+            .filter(|test| test.as_array().unwrap().len() > 1)
+        {
+            let mut test = test.as_array().unwrap().as_slice();
+            let mut witness = Vec::new();
+            let mut value = 0;
+            let mut output_key = None;
+
+            if let Some(items) = test[0].as_array() {
+                let (amount, items) = items.split_last().unwrap();
+                value = (amount.as_f64().unwrap() * 100_000_000.0).round() as u64;
+
+                for item in items {
+                    let item = item.as_str().unwrap();
+                    if let Some(asm) = item.strip_prefix("#SCRIPT#") {
+                        witness.push(parse_script(asm).into_bytes());
+                    } else if item == "#CONTROLBLOCK#" {
+                        let (control, key) = taproot_single_leaf(witness.last().unwrap());
+                        witness.push(control);
+                        output_key = Some(key);
+                    } else {
+                        witness.push(hex::decode(item).unwrap());
+                    }
+                }
+
+                test = &test[1..];
+            }
+
+            let script_sig = parse_script(test[0].as_str().unwrap());
+            let mut script_pubkey = test[1].as_str().unwrap().to_string();
+            if let Some(key) = output_key {
+                script_pubkey =
+                    script_pubkey.replace("#TAPROOTOUTPUT#", &format!("0x{}", hex::encode(key)));
+            }
+            let script_pubkey = parse_script(&script_pubkey);
+            let mut flags = parse_flags(test[2].as_str().unwrap());
+            let expected = test[3].as_str().unwrap();
+
+            if flags.contains(VerifyFlags::CLEANSTACK) {
+                flags |= VerifyFlags::P2SH | VerifyFlags::WITNESS;
+            }
+
+            let credit = crediting_tx(script_pubkey.clone(), value);
+            let spend = spending_tx(&credit, script_sig.clone(), witness.clone());
+            let checker = TransactionChecker::new(&spend, 0, &credit.outputs[0]);
+
+            let result = verify_script(&script_sig, &script_pubkey, &witness, flags, &checker);
+            let actual = result.err().map_or("OK", error_name);
+            assert_eq!(actual, expected, "{:?}", test);
+        }
+    }
+
+    /// Parses a Bitcoin Core transaction test, returning the transaction, its flags string and
+    /// the outputs it spends.
+    fn parse_tx_test(test: &[Value]) -> (Transaction, &str, HashMap<OutPoint, TxOut>) {
+        let mut prevouts = HashMap::new();
+
+        for prevout in test[0].as_array().unwrap() {
+            let prevout = prevout.as_array().unwrap();
+            let outpoint = OutPoint {
+                txid: prevout[0].as_str().unwrap().parse().unwrap(),
+                vout: prevout[1].as_i64().unwrap() as u32,
+            };
+            let value = prevout.get(3).map_or(0, |value| value.as_u64().unwrap());
+
+            prevouts.insert(
+                outpoint,
+                TxOut {
+                    value,
+                    script_pubkey: parse_script(prevout[2].as_str().unwrap()),
+                },
+            );
+        }
+
+        let tx = hex::decode(test[1].as_str().unwrap()).unwrap();
+        let tx = Transaction::deserialize(&tx).unwrap();
+
+        (tx, test[2].as_str().unwrap(), prevouts)
+    }
+
+    fn verify_tx(
+        tx: &Transaction,
+        prevouts: &HashMap<OutPoint, TxOut>,
+        flags: VerifyFlags,
+    ) -> Result<(), Error> {
+        let spent = tx
+            .inputs
+            .iter()
+            .map(|input| prevouts.get(&input.previous_output).cloned())
+            .collect::<Option<Vec<_>>>()
+            .ok_or(Error::MissingPrevouts)?;
+
+        (0..tx.inputs.len())
+            .try_for_each(|i| verify_input_with_prevouts(tx, i, &spent, trim_flags(flags)))
+    }
+
+    #[test]
+    fn test_bitcoin_core_tx_valid() {
+        let tests: Vec<Value> =
+            serde_json::from_str(include_str!("../../testdata/tx_valid.json")).unwrap();
+
+        for test in tests.iter().filter(|test| test[0].is_array()) {
+            let test = test.as_array().unwrap();
+            let (tx, excluded, prevouts) = parse_tx_test(test);
+
+            assert_eq!(tx.check(), Ok(()), "{:?}", test);
+
+            let flags = all_flags().difference(parse_flags(excluded));
+            assert_eq!(verify_tx(&tx, &prevouts, flags), Ok(()), "{:?}", test);
+        }
+    }
+
+    #[test]
+    fn test_bitcoin_core_tx_invalid() {
+        let tests: Vec<Value> =
+            serde_json::from_str(include_str!("../../testdata/tx_invalid.json")).unwrap();
+
+        for test in tests.iter().filter(|test| test[0].is_array()) {
+            let test = test.as_array().unwrap();
+            let (tx, flags, prevouts) = parse_tx_test(test);
+
+            if flags == "BADTX" {
+                assert!(tx.check().is_err(), "{:?}", test);
+                continue;
+            }
+
+            assert_eq!(tx.check(), Ok(()), "{:?}", test);
+            assert!(
+                verify_tx(&tx, &prevouts, parse_flags(flags)).is_err(),
+                "{:?}",
+                test
+            );
+        }
+    }
+
+    #[test]
+    fn test_decode_num() {
+        assert_eq!(decode_num(&[], true, 4), Ok(0));
+        assert_eq!(decode_num(&[0x81], true, 4), Ok(-1));
+        assert_eq!(decode_num(&[0xff, 0x00], true, 4), Ok(255));
+        assert_eq!(decode_num(&[0x00], true, 4), Err(Error::ScriptNum));
+        assert_eq!(decode_num(&[0x00], false, 4), Ok(0));
+        assert_eq!(
+            decode_num(&[1, 0, 0, 0, 0], false, 4),
+            Err(Error::ScriptNum)
+        );
+        assert_eq!(
+            decode_num(&[0xff, 0xff, 0xff, 0xff, 0x00], true, 5),
+            Ok(0xffff_ffff)
+        );
+    }
+
+    #[test]
+    fn test_cast_to_bool() {
+        assert!(!cast_to_bool(&[]));
+        assert!(!cast_to_bool(&[0x00, 0x00]));
+        assert!(!cast_to_bool(&[0x00, 0x80]));
+        assert!(cast_to_bool(&[0x80, 0x00]));
+        assert!(cast_to_bool(&[0x01]));
+    }
+}
@@ -0,0 +1,414 @@
+pub mod interpreter;
+pub mod opcodes;
+
+pub use interpreter::{verify_input, verify_input_with_prevouts, verify_script, VerifyFlags};
+
+use crate::encode;
+use crate::hash;
+use opcodes::*;
+use std::fmt;
+
+/// A Bitcoin script, stored as its raw serialized bytes.
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct Script(Vec<u8>);
+
+/// A single parsed script operation: the opcode and, for push opcodes, the pushed data.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Instruction<'a> {
+    pub opcode: u8,
+    pub data: &'a [u8],
+}
+
+/// An iterator over the operations in a script.
+///
+/// Yields `Err(())` once and then stops if the script ends in the middle of a push.
+pub struct Instructions<'a> {
+    script: &'a [u8],
+    pc: usize,
+    done: bool,
+}
+
+/// Incrementally assembles a script from opcodes and pushes.
+#[derive(Default)]
+pub struct Builder(Vec<u8>);
+
+impl Script {
+    pub fn new() -> Script {
+        Script(Vec::new())
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn instructions(&self) -> Instructions<'_> {
+        Instructions {
+            script: &self.0,
+            pc: 0,
+            done: false,
+        }
+    }
+
+    /// Returns true for `OP_HASH160 <20 bytes> OP_EQUAL`.
+    pub fn is_p2sh(&self) -> bool {
+        self.0.len() == 23 && self.0[0] == OP_HASH160 && self.0[1] == 0x14 && self.0[22] == OP_EQUAL
+    }
+
+    /// Returns true for `OP_DUP OP_HASH160 <20 bytes> OP_EQUALVERIFY OP_CHECKSIG`.
+    pub fn is_p2pkh(&self) -> bool {
+        self.0.len() == 25
+            && self.0[0] == OP_DUP
+            && self.0[1] == OP_HASH160
+            && self.0[2] == 0x14
+            && self.0[23] == OP_EQUALVERIFY
+            && self.0[24] == OP_CHECKSIG
+    }
+
+    /// Returns the version and program of a BIP141 witness program script.
+    pub fn witness_program(&self) -> Option<(u8, &[u8])> {
+        let bytes = &self.0;
+
+        if bytes.len() < 4 || bytes.len() > 42 {
+            return None;
+        }
+
+        if bytes[0] != OP_0 && (bytes[0] < OP_1 || bytes[0] > OP_16) {
+            return None;
+        }
+
+        if bytes[1] as usize + 2 != bytes.len() {
+            return None;
+        }
+
+        Some((decode_op_n(bytes[0]), &bytes[2..]))
+    }
+
+    /// Returns true if the script consists only of push operations.
+    ///
+    /// `OP_RESERVED` is considered a push here, matching Bitcoin Core.
+    pub fn is_push_only(&self) -> bool {
+        self.instructions()
+            .all(|ins| matches!(ins, Ok(ins) if ins.opcode <= OP_16))
+    }
+
+    /// Returns true if the script is provably unspendable.
+    pub fn is_unspendable(&self) -> bool {
+        (!self.0.is_empty() && self.0[0] == OP_RETURN)
+            || self.0.len() > interpreter::MAX_SCRIPT_SIZE
+    }
+
+    /// Builds the P2SH output script which commits to this script.
+    pub fn to_p2sh(&self) -> Script {
+        Builder::new()
+            .push_opcode(OP_HASH160)
+            .push_slice(&hash::hash160(&self.0))
+            .push_opcode(OP_EQUAL)
+            .into_script()
+    }
+
+    /// Builds the P2WSH output script which commits to this script.
+    pub fn to_p2wsh(&self) -> Script {
+        Builder::new()
+            .push_opcode(OP_0)
+            .push_slice(&hash::sha256(&self.0))
+            .into_script()
+    }
+
+    /// Serializes the script with a CompactSize length prefix.
+    pub fn consensus_encode(&self, buf: &mut Vec<u8>) {
+        encode::write_var_bytes(buf, &self.0);
+    }
+}
+
+impl From<Vec<u8>> for Script {
+    fn from(bytes: Vec<u8>) -> Script {
+        Script(bytes)
+    }
+}
+
+impl From<&[u8]> for Script {
+    fn from(bytes: &[u8]) -> Script {
+        Script(bytes.to_vec())
+    }
+}
+
+impl AsRef<[u8]> for Script {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Script(")?;
+        fmt::Display::fmt(self, f)?;
+        write!(f, ")")
+    }
+}
+
+/// Formats the script in the disassembled form used by Bitcoin Core's `asm` fields.
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, ins) in self.instructions().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+
+            match ins {
+                Ok(ins) if ins.opcode > OP_0 && ins.opcode <= OP_PUSHDATA4 => {
+                    for byte in ins.data {
+                        write!(f, "{:02x}", byte)?;
+                    }
+                }
+                Ok(ins) => write!(f, "{}", opcodes::name(ins.opcode))?,
+                Err(()) => write!(f, "[error]")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for Instructions<'a> {
+    type Item = Result<Instruction<'a>, ()>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.pc >= self.script.len() {
+            return None;
+        }
+
+        match get_op(self.script, &mut self.pc) {
+            Some(ins) => Some(Ok(ins)),
+            None => {
+                self.done = true;
+                Some(Err(()))
+            }
+        }
+    }
+}
+
+impl Builder {
+    pub fn new() -> Builder {
+        Builder(Vec::new())
+    }
+
+    pub fn push_opcode(mut self, opcode: u8) -> Builder {
+        self.0.push(opcode);
+        self
+    }
+
+    /// Pushes data using the smallest push opcode which can hold it.
+    pub fn push_slice(mut self, data: &[u8]) -> Builder {
+        push_data(&mut self.0, data);
+        self
+    }
+
+    /// Pushes an integer, using `OP_0`, `OP_1NEGATE` and `OP_1..OP_16` where possible.
+    pub fn push_int(self, n: i64) -> Builder {
+        match n {
+            0 => self.push_opcode(OP_0),
+            -1 => self.push_opcode(OP_1NEGATE),
+            1..=16 => self.push_opcode(OP_1 + (n as u8) - 1),
+            _ => self.push_slice(&encode_num(n)),
+        }
+    }
+
+    pub fn into_script(self) -> Script {
+        Script(self.0)
+    }
+}
+
+/// Appends a push of `data` to `script` using the canonical push opcode for its length.
+pub(crate) fn push_data(script: &mut Vec<u8>, data: &[u8]) {
+    match data.len() {
+        len if len < OP_PUSHDATA1 as usize => script.push(len as u8),
+        len if len <= 0xff => {
+            script.push(OP_PUSHDATA1);
+            script.push(len as u8);
+        }
+        len if len <= 0xffff => {
+            script.push(OP_PUSHDATA2);
+            script.extend(&(len as u16).to_le_bytes());
+        }
+        len => {
+            script.push(OP_PUSHDATA4);
+            script.extend(&(len as u32).to_le_bytes());
+        }
+    }
+
+    script.extend(data);
+}
+
+/// Reads the operation at `*pc`, advancing `pc` past it.
+///
+/// Returns `None` if the script ends in the middle of the operation. As in Bitcoin Core, `pc` may
+/// still have been advanced past the opcode and length bytes in that case.
+pub(crate) fn get_op<'a>(script: &'a [u8], pc: &mut usize) -> Option<Instruction<'a>> {
+    if *pc >= script.len() {
+        return None;
+    }
+
+    let opcode = script[*pc];
+    *pc += 1;
+
+    let len = match opcode {
+        op if op < OP_PUSHDATA1 => op as usize,
+        OP_PUSHDATA1 => {
+            let len = *script.get(*pc)? as usize;
+            *pc += 1;
+            len
+        }
+        OP_PUSHDATA2 => {
+            let bytes = script.get(*pc..*pc + 2)?;
+            *pc += 2;
+            u16::from_le_bytes(copy_from_slice!([0u8; 2], bytes)) as usize
+        }
+        OP_PUSHDATA4 => {
+            let bytes = script.get(*pc..*pc + 4)?;
+            *pc += 4;
+            u32::from_le_bytes(copy_from_slice!([0u8; 4], bytes)) as usize
+        }
+        _ => 0,
+    };
+
+    if script.len() - *pc < len {
+        return None;
+    }
+
+    let data = &script[*pc..*pc + len];
+    *pc += len;
+
+    Some(Instruction { opcode, data })
+}
+
+/// Encodes an integer in the minimal little-endian sign-magnitude form used by script numbers.
+pub fn encode_num(n: i64) -> Vec<u8> {
+    let mut result = Vec::new();
+
+    if n == 0 {
+        return result;
+    }
+
+    let negative = n < 0;
+    let mut abs = n.unsigned_abs();
+
+    while abs > 0 {
+        result.push((abs & 0xff) as u8);
+        abs >>= 8;
+    }
+
+    // The most significant bit carries the sign, so add a byte if it is already in use.
+    if result.last().unwrap() & 0x80 != 0 {
+        result.push(if negative { 0x80 } else { 0x00 });
+    } else if negative {
+        *result.last_mut().unwrap() |= 0x80;
+    }
+
+    result
+}
+
+/// Decodes `OP_0` and `OP_1..OP_16` into the small integer they represent.
+pub fn decode_op_n(opcode: u8) -> u8 {
+    match opcode {
+        OP_0 => 0,
+        op => op - (OP_1 - 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_num() {
+        assert_eq!(encode_num(0), b"");
+        assert_eq!(encode_num(1), [0x01]);
+        assert_eq!(encode_num(-1), [0x81]);
+        assert_eq!(encode_num(127), [0x7f]);
+        assert_eq!(encode_num(128), [0x80, 0x00]);
+        assert_eq!(encode_num(-128), [0x80, 0x80]);
+        assert_eq!(encode_num(255), [0xff, 0x00]);
+        assert_eq!(encode_num(256), [0x00, 0x01]);
+        assert_eq!(encode_num(-0x7fff_ffff), [0xff, 0xff, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn test_builder_push_int() {
+        let script = Builder::new()
+            .push_int(0)
+            .push_int(-1)
+            .push_int(16)
+            .push_int(17)
+            .push_int(500_000)
+            .into_script();
+
+        assert_eq!(
+            script.as_bytes(),
+            [OP_0, OP_1NEGATE, OP_16, 0x01, 0x11, 0x03, 0x20, 0xa1, 0x07]
+        );
+    }
+
+    #[test]
+    fn test_witness_program() {
+        let script = Script::from(vec![OP_0, 0x02, 0xaa, 0xbb]);
+        assert_eq!(script.witness_program(), Some((0, &[0xaa, 0xbb][..])));
+
+        let script = Script::from(vec![OP_16, 0x02, 0xaa, 0xbb]);
+        assert_eq!(script.witness_program(), Some((16, &[0xaa, 0xbb][..])));
+
+        let script = Script::from(vec![OP_1NEGATE, 0x02, 0xaa, 0xbb]);
+        assert_eq!(script.witness_program(), None);
+
+        let script = Script::from(vec![OP_0, 0x03, 0xaa, 0xbb]);
+        assert_eq!(script.witness_program(), None);
+    }
+
+    #[test]
+    fn test_instructions() {
+        let script = Script::from(vec![OP_DUP, 0x02, 0xaa, 0xbb, OP_PUSHDATA1, 0x05, 0x00]);
+        let ops = script.instructions().collect::<Vec<_>>();
+
+        assert_eq!(
+            ops,
+            vec![
+                Ok(Instruction {
+                    opcode: OP_DUP,
+                    data: &[]
+                }),
+                Ok(Instruction {
+                    opcode: 0x02,
+                    data: &[0xaa, 0xbb]
+                }),
+                Err(()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let script = Builder::new()
+            .push_opcode(OP_DUP)
+            .push_opcode(OP_HASH160)
+            .push_slice(&[0xab; 20])
+            .push_opcode(OP_EQUALVERIFY)
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+
+        assert_eq!(
+            script.to_string(),
+            "OP_DUP OP_HASH160 abababababababababababababababababababab OP_EQUALVERIFY OP_CHECKSIG"
+        );
+    }
+}
@@ -0,0 +1,599 @@
+//! SLIP-10 hierarchical derivation, which generalizes BIP32 to the NIST P-256 and ed25519 curves.
+//!
+//! Ed25519 keys only have hardened children, and their public keys are prefixed with a zero byte
+//! so that they're 33 bytes like compressed points on the other curves.
+
+use crate::bip32::{ChainCode, ChildNumber, Error, Fingerprint};
+use crate::context;
+use crate::hash;
+use crate::wif;
+use ed25519_dalek::SigningKey;
+use p256::elliptic_curve::group::Group;
+use p256::elliptic_curve::sec1::{FromEncodedPoint, ToEncodedPoint};
+use p256::elliptic_curve::{Field, PrimeField};
+use p256::{AffinePoint, EncodedPoint, ProjectivePoint, Scalar};
+use secp256k1::{PublicKey, SecretKey};
+use std::fmt;
+use zeroize::{Zeroize, Zeroizing};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Curve {
+    Secp256k1,
+    Nist256p1,
+    Ed25519,
+}
+
+/// An extended private key on any of the SLIP-10 curves. Its private key and chain code are
+/// wiped when dropped and left out of the `Debug` output.
+#[derive(Clone, PartialEq)]
+pub struct ExtendedPrivateKey {
+    pub curve: Curve,
+    pub depth: u8,
+    pub parent_fingerprint: Fingerprint,
+    pub child_number: ChildNumber,
+    pub chain_code: ChainCode,
+    private_key: Zeroizing<[u8; 32]>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExtendedPublicKey {
+    pub curve: Curve,
+    pub depth: u8,
+    pub parent_fingerprint: Fingerprint,
+    pub child_number: ChildNumber,
+    pub chain_code: ChainCode,
+    pub public_key: [u8; 33],
+}
+
+impl Curve {
+    /// The HMAC key which master keys are generated with.
+    fn seed_key(self) -> &'static [u8] {
+        match self {
+            Curve::Secp256k1 => b"Bitcoin seed",
+            Curve::Nist256p1 => b"Nist256p1 seed",
+            Curve::Ed25519 => b"ed25519 seed",
+        }
+    }
+
+    fn is_valid_private_key(self, private_key: &[u8]) -> bool {
+        match self {
+            Curve::Secp256k1 => SecretKey::from_slice(private_key).is_ok(),
+            Curve::Nist256p1 => {
+                p256_scalar(private_key).map_or(false, |k| !bool::from(k.is_zero()))
+            }
+            Curve::Ed25519 => true,
+        }
+    }
+
+    fn public_key(self, private_key: &[u8; 32]) -> [u8; 33] {
+        match self {
+            Curve::Secp256k1 => {
+                let mut secret_key = SecretKey::from_slice(private_key).expect("valid private key");
+                let public_key = PublicKey::from_secret_key(context::secp256k1(), &secret_key);
+                wif::zeroize_secret_key(&mut secret_key);
+                public_key.serialize()
+            }
+            Curve::Nist256p1 => {
+                let scalar = p256_scalar(private_key).expect("valid private key");
+                let point = (ProjectivePoint::GENERATOR * scalar).to_affine();
+                copy_from_slice!([0u8; 33], point.to_encoded_point(true).as_bytes())
+            }
+            Curve::Ed25519 => {
+                let verifying_key = SigningKey::from_bytes(private_key).verifying_key();
+                let mut public_key = [0u8; 33];
+                public_key[1..].copy_from_slice(verifying_key.as_bytes());
+                public_key
+            }
+        }
+    }
+
+    /// Adds `tweak` to `private_key` modulo the curve order, failing if the tweak isn't below the
+    /// order or the sum is zero.
+    fn add_private(self, tweak: &[u8], private_key: &[u8; 32]) -> Option<Zeroizing<[u8; 32]>> {
+        match self {
+            Curve::Secp256k1 => {
+                let mut secret_key = SecretKey::from_slice(private_key).ok()?;
+                let sum = secret_key
+                    .add_assign(tweak)
+                    .ok()
+                    .map(|()| Zeroizing::new(copy_from_slice!([0u8; 32], &secret_key[..])));
+                wif::zeroize_secret_key(&mut secret_key);
+                sum
+            }
+            Curve::Nist256p1 => {
+                let sum = p256_scalar(tweak)? + p256_scalar(private_key)?;
+                if bool::from(sum.is_zero()) {
+                    return None;
+                }
+                Some(Zeroizing::new(copy_from_slice!([0u8; 32], &sum.to_repr())))
+            }
+            Curve::Ed25519 => None,
+        }
+    }
+
+    /// Adds the point of `tweak` to `public_key`, failing if the tweak isn't below the order or
+    /// the sum is the point at infinity.
+    fn add_public(self, tweak: &[u8], public_key: &[u8; 33]) -> Option<[u8; 33]> {
+        match self {
+            Curve::Secp256k1 => {
+                let mut public_key = PublicKey::from_slice(public_key).ok()?;
+                public_key
+                    .add_exp_assign(context::secp256k1(), tweak)
+                    .ok()?;
+                Some(public_key.serialize())
+            }
+            Curve::Nist256p1 => {
+                let point = EncodedPoint::from_bytes(public_key).ok()?;
+                let point = Option::<AffinePoint>::from(AffinePoint::from_encoded_point(&point))?;
+                let sum = ProjectivePoint::GENERATOR * p256_scalar(tweak)? + point;
+                if bool::from(sum.is_identity()) {
+                    return None;
+                }
+                Some(copy_from_slice!(
+                    [0u8; 33],
+                    sum.to_affine().to_encoded_point(true).as_bytes()
+                ))
+            }
+            Curve::Ed25519 => None,
+        }
+    }
+}
+
+/// Parses a big-endian P-256 scalar, failing if it isn't below the curve order.
+fn p256_scalar(bytes: &[u8]) -> Option<Scalar> {
+    let repr = copy_from_slice!([0u8; 32], bytes);
+    Scalar::from_repr(repr.into()).into()
+}
+
+impl fmt::Debug for ExtendedPrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtendedPrivateKey")
+            .field("curve", &self.curve)
+            .field("depth", &self.depth)
+            .field("parent_fingerprint", &self.parent_fingerprint)
+            .field("child_number", &self.child_number)
+            .field("chain_code", &format_args!("[redacted]"))
+            .field("private_key", &format_args!("[redacted]"))
+            .finish()
+    }
+}
+
+impl Drop for ExtendedPrivateKey {
+    fn drop(&mut self) {
+        self.chain_code.zeroize();
+    }
+}
+
+impl ExtendedPrivateKey {
+    /// Creates the master key generated from `seed` on `curve`.
+    pub fn new_master(seed: &[u8], curve: Curve) -> ExtendedPrivateKey {
+        let mut hmac_result = hash::hmac_sha512(seed, curve.seed_key());
+        // An invalid key is vanishingly unlikely, and is replaced by hashing the whole result.
+        while !curve.is_valid_private_key(&hmac_result[..32]) {
+            hmac_result = hash::hmac_sha512(&hmac_result[..], curve.seed_key());
+        }
+
+        ExtendedPrivateKey {
+            curve,
+            depth: 0,
+            parent_fingerprint: Fingerprint::from([0u8; 4]),
+            child_number: ChildNumber::Normal(0),
+            chain_code: copy_from_slice!([0u8; 32], &hmac_result[32..]).into(),
+            private_key: Zeroizing::new(copy_from_slice!([0u8; 32], &hmac_result[..32])),
+        }
+    }
+
+    pub fn private_key(&self) -> &[u8; 32] {
+        &self.private_key
+    }
+
+    pub fn public_key(&self) -> [u8; 33] {
+        self.curve.public_key(&self.private_key)
+    }
+
+    /// Derives the descendant at `path`, relative to this key.
+    pub fn derive_private_path(&self, path: &[ChildNumber]) -> Result<ExtendedPrivateKey, Error> {
+        path.iter().try_fold(self.clone(), |xprv, child_number| {
+            xprv.derive_private(*child_number)
+        })
+    }
+
+    /// Derives the child at `child_number`, which must be hardened on ed25519.
+    pub fn derive_private(&self, child_number: ChildNumber) -> Result<ExtendedPrivateKey, Error> {
+        let public_key = self.public_key();
+
+        let mut hmac_data = Zeroizing::new([0u8; 37]);
+        match child_number {
+            ChildNumber::Hardened(_) => hmac_data[1..33].copy_from_slice(&self.private_key[..]),
+            ChildNumber::Normal(_) if self.curve == Curve::Ed25519 => {
+                return Err(Error::ImpossibleDerivation)
+            }
+            ChildNumber::Normal(_) => hmac_data[..33].copy_from_slice(&public_key),
+        }
+        hmac_data[33..].copy_from_slice(&u32::from(child_number).to_be_bytes());
+
+        loop {
+            let hmac_result = hash::hmac_sha512(&hmac_data[..], self.chain_code.as_bytes());
+            let private_key = match self.curve {
+                Curve::Ed25519 => Some(Zeroizing::new(copy_from_slice!(
+                    [0u8; 32],
+                    &hmac_result[..32]
+                ))),
+                curve => curve.add_private(&hmac_result[..32], &self.private_key),
+            };
+
+            if let Some(private_key) = private_key {
+                return Ok(ExtendedPrivateKey {
+                    curve: self.curve,
+                    depth: self.depth + 1,
+                    parent_fingerprint: fingerprint(&public_key),
+                    child_number,
+                    chain_code: copy_from_slice!([0u8; 32], &hmac_result[32..]).into(),
+                    private_key,
+                });
+            }
+
+            // The child would be invalid, so derive it again from the right half of the result.
+            hmac_data[0] = 1;
+            hmac_data[1..33].copy_from_slice(&hmac_result[32..]);
+        }
+    }
+
+    /// Returns the HASH160 of this key's public key, which identifies it.
+    pub fn identifier(&self) -> [u8; 20] {
+        identifier(&self.public_key())
+    }
+
+    /// Returns the first four bytes of this key's identifier, which its children record as
+    /// their parent fingerprint.
+    pub fn fingerprint(&self) -> Fingerprint {
+        fingerprint(&self.public_key())
+    }
+}
+
+impl ExtendedPublicKey {
+    /// Derives the descendant at `path`, relative to this key.
+    pub fn derive_public_path(&self, path: &[ChildNumber]) -> Result<ExtendedPublicKey, Error> {
+        path.iter().try_fold(self.clone(), |xpub, child_number| {
+            xpub.derive_public(*child_number)
+        })
+    }
+
+    /// Derives the normal child at `child_number`, which isn't possible on ed25519.
+    pub fn derive_public(&self, child_number: ChildNumber) -> Result<ExtendedPublicKey, Error> {
+        let index = match child_number {
+            ChildNumber::Normal(index) if self.curve != Curve::Ed25519 => index,
+            _ => return Err(Error::ImpossibleDerivation),
+        };
+
+        let mut hmac_data = [0u8; 37];
+        hmac_data[..33].copy_from_slice(&self.public_key);
+        hmac_data[33..].copy_from_slice(&index.to_be_bytes());
+
+        loop {
+            let hmac_result = hash::hmac_sha512(&hmac_data, self.chain_code.as_bytes());
+            if let Some(public_key) = self.curve.add_public(&hmac_result[..32], &self.public_key) {
+                return Ok(ExtendedPublicKey {
+                    curve: self.curve,
+                    depth: self.depth + 1,
+                    parent_fingerprint: self.fingerprint(),
+                    child_number,
+                    chain_code: copy_from_slice!([0u8; 32], &hmac_result[32..]).into(),
+                    public_key,
+                });
+            }
+
+            hmac_data[0] = 1;
+            hmac_data[1..33].copy_from_slice(&hmac_result[32..]);
+        }
+    }
+
+    /// Returns the HASH160 of this key's public key, which identifies it.
+    pub fn identifier(&self) -> [u8; 20] {
+        identifier(&self.public_key)
+    }
+
+    /// Returns the first four bytes of this key's identifier, which its children record as
+    /// their parent fingerprint.
+    pub fn fingerprint(&self) -> Fingerprint {
+        fingerprint(&self.public_key)
+    }
+}
+
+fn identifier(public_key: &[u8; 33]) -> [u8; 20] {
+    hash::hash160(public_key)
+}
+
+fn fingerprint(public_key: &[u8; 33]) -> Fingerprint {
+    copy_from_slice!([0u8; 4], &identifier(public_key)[..4]).into()
+}
+
+impl From<&ExtendedPrivateKey> for ExtendedPublicKey {
+    fn from(xprv: &ExtendedPrivateKey) -> Self {
+        ExtendedPublicKey {
+            curve: xprv.curve,
+            depth: xprv.depth,
+            parent_fingerprint: xprv.parent_fingerprint,
+            child_number: xprv.child_number,
+            chain_code: xprv.chain_code,
+            public_key: xprv.public_key(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bip32::{self, DerivationPath};
+    use crate::network::Network;
+
+    const SEED_1: &str = "000102030405060708090a0b0c0d0e0f";
+    const SEED_2: &str = "fffcf9f6f3f0edeae7e4e1dedbd8d5d2cfccc9c6c3c0bdbab7b4b1aeaba8a5a29f9c999693908d8a8784817e7b7875726f6c696663605d5a5754514e4b484542";
+
+    /// Checks the keys derived from `seed` against `(path, fingerprint, chain code, private key,
+    /// public key)` vectors.
+    fn check_vectors(curve: Curve, seed: &str, vectors: &[(&str, &str, &str, &str, &str)]) {
+        let master = ExtendedPrivateKey::new_master(&hex::decode(seed).unwrap(), curve);
+
+        for (path, parent_fingerprint, chain_code, private_key, public_key) in vectors {
+            let path = path.parse::<DerivationPath>().unwrap();
+            let xprv = master.derive_private_path(path.as_ref()).unwrap();
+            assert_eq!(xprv.depth as usize, path.len());
+            assert_eq!(
+                hex::encode(xprv.parent_fingerprint.as_bytes()),
+                *parent_fingerprint
+            );
+            assert_eq!(hex::encode(xprv.chain_code.as_bytes()), *chain_code);
+            assert_eq!(hex::encode(xprv.private_key()), *private_key);
+
+            let xpub = ExtendedPublicKey::from(&xprv);
+            assert_eq!(hex::encode(xpub.public_key), *public_key);
+
+            // Normal children derive the same public key from their parent's.
+            if let Some((ChildNumber::Normal(_), parent_path)) = path.as_ref().split_last() {
+                let parent = master.derive_private_path(parent_path).unwrap();
+                let parent = ExtendedPublicKey::from(&parent);
+                assert_eq!(parent.derive_public(xprv.child_number), Ok(xpub));
+            }
+        }
+    }
+
+    #[test]
+    fn test_ed25519_vectors() {
+        check_vectors(
+            Curve::Ed25519,
+            SEED_1,
+            &[
+                (
+                    "m",
+                    "00000000",
+                    "90046a93de5380a72b5e45010748567d5ea02bbf6522f979e05c0d8d8ca9fffb",
+                    "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7",
+                    "00a4b2856bfec510abab89753fac1ac0e1112364e7d250545963f135f2a33188ed",
+                ),
+                (
+                    "m/0'",
+                    "ddebc675",
+                    "8b59aa11380b624e81507a27fedda59fea6d0b779a778918a2fd3590e16e9c69",
+                    "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3",
+                    "008c8a13df77a28f3445213a0f432fde644acaa215fc72dcdf300d5efaa85d350c",
+                ),
+                (
+                    "m/0'/1'",
+                    "13dab143",
+                    "a320425f77d1b5c2505a6b1b27382b37368ee640e3557c315416801243552f14",
+                    "b1d0bad404bf35da785a64ca1ac54b2617211d2777696fbffaf208f746ae84f2",
+                    "001932a5270f335bed617d5b935c80aedb1a35bd9fc1e31acafd5372c30f5c1187",
+                ),
+                (
+                    "m/0'/1'/2'",
+                    "ebe4cb29",
+                    "2e69929e00b5ab250f49c3fb1c12f252de4fed2c1db88387094a0f8c4c9ccd6c",
+                    "92a5b23c0b8a99e37d07df3fb9966917f5d06e02ddbd909c7e184371463e9fc9",
+                    "00ae98736566d30ed0e9d2f4486a64bc95740d89c7db33f52121f8ea8f76ff0fc1",
+                ),
+                (
+                    "m/0'/1'/2'/2'",
+                    "316ec1c6",
+                    "8f6d87f93d750e0efccda017d662a1b31a266e4a6f5993b15f5c1f07f74dd5cc",
+                    "30d1dc7e5fc04c31219ab25a27ae00b50f6fd66622f6e9c913253d6511d1e662",
+                    "008abae2d66361c879b900d204ad2cc4984fa2aa344dd7ddc46007329ac76c429c",
+                ),
+                (
+                    "m/0'/1'/2'/2'/1000000000'",
+                    "d6322ccd",
+                    "68789923a0cac2cd5a29172a475fe9e0fb14cd6adb5ad98a3fa70333e7afa230",
+                    "8f94d394a8e8fd6b1bc2f3f49f5c47e385281d5c17e65324b0f62483e37e8793",
+                    "003c24da049451555d51a7014a37337aa4e12d41e485abccfa46b47dfb2af54b7a",
+                ),
+            ],
+        );
+        check_vectors(
+            Curve::Ed25519,
+            SEED_2,
+            &[
+                (
+                    "m",
+                    "00000000",
+                    "ef70a74db9c3a5af931b5fe73ed8e1a53464133654fd55e7a66f8570b8e33c3b",
+                    "171cb88b1b3c1db25add599712e36245d75bc65a1a5c9e18d76f9f2b1eab4012",
+                    "008fe9693f8fa62a4305a140b9764c5ee01e455963744fe18204b4fb948249308a",
+                ),
+                (
+                    "m/0'/2147483647'/1'/2147483646'/2'",
+                    "422c654b",
+                    "5d70af781f3a37b829f0d060924d5e960bdc02e85423494afc0b1a41bbe196d4",
+                    "551d333177df541ad876a60ea71f00447931c0a9da16f227c11ea080d7391b8d",
+                    "0047150c75db263559a70d5778bf36abbab30fb061ad69f69ece61a72b0cfa4fc0",
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_nist256p1_vectors() {
+        check_vectors(
+            Curve::Nist256p1,
+            SEED_1,
+            &[
+                (
+                    "m",
+                    "00000000",
+                    "beeb672fe4621673f722f38529c07392fecaa61015c80c34f29ce8b41b3cb6ea",
+                    "612091aaa12e22dd2abef664f8a01a82cae99ad7441b7ef8110424915c268bc2",
+                    "0266874dc6ade47b3ecd096745ca09bcd29638dd52c2c12117b11ed3e458cfa9e8",
+                ),
+                (
+                    "m/0'",
+                    "be6105b5",
+                    "3460cea53e6a6bb5fb391eeef3237ffd8724bf0a40e94943c98b83825342ee11",
+                    "6939694369114c67917a182c59ddb8cafc3004e63ca5d3b84403ba8613debc0c",
+                    "0384610f5ecffe8fda089363a41f56a5c7ffc1d81b59a612d0d649b2d22355590c",
+                ),
+                (
+                    "m/0'/1",
+                    "9b02312f",
+                    "4187afff1aafa8445010097fb99d23aee9f599450c7bd140b6826ac22ba21d0c",
+                    "284e9d38d07d21e4e281b645089a94f4cf5a5a81369acf151a1c3a57f18b2129",
+                    "03526c63f8d0b4bbbf9c80df553fe66742df4676b241dabefdef67733e070f6844",
+                ),
+                (
+                    "m/0'/1/2'",
+                    "b98005c1",
+                    "98c7514f562e64e74170cc3cf304ee1ce54d6b6da4f880f313e8204c2a185318",
+                    "694596e8a54f252c960eb771a3c41e7e32496d03b954aeb90f61635b8e092aa7",
+                    "0359cf160040778a4b14c5f4d7b76e327ccc8c4a6086dd9451b7482b5a4972dda0",
+                ),
+                (
+                    "m/0'/1/2'/2",
+                    "0e9f3274",
+                    "ba96f776a5c3907d7fd48bde5620ee374d4acfd540378476019eab70790c63a0",
+                    "5996c37fd3dd2679039b23ed6f70b506c6b56b3cb5e424681fb0fa64caf82aaa",
+                    "029f871f4cb9e1c97f9f4de9ccd0d4a2f2a171110c61178f84430062230833ff20",
+                ),
+                (
+                    "m/0'/1/2'/2/1000000000",
+                    "8b2b5c4b",
+                    "b9b7b82d326bb9cb5b5b121066feea4eb93d5241103c9e7a18aad40f1dde8059",
+                    "21c4f269ef0a5fd1badf47eeacebeeaa3de22eb8e5b0adcd0f27dd99d34d0119",
+                    "02216cd26d31147f72427a453c443ed2cde8a1e53c9cc44e5ddf739725413fe3f4",
+                ),
+            ],
+        );
+        check_vectors(
+            Curve::Nist256p1,
+            SEED_2,
+            &[
+                (
+                    "m",
+                    "00000000",
+                    "96cd4465a9644e31528eda3592aa35eb39a9527769ce1855beafc1b81055e75d",
+                    "eaa31c2e46ca2962227cf21d73a7ef0ce8b31c756897521eb6c7b39796633357",
+                    "02c9e16154474b3ed5b38218bb0463e008f89ee03e62d22fdcc8014beab25b48fa",
+                ),
+                (
+                    "m/0",
+                    "607f628f",
+                    "84e9c258bb8557a40e0d041115b376dd55eda99c0042ce29e81ebe4efed9b86a",
+                    "d7d065f63a62624888500cdb4f88b6d59c2927fee9e6d0cdff9cad555884df6e",
+                    "039b6df4bece7b6c81e2adfeea4bcf5c8c8a6e40ea7ffa3cf6e8494c61a1fc82cc",
+                ),
+                (
+                    "m/0/2147483647'/1/2147483646'/2",
+                    "956c4629",
+                    "3bfb29ee8ac4484f09db09c2079b520ea5616df7820f071a20320366fbe226a7",
+                    "bb0a77ba01cc31d77205d51d08bd313b979a71ef4de9b062f8958297e746bd67",
+                    "020ee02e18967237cf62672983b253ee62fa4dd431f8243bfeccdf39dbe181387f",
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_nist256p1_retries() {
+        // The first attempt at m/28578'/33941 gives a key above the curve order.
+        check_vectors(
+            Curve::Nist256p1,
+            SEED_1,
+            &[
+                (
+                    "m/28578'",
+                    "be6105b5",
+                    "e94c8ebe30c2250a14713212f6449b20f3329105ea15b652ca5bdfc68f6c65c2",
+                    "06f0db126f023755d0b8d86d4591718a5210dd8d024e3e14b6159d63f53aa669",
+                    "02519b5554a4872e8c9c1c847115363051ec43e93400e030ba3c36b52a3e70a5b7",
+                ),
+                (
+                    "m/28578'/33941",
+                    "3e2b7bc6",
+                    "9e87fe95031f14736774cd82f25fd885065cb7c358c1edf813c72af535e83071",
+                    "092154eed4af83e078ff9b84322015aefe5769e31270f62c3f66c33888335f3a",
+                    "0235bfee614c0d5b2cae260000bb1d0d84b270099ad790022c1ae0b2e782efe120",
+                ),
+            ],
+        );
+
+        // And so does the first attempt at the master key of this seed.
+        check_vectors(
+            Curve::Nist256p1,
+            "a7305bc8df8d0951f0cb224c0e95d7707cbdf2c6ce7e8d481fec69c7ff5e9446",
+            &[(
+                "m",
+                "00000000",
+                "7762f9729fed06121fd13f326884c82f59aa95c57ac492ce8c9654e60efd130c",
+                "3b8c18469a4634517d6d0b65448f8e6c62091b45540a1743c5846be55d47d88f",
+                "0383619fadcde31063d8c5cb00dbfe1713f3e6fa169d8541a798752a1c1ca0cb20",
+            )],
+        );
+    }
+
+    #[test]
+    fn test_secp256k1_matches_bip32() {
+        let seed = hex::decode(SEED_1).unwrap();
+        let master = ExtendedPrivateKey::new_master(&seed, Curve::Secp256k1);
+        let bip32_master = bip32::ExtendedPrivateKey::new_master(&seed, Network::Mainnet).unwrap();
+
+        let path = "m/0'/1/2'/2/1000000000".parse::<DerivationPath>().unwrap();
+        let xprv = master.derive_private_path(path.as_ref()).unwrap();
+        let bip32_xprv = bip32_master.derive_private_path(path.as_ref()).unwrap();
+        assert_eq!(&xprv.private_key()[..], &bip32_xprv.private_key[..]);
+        assert_eq!(xprv.chain_code, bip32_xprv.chain_code);
+        assert_eq!(xprv.parent_fingerprint, bip32_xprv.parent_fingerprint);
+        assert_eq!(xprv.fingerprint(), bip32_xprv.fingerprint());
+
+        let xpub =
+            ExtendedPublicKey::from(&master.derive_private_path(&path.as_ref()[..3]).unwrap())
+                .derive_public_path(&path.as_ref()[3..])
+                .unwrap();
+        assert_eq!(xpub, ExtendedPublicKey::from(&xprv));
+    }
+
+    #[test]
+    fn test_impossible_derivations() {
+        let seed = hex::decode(SEED_1).unwrap();
+
+        let master = ExtendedPrivateKey::new_master(&seed, Curve::Ed25519);
+        assert_eq!(
+            master.derive_private(ChildNumber::Normal(0)),
+            Err(Error::ImpossibleDerivation)
+        );
+        assert_eq!(
+            ExtendedPublicKey::from(&master).derive_public(ChildNumber::Normal(0)),
+            Err(Error::ImpossibleDerivation)
+        );
+
+        let master = ExtendedPrivateKey::new_master(&seed, Curve::Nist256p1);
+        assert_eq!(
+            ExtendedPublicKey::from(&master).derive_public(ChildNumber::Hardened(0)),
+            Err(Error::ImpossibleDerivation)
+        );
+    }
+
+    #[test]
+    fn test_debug_is_redacted() {
+        let master = ExtendedPrivateKey::new_master(&hex::decode(SEED_1).unwrap(), Curve::Ed25519);
+        let debug = format!("{:?}", master);
+        assert!(debug.contains("Ed25519"));
+        assert!(!debug.contains("43, 75"));
+    }
+}
@@ -0,0 +1,914 @@
+//! SLIP-39 Shamir backups, which split a master secret into groups of mnemonic shares so that a
+//! threshold of shares from a threshold of groups recovers it. The recovered secret is the seed
+//! of a BIP32 master key.
+
+use crate::hash;
+use rand::Rng;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
+
+/// The maximum number of groups, and of shares in a group.
+const MAX_SHARES: usize = 16;
+
+/// The number of bytes of the digest guarding a shared secret against a wrong set of shares.
+const DIGEST_LENGTH: usize = 4;
+
+/// The x coordinates of the shared secret and of its digest.
+const SECRET_INDEX: u8 = 255;
+const DIGEST_INDEX: u8 = 254;
+
+/// The longest master secret, in bytes, so that each encryption round's key is a single PBKDF2
+/// block. SLIP-39 sets no limit, but wallets use 16 or 32 bytes.
+const MAX_SECRET_LENGTH: usize = 64;
+
+/// PBKDF2 iterations in each of the 4 encryption rounds, before scaling by the iteration
+/// exponent.
+const BASE_ITERATIONS: u32 = 10000 / 4;
+const ROUNDS: [u8; 4] = [0, 1, 2, 3];
+
+/// The words holding the share parameters and the checksum.
+const METADATA_WORDS: usize = 4;
+const CHECKSUM_WORDS: usize = 3;
+const MIN_WORDS: usize = 20;
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    InvalidWordCount(usize),
+    UnknownWord(String),
+    InvalidChecksum,
+    InvalidPadding,
+    /// Master secrets must be 16 to 64 bytes and a multiple of 2 bytes.
+    InvalidSecretLength(usize),
+    InvalidThreshold {
+        threshold: u8,
+        count: u8,
+    },
+    TooManyShares(usize),
+    InvalidIterationExponent(u8),
+    /// Passphrases may only contain printable ASCII characters.
+    InvalidPassphrase,
+    /// The shares don't all belong to the same secret.
+    MismatchedShares,
+    WrongGroupCount(usize),
+    WrongShareCount {
+        group_index: u8,
+        shares: usize,
+    },
+    DuplicateShare(u8),
+    InvalidDigest,
+}
+
+/// A share of a master secret, encoded as a mnemonic. The share value is wiped when dropped and
+/// left out of the `Debug` output.
+#[derive(Clone, PartialEq)]
+pub struct Share {
+    identifier: u16,
+    extendable: bool,
+    iteration_exponent: u8,
+    group_index: u8,
+    group_threshold: u8,
+    group_count: u8,
+    member_index: u8,
+    member_threshold: u8,
+    value: Zeroizing<Vec<u8>>,
+}
+
+/// Returns the wordlist, in the order its words encode the values 0 to 1023.
+pub fn wordlist() -> &'static [&'static str] {
+    static WORDLIST: OnceLock<Vec<&'static str>> = OnceLock::new();
+    WORDLIST.get_or_init(|| include_str!("wordlist.txt").lines().collect())
+}
+
+/// Splits `master_secret` into groups of shares, encrypting it first with `passphrase`.
+///
+/// `groups` lists the member threshold and member count of each group, and `group_threshold` of
+/// them are needed to recover the secret. Each encryption round runs `2500 << iteration_exponent`
+/// iterations of PBKDF2. Extendable backups can later be extended with more groups for the same
+/// secret, so their encryption doesn't depend on the random identifier.
+pub fn generate_shares(
+    group_threshold: u8,
+    groups: &[(u8, u8)],
+    master_secret: &[u8],
+    passphrase: &str,
+    extendable: bool,
+    iteration_exponent: u8,
+) -> Result<Vec<Vec<Share>>, Error> {
+    generate_shares_with_rng(
+        group_threshold,
+        groups,
+        master_secret,
+        passphrase,
+        extendable,
+        iteration_exponent,
+        &mut rand::thread_rng(),
+    )
+}
+
+pub fn generate_shares_with_rng<R: Rng>(
+    group_threshold: u8,
+    groups: &[(u8, u8)],
+    master_secret: &[u8],
+    passphrase: &str,
+    extendable: bool,
+    iteration_exponent: u8,
+    rng: &mut R,
+) -> Result<Vec<Vec<Share>>, Error> {
+    if master_secret.len() < 16
+        || master_secret.len() > MAX_SECRET_LENGTH
+        || master_secret.len() % 2 != 0
+    {
+        return Err(Error::InvalidSecretLength(master_secret.len()));
+    }
+    check_passphrase(passphrase)?;
+    if iteration_exponent > 15 {
+        return Err(Error::InvalidIterationExponent(iteration_exponent));
+    }
+    check_threshold(group_threshold, groups.len())?;
+    for &(member_threshold, member_count) in groups {
+        check_threshold(member_threshold, member_count as usize)?;
+        // A threshold of 1 gives every member the same share, which is better done with 1 share.
+        if member_threshold == 1 && member_count > 1 {
+            return Err(Error::InvalidThreshold {
+                threshold: member_threshold,
+                count: member_count,
+            });
+        }
+    }
+
+    let identifier = rng.gen::<u16>() & 0x7fff;
+    let encrypted = feistel(
+        master_secret,
+        passphrase,
+        iteration_exponent,
+        identifier,
+        extendable,
+        ROUNDS.iter().copied(),
+    );
+
+    let group_secrets = split_secret(group_threshold, groups.len() as u8, &encrypted, rng);
+    let mut shares = Vec::with_capacity(groups.len());
+    for (&(member_threshold, member_count), (group_index, group_secret)) in
+        groups.iter().zip(group_secrets)
+    {
+        let members = split_secret(member_threshold, member_count, &group_secret, rng)
+            .into_iter()
+            .map(|(member_index, value)| Share {
+                identifier,
+                extendable,
+                iteration_exponent,
+                group_index,
+                group_threshold,
+                group_count: groups.len() as u8,
+                member_index,
+                member_threshold,
+                value,
+            })
+            .collect();
+        shares.push(members);
+    }
+
+    Ok(shares)
+}
+
+/// Recovers a master secret from its shares, decrypting it with `passphrase`.
+///
+/// Every passphrase decrypts to a valid secret, so a wrong one can't be detected here.
+pub fn combine_shares(shares: &[Share], passphrase: &str) -> Result<Zeroizing<Vec<u8>>, Error> {
+    check_passphrase(passphrase)?;
+    let first = shares.first().ok_or(Error::WrongGroupCount(0))?;
+    if shares.iter().any(|share| !share.same_secret(first)) {
+        return Err(Error::MismatchedShares);
+    }
+
+    let mut groups = BTreeMap::<u8, Vec<&Share>>::new();
+    for share in shares {
+        groups.entry(share.group_index).or_default().push(share);
+    }
+    if groups.len() != first.group_threshold as usize {
+        return Err(Error::WrongGroupCount(groups.len()));
+    }
+
+    let mut group_secrets = Vec::with_capacity(groups.len());
+    for (group_index, members) in groups {
+        let member_threshold = members[0].member_threshold;
+        if members
+            .iter()
+            .any(|share| share.member_threshold != member_threshold)
+        {
+            return Err(Error::MismatchedShares);
+        }
+        if members.len() != member_threshold as usize {
+            return Err(Error::WrongShareCount {
+                group_index,
+                shares: members.len(),
+            });
+        }
+
+        let members = members
+            .iter()
+            .map(|share| (share.member_index, &share.value[..]))
+            .collect::<Vec<_>>();
+        group_secrets.push((group_index, recover_secret(member_threshold, &members)?));
+    }
+
+    let group_secrets = group_secrets
+        .iter()
+        .map(|(group_index, secret)| (*group_index, &secret[..]))
+        .collect::<Vec<_>>();
+    let encrypted = recover_secret(first.group_threshold, &group_secrets)?;
+
+    Ok(feistel(
+        &encrypted,
+        passphrase,
+        first.iteration_exponent,
+        first.identifier,
+        first.extendable,
+        ROUNDS.iter().rev().copied(),
+    ))
+}
+
+impl Share {
+    pub fn identifier(&self) -> u16 {
+        self.identifier
+    }
+
+    pub fn extendable(&self) -> bool {
+        self.extendable
+    }
+
+    pub fn iteration_exponent(&self) -> u8 {
+        self.iteration_exponent
+    }
+
+    pub fn group_index(&self) -> u8 {
+        self.group_index
+    }
+
+    pub fn group_threshold(&self) -> u8 {
+        self.group_threshold
+    }
+
+    pub fn group_count(&self) -> u8 {
+        self.group_count
+    }
+
+    pub fn member_index(&self) -> u8 {
+        self.member_index
+    }
+
+    pub fn member_threshold(&self) -> u8 {
+        self.member_threshold
+    }
+
+    /// Returns the words of the mnemonic.
+    pub fn words(&self) -> Vec<&'static str> {
+        // The identifier, extendable flag and iteration exponent fill the first 20 bits, and the
+        // group and member parameters the next 20. The value follows, padded at the front with
+        // zeros to a multiple of 10 bits, and then the checksum.
+        let id = self.identifier as u32;
+        let first = (id << 5) | (self.extendable as u32) << 4 | self.iteration_exponent as u32;
+        let second = (self.group_index as u32) << 16
+            | (self.group_threshold as u32 - 1) << 12
+            | (self.group_count as u32 - 1) << 8
+            | (self.member_index as u32) << 4
+            | (self.member_threshold as u32 - 1);
+
+        let mut values = Zeroizing::new(vec![
+            (first >> 10) as u16,
+            (first & 0x3ff) as u16,
+            (second >> 10) as u16,
+            (second & 0x3ff) as u16,
+        ]);
+
+        let value_words = (self.value.len() * 8).div_ceil(10);
+        let padding = value_words * 10 - self.value.len() * 8;
+        let bit = |index: usize| match index.checked_sub(padding) {
+            Some(index) => (self.value[index / 8] >> (7 - index % 8)) & 1,
+            None => 0,
+        };
+        for word in 0..value_words {
+            values.push((0..10).fold(0, |acc, i| (acc << 1) | bit(word * 10 + i) as u16));
+        }
+
+        let checksum = checksum(self.extendable, &values);
+        values.extend_from_slice(&checksum);
+        values
+            .iter()
+            .map(|&index| wordlist()[index as usize])
+            .collect()
+    }
+
+    /// Returns the mnemonic, with its words separated by spaces.
+    pub fn mnemonic(&self) -> Zeroizing<String> {
+        Zeroizing::new(self.words().join(" "))
+    }
+
+    fn same_secret(&self, other: &Share) -> bool {
+        self.identifier == other.identifier
+            && self.extendable == other.extendable
+            && self.iteration_exponent == other.iteration_exponent
+            && self.group_threshold == other.group_threshold
+            && self.group_count == other.group_count
+            && self.value.len() == other.value.len()
+    }
+}
+
+impl FromStr for Share {
+    type Err = Error;
+
+    /// Decodes a mnemonic of whitespace separated words, checking its checksum.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words = s.split_whitespace().collect::<Vec<_>>();
+        if words.len() < MIN_WORDS {
+            return Err(Error::InvalidWordCount(words.len()));
+        }
+
+        let mut values = Zeroizing::new(Vec::with_capacity(words.len()));
+        for word in &words {
+            let index = wordlist()
+                .binary_search(word)
+                .map_err(|_| Error::UnknownWord(word.to_string()))?;
+            values.push(index as u16);
+        }
+
+        let first = (values[0] as u32) << 10 | values[1] as u32;
+        let second = (values[2] as u32) << 10 | values[3] as u32;
+        let extendable = (first >> 4) & 1 == 1;
+        if checksum(extendable, &values[..values.len() - CHECKSUM_WORDS])
+            != values[values.len() - CHECKSUM_WORDS..]
+        {
+            return Err(Error::InvalidChecksum);
+        }
+
+        let value_words = &values[METADATA_WORDS..values.len() - CHECKSUM_WORDS];
+        let padding = value_words.len() * 10 % 16;
+        if padding > 8 {
+            return Err(Error::InvalidPadding);
+        }
+
+        let bit = |index: usize| (value_words[index / 10] >> (9 - index % 10)) & 1;
+        if (0..padding).any(|index| bit(index) != 0) {
+            return Err(Error::InvalidPadding);
+        }
+
+        let value_len = (value_words.len() * 10 - padding) / 8;
+        if value_len > MAX_SECRET_LENGTH {
+            return Err(Error::InvalidSecretLength(value_len));
+        }
+
+        let mut value = Zeroizing::new(vec![0u8; value_len]);
+        for (i, byte) in value.iter_mut().enumerate() {
+            *byte = (0..8).fold(0, |acc, j| (acc << 1) | bit(padding + i * 8 + j) as u8);
+        }
+
+        let group_threshold = ((second >> 12) & 0xf) as u8 + 1;
+        let group_count = ((second >> 8) & 0xf) as u8 + 1;
+        if group_threshold > group_count {
+            return Err(Error::InvalidThreshold {
+                threshold: group_threshold,
+                count: group_count,
+            });
+        }
+
+        Ok(Share {
+            identifier: (first >> 5) as u16,
+            extendable,
+            iteration_exponent: (first & 0xf) as u8,
+            group_index: (second >> 16) as u8,
+            group_threshold,
+            group_count,
+            member_index: ((second >> 4) & 0xf) as u8,
+            member_threshold: (second & 0xf) as u8 + 1,
+            value,
+        })
+    }
+}
+
+impl fmt::Debug for Share {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Share")
+            .field("identifier", &self.identifier)
+            .field("extendable", &self.extendable)
+            .field("iteration_exponent", &self.iteration_exponent)
+            .field("group_index", &self.group_index)
+            .field("group_threshold", &self.group_threshold)
+            .field("group_count", &self.group_count)
+            .field("member_index", &self.member_index)
+            .field("member_threshold", &self.member_threshold)
+            .field("value", &format_args!("[redacted]"))
+            .finish()
+    }
+}
+
+fn check_passphrase(passphrase: &str) -> Result<(), Error> {
+    if passphrase.bytes().all(|c| (32..=126).contains(&c)) {
+        Ok(())
+    } else {
+        Err(Error::InvalidPassphrase)
+    }
+}
+
+fn check_threshold(threshold: u8, count: usize) -> Result<(), Error> {
+    if count > MAX_SHARES {
+        return Err(Error::TooManyShares(count));
+    }
+    if threshold == 0 || threshold as usize > count {
+        return Err(Error::InvalidThreshold {
+            threshold,
+            count: count as u8,
+        });
+    }
+    Ok(())
+}
+
+/// The RS1024 checksum over the customization string and the word values.
+fn polymod(extendable: bool, values: &[u16]) -> u32 {
+    const GENERATOR: [u32; 10] = [
+        0xe0e040, 0x1c1c080, 0x3838100, 0x7070200, 0xe0e0009, 0x1c0c2412, 0x38086c24, 0x3090fc48,
+        0x21b1f890, 0x3f3f120,
+    ];
+    let customization: &[u8] = if extendable {
+        b"shamir_extendable"
+    } else {
+        b"shamir"
+    };
+
+    let values = customization
+        .iter()
+        .map(|&c| c as u16)
+        .chain(values.iter().copied());
+    values.fold(1, |chk, value| {
+        let top = chk >> 20;
+        let chk = (chk & 0xfffff) << 10 ^ value as u32;
+        (0..10)
+            .filter(|i| (top >> i) & 1 == 1)
+            .fold(chk, |chk, i| chk ^ GENERATOR[i])
+    })
+}
+
+/// Returns the 3 checksum words of a share.
+fn checksum(extendable: bool, values: &[u16]) -> [u16; CHECKSUM_WORDS] {
+    let mut values = values.to_vec();
+    values.extend_from_slice(&[0; CHECKSUM_WORDS]);
+    let chk = polymod(extendable, &values) ^ 1;
+    [
+        (chk >> 20) as u16,
+        (chk >> 10) as u16 & 0x3ff,
+        chk as u16 & 0x3ff,
+    ]
+}
+
+/// Encrypts or decrypts a master secret with a 4 round Feistel network, depending on the order of
+/// the `rounds`.
+fn feistel(
+    data: &[u8],
+    passphrase: &str,
+    iteration_exponent: u8,
+    identifier: u16,
+    extendable: bool,
+    rounds: impl Iterator<Item = u8>,
+) -> Zeroizing<Vec<u8>> {
+    let mut salt = Vec::new();
+    if !extendable {
+        salt.extend_from_slice(b"shamir");
+        salt.extend_from_slice(&identifier.to_be_bytes());
+    }
+    let iterations = BASE_ITERATIONS << iteration_exponent;
+
+    let (left, right) = data.split_at(data.len() / 2);
+    let mut left = Zeroizing::new(left.to_vec());
+    let mut right = Zeroizing::new(right.to_vec());
+    for round in rounds {
+        let mut password = Zeroizing::new(vec![round]);
+        password.extend_from_slice(passphrase.as_bytes());
+        let round_salt = [&salt[..], &right[..]].concat();
+        let key = hash::pbkdf2_hmac_sha256::<32>(&password, &round_salt, iterations);
+
+        left.iter_mut().zip(key.iter()).for_each(|(l, k)| *l ^= k);
+        std::mem::swap(&mut left, &mut right);
+    }
+
+    right.extend_from_slice(&left);
+    right
+}
+
+/// The exponent and logarithm tables of GF(256), with the AES reduction polynomial.
+struct Tables {
+    exp: [u8; 255],
+    log: [u8; 256],
+}
+
+fn tables() -> &'static Tables {
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let mut tables = Tables {
+            exp: [0; 255],
+            log: [0; 256],
+        };
+        let mut power = 1u16;
+        for i in 0..255 {
+            tables.exp[i] = power as u8;
+            tables.log[power as usize] = i as u8;
+            // Multiply by the generator 3.
+            power ^= power << 1;
+            if power & 0x100 != 0 {
+                power ^= 0x11b;
+            }
+        }
+        tables
+    })
+}
+
+/// Evaluates at `x` the polynomial through the points `shares`, which have distinct x
+/// coordinates and values of the same length.
+fn interpolate(shares: &[(u8, &[u8])], x: u8) -> Zeroizing<Vec<u8>> {
+    if let Some((_, value)) = shares.iter().find(|(share_x, _)| *share_x == x) {
+        return Zeroizing::new(value.to_vec());
+    }
+
+    let Tables { exp, log } = tables();
+    let log_product = shares
+        .iter()
+        .map(|(share_x, _)| log[(share_x ^ x) as usize] as u32)
+        .sum::<u32>();
+
+    let mut result = Zeroizing::new(vec![0u8; shares[0].1.len()]);
+    for (share_x, value) in shares {
+        let log_denominator = shares
+            .iter()
+            .map(|(other_x, _)| log[(share_x ^ other_x) as usize] as u32)
+            .sum::<u32>();
+        let log_basis =
+            (log_product + 255 * 16 - log[(share_x ^ x) as usize] as u32 - log_denominator) % 255;
+
+        for (r, &v) in result.iter_mut().zip(value.iter()) {
+            if v != 0 {
+                *r ^= exp[((log[v as usize] as u32 + log_basis) % 255) as usize];
+            }
+        }
+    }
+
+    result
+}
+
+/// Splits `secret` into `count` shares, any `threshold` of which recover it. The polynomial also
+/// passes through a digest of the secret, which recovery checks.
+fn split_secret<R: Rng>(
+    threshold: u8,
+    count: u8,
+    secret: &[u8],
+    rng: &mut R,
+) -> Vec<(u8, Zeroizing<Vec<u8>>)> {
+    if threshold == 1 {
+        return (0..count)
+            .map(|x| (x, Zeroizing::new(secret.to_vec())))
+            .collect();
+    }
+
+    let mut shares = (0..threshold - 2)
+        .map(|x| {
+            let mut value = Zeroizing::new(vec![0u8; secret.len()]);
+            rng.fill(&mut value[..]);
+            (x, value)
+        })
+        .collect::<Vec<_>>();
+
+    let mut digest_share = Zeroizing::new(vec![0u8; secret.len()]);
+    rng.fill(&mut digest_share[DIGEST_LENGTH..]);
+    let digest = hash::hmac_sha256(secret, &digest_share[DIGEST_LENGTH..]);
+    digest_share[..DIGEST_LENGTH].copy_from_slice(&digest[..DIGEST_LENGTH]);
+
+    let mut points = shares
+        .iter()
+        .map(|(x, value)| (*x, &value[..]))
+        .collect::<Vec<_>>();
+    points.push((DIGEST_INDEX, &digest_share));
+    points.push((SECRET_INDEX, secret));
+
+    let derived = (threshold - 2..count)
+        .map(|x| (x, interpolate(&points, x)))
+        .collect::<Vec<_>>();
+    shares.extend(derived);
+    shares
+}
+
+fn recover_secret(threshold: u8, shares: &[(u8, &[u8])]) -> Result<Zeroizing<Vec<u8>>, Error> {
+    for (i, (x, _)) in shares.iter().enumerate() {
+        if shares[..i].iter().any(|(other_x, _)| other_x == x) {
+            return Err(Error::DuplicateShare(*x));
+        }
+    }
+
+    if threshold == 1 {
+        return Ok(Zeroizing::new(shares[0].1.to_vec()));
+    }
+
+    let secret = interpolate(shares, SECRET_INDEX);
+    let digest_share = interpolate(shares, DIGEST_INDEX);
+    let digest = hash::hmac_sha256(&secret, &digest_share[DIGEST_LENGTH..]);
+    if bool::from(digest[..DIGEST_LENGTH].ct_eq(&digest_share[..DIGEST_LENGTH])) {
+        Ok(secret)
+    } else {
+        Err(Error::InvalidDigest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base58check::Base58CheckString;
+    use crate::bip32::ExtendedPrivateKey;
+    use crate::network::Network;
+    use serde_json::Value;
+
+    fn parse(mnemonics: &[&str]) -> Vec<Share> {
+        mnemonics.iter().map(|m| m.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_wordlist() {
+        let wordlist = wordlist();
+        assert_eq!(wordlist.len(), 1024);
+        assert!(wordlist.windows(2).all(|words| words[0] < words[1]));
+        assert_eq!(
+            hex::encode(hash::sha256(include_bytes!("wordlist.txt"))),
+            "bcc4555340332d169718aed8bf31dd9d5248cb7da6e5d355140ef4f1e601eec3"
+        );
+    }
+
+    #[test]
+    fn test_vectors() {
+        // From the SLIP-39 test vectors, all with the passphrase "TREZOR".
+        let tests: &[(&[&str], &str)] = &[
+            (
+                &["duckling enlarge academic academic agency result length solution fridge kidney coal piece deal husband erode duke ajar critical decision keyboard"],
+                "bb54aac4b89dc868ba37d9cc21b2cece",
+            ),
+            (
+                &[
+                    "shadow pistol academic always adequate wildlife fancy gross oasis cylinder mustang wrist rescue view short owner flip making coding armed",
+                    "shadow pistol academic acid actress prayer class unknown daughter sweater depict flip twice unkind craft early superior advocate guest smoking",
+                ],
+                "b43ceb7e57a0ea8766221624d01b0864",
+            ),
+            (
+                &[
+                    "eraser senior beard romp adorn nuclear spill corner cradle style ancient family general leader ambition exchange unusual garlic promise voice",
+                    "eraser senior ceramic snake clay various huge numb argue hesitate auction category timber browser greatest hanger petition script leaf pickup",
+                    "eraser senior ceramic shaft dynamic become junior wrist silver peasant force math alto coal amazing segment yelp velvet image paces",
+                    "eraser senior ceramic round column hawk trust auction smug shame alive greatest sheriff living perfect corner chest sled fumes adequate",
+                ],
+                "7c3397a292a5941682d7a4ae2d898d11",
+            ),
+            (
+                &["theory painting academic academic armed sweater year military elder discuss acne wildlife boring employer fused large satoshi bundle carbon diagnose anatomy hamster leaves tracks paces beyond phantom capital marvel lips brave detect luck"],
+                "989baf9dcaad5b10ca33dfd8cc75e42477025dce88ae83e75a230086a0e00e92",
+            ),
+        ];
+
+        for (mnemonics, secret) in tests {
+            let shares = parse(mnemonics);
+            for (share, mnemonic) in shares.iter().zip(mnemonics.iter()) {
+                assert_eq!(share.mnemonic().as_str(), *mnemonic);
+            }
+            let master_secret = combine_shares(&shares, "TREZOR").unwrap();
+            assert_eq!(hex::encode(&master_secret[..]), *secret);
+        }
+    }
+
+    #[test]
+    fn test_generated_vectors() {
+        // Covering the cases of the official SLIP-39 test vectors, including extendable shares,
+        // iteration exponents and invalid share sets, but generated by an independent
+        // implementation which reproduces the official vectors above. Invalid vectors give the
+        // error they're expected to fail with.
+        let tests: Vec<Value> =
+            serde_json::from_str(include_str!("../../testdata/slip39_generated_vectors.json"))
+                .unwrap();
+
+        for test in tests {
+            let description = test["description"].as_str().unwrap();
+            let mnemonics = test["mnemonics"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|mnemonic| mnemonic.as_str().unwrap())
+                .collect::<Vec<_>>();
+            let result = mnemonics
+                .iter()
+                .map(|mnemonic| mnemonic.parse::<Share>())
+                .collect::<Result<Vec<_>, _>>()
+                .and_then(|shares| combine_shares(&shares, "TREZOR"));
+
+            if let Some(error) = test["error"].as_str() {
+                let err = result.err().unwrap();
+                assert_eq!(format!("{:?}", err), error, "{}", description);
+                continue;
+            }
+
+            let master_secret = result.unwrap();
+            assert_eq!(
+                hex::encode(&master_secret[..]),
+                test["master_secret"].as_str().unwrap(),
+                "{}",
+                description
+            );
+            let master = ExtendedPrivateKey::new_master(&master_secret, Network::Mainnet).unwrap();
+            assert_eq!(
+                Base58CheckString::from(&master).as_str(),
+                test["xprv"].as_str().unwrap(),
+                "{}",
+                description
+            );
+
+            for mnemonic in mnemonics {
+                let share = mnemonic.parse::<Share>().unwrap();
+                assert_eq!(share.mnemonic().as_str(), mnemonic);
+                assert_eq!(
+                    Some(share.extendable()),
+                    test["extendable"].as_bool(),
+                    "{}",
+                    description
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_master_key() {
+        let share = "duckling enlarge academic academic agency result length solution fridge kidney coal piece deal husband erode duke ajar critical decision keyboard";
+        let master_secret = combine_shares(&parse(&[share]), "TREZOR").unwrap();
+        let master = ExtendedPrivateKey::new_master(&master_secret, Network::Mainnet).unwrap();
+        assert_eq!(
+            Base58CheckString::from(&master).as_str(),
+            "xprv9s21ZrQH143K4QViKpwKCpS2zVbz8GrZgpEchMDg6KME9HZtjfL7iThE9w5muQA4YPHKN1u5VM1w8D4pvnjxa2BmpGMfXr7hnRrRHZ93awZ"
+        );
+    }
+
+    #[test]
+    fn test_invalid_mnemonics() {
+        assert_eq!(
+            "duckling enlarge academic academic agency result length solution fridge kidney coal piece deal husband erode duke ajar critical decision kidney"
+                .parse::<Share>(),
+            Err(Error::InvalidChecksum)
+        );
+        assert_eq!(
+            "duckling enlarge academic academic".parse::<Share>(),
+            Err(Error::InvalidWordCount(4))
+        );
+        assert_eq!(
+            "duckling enlarge academic academic agency result length solution fridge kidney coal piece deal husband erode duke ajar critical decision bitcoin"
+                .parse::<Share>(),
+            Err(Error::UnknownWord("bitcoin".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_invalid_combinations() {
+        let basic = parse(&[
+            "shadow pistol academic always adequate wildlife fancy gross oasis cylinder mustang wrist rescue view short owner flip making coding armed",
+            "shadow pistol academic acid actress prayer class unknown daughter sweater depict flip twice unkind craft early superior advocate guest smoking",
+        ]);
+        assert_eq!(
+            combine_shares(&basic[..1], "TREZOR"),
+            Err(Error::WrongShareCount {
+                group_index: 0,
+                shares: 1
+            })
+        );
+        assert_eq!(
+            combine_shares(&[basic[0].clone(), basic[0].clone()], "TREZOR"),
+            Err(Error::DuplicateShare(basic[0].member_index()))
+        );
+        assert_eq!(
+            combine_shares(&[], "TREZOR"),
+            Err(Error::WrongGroupCount(0))
+        );
+
+        let single = parse(&["duckling enlarge academic academic agency result length solution fridge kidney coal piece deal husband erode duke ajar critical decision keyboard"]);
+        assert_eq!(
+            combine_shares(&[basic[0].clone(), single[0].clone()], "TREZOR"),
+            Err(Error::MismatchedShares)
+        );
+
+        // Shares from one group too many.
+        let groups = parse(&[
+            "eraser senior beard romp adorn nuclear spill corner cradle style ancient family general leader ambition exchange unusual garlic promise voice",
+            "eraser senior ceramic snake clay various huge numb argue hesitate auction category timber browser greatest hanger petition script leaf pickup",
+            "eraser senior ceramic shaft dynamic become junior wrist silver peasant force math alto coal amazing segment yelp velvet image paces",
+            "eraser senior ceramic round column hawk trust auction smug shame alive greatest sheriff living perfect corner chest sled fumes adequate",
+            "eraser senior decision smug corner ruin rescue cubic angel tackle skin skunk program roster trash rumor slush angel flea amazing",
+        ]);
+        assert_eq!(
+            combine_shares(&groups, "TREZOR"),
+            Err(Error::WrongGroupCount(3))
+        );
+    }
+
+    #[test]
+    fn test_generate_and_combine() {
+        let master_secret = hex::decode("0f1e2d3c4b5a69788796a5b4c3d2e1f0").unwrap();
+        let groups = [(1, 1), (2, 3), (3, 5)];
+
+        for &extendable in &[false, true] {
+            let shares =
+                generate_shares(2, &groups, &master_secret, "TREZOR", extendable, 0).unwrap();
+            assert_eq!(
+                shares.iter().map(Vec::len).collect::<Vec<_>>(),
+                vec![1, 3, 5]
+            );
+
+            // Every share round trips through its mnemonic.
+            for share in shares.iter().flatten() {
+                assert_eq!(share.mnemonic().parse::<Share>().as_ref(), Ok(share));
+                assert_eq!(share.extendable(), extendable);
+            }
+
+            let mut chosen = shares[0].clone();
+            chosen.extend(shares[2][1..4].iter().cloned());
+            let recovered = combine_shares(&chosen, "TREZOR").unwrap();
+            assert_eq!(&recovered[..], &master_secret[..]);
+
+            let mut chosen = shares[1][1..].to_vec();
+            chosen.extend(shares[2][..2].iter().cloned());
+            chosen.push(shares[2][4].clone());
+            let recovered = combine_shares(&chosen, "TREZOR").unwrap();
+            assert_eq!(&recovered[..], &master_secret[..]);
+
+            // A different passphrase recovers a different secret.
+            let recovered = combine_shares(&chosen, "").unwrap();
+            assert_ne!(&recovered[..], &master_secret[..]);
+        }
+    }
+
+    #[test]
+    fn test_secret_length_limit() {
+        let master_secret = (0..MAX_SECRET_LENGTH as u8).collect::<Vec<_>>();
+        let shares = generate_shares(1, &[(2, 3)], &master_secret, "", false, 0).unwrap();
+        let recovered = combine_shares(&shares[0][..2], "").unwrap();
+        assert_eq!(&recovered[..], &master_secret[..]);
+
+        // Shares of longer secrets are rejected when parsed.
+        let mut share = shares[0][0].clone();
+        share.value = Zeroizing::new(vec![0u8; MAX_SECRET_LENGTH + 2]);
+        assert_eq!(
+            share.mnemonic().parse::<Share>(),
+            Err(Error::InvalidSecretLength(MAX_SECRET_LENGTH + 2))
+        );
+    }
+
+    #[test]
+    fn test_invalid_generation() {
+        let master_secret = [0u8; 16];
+        assert_eq!(
+            generate_shares(1, &[(1, 1)], &master_secret[..15], "", false, 0),
+            Err(Error::InvalidSecretLength(15))
+        );
+        assert_eq!(
+            generate_shares(1, &[(1, 1)], &[0u8; 18][..17], "", false, 0),
+            Err(Error::InvalidSecretLength(17))
+        );
+        assert_eq!(
+            generate_shares(1, &[(1, 1)], &[0u8; 66], "", false, 0),
+            Err(Error::InvalidSecretLength(66))
+        );
+        assert_eq!(
+            generate_shares(3, &[(1, 1), (2, 3)], &master_secret, "", false, 0),
+            Err(Error::InvalidThreshold {
+                threshold: 3,
+                count: 2
+            })
+        );
+        assert_eq!(
+            generate_shares(1, &[(1, 3)], &master_secret, "", false, 0),
+            Err(Error::InvalidThreshold {
+                threshold: 1,
+                count: 3
+            })
+        );
+        assert_eq!(
+            generate_shares(1, &[(2, 17)], &master_secret, "", false, 0),
+            Err(Error::TooManyShares(17))
+        );
+        assert_eq!(
+            generate_shares(1, &[(1, 1)], &master_secret, "", false, 16),
+            Err(Error::InvalidIterationExponent(16))
+        );
+        assert_eq!(
+            generate_shares(1, &[(1, 1)], &master_secret, "pässword", false, 0),
+            Err(Error::InvalidPassphrase)
+        );
+    }
+
+    #[test]
+    fn test_debug_is_redacted() {
+        let share = generate_shares(1, &[(1, 1)], &[0x7f; 16], "", false, 0).unwrap();
+        let debug = format!("{:?}", share[0][0]);
+        assert!(debug.contains("[redacted]"));
+        assert!(!debug.contains("127, 127"));
+    }
+}
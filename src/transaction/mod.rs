@@ -0,0 +1,399 @@
+pub mod sighash;
+
+use crate::encode::{self, Reader};
+use crate::hash::{Txid, Wtxid};
+use crate::script::Script;
+use std::collections::HashSet;
+
+/// Maximum number of satoshis which can ever exist.
+pub const MAX_MONEY: u64 = 21_000_000 * 100_000_000;
+
+/// Maximum weight of a block, and therefore of any single transaction.
+pub const MAX_BLOCK_WEIGHT: usize = 4_000_000;
+
+/// Sequence number which disables lock time and relative lock time for an input.
+pub const SEQUENCE_FINAL: u32 = 0xffff_ffff;
+
+/// If set in a sequence number, BIP68 relative lock time is disabled for that input.
+pub const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+
+/// If set in a sequence number, the relative lock time is in units of 512 seconds.
+pub const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+
+/// Mask extracting the relative lock time value from a sequence number.
+pub const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000_ffff;
+
+/// Lock times below this threshold are block heights, and at or above it are UNIX timestamps.
+pub const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct OutPoint {
+    pub txid: Txid,
+    pub vout: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TxIn {
+    pub previous_output: OutPoint,
+    pub script_sig: Script,
+    pub sequence: u32,
+    pub witness: Vec<Vec<u8>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TxOut {
+    pub value: u64,
+    pub script_pubkey: Script,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Transaction {
+    pub version: i32,
+    pub inputs: Vec<TxIn>,
+    pub outputs: Vec<TxOut>,
+    pub lock_time: u32,
+}
+
+/// Error represents the ways in which a transaction can violate the context-free consensus
+/// rules checked by `Transaction::check`.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    NoInputs,
+    NoOutputs,
+    Oversize(usize),
+    OutputValueTooLarge(u64),
+    TotalOutputValueTooLarge,
+    DuplicateInput(OutPoint),
+    BadCoinbaseLength(usize),
+    NullPrevout,
+}
+
+impl OutPoint {
+    /// The outpoint referenced by coinbase inputs.
+    pub fn null() -> OutPoint {
+        OutPoint {
+            txid: Txid::default(),
+            vout: 0xffff_ffff,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == OutPoint::null()
+    }
+
+    pub fn consensus_encode(&self, buf: &mut Vec<u8>) {
+        buf.extend(self.txid.as_bytes());
+        buf.extend(&self.vout.to_le_bytes());
+    }
+
+    fn consensus_decode(reader: &mut Reader<'_>) -> Result<OutPoint, encode::Error> {
+        Ok(OutPoint {
+            txid: reader.read_hash()?.into(),
+            vout: reader.read_u32()?,
+        })
+    }
+}
+
+impl TxOut {
+    pub fn consensus_encode(&self, buf: &mut Vec<u8>) {
+        buf.extend(&self.value.to_le_bytes());
+        self.script_pubkey.consensus_encode(buf);
+    }
+
+    fn consensus_decode(reader: &mut Reader<'_>) -> Result<TxOut, encode::Error> {
+        Ok(TxOut {
+            value: reader.read_u64()?,
+            script_pubkey: Script::from(reader.read_var_bytes()?),
+        })
+    }
+}
+
+impl Transaction {
+    /// Decodes a transaction, accepting both the legacy and the BIP144 witness serialization.
+    pub fn deserialize(data: &[u8]) -> Result<Transaction, encode::Error> {
+        let mut reader = Reader::new(data);
+        let tx = Transaction::consensus_decode(&mut reader)?;
+        reader.finish()?;
+        Ok(tx)
+    }
+
+    /// Serializes the transaction, including witness data if any input has some.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.consensus_encode(&mut buf, self.has_witness());
+        buf
+    }
+
+    /// Serializes the transaction without witness data, as committed to by the txid.
+    pub fn serialize_no_witness(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.consensus_encode(&mut buf, false);
+        buf
+    }
+
+    pub fn txid(&self) -> Txid {
+        Txid::hash(&self.serialize_no_witness())
+    }
+
+    pub fn wtxid(&self) -> Wtxid {
+        Wtxid::hash(&self.serialize())
+    }
+
+    pub fn has_witness(&self) -> bool {
+        self.inputs.iter().any(|input| !input.witness.is_empty())
+    }
+
+    pub fn is_coinbase(&self) -> bool {
+        self.inputs.len() == 1 && self.inputs[0].previous_output.is_null()
+    }
+
+    /// Returns the BIP141 weight of the transaction.
+    pub fn weight(&self) -> usize {
+        self.serialize_no_witness().len() * 3 + self.serialize().len()
+    }
+
+    /// Checks the context-free consensus rules, equivalent to Bitcoin Core's `CheckTransaction`.
+    pub fn check(&self) -> Result<(), Error> {
+        if self.inputs.is_empty() {
+            return Err(Error::NoInputs);
+        }
+
+        if self.outputs.is_empty() {
+            return Err(Error::NoOutputs);
+        }
+
+        let size = self.serialize_no_witness().len();
+        if size * 4 > MAX_BLOCK_WEIGHT {
+            return Err(Error::Oversize(size));
+        }
+
+        let mut total: u64 = 0;
+        for output in self.outputs.iter() {
+            if output.value > MAX_MONEY {
+                return Err(Error::OutputValueTooLarge(output.value));
+            }
+
+            total += output.value;
+            if total > MAX_MONEY {
+                return Err(Error::TotalOutputValueTooLarge);
+            }
+        }
+
+        let mut seen = HashSet::new();
+        for input in self.inputs.iter() {
+            if !seen.insert(input.previous_output) {
+                return Err(Error::DuplicateInput(input.previous_output));
+            }
+        }
+
+        if self.is_coinbase() {
+            let len = self.inputs[0].script_sig.len();
+            if !(2..=100).contains(&len) {
+                return Err(Error::BadCoinbaseLength(len));
+            }
+        } else if self.inputs.iter().any(|i| i.previous_output.is_null()) {
+            return Err(Error::NullPrevout);
+        }
+
+        Ok(())
+    }
+
+    fn consensus_encode(&self, buf: &mut Vec<u8>, include_witness: bool) {
+        buf.extend(&self.version.to_le_bytes());
+
+        if include_witness {
+            buf.extend(&[0x00, 0x01]);
+        }
+
+        encode::write_compact_size(buf, self.inputs.len() as u64);
+        for input in self.inputs.iter() {
+            input.previous_output.consensus_encode(buf);
+            input.script_sig.consensus_encode(buf);
+            buf.extend(&input.sequence.to_le_bytes());
+        }
+
+        encode::write_compact_size(buf, self.outputs.len() as u64);
+        for output in self.outputs.iter() {
+            output.consensus_encode(buf);
+        }
+
+        if include_witness {
+            for input in self.inputs.iter() {
+                encode::write_compact_size(buf, input.witness.len() as u64);
+                for item in input.witness.iter() {
+                    encode::write_var_bytes(buf, item);
+                }
+            }
+        }
+
+        buf.extend(&self.lock_time.to_le_bytes());
+    }
+
+    fn consensus_decode(reader: &mut Reader<'_>) -> Result<Transaction, encode::Error> {
+        let version = reader.read_i32()?;
+        let mut flags = 0;
+        let mut inputs = decode_inputs(reader)?;
+
+        // An empty input vector signals the BIP144 extended format, followed by a flag byte.
+        let outputs = if inputs.is_empty() {
+            flags = reader.read_u8()?;
+            if flags != 0 {
+                inputs = decode_inputs(reader)?;
+                decode_outputs(reader)?
+            } else {
+                Vec::new()
+            }
+        } else {
+            decode_outputs(reader)?
+        };
+
+        if flags & 1 != 0 {
+            flags ^= 1;
+
+            for input in inputs.iter_mut() {
+                let count = reader.read_compact_size()?;
+                for _ in 0..count {
+                    input.witness.push(reader.read_var_bytes()?.to_vec());
+                }
+            }
+
+            if inputs.iter().all(|input| input.witness.is_empty()) {
+                return Err(encode::Error::SuperfluousWitness);
+            }
+        }
+
+        if flags != 0 {
+            return Err(encode::Error::UnknownOptionalData(flags));
+        }
+
+        let lock_time = reader.read_u32()?;
+
+        Ok(Transaction {
+            version,
+            inputs,
+            outputs,
+            lock_time,
+        })
+    }
+}
+
+fn decode_inputs(reader: &mut Reader<'_>) -> Result<Vec<TxIn>, encode::Error> {
+    let count = reader.read_compact_size()?;
+    let mut inputs = Vec::new();
+
+    for _ in 0..count {
+        inputs.push(TxIn {
+            previous_output: OutPoint::consensus_decode(reader)?,
+            script_sig: Script::from(reader.read_var_bytes()?),
+            sequence: reader.read_u32()?,
+            witness: Vec::new(),
+        });
+    }
+
+    Ok(inputs)
+}
+
+fn decode_outputs(reader: &mut Reader<'_>) -> Result<Vec<TxOut>, encode::Error> {
+    let count = reader.read_compact_size()?;
+    let mut outputs = Vec::new();
+
+    for _ in 0..count {
+        outputs.push(TxOut::consensus_decode(reader)?);
+    }
+
+    Ok(outputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_legacy() {
+        // The first transaction spending a coinbase output, from block 170.
+        let raw = hex::decode("0100000001c997a5e56e104102fa209c6a852dd90660a20b2d9c352423edce25857fcd3704000000004847304402204e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548ab5fb8cd410220181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d0901ffffffff0200ca9a3b00000000434104ae1a62fe09c5f51b13905f07f06b99a2f7159b2225f374cd378d71302fa28414e7aab37397f554a7df5f142c21c1b7303b8a0626f1baded5c72a704f7e6cd84cac00286bee0000000043410411db93e1dcdb8a016b49840f8c53bc1eb68a382e97b1482ecad7b148a6909a5cb2e0eaddfb84ccf9744464f82e160bfa9b8b64f9d4c03f999b8643f656b412a3ac00000000").unwrap();
+        let tx = Transaction::deserialize(&raw).unwrap();
+
+        assert_eq!(tx.version, 1);
+        assert_eq!(tx.inputs.len(), 1);
+        assert_eq!(tx.outputs.len(), 2);
+        assert_eq!(tx.outputs[0].value, 1_000_000_000);
+        assert_eq!(tx.outputs[1].value, 4_000_000_000);
+        assert!(!tx.has_witness());
+        assert_eq!(tx.serialize(), raw);
+
+        assert_eq!(
+            tx.txid().to_string(),
+            "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16"
+        );
+    }
+
+    #[test]
+    fn test_deserialize_witness() {
+        // The BIP143 native P2WPKH example transaction, fully signed.
+        let raw = hex::decode("01000000000102fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f00000000494830450221008b9d1dc26ba6a9cb62127b02742fa9d754cd3bebf337f7a55d114c8e5cdd30be022040529b194ba3f9281a99f2b1c0a19c0489bc22ede944ccf4ecbab4cc618ef3ed01eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac000247304402203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a0220573a954c4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee0121025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee635711000000").unwrap();
+        let tx = Transaction::deserialize(&raw).unwrap();
+
+        assert!(tx.has_witness());
+        assert!(tx.inputs[0].witness.is_empty());
+        assert_eq!(tx.inputs[1].witness.len(), 2);
+        assert_eq!(tx.lock_time, 0x11);
+        assert_eq!(tx.serialize(), raw);
+        assert_eq!(tx.weight(), tx.serialize_no_witness().len() * 3 + raw.len());
+    }
+
+    #[test]
+    fn test_deserialize_rejects_superfluous_witness() {
+        let raw = hex::decode("0100000000010100000000000000000000000000000000000000000000000000000000000000000000000000ffffffff0100000000000000000000000000").unwrap();
+        assert_eq!(
+            Transaction::deserialize(&raw),
+            Err(encode::Error::SuperfluousWitness)
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let input = TxIn {
+            previous_output: OutPoint {
+                txid: [1u8; 32].into(),
+                vout: 0,
+            },
+            script_sig: Script::new(),
+            sequence: SEQUENCE_FINAL,
+            witness: Vec::new(),
+        };
+        let output = TxOut {
+            value: 1000,
+            script_pubkey: Script::new(),
+        };
+        let tx = Transaction {
+            version: 2,
+            inputs: vec![input.clone()],
+            outputs: vec![output.clone()],
+            lock_time: 0,
+        };
+        assert_eq!(tx.check(), Ok(()));
+
+        let mut bad = tx.clone();
+        bad.inputs.push(input.clone());
+        assert_eq!(
+            bad.check(),
+            Err(Error::DuplicateInput(input.previous_output))
+        );
+
+        let mut bad = tx.clone();
+        bad.outputs[0].value = MAX_MONEY + 1;
+        assert_eq!(bad.check(), Err(Error::OutputValueTooLarge(MAX_MONEY + 1)));
+
+        let mut bad = tx.clone();
+        bad.outputs.push(TxOut {
+            value: MAX_MONEY,
+            script_pubkey: Script::new(),
+        });
+        assert_eq!(bad.check(), Err(Error::TotalOutputValueTooLarge));
+
+        let mut bad = tx;
+        bad.inputs[0].previous_output = OutPoint::null();
+        assert_eq!(bad.check(), Err(Error::BadCoinbaseLength(0)));
+    }
+}
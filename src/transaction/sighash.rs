@@ -0,0 +1,403 @@
+//! Signature hash computation for legacy, BIP143 (segwit v0) and BIP341 (taproot) signatures.
+
+use crate::encode;
+use crate::hash;
+use crate::script::{self, opcodes::OP_CODESEPARATOR};
+use crate::transaction::{Transaction, TxOut};
+use digest::Digest;
+use sha2::Sha256;
+
+pub const SIGHASH_DEFAULT: u8 = 0x00;
+pub const SIGHASH_ALL: u8 = 0x01;
+pub const SIGHASH_NONE: u8 = 0x02;
+pub const SIGHASH_SINGLE: u8 = 0x03;
+pub const SIGHASH_ANYONECANPAY: u8 = 0x80;
+
+/// Extra data committed to by tapscript signatures (BIP342).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ScriptPath {
+    pub leaf_hash: [u8; 32],
+    pub codeseparator_pos: u32,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    InvalidHashType(u8),
+    InputIndexOutOfRange(usize),
+    SingleWithoutOutput(usize),
+    PrevoutsMismatch { inputs: usize, prevouts: usize },
+}
+
+/// Computes the original, pre-segwit signature hash.
+///
+/// `script_code` must already have had any signatures removed by `FindAndDelete`; code separators
+/// are removed here. As in Bitcoin Core, `SIGHASH_SINGLE` without a matching output yields the
+/// value one rather than an error.
+pub fn legacy(
+    tx: &Transaction,
+    input_index: usize,
+    script_code: &[u8],
+    hash_type: u32,
+) -> [u8; 32] {
+    if input_index >= tx.inputs.len() {
+        let mut one = [0u8; 32];
+        one[0] = 1;
+        return one;
+    }
+
+    let anyone_can_pay = hash_type & SIGHASH_ANYONECANPAY as u32 != 0;
+    let base_type = (hash_type & 0x1f) as u8;
+    let hash_single = base_type == SIGHASH_SINGLE;
+    let hash_none = base_type == SIGHASH_NONE;
+
+    if hash_single && input_index >= tx.outputs.len() {
+        let mut one = [0u8; 32];
+        one[0] = 1;
+        return one;
+    }
+
+    let mut buf = Vec::new();
+    buf.extend(&tx.version.to_le_bytes());
+
+    let inputs: Vec<usize> = if anyone_can_pay {
+        vec![input_index]
+    } else {
+        (0..tx.inputs.len()).collect()
+    };
+
+    encode::write_compact_size(&mut buf, inputs.len() as u64);
+    for i in inputs {
+        let input = &tx.inputs[i];
+        input.previous_output.consensus_encode(&mut buf);
+
+        if i == input_index {
+            write_script_code(&mut buf, script_code);
+        } else {
+            buf.push(0x00);
+        }
+
+        if i != input_index && (hash_single || hash_none) {
+            buf.extend(&0u32.to_le_bytes());
+        } else {
+            buf.extend(&input.sequence.to_le_bytes());
+        }
+    }
+
+    let output_count = if hash_none {
+        0
+    } else if hash_single {
+        input_index + 1
+    } else {
+        tx.outputs.len()
+    };
+
+    encode::write_compact_size(&mut buf, output_count as u64);
+    for (i, output) in tx.outputs.iter().take(output_count).enumerate() {
+        if hash_single && i != input_index {
+            // A "null" output: a value of -1 and an empty script.
+            buf.extend(&(-1i64).to_le_bytes());
+            buf.push(0x00);
+        } else {
+            output.consensus_encode(&mut buf);
+        }
+    }
+
+    buf.extend(&tx.lock_time.to_le_bytes());
+    buf.extend(&hash_type.to_le_bytes());
+
+    hash::double_sha256(&buf)
+}
+
+/// Computes a BIP143 signature hash for a segwit v0 input spending `value` satoshis.
+pub fn segwit_v0(
+    tx: &Transaction,
+    input_index: usize,
+    script_code: &[u8],
+    value: u64,
+    hash_type: u32,
+) -> Result<[u8; 32], Error> {
+    let input = tx
+        .inputs
+        .get(input_index)
+        .ok_or(Error::InputIndexOutOfRange(input_index))?;
+
+    let anyone_can_pay = hash_type & SIGHASH_ANYONECANPAY as u32 != 0;
+    let base_type = (hash_type & 0x1f) as u8;
+
+    let mut hash_prevouts = [0u8; 32];
+    let mut hash_sequence = [0u8; 32];
+    let mut hash_outputs = [0u8; 32];
+
+    if !anyone_can_pay {
+        let mut buf = Vec::new();
+        for input in tx.inputs.iter() {
+            input.previous_output.consensus_encode(&mut buf);
+        }
+        hash_prevouts = hash::double_sha256(&buf);
+    }
+
+    if !anyone_can_pay && base_type != SIGHASH_SINGLE && base_type != SIGHASH_NONE {
+        let mut buf = Vec::new();
+        for input in tx.inputs.iter() {
+            buf.extend(&input.sequence.to_le_bytes());
+        }
+        hash_sequence = hash::double_sha256(&buf);
+    }
+
+    if base_type != SIGHASH_SINGLE && base_type != SIGHASH_NONE {
+        let mut buf = Vec::new();
+        for output in tx.outputs.iter() {
+            output.consensus_encode(&mut buf);
+        }
+        hash_outputs = hash::double_sha256(&buf);
+    } else if base_type == SIGHASH_SINGLE && input_index < tx.outputs.len() {
+        let mut buf = Vec::new();
+        tx.outputs[input_index].consensus_encode(&mut buf);
+        hash_outputs = hash::double_sha256(&buf);
+    }
+
+    let mut buf = Vec::new();
+    buf.extend(&tx.version.to_le_bytes());
+    buf.extend(&hash_prevouts);
+    buf.extend(&hash_sequence);
+    input.previous_output.consensus_encode(&mut buf);
+    encode::write_var_bytes(&mut buf, script_code);
+    buf.extend(&value.to_le_bytes());
+    buf.extend(&input.sequence.to_le_bytes());
+    buf.extend(&hash_outputs);
+    buf.extend(&tx.lock_time.to_le_bytes());
+    buf.extend(&hash_type.to_le_bytes());
+
+    Ok(hash::double_sha256(&buf))
+}
+
+/// Computes a BIP341 signature hash.
+///
+/// `prevouts` must contain the output spent by every input of `tx`, in order. `annex` is the
+/// annex including its leading `0x50` tag, and `script_path` is present for tapscript
+/// signatures.
+pub fn taproot(
+    tx: &Transaction,
+    input_index: usize,
+    prevouts: &[TxOut],
+    hash_type: u8,
+    annex: Option<&[u8]>,
+    script_path: Option<ScriptPath>,
+) -> Result<[u8; 32], Error> {
+    if !(hash_type <= SIGHASH_SINGLE || (0x81..=0x83).contains(&hash_type)) {
+        return Err(Error::InvalidHashType(hash_type));
+    }
+
+    if prevouts.len() != tx.inputs.len() {
+        return Err(Error::PrevoutsMismatch {
+            inputs: tx.inputs.len(),
+            prevouts: prevouts.len(),
+        });
+    }
+
+    let input = tx
+        .inputs
+        .get(input_index)
+        .ok_or(Error::InputIndexOutOfRange(input_index))?;
+
+    let output_type = if hash_type == SIGHASH_DEFAULT {
+        SIGHASH_ALL
+    } else {
+        hash_type & 0x03
+    };
+    let anyone_can_pay = hash_type & SIGHASH_ANYONECANPAY != 0;
+
+    let mut buf = Vec::new();
+    // Epoch
+    buf.push(0x00);
+    buf.push(hash_type);
+    buf.extend(&tx.version.to_le_bytes());
+    buf.extend(&tx.lock_time.to_le_bytes());
+
+    if !anyone_can_pay {
+        let mut outpoints = Sha256::new();
+        let mut amounts = Sha256::new();
+        let mut script_pubkeys = Sha256::new();
+        let mut sequences = Sha256::new();
+
+        for (input, prevout) in tx.inputs.iter().zip(prevouts) {
+            let mut outpoint = Vec::new();
+            input.previous_output.consensus_encode(&mut outpoint);
+            outpoints.update(&outpoint);
+
+            amounts.update(prevout.value.to_le_bytes());
+
+            let mut script_pubkey = Vec::new();
+            prevout.script_pubkey.consensus_encode(&mut script_pubkey);
+            script_pubkeys.update(&script_pubkey);
+
+            sequences.update(input.sequence.to_le_bytes());
+        }
+
+        buf.extend(&outpoints.finalize());
+        buf.extend(&amounts.finalize());
+        buf.extend(&script_pubkeys.finalize());
+        buf.extend(&sequences.finalize());
+    }
+
+    if output_type == SIGHASH_ALL {
+        let mut outputs = Vec::new();
+        for output in tx.outputs.iter() {
+            output.consensus_encode(&mut outputs);
+        }
+        buf.extend(&hash::sha256(&outputs));
+    }
+
+    let ext_flag = if script_path.is_some() { 1 } else { 0 };
+    let spend_type = (ext_flag << 1) + if annex.is_some() { 1 } else { 0 };
+    buf.push(spend_type);
+
+    if anyone_can_pay {
+        input.previous_output.consensus_encode(&mut buf);
+        prevouts[input_index].consensus_encode(&mut buf);
+        buf.extend(&input.sequence.to_le_bytes());
+    } else {
+        buf.extend(&(input_index as u32).to_le_bytes());
+    }
+
+    if let Some(annex) = annex {
+        let mut serialized = Vec::new();
+        encode::write_var_bytes(&mut serialized, annex);
+        buf.extend(&hash::sha256(&serialized));
+    }
+
+    if output_type == SIGHASH_SINGLE {
+        let output = tx
+            .outputs
+            .get(input_index)
+            .ok_or(Error::SingleWithoutOutput(input_index))?;
+        let mut serialized = Vec::new();
+        output.consensus_encode(&mut serialized);
+        buf.extend(&hash::sha256(&serialized));
+    }
+
+    if let Some(script_path) = script_path {
+        buf.extend(&script_path.leaf_hash);
+        // Key version
+        buf.push(0x00);
+        buf.extend(&script_path.codeseparator_pos.to_le_bytes());
+    }
+
+    Ok(hash::tagged_hash("TapSighash", &buf))
+}
+
+/// Writes `script_code` with a length prefix and with every `OP_CODESEPARATOR` removed.
+///
+/// This reproduces Bitcoin Core's `SerializeScriptCode` exactly, including its behaviour for
+/// scripts which end in a truncated push.
+fn write_script_code(buf: &mut Vec<u8>, script_code: &[u8]) {
+    let mut pc = 0;
+    let mut separators = 0;
+    while let Some(ins) = script::get_op(script_code, &mut pc) {
+        if ins.opcode == OP_CODESEPARATOR {
+            separators += 1;
+        }
+    }
+
+    encode::write_compact_size(buf, (script_code.len() - separators) as u64);
+
+    let mut pc = 0;
+    let mut begin = 0;
+    while let Some(ins) = script::get_op(script_code, &mut pc) {
+        if ins.opcode == OP_CODESEPARATOR {
+            buf.extend(&script_code[begin..pc - 1]);
+            begin = pc;
+        }
+    }
+
+    if begin != script_code.len() {
+        buf.extend(&script_code[begin..pc.min(script_code.len())]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::Script;
+    use serde_json::Value;
+
+    #[test]
+    fn test_legacy_bitcoin_core_vectors() {
+        let tests: Vec<Value> =
+            serde_json::from_str(include_str!("../../testdata/sighash.json")).unwrap();
+
+        for test in tests.iter().skip(1) {
+            let test = test.as_array().unwrap();
+            let tx = hex::decode(test[0].as_str().unwrap()).unwrap();
+            let tx = Transaction::deserialize(&tx).unwrap();
+            let script_code = hex::decode(test[1].as_str().unwrap()).unwrap();
+            let input_index = test[2].as_u64().unwrap() as usize;
+            let hash_type = test[3].as_i64().unwrap() as u32;
+            let mut expected = hex::decode(test[4].as_str().unwrap()).unwrap();
+            expected.reverse();
+
+            let actual = legacy(&tx, input_index, &script_code, hash_type);
+            assert_eq!(expected, actual, "{:?}", test);
+        }
+    }
+
+    #[test]
+    fn test_segwit_v0_bip143_native_p2wpkh() {
+        let tx = hex::decode("0100000002fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f0000000000eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac11000000").unwrap();
+        let tx = Transaction::deserialize(&tx).unwrap();
+        let script_code =
+            hex::decode("76a9141d0f172a0ecb48aee1be1f2687d2963ae33f71a188ac").unwrap();
+
+        let actual = segwit_v0(&tx, 1, &script_code, 600_000_000, SIGHASH_ALL as u32).unwrap();
+        assert_eq!(
+            hex::encode(actual),
+            "c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670"
+        );
+    }
+
+    #[test]
+    fn test_taproot_bip341_vectors() {
+        let data: Value =
+            serde_json::from_str(include_str!("../../testdata/bip341_wallet_vectors.json"))
+                .unwrap();
+        let spending = &data["keyPathSpending"][0];
+
+        let tx = hex::decode(spending["given"]["rawUnsignedTx"].as_str().unwrap()).unwrap();
+        let tx = Transaction::deserialize(&tx).unwrap();
+        let prevouts = spending["given"]["utxosSpent"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|utxo| TxOut {
+                value: utxo["amountSats"].as_u64().unwrap(),
+                script_pubkey: Script::from(
+                    hex::decode(utxo["scriptPubKey"].as_str().unwrap()).unwrap(),
+                ),
+            })
+            .collect::<Vec<_>>();
+
+        for input in spending["inputSpending"].as_array().unwrap() {
+            let index = input["given"]["txinIndex"].as_u64().unwrap() as usize;
+            let hash_type = input["given"]["hashType"].as_u64().unwrap() as u8;
+            let expected = input["intermediary"]["sigHash"].as_str().unwrap();
+
+            let actual = taproot(&tx, index, &prevouts, hash_type, None, None).unwrap();
+            assert_eq!(expected, hex::encode(actual));
+        }
+    }
+
+    #[test]
+    fn test_taproot_rejects_invalid_hash_type() {
+        let tx = Transaction {
+            version: 2,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            lock_time: 0,
+        };
+
+        assert_eq!(
+            taproot(&tx, 0, &[], 0x04, None, None),
+            Err(Error::InvalidHashType(0x04))
+        );
+    }
+}
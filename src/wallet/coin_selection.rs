@@ -0,0 +1,301 @@
+//! Coin selection algorithms.
+//!
+//! Each algorithm works on effective values (an output's value minus the fee needed to spend
+//! it) and returns the indices of the chosen values, or `None` if no acceptable selection exists.
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+/// The number of branches branch-and-bound explores before giving up.
+pub const BNB_TOTAL_TRIES: usize = 100_000;
+
+/// The number of random subsets the knapsack solver tries.
+pub const KNAPSACK_ITERATIONS: usize = 1000;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CoinSelection {
+    /// Searches for a selection close enough to the target that no change output is needed.
+    BranchAndBound,
+    /// Bitcoin Core's stochastic approximation of the smallest selection above the target.
+    Knapsack,
+    /// Spends the largest values first.
+    LargestFirst,
+}
+
+/// Finds a selection whose total lies within `[target, target + cost_of_change]`, so that the
+/// excess can be dropped to fees instead of paying for a change output, preferring the
+/// selection with the least excess.
+pub fn branch_and_bound(values: &[u64], target: u64, cost_of_change: u64) -> Option<Vec<usize>> {
+    let mut pool = (0..values.len()).collect::<Vec<_>>();
+    pool.sort_by(|a, b| values[*b].cmp(&values[*a]));
+    let pool = pool.into_iter().map(|i| (i, values[i])).collect::<Vec<_>>();
+
+    let mut curr_available = pool.iter().map(|(_, value)| value).sum::<u64>();
+    if curr_available < target {
+        return None;
+    }
+
+    let mut curr_value = 0;
+    let mut curr_selection: Vec<usize> = Vec::new();
+    let mut best_selection = None;
+    let mut best_excess = u64::MAX;
+    let mut index = 0;
+
+    for _ in 0..BNB_TOTAL_TRIES {
+        let mut backtrack = false;
+
+        if curr_value + curr_available < target || curr_value > target + cost_of_change {
+            backtrack = true;
+        } else if curr_value >= target {
+            let excess = curr_value - target;
+            if excess <= best_excess {
+                best_excess = excess;
+                best_selection = Some(curr_selection.clone());
+            }
+            backtrack = true;
+        }
+
+        if backtrack {
+            let last = match curr_selection.last() {
+                Some(last) => *last,
+                None => break,
+            };
+
+            // Return the values skipped since the last inclusion to the lookahead, then explore
+            // the branch which omits that inclusion.
+            index -= 1;
+            while index > last {
+                curr_available += pool[index].1;
+                index -= 1;
+            }
+
+            curr_value -= pool[index].1;
+            curr_selection.pop();
+        } else if index < pool.len() {
+            let value = pool[index].1;
+            curr_available -= value;
+
+            // Omitting a value and then including an identical one would repeat a branch which
+            // has already been explored.
+            if curr_selection.is_empty()
+                || index - 1 == curr_selection[curr_selection.len() - 1]
+                || value != pool[index - 1].1
+            {
+                curr_selection.push(index);
+                curr_value += value;
+            }
+        }
+
+        index += 1;
+    }
+
+    best_selection.map(|selection| selection.into_iter().map(|i| pool[i].0).collect())
+}
+
+/// Bitcoin Core's knapsack solver: spends a single exact match if one exists, and otherwise
+/// prefers a random-search approximation of the smallest selection of values below
+/// `target + min_change` which leaves at least `min_change` over, falling back to the smallest
+/// single value above it.
+pub fn knapsack<R: Rng>(
+    values: &[u64],
+    target: u64,
+    min_change: u64,
+    rng: &mut R,
+) -> Option<Vec<usize>> {
+    let mut order = (0..values.len()).collect::<Vec<_>>();
+    order.shuffle(rng);
+
+    let mut applicable = Vec::new();
+    let mut total_lower = 0;
+    let mut lowest_larger: Option<usize> = None;
+
+    for i in order {
+        let value = values[i];
+        if value == target {
+            return Some(vec![i]);
+        } else if value < target + min_change {
+            applicable.push(i);
+            total_lower += value;
+        } else if lowest_larger.map_or(true, |larger| value < values[larger]) {
+            lowest_larger = Some(i);
+        }
+    }
+
+    if total_lower == target {
+        return Some(applicable);
+    }
+
+    if total_lower < target {
+        return lowest_larger.map(|larger| vec![larger]);
+    }
+
+    applicable.sort_by(|a, b| values[*b].cmp(&values[*a]));
+    let applicable_values = applicable.iter().map(|i| values[*i]).collect::<Vec<_>>();
+
+    let (mut best, mut best_value) =
+        approximate_best_subset(&applicable_values, total_lower, target, rng);
+    if best_value != target && total_lower >= target + min_change {
+        let (subset, value) =
+            approximate_best_subset(&applicable_values, total_lower, target + min_change, rng);
+        best = subset;
+        best_value = value;
+    }
+
+    if let Some(larger) = lowest_larger {
+        if (best_value != target && best_value < target + min_change)
+            || values[larger] <= best_value
+        {
+            return Some(vec![larger]);
+        }
+    }
+
+    Some(
+        applicable
+            .into_iter()
+            .zip(best)
+            .filter(|(_, included)| *included)
+            .map(|(i, _)| i)
+            .collect(),
+    )
+}
+
+fn approximate_best_subset<R: Rng>(
+    values: &[u64],
+    total_lower: u64,
+    target: u64,
+    rng: &mut R,
+) -> (Vec<bool>, u64) {
+    let mut best = vec![true; values.len()];
+    let mut best_value = total_lower;
+
+    for _ in 0..KNAPSACK_ITERATIONS {
+        if best_value == target {
+            break;
+        }
+
+        let mut included = vec![false; values.len()];
+        let mut total = 0;
+        let mut reached_target = false;
+
+        for pass in 0..2 {
+            if reached_target {
+                break;
+            }
+
+            for i in 0..values.len() {
+                // The first pass picks values at random and the second fills in the rest, so
+                // that every run has a chance of reaching the target.
+                let include = if pass == 0 {
+                    rng.gen_bool(0.5)
+                } else {
+                    !included[i]
+                };
+
+                if include {
+                    total += values[i];
+                    included[i] = true;
+
+                    if total >= target {
+                        reached_target = true;
+                        if total < best_value {
+                            best_value = total;
+                            best = included.clone();
+                        }
+                        total -= values[i];
+                        included[i] = false;
+                    }
+                }
+            }
+        }
+    }
+
+    (best, best_value)
+}
+
+/// Spends the largest values until the target is reached.
+pub fn largest_first(values: &[u64], target: u64) -> Option<Vec<usize>> {
+    let mut order = (0..values.len()).collect::<Vec<_>>();
+    order.sort_by(|a, b| values[*b].cmp(&values[*a]));
+
+    let mut selection = Vec::new();
+    let mut total = 0;
+
+    for i in order {
+        if total >= target {
+            break;
+        }
+        selection.push(i);
+        total += values[i];
+    }
+
+    if total >= target {
+        Some(selection)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn total(values: &[u64], selection: &[usize]) -> u64 {
+        selection.iter().map(|i| values[*i]).sum()
+    }
+
+    #[test]
+    fn test_branch_and_bound() {
+        let values = [1, 2, 3, 4, 5, 7, 11];
+
+        let selection = branch_and_bound(&values, 10, 0).unwrap();
+        assert_eq!(total(&values, &selection), 10);
+
+        // Only spending every value reaches 33.
+        let selection = branch_and_bound(&values, 33, 0).unwrap();
+        assert_eq!(selection.len(), values.len());
+
+        let selection = branch_and_bound(&[10, 20, 30], 15, 6).unwrap();
+        assert_eq!(total(&[10, 20, 30], &selection), 20);
+
+        assert_eq!(branch_and_bound(&[10, 20, 30], 15, 4), None);
+        assert_eq!(branch_and_bound(&values, 34, 100), None);
+        assert_eq!(branch_and_bound(&[], 1, 100), None);
+    }
+
+    #[test]
+    fn test_branch_and_bound_prefers_least_excess() {
+        let values = [6, 8, 14];
+        let selection = branch_and_bound(&values, 13, 10).unwrap();
+        assert_eq!(total(&values, &selection), 14);
+    }
+
+    #[test]
+    fn test_knapsack() {
+        let mut rng = rand::thread_rng();
+
+        let values = [5, 10, 20, 50];
+        assert_eq!(knapsack(&values, 20, 5, &mut rng), Some(vec![2]));
+
+        let selection = knapsack(&values, 30, 5, &mut rng).unwrap();
+        assert!(total(&values, &selection) >= 35 || total(&values, &selection) == 30);
+
+        // The smaller values can't reach the target, so the single larger one is used.
+        assert_eq!(knapsack(&[1, 2, 100], 10, 5, &mut rng), Some(vec![2]));
+
+        // All smaller values together exactly match the target.
+        let mut selection = knapsack(&[1, 2, 3, 100], 6, 5, &mut rng).unwrap();
+        selection.sort_unstable();
+        assert_eq!(selection, vec![0, 1, 2]);
+
+        assert_eq!(knapsack(&[1, 2, 3], 7, 5, &mut rng), None);
+    }
+
+    #[test]
+    fn test_largest_first() {
+        let values = [3, 9, 1, 7];
+        assert_eq!(largest_first(&values, 10), Some(vec![1, 3]));
+        assert_eq!(largest_first(&values, 9), Some(vec![1]));
+        assert_eq!(largest_first(&values, 21), None);
+        assert_eq!(largest_first(&values, 0), Some(vec![]));
+    }
+}
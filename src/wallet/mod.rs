@@ -0,0 +1,912 @@
+//! Building unsigned transactions from a wallet's unspent outputs.
+
+pub mod coin_selection;
+
+pub use coin_selection::CoinSelection;
+
+use crate::account::{self, Account};
+use crate::address;
+use crate::bip32::Chain;
+use crate::encode::compact_size_len;
+use crate::multisig::{self, MultisigWallet, ScriptType};
+use crate::psbt::{self, Psbt};
+use crate::script::Script;
+use crate::transaction::{OutPoint, Transaction, TxIn, TxOut, SEQUENCE_FINAL};
+use rand::Rng;
+
+/// The highest sequence number which signals that an input may be replaced (BIP125).
+pub const MAX_BIP125_RBF_SEQUENCE: u32 = 0xffff_fffd;
+
+/// The highest fee `TxBuilder` will pay unless configured otherwise: 0.1 BTC.
+pub const DEFAULT_MAX_FEE: u64 = 10_000_000;
+
+/// The feerate Bitcoin Core uses to decide whether an output is dust.
+pub const DUST_RELAY_FEE_RATE: FeeRate = FeeRate(3000);
+
+/// Length of the longest DER signature with a low S value, plus its sighash byte.
+const ECDSA_SIGNATURE_LEN: usize = 72;
+const SCHNORR_SIGNATURE_LEN: usize = 64;
+const PUBKEY_LEN: usize = 33;
+
+/// Error represents all of the possible errors that can arise while building a transaction.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    Account(account::Error),
+    Multisig(multisig::Error),
+    Psbt(psbt::Error),
+    NoRecipients,
+    DustOutput {
+        index: usize,
+        value: u64,
+        dust_limit: u64,
+    },
+    /// The available outputs are worth less than the recipients and fees, after subtracting the
+    /// fee needed to spend each of them.
+    InsufficientFunds {
+        needed: u64,
+        available: u64,
+    },
+    NoSelection(CoinSelection),
+    FeeExceedsCap {
+        fee: u64,
+        max_fee: u64,
+    },
+}
+
+impl From<account::Error> for Error {
+    fn from(err: account::Error) -> Error {
+        Error::Account(err)
+    }
+}
+
+impl From<multisig::Error> for Error {
+    fn from(err: multisig::Error) -> Error {
+        Error::Multisig(err)
+    }
+}
+
+impl From<psbt::Error> for Error {
+    fn from(err: psbt::Error) -> Error {
+        Error::Psbt(err)
+    }
+}
+
+/// A feerate in satoshis per 1000 virtual bytes.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FeeRate(pub u64);
+
+impl FeeRate {
+    pub fn from_sat_per_vb(sat_per_vb: u64) -> FeeRate {
+        FeeRate(sat_per_vb * 1000)
+    }
+
+    /// Returns the fee for `weight` weight units, rounding the virtual size and fee up.
+    pub fn fee(self, weight: usize) -> u64 {
+        let vsize = weight.div_ceil(4) as u64;
+        (vsize * self.0).div_ceil(1000)
+    }
+}
+
+/// How an output is spent, which determines the size of the input spending it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InputType {
+    P2pkh,
+    P2shP2wpkh,
+    P2wpkh,
+    /// A taproot key path spend with the default sighash type.
+    P2trKeySpend,
+    Multisig {
+        script_type: ScriptType,
+        threshold: usize,
+        keys: usize,
+    },
+}
+
+impl InputType {
+    /// Returns the input type spending outputs of `wallet`.
+    pub fn multisig(wallet: &MultisigWallet) -> InputType {
+        InputType::Multisig {
+            script_type: wallet.script_type(),
+            threshold: wallet.threshold(),
+            keys: wallet.cosigners().len(),
+        }
+    }
+
+    /// Returns the input type spending single-key outputs of `script_type`.
+    pub fn single_key(script_type: address::ScriptType) -> InputType {
+        match script_type {
+            address::ScriptType::P2pkh => InputType::P2pkh,
+            address::ScriptType::P2shP2wpkh => InputType::P2shP2wpkh,
+            address::ScriptType::P2wpkh => InputType::P2wpkh,
+            address::ScriptType::P2tr => InputType::P2trKeySpend,
+        }
+    }
+
+    pub fn is_segwit(&self) -> bool {
+        !matches!(
+            self,
+            InputType::P2pkh
+                | InputType::Multisig {
+                    script_type: ScriptType::P2sh,
+                    ..
+                }
+        )
+    }
+
+    /// Returns the largest weight an input of this type can add to a segwit transaction,
+    /// including its witness or, for legacy inputs, its empty witness.
+    pub fn weight(&self) -> usize {
+        let (script_sig_len, witness_len) = self.satisfaction_len();
+        (32 + 4 + compact_size_len(script_sig_len as u64) + script_sig_len + 4) * 4 + witness_len
+    }
+
+    /// Returns the lengths of the scriptSig and serialized witness needed to spend this type.
+    fn satisfaction_len(&self) -> (usize, usize) {
+        let signature = 1 + ECDSA_SIGNATURE_LEN;
+        let pubkey = 1 + PUBKEY_LEN;
+        let empty_witness = 1;
+
+        match *self {
+            InputType::P2pkh => (signature + pubkey, empty_witness),
+            InputType::P2shP2wpkh => (1 + 22, 1 + signature + pubkey),
+            InputType::P2wpkh => (0, 1 + signature + pubkey),
+            InputType::P2trKeySpend => (0, 1 + 1 + SCHNORR_SIGNATURE_LEN),
+            InputType::Multisig {
+                script_type,
+                threshold,
+                keys,
+            } => {
+                let script_len = 1 + keys * pubkey + 1 + 1;
+                // The dummy element consumed by OP_CHECKMULTISIG, then the signatures.
+                let items = 1 + threshold * signature;
+
+                match script_type {
+                    ScriptType::P2sh => (items + push_len(script_len), empty_witness),
+                    ScriptType::P2shP2wsh => (
+                        1 + 34,
+                        compact_size_len(threshold as u64 + 2)
+                            + items
+                            + compact_size_len(script_len as u64)
+                            + script_len,
+                    ),
+                    ScriptType::P2wsh => (
+                        0,
+                        compact_size_len(threshold as u64 + 2)
+                            + items
+                            + compact_size_len(script_len as u64)
+                            + script_len,
+                    ),
+                }
+            }
+        }
+    }
+}
+
+/// Returns the length of a minimal push of `len` bytes, including the bytes themselves.
+fn push_len(len: usize) -> usize {
+    let prefix = match len {
+        0..=75 => 1,
+        76..=0xff => 2,
+        0x100..=0xffff => 3,
+        _ => 5,
+    };
+    prefix + len
+}
+
+/// An unspent output the builder may spend.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Utxo {
+    pub outpoint: OutPoint,
+    pub txout: TxOut,
+    pub input_type: InputType,
+}
+
+/// Returns the value below which Bitcoin Core considers an output paying to `script_pubkey`
+/// dust: less than the fee of creating and later spending it at `DUST_RELAY_FEE_RATE`.
+pub fn dust_limit(script_pubkey: &Script) -> u64 {
+    if script_pubkey.is_unspendable() {
+        return 0;
+    }
+
+    let output_len = output_weight(script_pubkey) / 4;
+    let spend_len = match script_pubkey.witness_program() {
+        Some(_) => 32 + 4 + 1 + 107 / 4 + 4,
+        None => 32 + 4 + 1 + 107 + 4,
+    };
+
+    DUST_RELAY_FEE_RATE.fee((output_len + spend_len) * 4)
+}
+
+fn output_weight(script_pubkey: &Script) -> usize {
+    (8 + compact_size_len(script_pubkey.len() as u64) + script_pubkey.len()) * 4
+}
+
+/// Estimates the weight of a transaction spending `inputs` to `outputs` once fully signed.
+pub fn estimate_weight(inputs: &[InputType], outputs: &[TxOut]) -> usize {
+    let segwit = inputs.iter().any(InputType::is_segwit);
+
+    let mut weight =
+        (4 + compact_size_len(inputs.len() as u64) + compact_size_len(outputs.len() as u64) + 4)
+            * 4;
+    weight += outputs
+        .iter()
+        .map(|output| output_weight(&output.script_pubkey))
+        .sum::<usize>();
+    weight += inputs.iter().map(InputType::weight).sum::<usize>();
+
+    if segwit {
+        // The marker and flag bytes.
+        weight += 2;
+    } else {
+        // Legacy transactions omit the empty witnesses counted for each input.
+        weight -= inputs.len();
+    }
+
+    weight
+}
+
+#[derive(Clone, Debug)]
+struct Change {
+    script_pubkey: Script,
+    input_type: InputType,
+    wallet: Option<ChangeWallet>,
+}
+
+/// The wallet change is sent to, and the index of its change address.
+#[derive(Clone, Debug)]
+enum ChangeWallet {
+    Multisig(MultisigWallet, u32),
+    Account(Box<Account>, u32),
+}
+
+/// The transaction built by a `TxBuilder`, along with the outputs it spends.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BuiltTransaction {
+    pub tx: Transaction,
+    /// The spent outputs, in input order.
+    pub utxos: Vec<Utxo>,
+    pub fee: u64,
+    pub change_index: Option<usize>,
+    /// The weight of the transaction once signed, assuming signatures of the usual length.
+    pub estimated_weight: usize,
+}
+
+/// Selects coins and builds an unsigned transaction paying a set of recipients.
+#[derive(Clone, Debug)]
+pub struct TxBuilder {
+    fee_rate: FeeRate,
+    utxos: Vec<Utxo>,
+    recipients: Vec<TxOut>,
+    change: Option<Change>,
+    coin_selection: Option<CoinSelection>,
+    rbf: bool,
+    lock_time: u32,
+    max_fee: u64,
+}
+
+impl TxBuilder {
+    pub fn new(fee_rate: FeeRate) -> TxBuilder {
+        TxBuilder {
+            fee_rate,
+            utxos: Vec::new(),
+            recipients: Vec::new(),
+            change: None,
+            coin_selection: None,
+            rbf: false,
+            lock_time: 0,
+            max_fee: DEFAULT_MAX_FEE,
+        }
+    }
+
+    pub fn add_utxo(mut self, utxo: Utxo) -> TxBuilder {
+        self.utxos.push(utxo);
+        self
+    }
+
+    pub fn add_utxos(mut self, utxos: impl IntoIterator<Item = Utxo>) -> TxBuilder {
+        self.utxos.extend(utxos);
+        self
+    }
+
+    pub fn add_recipient(mut self, script_pubkey: Script, value: u64) -> TxBuilder {
+        self.recipients.push(TxOut {
+            value,
+            script_pubkey,
+        });
+        self
+    }
+
+    /// Sends change to `script_pubkey`, which will later be spent as `input_type`.
+    pub fn change_script(mut self, script_pubkey: Script, input_type: InputType) -> TxBuilder {
+        self.change = Some(Change {
+            script_pubkey,
+            input_type,
+            wallet: None,
+        });
+        self
+    }
+
+    /// Sends change to `wallet`'s change address at `index`.
+    pub fn change_address(
+        mut self,
+        wallet: &MultisigWallet,
+        index: u32,
+    ) -> Result<TxBuilder, Error> {
+        self.change = Some(Change {
+            script_pubkey: wallet.derive(true, index)?.script_pubkey,
+            input_type: InputType::multisig(wallet),
+            wallet: Some(ChangeWallet::Multisig(wallet.clone(), index)),
+        });
+        Ok(self)
+    }
+
+    /// Sends change to `account`'s change address at `index`.
+    pub fn change_account(mut self, account: &Account, index: u32) -> Result<TxBuilder, Error> {
+        self.change = Some(Change {
+            script_pubkey: account.script_pubkey(Chain::Change, index)?,
+            input_type: InputType::single_key(account.purpose().script_type()),
+            wallet: Some(ChangeWallet::Account(Box::new(account.clone()), index)),
+        });
+        Ok(self)
+    }
+
+    /// Restricts coin selection to `algorithm`. By default, branch-and-bound is tried first to
+    /// avoid creating change, falling back to knapsack.
+    pub fn coin_selection(mut self, algorithm: CoinSelection) -> TxBuilder {
+        self.coin_selection = Some(algorithm);
+        self
+    }
+
+    /// Signals that the transaction may be replaced by one paying a higher fee (BIP125).
+    pub fn enable_rbf(mut self) -> TxBuilder {
+        self.rbf = true;
+        self
+    }
+
+    pub fn lock_time(mut self, lock_time: u32) -> TxBuilder {
+        self.lock_time = lock_time;
+        self
+    }
+
+    /// Refuses to build transactions paying more than `max_fee` satoshis in fees.
+    pub fn max_fee(mut self, max_fee: u64) -> TxBuilder {
+        self.max_fee = max_fee;
+        self
+    }
+
+    /// Selects inputs and builds the transaction. Without a change output, any value left over
+    /// after paying the recipients is paid in fees, subject to the fee cap.
+    pub fn build(&self) -> Result<BuiltTransaction, Error> {
+        self.build_with_rng(&mut rand::thread_rng())
+    }
+
+    /// Builds the transaction and wraps it in a PSBT, filling in the spent outputs of segwit
+    /// inputs and the scripts and key derivations of change sent to a wallet.
+    ///
+    /// Legacy inputs need their full previous transactions, which the caller must add.
+    pub fn build_psbt(&self) -> Result<(Psbt, BuiltTransaction), Error> {
+        let built = self.build()?;
+        let mut psbt = Psbt::from_unsigned_tx(built.tx.clone())?;
+
+        for (input, utxo) in psbt.inputs.iter_mut().zip(&built.utxos) {
+            if utxo.input_type.is_segwit() {
+                input.witness_utxo = Some(utxo.txout.clone());
+            }
+        }
+
+        if let (Some(index), Some(change)) = (built.change_index, &self.change) {
+            let output = &mut psbt.outputs[index];
+            match &change.wallet {
+                Some(ChangeWallet::Multisig(wallet, change_index)) => {
+                    wallet.update_psbt_output(output, true, *change_index)?
+                }
+                Some(ChangeWallet::Account(account, change_index)) => {
+                    account.update_psbt_output(output, Chain::Change, *change_index)?
+                }
+                None => {}
+            }
+        }
+
+        Ok((psbt, built))
+    }
+
+    fn build_with_rng<R: Rng>(&self, rng: &mut R) -> Result<BuiltTransaction, Error> {
+        if self.recipients.is_empty() {
+            return Err(Error::NoRecipients);
+        }
+
+        for (index, recipient) in self.recipients.iter().enumerate() {
+            let dust_limit = dust_limit(&recipient.script_pubkey);
+            if recipient.value < dust_limit {
+                return Err(Error::DustOutput {
+                    index,
+                    value: recipient.value,
+                    dust_limit,
+                });
+            }
+        }
+
+        let recipients_value = self
+            .recipients
+            .iter()
+            .map(|output| output.value)
+            .sum::<u64>();
+
+        // Estimates are made for a segwit transaction with no inputs, which (besides the
+        // input count) is the largest the overhead can be.
+        let base_weight = estimate_weight(&[], &self.recipients) + 2;
+        let target = recipients_value + self.fee_rate.fee(base_weight);
+
+        // Outputs which cost more to spend than they're worth are never selected.
+        let candidates = self
+            .utxos
+            .iter()
+            .filter_map(|utxo| {
+                let fee = self.fee_rate.fee(utxo.input_type.weight());
+                utxo.txout
+                    .value
+                    .checked_sub(fee)
+                    .filter(|value| *value > 0)
+                    .map(|value| (utxo, value))
+            })
+            .collect::<Vec<_>>();
+        let values = candidates
+            .iter()
+            .map(|(_, value)| *value)
+            .collect::<Vec<_>>();
+
+        let available = values.iter().sum::<u64>();
+        if available < target {
+            return Err(Error::InsufficientFunds {
+                needed: target,
+                available,
+            });
+        }
+
+        // Change is only worth creating if it's more than dust and more than it costs to spend.
+        let (change_fee, spend_fee, min_change) = match &self.change {
+            Some(change) => {
+                let spend_fee = self.fee_rate.fee(change.input_type.weight());
+                (
+                    self.fee_rate.fee(output_weight(&change.script_pubkey)),
+                    spend_fee,
+                    dust_limit(&change.script_pubkey).max(spend_fee + 1),
+                )
+            }
+            None => (0, 0, 0),
+        };
+        let cost_of_change = change_fee + spend_fee;
+
+        let selection =
+            match self.coin_selection {
+                Some(CoinSelection::BranchAndBound) => {
+                    coin_selection::branch_and_bound(&values, target, cost_of_change)
+                }
+                Some(CoinSelection::Knapsack) => {
+                    coin_selection::knapsack(&values, target, change_fee + min_change, rng)
+                }
+                Some(CoinSelection::LargestFirst) => coin_selection::largest_first(&values, target),
+                None => coin_selection::branch_and_bound(&values, target, cost_of_change).or_else(
+                    || coin_selection::knapsack(&values, target, change_fee + min_change, rng),
+                ),
+            };
+        let selection = selection.ok_or_else(|| {
+            Error::NoSelection(self.coin_selection.unwrap_or(CoinSelection::Knapsack))
+        })?;
+
+        let utxos = selection
+            .into_iter()
+            .map(|i| candidates[i].0.clone())
+            .collect::<Vec<_>>();
+        let input_types = utxos.iter().map(|utxo| utxo.input_type).collect::<Vec<_>>();
+        let input_value = utxos.iter().map(|utxo| utxo.txout.value).sum::<u64>();
+
+        let mut outputs = self.recipients.clone();
+        let mut estimated_weight = estimate_weight(&input_types, &outputs);
+        let fee_without_change = self.fee_rate.fee(estimated_weight);
+
+        if input_value < recipients_value + fee_without_change {
+            return Err(Error::InsufficientFunds {
+                needed: recipients_value + fee_without_change,
+                available: input_value,
+            });
+        }
+
+        let mut change_index = None;
+        if let Some(change) = &self.change {
+            let mut with_change = outputs.clone();
+            with_change.push(TxOut {
+                value: 0,
+                script_pubkey: change.script_pubkey.clone(),
+            });
+            let weight = estimate_weight(&input_types, &with_change);
+            let value = (input_value - recipients_value).saturating_sub(self.fee_rate.fee(weight));
+
+            if value >= min_change {
+                let index = rng.gen_range(0..=outputs.len());
+                outputs.insert(
+                    index,
+                    TxOut {
+                        value,
+                        script_pubkey: change.script_pubkey.clone(),
+                    },
+                );
+                change_index = Some(index);
+                estimated_weight = weight;
+            }
+        }
+
+        let fee = input_value - outputs.iter().map(|output| output.value).sum::<u64>();
+        if fee > self.max_fee {
+            return Err(Error::FeeExceedsCap {
+                fee,
+                max_fee: self.max_fee,
+            });
+        }
+
+        let sequence = if self.rbf {
+            MAX_BIP125_RBF_SEQUENCE
+        } else if self.lock_time != 0 {
+            // Locktimes are only enforced when some input isn't final.
+            SEQUENCE_FINAL - 1
+        } else {
+            SEQUENCE_FINAL
+        };
+
+        let tx = Transaction {
+            version: 2,
+            inputs: utxos
+                .iter()
+                .map(|utxo| TxIn {
+                    previous_output: utxo.outpoint,
+                    script_sig: Script::new(),
+                    sequence,
+                    witness: Vec::new(),
+                })
+                .collect(),
+            outputs,
+            lock_time: self.lock_time,
+        };
+
+        Ok(BuiltTransaction {
+            tx,
+            utxos,
+            fee,
+            change_index,
+            estimated_weight,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::Purpose;
+    use crate::base58check::Base58CheckString;
+    use crate::bip32::{ChildNumber, DerivationPath, ExtendedPrivateKey, ExtendedPublicKey};
+    use crate::multisig::Cosigner;
+    use crate::script::interpreter::{self, VerifyFlags};
+    use crate::script::{opcodes, Builder};
+    use std::convert::TryFrom;
+
+    const MASTER_XPRV: &str = "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi";
+
+    fn treasury(script_type: ScriptType) -> (Vec<ExtendedPrivateKey>, MultisigWallet) {
+        let master = Base58CheckString::try_from(MASTER_XPRV.to_string()).unwrap();
+        let master = ExtendedPrivateKey::try_from(&master).unwrap();
+
+        // Each signer stands in for a separate master key.
+        let signers = (0..3)
+            .map(|i| {
+                let mut signer = master.derive_private(ChildNumber::Hardened(i)).unwrap();
+                signer.origin = Some((signer.fingerprint(), DerivationPath::default()));
+                signer
+            })
+            .collect::<Vec<_>>();
+        let cosigners = signers
+            .iter()
+            .map(|signer| Cosigner::from(ExtendedPublicKey::from(signer)))
+            .collect();
+
+        (
+            signers,
+            MultisigWallet::new(2, cosigners, script_type).unwrap(),
+        )
+    }
+
+    fn p2wpkh_script(byte: u8) -> Script {
+        Builder::new()
+            .push_opcode(opcodes::OP_0)
+            .push_slice(&[byte; 20])
+            .into_script()
+    }
+
+    fn utxo(vout: u32, value: u64) -> Utxo {
+        Utxo {
+            outpoint: OutPoint {
+                txid: [0x55; 32].into(),
+                vout,
+            },
+            txout: TxOut {
+                value,
+                script_pubkey: p2wpkh_script(0x66),
+            },
+            input_type: InputType::P2wpkh,
+        }
+    }
+
+    #[test]
+    fn test_input_weights() {
+        assert_eq!(InputType::P2wpkh.weight(), 272);
+        assert_eq!(InputType::P2shP2wpkh.weight(), 364);
+        assert_eq!(InputType::P2trKeySpend.weight(), 230);
+
+        // A one-input, one-output P2PKH transaction is 192 bytes.
+        let p2pkh = Builder::new()
+            .push_opcode(opcodes::OP_DUP)
+            .push_opcode(opcodes::OP_HASH160)
+            .push_slice(&[0; 20])
+            .push_opcode(opcodes::OP_EQUALVERIFY)
+            .push_opcode(opcodes::OP_CHECKSIG)
+            .into_script();
+        let output = TxOut {
+            value: 1000,
+            script_pubkey: p2pkh,
+        };
+        assert_eq!(estimate_weight(&[InputType::P2pkh], &[output]), 192 * 4);
+    }
+
+    #[test]
+    fn test_dust_limit() {
+        let (_, wallet) = treasury(ScriptType::P2sh);
+        let p2sh = wallet.derive(false, 0).unwrap().script_pubkey;
+
+        assert_eq!(dust_limit(&p2sh), 540);
+        assert_eq!(dust_limit(&p2wpkh_script(0)), 294);
+        assert_eq!(
+            dust_limit(&Builder::new().push_opcode(opcodes::OP_RETURN).into_script()),
+            0
+        );
+    }
+
+    #[test]
+    fn test_fee_rate() {
+        let fee_rate = FeeRate::from_sat_per_vb(2);
+        assert_eq!(fee_rate.fee(561), 282);
+        assert_eq!(FeeRate(1500).fee(400), 150);
+        assert_eq!(FeeRate(1001).fee(4), 2);
+    }
+
+    #[test]
+    fn test_build_exact_match_without_change() {
+        let fee_rate = FeeRate::from_sat_per_vb(1);
+        let recipient = p2wpkh_script(0x77);
+        let weight = estimate_weight(
+            &[InputType::P2wpkh],
+            &[TxOut {
+                value: 0,
+                script_pubkey: recipient.clone(),
+            }],
+        );
+
+        let built = TxBuilder::new(fee_rate)
+            .add_utxos(vec![
+                utxo(0, 50_000),
+                utxo(1, 20_000 + fee_rate.fee(weight)),
+            ])
+            .add_recipient(recipient, 20_000)
+            .change_script(p2wpkh_script(0x88), InputType::P2wpkh)
+            .coin_selection(CoinSelection::BranchAndBound)
+            .build()
+            .unwrap();
+
+        assert_eq!(built.utxos, vec![utxo(1, 20_000 + fee_rate.fee(weight))]);
+        assert_eq!(built.change_index, None);
+        assert_eq!(built.fee, fee_rate.fee(weight));
+        assert_eq!(built.tx.inputs[0].sequence, SEQUENCE_FINAL);
+    }
+
+    #[test]
+    fn test_build_with_change() {
+        let fee_rate = FeeRate::from_sat_per_vb(5);
+
+        for algorithm in &[CoinSelection::Knapsack, CoinSelection::LargestFirst] {
+            let built = TxBuilder::new(fee_rate)
+                .add_utxos(vec![utxo(0, 40_000), utxo(1, 70_000), utxo(2, 100)])
+                .add_recipient(p2wpkh_script(0x77), 60_000)
+                .change_script(p2wpkh_script(0x88), InputType::P2wpkh)
+                .coin_selection(*algorithm)
+                .enable_rbf()
+                .build()
+                .unwrap();
+
+            // The 100 satoshi output costs more to spend than it's worth.
+            assert!(built.utxos.iter().all(|utxo| utxo.txout.value > 100));
+
+            let change = &built.tx.outputs[built.change_index.unwrap()];
+            assert_eq!(change.script_pubkey, p2wpkh_script(0x88));
+
+            let input_value = built.utxos.iter().map(|utxo| utxo.txout.value).sum::<u64>();
+            assert_eq!(built.fee, input_value - 60_000 - change.value);
+            assert_eq!(built.fee, fee_rate.fee(built.estimated_weight));
+            assert!(built
+                .tx
+                .inputs
+                .iter()
+                .all(|input| input.sequence == MAX_BIP125_RBF_SEQUENCE));
+        }
+    }
+
+    #[test]
+    fn test_build_errors() {
+        let fee_rate = FeeRate::from_sat_per_vb(1);
+        let builder = TxBuilder::new(fee_rate).add_utxo(utxo(0, 10_000));
+
+        assert_eq!(builder.build(), Err(Error::NoRecipients));
+
+        assert_eq!(
+            builder
+                .clone()
+                .add_recipient(p2wpkh_script(0x77), 293)
+                .build(),
+            Err(Error::DustOutput {
+                index: 0,
+                value: 293,
+                dust_limit: 294
+            })
+        );
+
+        match builder
+            .clone()
+            .add_recipient(p2wpkh_script(0x77), 10_000)
+            .build()
+        {
+            Err(Error::InsufficientFunds { needed, available }) => assert!(needed > available),
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        // Without change, the leftover would all be paid in fees.
+        assert_eq!(
+            builder
+                .clone()
+                .add_recipient(p2wpkh_script(0x77), 1000)
+                .max_fee(5000)
+                .build(),
+            Err(Error::FeeExceedsCap {
+                fee: 9000,
+                max_fee: 5000
+            })
+        );
+
+        assert_eq!(
+            builder
+                .add_recipient(p2wpkh_script(0x77), 9000)
+                .coin_selection(CoinSelection::BranchAndBound)
+                .build(),
+            Err(Error::NoSelection(CoinSelection::BranchAndBound))
+        );
+    }
+
+    #[test]
+    fn test_build_multisig_psbt() {
+        for script_type in &[ScriptType::P2sh, ScriptType::P2shP2wsh, ScriptType::P2wsh] {
+            let (signers, wallet) = treasury(*script_type);
+
+            let utxos = (0..3)
+                .map(|index| Utxo {
+                    outpoint: OutPoint {
+                        txid: [0x99; 32].into(),
+                        vout: index,
+                    },
+                    txout: TxOut {
+                        value: 30_000 * (index as u64 + 1),
+                        script_pubkey: wallet.derive(false, index).unwrap().script_pubkey,
+                    },
+                    input_type: InputType::multisig(&wallet),
+                })
+                .collect::<Vec<_>>();
+
+            let (mut psbt, built) = TxBuilder::new(FeeRate::from_sat_per_vb(10))
+                .add_utxos(utxos)
+                .add_recipient(p2wpkh_script(0x77), 75_000)
+                .change_address(&wallet, 4)
+                .unwrap()
+                .lock_time(700_000)
+                .build_psbt()
+                .unwrap();
+
+            let change_index = built.change_index.unwrap();
+            assert_eq!(
+                built.tx.outputs[change_index].script_pubkey,
+                wallet.derive(true, 4).unwrap().script_pubkey
+            );
+            assert_eq!(psbt.outputs[change_index].bip32_derivation.len(), 3);
+            assert_eq!(built.tx.inputs[0].sequence, SEQUENCE_FINAL - 1);
+
+            for (input, utxo) in psbt.inputs.iter_mut().zip(&built.utxos) {
+                if !utxo.input_type.is_segwit() {
+                    input.witness_utxo = Some(utxo.txout.clone());
+                }
+                wallet
+                    .update_psbt_input(input, false, utxo.outpoint.vout)
+                    .unwrap();
+            }
+
+            psbt.sign(&signers[0]).unwrap();
+            psbt.sign(&signers[1]).unwrap();
+            psbt.finalize().unwrap();
+            let tx = psbt.extract_tx().unwrap();
+
+            let prevouts = built
+                .utxos
+                .iter()
+                .map(|utxo| utxo.txout.clone())
+                .collect::<Vec<_>>();
+            for index in 0..tx.inputs.len() {
+                assert_eq!(
+                    interpreter::verify_input_with_prevouts(
+                        &tx,
+                        index,
+                        &prevouts,
+                        VerifyFlags::STANDARD
+                    ),
+                    Ok(())
+                );
+            }
+
+            // Estimates assume the longest signatures, which real ones rarely fall far short of.
+            // Legacy scriptSig bytes weigh four units each.
+            assert!(tx.weight() <= built.estimated_weight);
+            assert!(built.estimated_weight - tx.weight() <= 4 * tx.inputs.len() * 4);
+        }
+    }
+
+    #[test]
+    fn test_build_single_key_psbt() {
+        let master = Base58CheckString::try_from(MASTER_XPRV.to_string()).unwrap();
+        let master = ExtendedPrivateKey::try_from(&master).unwrap();
+
+        for (purpose, input_type) in &[
+            (Purpose::Bip44, InputType::P2pkh),
+            (Purpose::Bip84, InputType::P2wpkh),
+            (Purpose::Bip86, InputType::P2trKeySpend),
+        ] {
+            let account = Account::from_master(&master, *purpose, 0, 0).unwrap();
+            assert_eq!(InputType::single_key(purpose.script_type()), *input_type);
+
+            let (psbt, built) = TxBuilder::new(FeeRate::from_sat_per_vb(10))
+                .add_utxos(vec![utxo(0, 40_000), utxo(1, 70_000)])
+                .add_recipient(p2wpkh_script(0x77), 75_000)
+                .change_account(&account, 2)
+                .unwrap()
+                .build_psbt()
+                .unwrap();
+
+            let change_index = built.change_index.unwrap();
+            assert_eq!(
+                built.tx.outputs[change_index].script_pubkey,
+                account.script_pubkey(Chain::Change, 2).unwrap()
+            );
+
+            let derivation = &psbt.outputs[change_index].bip32_derivation;
+            if *input_type == InputType::P2trKeySpend {
+                assert!(derivation.is_empty());
+                continue;
+            }
+
+            let change = master
+                .derive_private_path(
+                    account
+                        .path()
+                        .extend(&[ChildNumber::Normal(1), ChildNumber::Normal(2)])
+                        .as_ref(),
+                )
+                .unwrap();
+            let (pubkey, (fingerprint, path)) = derivation.iter().next().unwrap();
+            assert_eq!(derivation.len(), 1);
+            assert_eq!(*pubkey, ExtendedPublicKey::from(&change).public_key);
+            assert_eq!(*fingerprint, master.fingerprint());
+            assert_eq!(path.to_string(), format!("{}/1/2", account.path()));
+        }
+    }
+}
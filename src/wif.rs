@@ -0,0 +1,328 @@
+//! Wallet Import Format (WIF) encoding of single private keys.
+
+use crate::base58check::{self, Base58CheckString};
+use crate::bip32::ExtendedPrivateKey;
+use crate::context;
+use crate::network::Network;
+use secp256k1::{constants, PublicKey, SecretKey};
+use std::convert::TryFrom;
+use std::fmt;
+use zeroize::{Zeroize, Zeroizing};
+
+/// Marks a WIF key whose public key is serialized in compressed form.
+const COMPRESSED_FLAG: u8 = 0x01;
+
+/// Error represents all of the possible errors that can arise while decoding a WIF private key.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    Base58Check(base58check::Error),
+    Secp256k1(secp256k1::Error),
+    InvalidLength(usize),
+    InvalidPrefix(u8),
+    InvalidCompressionFlag(u8),
+    NetworkMismatch { expected: Network, actual: Network },
+}
+
+impl From<base58check::Error> for Error {
+    fn from(err: base58check::Error) -> Error {
+        Error::Base58Check(err)
+    }
+}
+
+impl From<secp256k1::Error> for Error {
+    fn from(err: secp256k1::Error) -> Error {
+        Error::Secp256k1(err)
+    }
+}
+
+/// A private key along with the network and public key serialization it is used with.
+///
+/// The key is wiped when dropped and left out of the `Debug` output.
+#[derive(Clone, PartialEq)]
+pub struct PrivateKey {
+    pub network: Network,
+    pub compressed: bool,
+    pub key: SecretKey,
+}
+
+impl PrivateKey {
+    /// Wraps `key` for use on `network` with a compressed public key.
+    pub fn new(key: SecretKey, network: Network) -> PrivateKey {
+        PrivateKey {
+            network,
+            compressed: true,
+            key,
+        }
+    }
+
+    /// Decodes a WIF private key, rejecting keys encoded for a network other than `network`.
+    pub fn from_wif(wif: &Base58CheckString, network: Network) -> Result<PrivateKey, Error> {
+        let mut key = PrivateKey::try_from(wif)?;
+
+        // Networks sharing a prefix decode as the first of them, so compare prefixes.
+        if key.network.wif_prefix() != network.wif_prefix() {
+            return Err(Error::NetworkMismatch {
+                expected: network,
+                actual: key.network,
+            });
+        }
+
+        key.network = network;
+        Ok(key)
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey::from_secret_key(context::secp256k1(), &self.key)
+    }
+
+    /// Serializes the public key in the form addresses for this key commit to.
+    pub fn public_key_bytes(&self) -> Vec<u8> {
+        if self.compressed {
+            self.public_key().serialize().to_vec()
+        } else {
+            self.public_key().serialize_uncompressed().to_vec()
+        }
+    }
+}
+
+impl fmt::Debug for PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PrivateKey")
+            .field("network", &self.network)
+            .field("compressed", &self.compressed)
+            .field("key", &format_args!("[redacted]"))
+            .finish()
+    }
+}
+
+impl Zeroize for PrivateKey {
+    fn zeroize(&mut self) {
+        zeroize_secret_key(&mut self.key);
+    }
+}
+
+impl Drop for PrivateKey {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+/// Overwrites `key` with zeros. The result isn't a valid secret key, so `key` must not be used
+/// afterwards.
+pub(crate) fn zeroize_secret_key(key: &mut SecretKey) {
+    // SecretKey is a plain byte array which secp256k1 doesn't expose mutably.
+    unsafe { std::slice::from_raw_parts_mut(key.as_mut_ptr(), constants::SECRET_KEY_SIZE) }
+        .zeroize();
+}
+
+impl TryFrom<&Base58CheckString> for PrivateKey {
+    type Error = Error;
+
+    fn try_from(v: &Base58CheckString) -> Result<Self, Self::Error> {
+        let data = v.into_bytes()?;
+
+        let compressed = match data.len() {
+            33 => false,
+            34 if data[33] == COMPRESSED_FLAG => true,
+            34 => return Err(Error::InvalidCompressionFlag(data[33])),
+            len => return Err(Error::InvalidLength(len)),
+        };
+
+        let network = Network::ALL
+            .iter()
+            .copied()
+            .find(|network| data[0] == network.wif_prefix())
+            .ok_or(Error::InvalidPrefix(data[0]))?;
+
+        Ok(PrivateKey {
+            network,
+            compressed,
+            key: SecretKey::from_slice(&data[1..33])?,
+        })
+    }
+}
+
+impl From<&PrivateKey> for Base58CheckString {
+    fn from(v: &PrivateKey) -> Self {
+        let mut data = Zeroizing::new(Vec::with_capacity(34));
+        data.push(v.network.wif_prefix());
+        data.extend(&v.key[..]);
+        if v.compressed {
+            data.push(COMPRESSED_FLAG);
+        }
+        Base58CheckString::from_bytes(&data[..])
+    }
+}
+
+impl From<&ExtendedPrivateKey> for PrivateKey {
+    /// BIP32 keys always use compressed public keys.
+    fn from(xprv: &ExtendedPrivateKey) -> Self {
+        PrivateKey::new(xprv.private_key, xprv.network)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bip32::{self, ChildNumber};
+    use crate::bip39;
+    use serde_json::Value;
+    use std::mem::ManuallyDrop;
+
+    #[test]
+    fn test_bitcoin_core_valid_keys() {
+        let tests: Vec<Value> =
+            serde_json::from_str(include_str!("../testdata/key_io_valid.json")).unwrap();
+
+        for test in tests {
+            if test[2]["isPrivkey"] != true {
+                continue;
+            }
+
+            let wif = Base58CheckString::try_from(test[0].as_str().unwrap().to_string()).unwrap();
+            let network = match test[2]["chain"].as_str().unwrap() {
+                "main" => Network::Mainnet,
+                _ => Network::Testnet,
+            };
+
+            let key = PrivateKey::try_from(&wif).unwrap();
+            assert_eq!(
+                key.key[..],
+                hex::decode(test[1].as_str().unwrap()).unwrap()[..]
+            );
+            assert_eq!(key.network, network);
+            assert_eq!(key.compressed, test[2]["isCompressed"] == true);
+            assert_eq!(Base58CheckString::from(&key), wif);
+        }
+    }
+
+    #[test]
+    fn test_bitcoin_core_invalid_keys() {
+        let tests: Vec<Value> =
+            serde_json::from_str(include_str!("../testdata/key_io_invalid.json")).unwrap();
+
+        for test in tests {
+            let string = test[0].as_str().unwrap().to_string();
+            if let Ok(wif) = Base58CheckString::try_from(string.clone()) {
+                assert!(PrivateKey::try_from(&wif).is_err(), "{}", string);
+            }
+        }
+    }
+
+    #[test]
+    fn test_network_mismatch() {
+        let key = PrivateKey::new(
+            SecretKey::from_slice(&[0x01; 32]).unwrap(),
+            Network::Testnet,
+        );
+        let wif = Base58CheckString::from(&key);
+
+        assert_eq!(PrivateKey::from_wif(&wif, Network::Testnet), Ok(key));
+        assert_eq!(
+            PrivateKey::from_wif(&wif, Network::Mainnet),
+            Err(Error::NetworkMismatch {
+                expected: Network::Mainnet,
+                actual: Network::Testnet
+            })
+        );
+    }
+
+    #[test]
+    fn test_export_extended_private_key_child() {
+        let xprv = Base58CheckString::try_from("xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi".to_string()).unwrap();
+        let xprv = ExtendedPrivateKey::try_from(&xprv).unwrap();
+        let child = xprv.derive_private(ChildNumber::Hardened(0)).unwrap();
+
+        let wif = child.to_wif(Network::Mainnet).unwrap();
+        let key = PrivateKey::from_wif(&wif, Network::Mainnet).unwrap();
+        assert_eq!(key.key, child.private_key);
+        assert!(key.compressed);
+
+        assert_eq!(
+            child.to_wif(Network::Testnet),
+            Err(bip32::Error::NetworkMismatch {
+                expected: Network::Mainnet,
+                actual: Network::Testnet
+            })
+        );
+    }
+
+    #[test]
+    fn test_altcoin_keys() {
+        // The keys of the first BIP44 receive addresses.
+        let mut root = bip39::test_root(Network::Mainnet);
+
+        let tests = [
+            (
+                Network::Litecoin,
+                "T5b4RiWRs7XG8xZ2bCHBoJcn4JrpMTbGRFYXgoZHd7nD8izwqhMK",
+            ),
+            (
+                Network::Dogecoin,
+                "QPkeC1ZfHx3c9g7WTj9cQ8gnvk2iSAfAcbq1aVAWjNTwDAKfZUzx",
+            ),
+        ];
+
+        for (network, wif) in tests.iter() {
+            root.network = *network;
+            let path = [
+                ChildNumber::Hardened(44),
+                ChildNumber::Hardened(network.coin_type()),
+                ChildNumber::Hardened(0),
+                ChildNumber::Normal(0),
+                ChildNumber::Normal(0),
+            ];
+            let child = root.derive_private_path(&path).unwrap();
+
+            assert_eq!(child.to_wif(*network).unwrap().as_str(), *wif);
+            let key = PrivateKey::try_from(&child.to_wif(*network).unwrap()).unwrap();
+            assert_eq!(key.network, *network);
+            assert_eq!(key.key, child.private_key);
+        }
+
+        // Bitcoin Cash keys share mainnet's prefix, so decode as mainnet ones.
+        root.network = Network::BitcoinCash;
+        let wif = root.to_wif(Network::BitcoinCash).unwrap();
+        assert_eq!(
+            PrivateKey::try_from(&wif).map(|key| key.network),
+            Ok(Network::Mainnet)
+        );
+        assert_eq!(
+            PrivateKey::from_wif(&wif, Network::BitcoinCash).map(|key| key.network),
+            Ok(Network::BitcoinCash)
+        );
+        assert_eq!(
+            PrivateKey::from_wif(&wif, Network::Litecoin).map(|key| key.network),
+            Err(Error::NetworkMismatch {
+                expected: Network::Litecoin,
+                actual: Network::Mainnet
+            })
+        );
+    }
+
+    #[test]
+    fn test_debug_is_redacted() {
+        let key = PrivateKey::new(
+            SecretKey::from_slice(&[0xab; 32]).unwrap(),
+            Network::Mainnet,
+        );
+        let debug = format!("{:?}", key);
+
+        assert!(debug.contains("[redacted]"));
+        assert!(!debug.contains(&hex::encode([0xab; 32])));
+    }
+
+    #[test]
+    fn test_wiped_on_drop() {
+        let mut key = ManuallyDrop::new(PrivateKey::new(
+            SecretKey::from_slice(&[0xab; 32]).unwrap(),
+            Network::Mainnet,
+        ));
+        let ptr = key.key.as_ptr();
+
+        unsafe {
+            ManuallyDrop::drop(&mut key);
+            assert_eq!(std::slice::from_raw_parts(ptr, 32), &[0u8; 32]);
+        }
+    }
+}